@@ -0,0 +1,2 @@
+ALTER TABLE login_sessions ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE login_sessions ADD COLUMN last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
@@ -0,0 +1,3 @@
+-- tokens derived from login and time could collide, keep only the newest of such sessions
+DELETE FROM login_sessions a USING login_sessions b WHERE a.token = b.token AND a.id < b.id;
+ALTER TABLE login_sessions ADD CONSTRAINT login_sessions_token_unique UNIQUE (token);
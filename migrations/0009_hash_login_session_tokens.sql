@@ -0,0 +1,3 @@
+-- plaintext tokens cannot be converted into digests, so every existing session is invalidated
+DELETE FROM login_sessions;
+ALTER TABLE login_sessions RENAME COLUMN token TO token_hash;
@@ -0,0 +1,12 @@
+CREATE TABLE refresh_tokens (
+  id SERIAL PRIMARY KEY,
+  -- every token rotated out of the same login shares the family
+  family VARCHAR NOT NULL,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login),
+  token_hash VARCHAR(64) NOT NULL UNIQUE,
+  used BOOLEAN NOT NULL DEFAULT FALSE,
+  family_created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE login_sessions ADD COLUMN refresh_family VARCHAR;
@@ -0,0 +1,2 @@
+ALTER TABLE login_sessions ADD COLUMN user_agent VARCHAR;
+ALTER TABLE login_sessions ADD COLUMN ip VARCHAR;
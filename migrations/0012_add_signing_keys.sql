@@ -0,0 +1,10 @@
+CREATE TABLE signing_keys (
+  id SERIAL PRIMARY KEY,
+  kid VARCHAR NOT NULL UNIQUE,
+  -- PKCS#8 document of an Ed25519 key pair
+  private_key BYTEA NOT NULL,
+  public_key BYTEA NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  -- retired keys are still published during the grace period
+  retired_at TIMESTAMPTZ
+);
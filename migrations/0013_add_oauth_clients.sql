@@ -0,0 +1,8 @@
+CREATE TABLE oauth_clients (
+  id SERIAL PRIMARY KEY,
+  client_id VARCHAR NOT NULL UNIQUE,
+  -- HMAC digest of the client secret, the secret itself is only shown once
+  secret_hash VARCHAR(64) NOT NULL,
+  name VARCHAR(255) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,10 @@
+CREATE TABLE user_totp (
+  user_login VARCHAR(255) PRIMARY KEY REFERENCES users(login),
+  -- base32 encoded secret shared with the authenticator app
+  secret VARCHAR NOT NULL,
+  -- the second factor is only required once the enrollment is confirmed with a code
+  confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+  -- time step of the last accepted code, so a code cannot be used twice
+  last_used_step BIGINT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,7 @@
+CREATE TABLE recovery_codes (
+  id SERIAL PRIMARY KEY,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login),
+  -- argon2 hash of the code, every code can be used once
+  code_hash VARCHAR NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
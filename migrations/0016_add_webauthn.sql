@@ -0,0 +1,23 @@
+CREATE TABLE webauthn_credentials (
+  id SERIAL PRIMARY KEY,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login),
+  credential_id BYTEA NOT NULL UNIQUE,
+  -- COSE algorithm identifier, -7 for ES256 and -8 for EdDSA
+  algorithm INTEGER NOT NULL,
+  public_key BYTEA NOT NULL,
+  -- signature counter of the authenticator, used to detect cloned authenticators
+  sign_count BIGINT NOT NULL DEFAULT 0,
+  name VARCHAR(255) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_used_at TIMESTAMPTZ
+);
+
+CREATE TABLE webauthn_challenges (
+  id SERIAL PRIMARY KEY,
+  challenge VARCHAR NOT NULL UNIQUE,
+  -- either registration or authentication
+  ceremony VARCHAR(32) NOT NULL,
+  -- required for registrations, optional for authentications without a known user
+  user_login VARCHAR(255) REFERENCES users(login),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
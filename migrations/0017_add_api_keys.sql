@@ -0,0 +1,17 @@
+-- service accounts are users without a usable password, they authenticate with API keys only
+ALTER TABLE users ADD COLUMN service_account BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE api_keys (
+  id SERIAL PRIMARY KEY,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login),
+  name VARCHAR(255) NOT NULL,
+  -- public part of the key, used to identify it in listings and logs
+  prefix VARCHAR(16) NOT NULL UNIQUE,
+  -- HMAC digest of the whole key, the key itself is only shown once
+  key_hash VARCHAR(64) NOT NULL UNIQUE,
+  -- subset of the owner's permissions the key is limited to, NULL for all of them
+  permissions TEXT[],
+  expires_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_used_at TIMESTAMPTZ
+);
@@ -0,0 +1,24 @@
+-- public clients (e.g. single page or mobile apps) cannot keep a secret and rely on PKCE only
+ALTER TABLE oauth_clients ALTER COLUMN secret_hash DROP NOT NULL;
+ALTER TABLE oauth_clients ADD COLUMN redirect_uris TEXT[] NOT NULL DEFAULT '{}';
+-- user the client_credentials grant issues tokens for, the grant is refused when missing
+ALTER TABLE oauth_clients ADD COLUMN service_account VARCHAR(255) REFERENCES users(login) ON DELETE SET NULL;
+
+-- scopes limit the token to a subset of the user's permissions, NULL for all of them
+ALTER TABLE login_sessions ADD COLUMN client_id VARCHAR REFERENCES oauth_clients(client_id) ON DELETE CASCADE;
+ALTER TABLE login_sessions ADD COLUMN scopes TEXT[];
+ALTER TABLE refresh_tokens ADD COLUMN client_id VARCHAR REFERENCES oauth_clients(client_id) ON DELETE CASCADE;
+ALTER TABLE refresh_tokens ADD COLUMN scopes TEXT[];
+
+CREATE TABLE oauth_authorization_codes (
+  id SERIAL PRIMARY KEY,
+  -- HMAC digest of the code, the code itself is only sent to the client's redirect uri
+  code_hash VARCHAR(64) NOT NULL UNIQUE,
+  client_id VARCHAR NOT NULL REFERENCES oauth_clients(client_id) ON DELETE CASCADE,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login),
+  redirect_uri VARCHAR NOT NULL,
+  scopes TEXT[] NOT NULL,
+  -- S256 PKCE challenge the token request has to present the verifier of
+  code_challenge VARCHAR NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
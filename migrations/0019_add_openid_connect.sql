@@ -0,0 +1,2 @@
+-- nonce of an OpenID Connect authentication request, copied into the ID token
+ALTER TABLE oauth_authorization_codes ADD COLUMN nonce VARCHAR;
@@ -0,0 +1,18 @@
+CREATE TABLE oauth_device_codes (
+  id SERIAL PRIMARY KEY,
+  -- HMAC digests of the codes, the device code is only known to the client
+  -- and the user code is typed in by the user on the verification page
+  device_code_hash VARCHAR(64) NOT NULL UNIQUE,
+  user_code_hash VARCHAR(64) NOT NULL UNIQUE,
+  client_id VARCHAR NOT NULL REFERENCES oauth_clients(client_id) ON DELETE CASCADE,
+  -- requested scopes, narrowed down to the granted ones on approval
+  scopes TEXT[] NOT NULL,
+  -- user who approved or denied the request, NULL while it's pending
+  user_login VARCHAR(255) REFERENCES users(login),
+  approved BOOLEAN,
+  -- minimum number of seconds between two polls of the token endpoint
+  poll_interval INTEGER NOT NULL,
+  last_polled_at TIMESTAMPTZ,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,23 @@
+-- users signed in through an upstream OpenID Connect provider, identified by the provider's subject
+CREATE TABLE federated_identities (
+  id SERIAL PRIMARY KEY,
+  provider VARCHAR(255) NOT NULL,
+  subject VARCHAR(255) NOT NULL,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_login_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  UNIQUE (provider, subject)
+);
+
+-- sign ins started with an upstream provider, waiting for its callback
+CREATE TABLE federated_login_states (
+  id SERIAL PRIMARY KEY,
+  -- HMAC digest of the state parameter sent to the provider
+  state_hash VARCHAR(64) NOT NULL UNIQUE,
+  provider VARCHAR(255) NOT NULL,
+  nonce VARCHAR NOT NULL,
+  code_verifier VARCHAR NOT NULL,
+  -- path the user is sent to once signed in
+  redirect_to VARCHAR NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,8 @@
+CREATE TABLE user_password_change_events (
+  id SERIAL PRIMARY KEY,
+  key VARCHAR NOT NULL,
+  user_login VARCHAR NOT NULL,
+  password_hash VARCHAR NOT NULL,
+  -- session kept when the other sessions of the user are revoked, missing when no session is revoked
+  kept_session_id INTEGER
+);
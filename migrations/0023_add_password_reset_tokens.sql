@@ -0,0 +1,8 @@
+CREATE TABLE password_reset_tokens (
+  id SERIAL PRIMARY KEY,
+  -- HMAC digest of the token, the token itself is only sent to the user
+  token_hash VARCHAR(64) NOT NULL UNIQUE,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login) ON DELETE CASCADE,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
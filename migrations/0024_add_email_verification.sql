@@ -0,0 +1,13 @@
+-- whether the user proved owning the email of their details, reset when the email changes
+ALTER TABLE users ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE email_verification_tokens (
+  id SERIAL PRIMARY KEY,
+  -- HMAC digest of the token, the token itself is only sent to the email
+  token_hash VARCHAR(64) NOT NULL UNIQUE,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login) ON DELETE CASCADE,
+  -- address the token was sent to, the user is only verified while it's still their email
+  email VARCHAR NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,12 @@
+CREATE TABLE magic_link_tokens (
+  id SERIAL PRIMARY KEY,
+  -- HMAC digest of the token, the token itself is only sent to the email
+  token_hash VARCHAR(64) NOT NULL UNIQUE,
+  user_login VARCHAR(255) NOT NULL REFERENCES users(login) ON DELETE CASCADE,
+  -- address the link was sent to, the links sent to an address are rate limited
+  email VARCHAR NOT NULL,
+  -- used links are kept until the rate limit window passes
+  used_at TIMESTAMPTZ,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,10 @@
+-- keys are random, duplicates are only possible through a collision, keep only the newest of such events
+DELETE FROM user_register_events a USING user_register_events b WHERE a.key = b.key AND a.id < b.id;
+DELETE FROM user_login_events a USING user_login_events b WHERE a.key = b.key AND a.id < b.id;
+DELETE FROM user_delete_events a USING user_delete_events b WHERE a.key = b.key AND a.id < b.id;
+DELETE FROM user_password_change_events a USING user_password_change_events b WHERE a.key = b.key AND a.id < b.id;
+
+ALTER TABLE user_register_events ADD CONSTRAINT user_register_events_key_unique UNIQUE (key);
+ALTER TABLE user_login_events ADD CONSTRAINT user_login_events_key_unique UNIQUE (key);
+ALTER TABLE user_delete_events ADD CONSTRAINT user_delete_events_key_unique UNIQUE (key);
+ALTER TABLE user_password_change_events ADD CONSTRAINT user_password_change_events_key_unique UNIQUE (key);
@@ -0,0 +1,2 @@
+-- imported scrypt and PBKDF2 hashes and argon2 hashes with high costs exceed 100 characters
+ALTER TABLE users ALTER COLUMN password_hash TYPE TEXT;
@@ -0,0 +1,16 @@
+-- permissions added after the first release, seeded here as the defaults are only inserted on fresh installs
+INSERT INTO permissions (name, description) VALUES
+  ('authoric:sessions:manage', 'permission to list and revoke login sessions of ANY user'),
+  ('authoric:service-accounts:manage', 'permission to create service accounts and manage API keys of ANY user'),
+  ('authoric:oauth-clients:manage', 'permission to register and delete OAuth clients')
+ON CONFLICT (name) DO NOTHING;
+
+-- root is only present on existing installs, the fresh ones grant these while creating it
+INSERT INTO groups_permissions (group_name, permission_name)
+SELECT 'root', permissions.name FROM permissions
+WHERE permissions.name IN ('authoric:sessions:manage', 'authoric:service-accounts:manage', 'authoric:oauth-clients:manage')
+  AND EXISTS (SELECT 1 FROM groups WHERE name = 'root')
+  AND NOT EXISTS (
+    SELECT 1 FROM groups_permissions
+    WHERE group_name = 'root' AND permission_name = permissions.name
+  );
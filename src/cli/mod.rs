@@ -101,6 +101,8 @@ pub async fn init_defaults(config: &CauthConfig) {
     )
     .await;
 
+    // authoric:sessions:manage, authoric:service-accounts:manage and authoric:oauth-clients:manage
+    // are seeded by a migration, so they're present on installs already having the ones above
     let _ = Group::insert(
     &mut tx,
     &"root".to_string(), 
@@ -114,10 +116,67 @@ pub async fn init_defaults(config: &CauthConfig) {
       "authoric:groups:delete".to_string(),
       "authoric:groups:update".to_string(),
       "authoric:users:update".to_string(),
-      "authoric:users:delete".to_string()
+      "authoric:users:delete".to_string(),
+      "authoric:sessions:manage".to_string(),
+      "authoric:service-accounts:manage".to_string(),
+      "authoric:oauth-clients:manage".to_string()
     ]
   )
   .await;
 
     let _ = tx.commit().await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::raw_sql;
+
+    const MANAGEMENT_PERMISSIONS: [&str; 3] = [
+        "authoric:sessions:manage",
+        "authoric:service-accounts:manage",
+        "authoric:oauth-clients:manage",
+    ];
+
+    #[tokio::test]
+    #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+    async fn grants_every_default_permission_to_root() {
+        let config = CauthConfig::for_tests().await;
+
+        init_defaults(&config).await;
+
+        let mut conn = config.db_conn.acquire().await.unwrap();
+        let root = Group::retrieve(&mut conn, &"root".to_string()).await.ok().unwrap();
+        assert_eq!(root.permissions.len(), 12);
+        for permission in MANAGEMENT_PERMISSIONS {
+            assert!(root.permissions.contains(&permission.to_string()));
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+    async fn grants_management_permissions_to_existing_root() {
+        let config = CauthConfig::for_tests().await;
+
+        // an install created before these permissions existed
+        let sql = "
+        DELETE FROM permissions WHERE name LIKE '%:manage';
+        INSERT INTO permissions (name, description) VALUES ('authoric:users:delete', '');
+        INSERT INTO groups (name, description) VALUES ('root', '');
+        INSERT INTO groups_permissions (group_name, permission_name) VALUES ('root', 'authoric:users:delete');
+        ";
+        raw_sql(sql).execute(&config.db_conn).await.unwrap();
+
+        let migration = include_str!("../../migrations/0028_seed_management_permissions.sql");
+        raw_sql(migration).execute(&config.db_conn).await.unwrap();
+        // applying it again changes nothing
+        raw_sql(migration).execute(&config.db_conn).await.unwrap();
+
+        let mut conn = config.db_conn.acquire().await.unwrap();
+        let root = Group::retrieve(&mut conn, &"root".to_string()).await.ok().unwrap();
+        assert_eq!(root.permissions.len(), 4);
+        for permission in MANAGEMENT_PERMISSIONS {
+            assert!(root.permissions.contains(&permission.to_string()));
+        }
+    }
+}
@@ -4,9 +4,14 @@ use clap::{
   Subcommand
 };
 use colored::Colorize;
+use chrono::{Duration, Utc};
 use futures::executor::block_on;
+use std::fs;
 
-use crate::{config::CauthConfig, models::{group::{Group, GroupGrantError, GroupRevokeError}, permission::Permission, user::{User, UserGrantError, UserRevokeError}}, util::io::input};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{config::CauthConfig, models::{api_key::ApiKey, group::{Group, GroupGrantError, GroupRevokeError}, login_session::LoginSession, oauth_client::OAuthClient, permission::Permission, signing_key::SigningKey, user::{User, UserGrantError, UserRevokeError}, user_totp::UserTotp}, util::{io::input, string::json_value_to_pretty_string}};
 
 
 #[derive(Debug, Args)]
@@ -20,7 +25,14 @@ pub enum AdminAction {
   Create(AdminCreateCommand),
   Inspect(AdminInspectCommand),
   Grant(AdminGrantCommand),
-  Revoke(AdminRevokeCommand)
+  Revoke(AdminRevokeCommand),
+  Session(AdminSessionCommand),
+  Keys(AdminKeysCommand),
+  Client(AdminClientCommand),
+  Mfa(AdminMfaCommand),
+  ServiceAccount(AdminServiceAccountCommand),
+  ApiKey(AdminApiKeyCommand),
+  Import(AdminImportCommand)
 }
 
 impl AdminCommand {
@@ -29,7 +41,14 @@ impl AdminCommand {
       AdminAction::Create(cmd) => cmd.run(config),
       AdminAction::Inspect(cmd) => cmd.run(config),
       AdminAction::Grant(cmd) => cmd.run(config),
-      AdminAction::Revoke(cmd) => cmd.run(config)
+      AdminAction::Revoke(cmd) => cmd.run(config),
+      AdminAction::Session(cmd) => cmd.run(config),
+      AdminAction::Keys(cmd) => cmd.run(config),
+      AdminAction::Client(cmd) => cmd.run(config),
+      AdminAction::Mfa(cmd) => cmd.run(config),
+      AdminAction::ServiceAccount(cmd) => cmd.run(config),
+      AdminAction::ApiKey(cmd) => cmd.run(config),
+      AdminAction::Import(cmd) => cmd.run(config)
     };
   }
 }
@@ -307,3 +326,584 @@ impl AdminRevokeCommand {
     return Ok(());
   }
 }
+
+
+
+#[derive(Debug, Args)]
+pub struct AdminSessionCommand {
+  #[clap(subcommand)]
+  pub action: AdminSessionAction
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminSessionAction {
+  List(AdminSessionListCommand),
+  Revoke(AdminSessionRevokeCommand)
+}
+
+#[derive(Debug, Args)]
+pub struct AdminSessionListCommand {
+  pub login: String
+}
+
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+pub struct AdminSessionRevokeCommand {
+  /// id of a single session to revoke
+  #[clap(long)]
+  pub id: Option<i32>,
+  /// login of the user to log out everywhere
+  #[clap(long)]
+  pub login: Option<String>
+}
+
+impl AdminSessionCommand {
+  pub fn run(self, config: CauthConfig) {
+    match self.action {
+      AdminSessionAction::List(data) => {
+        block_on(Self::list_sessions(config, &data.login));
+      },
+      AdminSessionAction::Revoke(data) => {
+        // clap requires exactly one of them
+        match (data.id, &data.login) {
+          (Some(id), _) => block_on(Self::revoke_session(config, id)),
+          (None, Some(login)) => block_on(Self::revoke_user_sessions(config, login)),
+          (None, None) => ()
+        };
+      }
+    }
+  }
+
+  async fn list_sessions(config: CauthConfig, login: &String) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    if let Err(_) = User::retrieve(&mut executor, login).await {
+      println!("{}", format!("User \"{}\" not found.", login).red());
+      return;
+    }
+
+    let sessions = LoginSession::list_by_user(&mut executor, &config, login).await;
+
+    if sessions.is_empty() {
+      println!("{}", format!("User \"{}\" has no active sessions.", login).green());
+      return;
+    }
+
+    for session in sessions {
+      println!("{}", json_value_to_pretty_string(&json!({
+        "id": session.id,
+        "created_at": session.created_at,
+        "last_seen_at": session.last_seen_at,
+        "user_agent": session.user_agent,
+        "ip": session.ip
+      })));
+    }
+  }
+
+  async fn revoke_session(config: CauthConfig, id: i32) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    match LoginSession::delete(&mut executor, id).await {
+      Ok(_) => println!("{}", format!("Successfully revoked session {}.", id).green()),
+      Err(_) => println!("{}", format!("Session {} not found.", id).red())
+    };
+  }
+
+  async fn revoke_user_sessions(config: CauthConfig, login: &String) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    if let Err(_) = User::retrieve(&mut executor, login).await {
+      println!("{}", format!("User \"{}\" not found.", login).red());
+      return;
+    }
+
+    let revoked = LoginSession::revoke_all(&mut executor, login).await;
+
+    println!("{}", format!("Successfully revoked {} sessions of user {}.", revoked, login).green());
+  }
+}
+
+
+
+#[derive(Debug, Args)]
+pub struct AdminKeysCommand {
+  #[clap(subcommand)]
+  pub action: AdminKeysAction
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminKeysAction {
+  List,
+  Rotate
+}
+
+impl AdminKeysCommand {
+  pub fn run(self, config: CauthConfig) {
+    match self.action {
+      AdminKeysAction::List => {
+        block_on(Self::list_keys(config));
+      },
+      AdminKeysAction::Rotate => {
+        block_on(Self::rotate_keys(config));
+      }
+    }
+  }
+
+  async fn list_keys(config: CauthConfig) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+    let keys = SigningKey::published(&mut executor, &config).await;
+
+    if keys.is_empty() {
+      println!("{}", "No signing keys are published yet.".green());
+      return;
+    }
+
+    for key in keys {
+      println!("{}", json_value_to_pretty_string(&json!({
+        "kid": key.kid,
+        "created_at": key.created_at,
+        "retired_at": key.retired_at
+      })));
+    }
+  }
+
+  async fn rotate_keys(config: CauthConfig) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    match SigningKey::rotate(&mut executor).await {
+      Ok(key) => println!("{}", format!("Successfully rotated signing keys, new key id: {}.", key.kid).green()),
+      Err(err) => println!("{}", format!("Error while rotating signing keys.\n{}", err.to_string()).red())
+    };
+  }
+}
+
+
+
+#[derive(Debug, Args)]
+pub struct AdminClientCommand {
+  #[clap(subcommand)]
+  pub action: AdminClientAction
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminClientAction {
+  Create,
+  List,
+  Delete(AdminClientDeleteCommand)
+}
+
+#[derive(Debug, Args)]
+pub struct AdminClientDeleteCommand {
+  pub client_id: String
+}
+
+impl AdminClientCommand {
+  pub fn run(self, config: CauthConfig) {
+    match self.action {
+      AdminClientAction::Create => {
+        block_on(Self::create_client(config));
+      },
+      AdminClientAction::List => {
+        block_on(Self::list_clients(config));
+      },
+      AdminClientAction::Delete(data) => {
+        block_on(Self::delete_client(config, &data.client_id));
+      }
+    }
+  }
+
+  async fn create_client(config: CauthConfig) {
+    let name = input(format!("{} Enter the name of the client: ", "+".green())).unwrap();
+    println!("{} Enter the redirect uris of the client (empty to stop): ", "+".green());
+
+    let mut redirect_uris: Vec<String> = vec![];
+
+    while let Ok(redirect_uri) = input(format!("  {} Enter the redirect uri: ", "+".green())) {
+      redirect_uris.push(redirect_uri);
+    }
+
+    let public = input(format!("{} Is the client public, e.g. a browser or mobile app without a secret? (y/N): ", "+".green()))
+      .map(|answer| answer.eq_ignore_ascii_case("y"))
+      .unwrap_or(false);
+    let service_account = match public {
+      true => None,
+      false => input(format!("{} Enter the service account of the client_credentials grant (empty for none): ", "+".green())).ok()
+    };
+
+    let mut executor = config.db_conn.acquire().await.unwrap();
+    match OAuthClient::insert(&mut executor, &config, &name, &redirect_uris, public, service_account.as_ref()).await {
+      Ok((client, secret)) => {
+        println!("{}", json_value_to_pretty_string(&json!({
+          "client_id": client.client_id,
+          "client_secret": secret
+        })));
+
+        if secret.is_some() {
+          println!("{}", "The client secret is shown only once, store it now.".yellow());
+        }
+      },
+      Err(err) => println!("{}", format!("Error while creating the client.\n{}", err.to_string()).red())
+    };
+  }
+
+  async fn list_clients(config: CauthConfig) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+    let clients = OAuthClient::list(&mut executor).await;
+
+    if clients.is_empty() {
+      println!("{}", "No OAuth clients are registered.".green());
+      return;
+    }
+
+    for client in clients {
+      println!("{}", client.to_string());
+    }
+  }
+
+  async fn delete_client(config: CauthConfig, client_id: &String) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    match OAuthClient::delete(&mut executor, client_id).await {
+      Ok(_) => println!("{}", format!("Successfully deleted client {}.", client_id).green()),
+      Err(_) => println!("{}", format!("Client \"{}\" not found.", client_id).red())
+    };
+  }
+}
+
+
+
+#[derive(Debug, Args)]
+pub struct AdminMfaCommand {
+  #[clap(subcommand)]
+  pub action: AdminMfaAction
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminMfaAction {
+  Reset(AdminMfaResetCommand)
+}
+
+#[derive(Debug, Args)]
+pub struct AdminMfaResetCommand {
+  pub login: String
+}
+
+impl AdminMfaCommand {
+  pub fn run(self, config: CauthConfig) {
+    match self.action {
+      AdminMfaAction::Reset(data) => {
+        block_on(Self::reset(config, &data.login));
+      }
+    }
+  }
+
+  async fn reset(config: CauthConfig, login: &String) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    if let Err(_) = User::retrieve(&mut executor, login).await {
+      println!("{}", format!("User \"{}\" not found.", login).red());
+      return;
+    }
+
+    match UserTotp::disable(&mut executor, login).await {
+      Ok(_) => println!("{}", format!("Successfully reset the second factor of user {}.", login).green()),
+      Err(_) => println!("{}", format!("User {} has no second factor enrolled.", login).red())
+    };
+  }
+}
+
+
+
+#[derive(Debug, Args)]
+pub struct AdminServiceAccountCommand {
+  #[clap(subcommand)]
+  pub action: AdminServiceAccountAction
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminServiceAccountAction {
+  Create(AdminServiceAccountCreateCommand)
+}
+
+#[derive(Debug, Args)]
+pub struct AdminServiceAccountCreateCommand {
+  pub login: String
+}
+
+impl AdminServiceAccountCommand {
+  pub fn run(self, config: CauthConfig) {
+    match self.action {
+      AdminServiceAccountAction::Create(data) => {
+        block_on(Self::create_service_account(config, &data.login));
+      }
+    }
+  }
+
+  async fn create_service_account(config: CauthConfig, login: &String) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    match User::insert_service_account(&mut executor, login, &json!({})).await {
+      Ok(_) => println!("{}", format!("Successfully created service account {}.", login).green()),
+      Err(_) => println!("{}", format!("User \"{}\" already exist.", login).red())
+    };
+  }
+}
+
+
+
+#[derive(Debug, Args)]
+pub struct AdminApiKeyCommand {
+  #[clap(subcommand)]
+  pub action: AdminApiKeyAction
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminApiKeyAction {
+  Create(AdminApiKeyCreateCommand),
+  List(AdminApiKeyListCommand),
+  Revoke(AdminApiKeyRevokeCommand)
+}
+
+#[derive(Debug, Args)]
+pub struct AdminApiKeyCreateCommand {
+  /// login of the user or service account owning the key
+  pub login: String,
+  pub name: String,
+  /// number of seconds the key is valid for, never expires when missing
+  #[clap(long)]
+  pub expires_in: Option<i64>,
+  /// permission the key is limited to, can be repeated. The key has all of the owner's permissions when missing
+  #[clap(long = "permission")]
+  pub permissions: Vec<String>
+}
+
+#[derive(Debug, Args)]
+pub struct AdminApiKeyListCommand {
+  pub login: String
+}
+
+#[derive(Debug, Args)]
+pub struct AdminApiKeyRevokeCommand {
+  pub id: i32
+}
+
+impl AdminApiKeyCommand {
+  pub fn run(self, config: CauthConfig) {
+    match self.action {
+      AdminApiKeyAction::Create(data) => {
+        block_on(Self::create_key(config, &data));
+      },
+      AdminApiKeyAction::List(data) => {
+        block_on(Self::list_keys(config, &data.login));
+      },
+      AdminApiKeyAction::Revoke(data) => {
+        block_on(Self::revoke_key(config, data.id));
+      }
+    }
+  }
+
+  async fn create_key(config: CauthConfig, data: &AdminApiKeyCreateCommand) {
+    let expires_at = match data.expires_in {
+      Some(expires_in) if expires_in <= 0 => {
+        println!("{}", "The key has to be valid for a positive number of seconds.".red());
+        return;
+      },
+      Some(expires_in) => Some(Utc::now() + Duration::seconds(expires_in)),
+      None => None
+    };
+    let permissions = match data.permissions.is_empty() {
+      true => None,
+      false => Some(&data.permissions)
+    };
+
+    let mut executor = config.db_conn.acquire().await.unwrap();
+    match ApiKey::insert(&mut executor, &config, &data.login, &data.name, permissions, expires_at).await {
+      Ok((api_key, key)) => {
+        println!("{}", json_value_to_pretty_string(&json!({
+          "id": api_key.id,
+          "prefix": api_key.prefix,
+          "key": key
+        })));
+        println!("{}", "The API key is shown only once, store it now.".yellow());
+      },
+      Err(err) => println!("{}", format!("Error while creating the API key.\n{}", err.to_string()).red())
+    };
+  }
+
+  async fn list_keys(config: CauthConfig, login: &String) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    if let Err(_) = User::retrieve(&mut executor, login).await {
+      println!("{}", format!("User \"{}\" not found.", login).red());
+      return;
+    }
+
+    let keys = ApiKey::list_by_user(&mut executor, login).await;
+
+    if keys.is_empty() {
+      println!("{}", format!("User \"{}\" has no API keys.", login).green());
+      return;
+    }
+
+    for key in keys {
+      println!("{}", key.to_string());
+    }
+  }
+
+  async fn revoke_key(config: CauthConfig, id: i32) {
+    let mut executor = config.db_conn.acquire().await.unwrap();
+
+    match ApiKey::delete(&mut executor, id).await {
+      Ok(_) => println!("{}", format!("Successfully revoked API key {}.", id).green()),
+      Err(_) => println!("{}", format!("API key {} not found.", id).red())
+    };
+  }
+}
+
+
+
+#[derive(Debug, Args)]
+pub struct AdminImportCommand {
+  #[clap(subcommand)]
+  pub entity_type: AdminImportEntityType
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminImportEntityType {
+  Users(AdminImportUsersCommand)
+}
+
+#[derive(Debug, Args)]
+pub struct AdminImportUsersCommand {
+  /// JSON lines file, every line holding the login, password_hash and optional details of a user
+  pub path: String
+}
+
+/// User read from a line of the imported file
+#[derive(Deserialize)]
+struct ImportedUser {
+  login: String,
+  /// argon2, bcrypt, scrypt or PBKDF2-SHA256 hash, replaced by an argon2 hash on the user's next login
+  password_hash: String,
+  details: Option<Value>
+}
+
+impl AdminImportCommand {
+  pub fn run(self, config: CauthConfig) {
+    match self.entity_type {
+      AdminImportEntityType::Users(data) => {
+        block_on(Self::import_users(config, &data.path));
+      }
+    }
+  }
+
+  async fn import_users(config: CauthConfig, path: &String) {
+    let content = match fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(err) => {
+        println!("{}", format!("Cannot read \"{}\": {}", path, err).red());
+        return;
+      }
+    };
+
+    let mut executor = config.db_conn.acquire().await.unwrap();
+    let mut imported = 0;
+    let mut failed = 0;
+
+    for (index, line) in content.lines().enumerate() {
+      if line.trim().is_empty() {
+        continue;
+      }
+
+      let user = match serde_json::from_str::<ImportedUser>(line) {
+        Ok(user) => user,
+        Err(err) => {
+          println!("{}", format!("Line {}: invalid user, {}.", index + 1, err).red());
+          failed += 1;
+          continue;
+        }
+      };
+
+      let details = user.details.unwrap_or(json!({}));
+
+      match User::insert_unhashed(&mut executor, &user.login, &user.password_hash, &details).await {
+        Ok(_) => imported += 1,
+        Err(err) => {
+          println!("{}", format!("Line {}: cannot import user \"{}\". {}", index + 1, user.login, err.to_string()).red());
+          failed += 1;
+        }
+      };
+    }
+
+    println!("{}", format!("Imported {} users, {} failed.", imported, failed).green());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    cli::{
+      ActionType,
+      CauthCli
+    },
+    util::token::generate_token
+  };
+  use clap::Parser;
+
+  fn parse_session_revoke(args: &[&str]) -> Result<AdminSessionRevokeCommand, clap::Error> {
+    let cli = CauthCli::try_parse_from([&["authoric", "admin", "session", "revoke"], args].concat())?;
+
+    return match cli.action {
+      ActionType::Admin(AdminCommand {
+        action: AdminAction::Session(AdminSessionCommand {
+          action: AdminSessionAction::Revoke(data)
+        })
+      }) => Ok(data),
+      _ => unreachable!()
+    };
+  }
+
+  #[test]
+  fn tells_session_ids_from_numeric_logins() {
+    let by_login = parse_session_revoke(&["--login", "42"]).unwrap();
+    assert_eq!(by_login.login.as_deref(), Some("42"));
+    assert!(by_login.id.is_none());
+
+    let by_id = parse_session_revoke(&["--id", "42"]).unwrap();
+    assert_eq!(by_id.id, Some(42));
+    assert!(by_id.login.is_none());
+
+    assert!(parse_session_revoke(&[]).is_err());
+    assert!(parse_session_revoke(&["42"]).is_err());
+    assert!(parse_session_revoke(&["--id", "42", "--login", "alice"]).is_err());
+    assert!(parse_session_revoke(&["--id", "alice"]).is_err());
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn imports_users_with_long_hashes() {
+    let config = CauthConfig::for_tests().await;
+
+    // scrypt hash with a 64 byte digest, longer than the 100 characters the column used to hold
+    let lines = [
+      json!({
+        "login": "alice",
+        "password_hash": "$scrypt$ln=10,r=8,p=1$MDEyMzQ1Njc4OWFiY2RlZg$6g3umF+uVrJsObaTZhIbbTlgrvOEFcCItdwSjtPF67O6y9Bv3Wwmz9isfvUwH/G4zXJYS65bOgwHS3m025dyrg",
+        "details": { "email": "alice@example.com" }
+      }),
+      json!({ "login": "bob", "password_hash": "not a hash" })
+    ];
+    let path = std::env::temp_dir().join(format!("authoric-import-{}.jsonl", generate_token()));
+    fs::write(&path, lines.map(|line| line.to_string()).join("\n")).unwrap();
+
+    AdminImportCommand::import_users(config.clone(), &path.to_string_lossy().to_string()).await;
+    let _ = fs::remove_file(&path);
+
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let alice = User::retrieve(&mut conn, &"alice".to_string()).await.ok().unwrap();
+    assert!(alice.password_hash.starts_with("$scrypt$"));
+    assert_eq!(alice.details["email"], "alice@example.com");
+    assert!(User::retrieve(&mut conn, &"bob".to_string()).await.is_err());
+  }
+}
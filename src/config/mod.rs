@@ -1,12 +1,16 @@
-use crate::util::io::input;
+use argon2::Params;
+use crate::util::{io::input, token::generate_token};
 use futures::executor::block_on;
+use percent_encoding::percent_decode_str;
 use serde::{Deserialize, Serialize};
 use serde_yml::Value;
 use simple_home_dir::home_dir;
 use sqlx::PgPool;
 use std::{
+    collections::HashMap,
     fs::{self, File},
     io::{self, Write},
+    net::IpAddr,
     path::Path,
     process::exit,
 };
@@ -15,14 +19,234 @@ use std::{
 pub struct CauthConfigRaw {
     database_url: String,
     port: u16,
+    session_lifetime: Option<i64>,
+    session_idle_timeout: Option<i64>,
+    session_pepper: String,
+    access_token_lifetime: Option<i64>,
+    session_cookie_name: Option<String>,
+    jwt_enabled: Option<bool>,
+    jwt_issuer: Option<String>,
+    jwt_key_grace_period: Option<i64>,
+    oidc_groups_claim: Option<String>,
+    forward_auth_rules: Option<Vec<ForwardAuthRule>>,
+    oidc_providers: Option<Vec<OidcProvider>>,
+    ldap: Option<LdapConfig>,
+    notifier: Option<NotifierConfig>,
+    password_reset_url: Option<String>,
+    email_verification_url: Option<String>,
+    require_verified_email: Option<bool>,
+    magic_link_enabled: Option<bool>,
+    magic_link_url: Option<String>,
+    argon2: Option<Argon2Config>,
+    totp_issuer: Option<String>,
+    webauthn_rp_id: Option<String>,
+    webauthn_rp_name: Option<String>,
+    webauthn_origin: Option<String>,
+    trusted_proxies: Option<Vec<IpAddr>>,
+}
+
+/// Maps requests checked by GET /forward-auth to the permission they require
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ForwardAuthRule {
+    /// Host the rule applies to, matched against X-Forwarded-Host. Applies to every host when missing
+    pub host: Option<String>,
+    /// Prefix of the path matched against X-Forwarded-Uri
+    pub path_prefix: String,
+    /// Permission the user needs. Only a valid session is required when missing
+    pub permission: Option<String>,
+}
+
+/// Upstream OpenID Connect identity provider users can sign in with, authoric acting as the relying party.
+/// Its redirect uri is `<jwt_issuer>/login/oidc/<name>/callback`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OidcProvider {
+    /// Name of the provider in the login urls
+    pub name: String,
+    /// Issuer of the provider, its discovery document is read from `<issuer>/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Scopes requested from the provider. Defaults to openid, profile and email when missing
+    pub scopes: Option<Vec<String>>,
+    /// Claim the login of a provisioned user is taken from. Defaults to preferred_username when missing
+    pub login_claim: Option<String>,
+    /// Upstream claims copied into the user details on every login, keyed by the upstream claim name
+    pub claim_mapping: Option<HashMap<String, String>>,
+    /// Claim listing the upstream groups. Defaults to groups when missing
+    pub groups_claim: Option<String>,
+    /// Authoric groups granted for the upstream groups, keyed by the upstream group name
+    pub group_mapping: Option<HashMap<String, String>>,
+    /// Whether users signing in for the first time are created. Defaults to true when missing
+    pub auto_provision: Option<bool>,
+    /// Whether an existing user with the same login is linked on the first sign in.
+    /// Only enable it when the provider is trusted with every login. Defaults to false when missing
+    pub link_existing_users: Option<bool>,
+}
+
+/// LDAP directory passwords are verified against with a simple bind
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LdapConfig {
+    /// Url of the directory, e.g. ldap://localhost:389 or ldaps://ldap.example.com
+    pub url: String,
+    /// Whether the connection is upgraded with StartTLS. Defaults to false when missing
+    pub starttls: Option<bool>,
+    /// DNs the user binds as, tried in order. {login} is replaced by the escaped login,
+    /// e.g. uid={login},ou=people,dc=example,dc=com
+    pub bind_dn_templates: Vec<String>,
+    /// Whether the directory replaces the local passwords instead of being tried
+    /// when the local password do not match. Defaults to false when missing
+    pub exclusive: Option<bool>,
+    /// Whether users with a local password can also sign in with the directory entry of the same login.
+    /// Only enable it when the directory is trusted with every login. Defaults to false when missing
+    pub link_existing_users: Option<bool>,
+    /// Whether users found in the directory only are created on their first login. Defaults to true when missing
+    pub auto_provision: Option<bool>,
+    /// Attributes of the user's entry copied into the user details on every login, keyed by the attribute name
+    pub attribute_mapping: Option<HashMap<String, String>>,
+    /// Base DN the user's groups are searched under, groups are not synchronized when missing
+    pub group_base_dn: Option<String>,
+    /// Filter of the group search, {dn} and {login} are replaced by the escaped DN and login of the user.
+    /// Defaults to (|(member={dn})(uniqueMember={dn})(memberUid={login})) when missing
+    pub group_filter: Option<String>,
+    /// Attribute holding the group name. Defaults to cn when missing
+    pub group_name_attribute: Option<String>,
+    /// Authoric groups granted for the directory groups, keyed by the directory group name
+    pub group_mapping: Option<HashMap<String, String>>,
+}
+
+/// Channel the one-time tokens sent to users are delivered through,
+/// e.g. the password reset tokens
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotifierConfig {
+    /// Prints the notifications to the standard output, for local development
+    Stdout,
+    /// Appends the notifications as JSON lines to a file, for local development and tests
+    File { path: String },
+    /// Posts the notifications as JSON to a url
+    Webhook {
+        url: String,
+        /// Sent as a bearer token in the Authorization header when present
+        secret: Option<String>,
+    },
+    /// Sends the notifications as emails to the user's email detail
+    Smtp {
+        host: String,
+        /// Defaults to the port of the encryption when missing, 465 for tls, 587 for starttls and 25 for none
+        port: Option<u16>,
+        username: Option<String>,
+        password: Option<String>,
+        /// Sender of the emails, e.g. Authoric <no-reply@example.com>
+        from: String,
+        /// Defaults to starttls when missing
+        encryption: Option<SmtpEncryption>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpEncryption {
+    /// TLS from the start of the connection
+    Tls,
+    /// Connection upgraded with STARTTLS
+    Starttls,
+    /// Plaintext, only for local mail catchers
+    None,
+}
+
+/// Cost parameters of the Argon2id password hashes. Raising them only affects new hashes,
+/// existing ones are rehashed when their users log in
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Argon2Config {
+    /// Memory size in KiB. Defaults to 19456 when missing
+    pub memory_cost: Option<u32>,
+    /// Number of iterations. Defaults to 2 when missing
+    pub time_cost: Option<u32>,
+    /// Degree of parallelism. Defaults to 1 when missing
+    pub parallelism: Option<u32>,
 }
 
 #[derive(Clone)]
 pub struct CauthConfig {
     pub db_conn: PgPool,
     pub port: u16,
+    /// Maximum age of a login session in seconds, counted from its creation.
+    /// For logins using refresh tokens this caps the whole refresh token family
+    pub session_lifetime: i64,
+    /// Maximum time in seconds a login session can stay unused before it expires
+    pub session_idle_timeout: i64,
+    /// Server-side secret mixed into the digests of stored session tokens
+    pub session_pepper: String,
+    /// Maximum age in seconds of a login session issued together with a refresh token
+    pub access_token_lifetime: i64,
+    /// Name of the cookie session tokens are read from
+    pub session_cookie_name: String,
+    /// Whether signed JWT access tokens are issued next to the opaque session tokens
+    pub jwt_enabled: bool,
+    /// Value of the "iss" claim of issued JWTs
+    pub jwt_issuer: String,
+    /// Time in seconds a retired signing key stays published after rotation
+    pub jwt_key_grace_period: i64,
+    /// Name of the ID token and userinfo claim listing the user's groups, sent for the "groups" scope
+    pub oidc_groups_claim: String,
+    /// Rules choosing the permission required by GET /forward-auth
+    pub forward_auth_rules: Vec<ForwardAuthRule>,
+    /// Upstream identity providers users can sign in with
+    pub oidc_providers: Vec<OidcProvider>,
+    /// LDAP directory passwords are verified against, local passwords only when missing
+    pub ldap: Option<LdapConfig>,
+    /// Channel the one-time tokens sent to users are delivered through
+    pub notifier: NotifierConfig,
+    /// Url of the page password reset links point to, {token} is replaced by the reset token.
+    /// Only the token is sent when missing
+    pub password_reset_url: Option<String>,
+    /// Url of the page email verification links point to, {token} is replaced by the verification token.
+    /// Only the token is sent when missing
+    pub email_verification_url: Option<String>,
+    /// Whether users have to verify their email before any permission is granted to them
+    pub require_verified_email: bool,
+    /// Whether users can log in with single-use links sent to their email instead of the password
+    pub magic_link_enabled: bool,
+    /// Url of the page magic links point to, {token} is replaced by the login token.
+    /// Only the token is sent when missing
+    pub magic_link_url: Option<String>,
+    /// Cost parameters new password hashes are created with
+    pub argon2_params: Params,
+    /// Name of the service shown in authenticator apps
+    pub totp_issuer: String,
+    /// Relying party id of WebAuthn credentials, the domain of the web apps
+    pub webauthn_rp_id: String,
+    /// Name of the relying party shown by the authenticator
+    pub webauthn_rp_name: String,
+    /// Origin WebAuthn ceremonies have to come from
+    pub webauthn_origin: String,
+    /// Addresses of the reverse proxies whose X-Forwarded-For header is trusted
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
+/// Default absolute lifetime of a login session (7 days)
+const DEFAULT_SESSION_LIFETIME: i64 = 60 * 60 * 24 * 7;
+/// Default idle timeout of a login session (1 day)
+const DEFAULT_SESSION_IDLE_TIMEOUT: i64 = 60 * 60 * 24;
+/// Default lifetime of an access token (15 minutes)
+const DEFAULT_ACCESS_TOKEN_LIFETIME: i64 = 60 * 15;
+/// Default name of the session token cookie
+const DEFAULT_SESSION_COOKIE_NAME: &str = "authoric_session";
+/// Default issuer of JWT access tokens
+const DEFAULT_JWT_ISSUER: &str = "authoric";
+/// Default grace period of a retired signing key (1 day)
+const DEFAULT_JWT_KEY_GRACE_PERIOD: i64 = 60 * 60 * 24;
+/// Default name of the claim listing the user's groups
+const DEFAULT_OIDC_GROUPS_CLAIM: &str = "groups";
+/// Default name of the service shown in authenticator apps
+const DEFAULT_TOTP_ISSUER: &str = "authoric";
+/// Default relying party id of WebAuthn credentials
+const DEFAULT_WEBAUTHN_RP_ID: &str = "localhost";
+/// Default name of the relying party shown by the authenticator
+const DEFAULT_WEBAUTHN_RP_NAME: &str = "authoric";
+/// Default origin of WebAuthn ceremonies
+const DEFAULT_WEBAUTHN_ORIGIN: &str = "http://localhost";
+
 #[derive(Debug)]
 pub enum CauthParseError {
     /// Returned when the file cannot be created/opened
@@ -33,7 +257,142 @@ pub enum CauthParseError {
     DatabaseError(String),
 }
 
+/// ## normalize_forwarded_uri
+///
+/// Reduces a forwarded uri to the path the upstream serves, so a rule cannot be bypassed
+/// with percent-encoded characters, dot segments or repeated slashes. The query is dropped
+///
+fn normalize_forwarded_uri(uri: &str) -> String {
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let decoded = percent_decode_str(path).decode_utf8_lossy();
+
+    let mut segments: Vec<&str> = vec![];
+    for segment in decoded.split(['/', '\\']) {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    return format!("/{}", segments.join("/"));
+}
+
+impl ForwardAuthRule {
+    /// ## ForwardAuthRule::matches
+    ///
+    /// Checks if the rule applies to a request forwarded for specified host and uri.
+    /// The prefix has to end at a segment boundary of the normalized path, so /admin do not match /administrator
+    ///
+    pub fn matches(&self, host: Option<&str>, uri: &str) -> bool {
+        let host_matches = match (&self.host, host) {
+            (None, _) => true,
+            (Some(expected), Some(host)) => expected.eq_ignore_ascii_case(host),
+            (Some(_), None) => false,
+        };
+
+        let path = normalize_forwarded_uri(uri);
+        let prefix = self.path_prefix.trim_end_matches('/');
+
+        return host_matches && (path == prefix || path.starts_with(&format!("{}/", prefix)));
+    }
+}
+
+impl OidcProvider {
+    /// ## OidcProvider::scopes
+    ///
+    /// Lists the scopes requested from the provider, always including openid
+    ///
+    pub fn scopes(&self) -> Vec<String> {
+        let mut scopes = match &self.scopes {
+            Some(scopes) => scopes.clone(),
+            None => vec!["openid".to_string(), "profile".to_string(), "email".to_string()],
+        };
+
+        if !scopes.iter().any(|scope| scope == "openid") {
+            scopes.insert(0, "openid".to_string());
+        }
+
+        return scopes;
+    }
+
+    pub fn login_claim(&self) -> &str {
+        return self.login_claim.as_deref().unwrap_or("preferred_username");
+    }
+
+    pub fn groups_claim(&self) -> &str {
+        return self.groups_claim.as_deref().unwrap_or("groups");
+    }
+}
+
+impl Argon2Config {
+    /// ## Argon2Config::params
+    ///
+    /// Builds the Argon2 parameters, falling back to the recommended defaults for the missing ones
+    ///
+    /// Errors:
+    /// + when a parameter is out of the range Argon2 accepts
+    ///
+    pub fn params(&self) -> Result<Params, argon2::Error> {
+        return Params::new(
+            self.memory_cost.unwrap_or(Params::DEFAULT_M_COST),
+            self.time_cost.unwrap_or(Params::DEFAULT_T_COST),
+            self.parallelism.unwrap_or(Params::DEFAULT_P_COST),
+            None,
+        );
+    }
+}
+
+impl Default for Argon2Config {
+    fn default() -> Self {
+        return Argon2Config {
+            memory_cost: Some(Params::DEFAULT_M_COST),
+            time_cost: Some(Params::DEFAULT_T_COST),
+            parallelism: Some(Params::DEFAULT_P_COST),
+        };
+    }
+}
+
 impl CauthConfig {
+    /// ## CauthConfig::oidc_provider
+    ///
+    /// Finds the upstream identity provider with specified name
+    ///
+    pub fn oidc_provider(&self, name: &str) -> Option<&OidcProvider> {
+        return self
+            .oidc_providers
+            .iter()
+            .find(|provider| provider.name == name);
+    }
+
+    /// ## CauthConfig::oidc_redirect_uri
+    ///
+    /// Builds the redirect uri registered at the upstream identity provider,
+    /// under jwt_issuer which has to be the public url of authoric
+    ///
+    pub fn oidc_redirect_uri(&self, provider: &OidcProvider) -> String {
+        return format!(
+            "{}/login/oidc/{}/callback",
+            self.jwt_issuer.trim_end_matches('/'),
+            provider.name
+        );
+    }
+
+    /// ## CauthConfig::forward_auth_rule
+    ///
+    /// Finds the forward auth rule with the longest path prefix
+    /// matching specified host and uri, the uri is normalized by the rules
+    ///
+    pub fn forward_auth_rule(&self, host: Option<&str>, uri: &str) -> Option<&ForwardAuthRule> {
+        return self
+            .forward_auth_rules
+            .iter()
+            .filter(|rule| rule.matches(host, uri))
+            .max_by_key(|rule| rule.path_prefix.len());
+    }
+
     pub fn parse_or_edit() -> Self {
         let config = Self::parse();
 
@@ -72,6 +431,11 @@ impl CauthConfig {
             Err(_) => return Err(CauthParseError::ParseError),
         };
 
+        let argon2_params = match config_raw.argon2.clone().unwrap_or_default().params() {
+            Ok(params) => params,
+            Err(_) => return Err(CauthParseError::ParseError),
+        };
+
         let db_conn = match block_on(PgPool::connect(&config_raw.database_url)) {
             Ok(db_conn) => db_conn,
             Err(err) => {
@@ -82,6 +446,52 @@ impl CauthConfig {
         let config = CauthConfig {
             db_conn,
             port: config_raw.port,
+            session_lifetime: config_raw
+                .session_lifetime
+                .unwrap_or(DEFAULT_SESSION_LIFETIME),
+            session_idle_timeout: config_raw
+                .session_idle_timeout
+                .unwrap_or(DEFAULT_SESSION_IDLE_TIMEOUT),
+            session_pepper: config_raw.session_pepper,
+            access_token_lifetime: config_raw
+                .access_token_lifetime
+                .unwrap_or(DEFAULT_ACCESS_TOKEN_LIFETIME),
+            session_cookie_name: config_raw
+                .session_cookie_name
+                .unwrap_or(DEFAULT_SESSION_COOKIE_NAME.to_string()),
+            jwt_enabled: config_raw.jwt_enabled.unwrap_or(false),
+            jwt_issuer: config_raw
+                .jwt_issuer
+                .unwrap_or(DEFAULT_JWT_ISSUER.to_string()),
+            jwt_key_grace_period: config_raw
+                .jwt_key_grace_period
+                .unwrap_or(DEFAULT_JWT_KEY_GRACE_PERIOD),
+            oidc_groups_claim: config_raw
+                .oidc_groups_claim
+                .unwrap_or(DEFAULT_OIDC_GROUPS_CLAIM.to_string()),
+            forward_auth_rules: config_raw.forward_auth_rules.unwrap_or(vec![]),
+            oidc_providers: config_raw.oidc_providers.unwrap_or(vec![]),
+            ldap: config_raw.ldap,
+            notifier: config_raw.notifier.unwrap_or(NotifierConfig::Stdout),
+            password_reset_url: config_raw.password_reset_url,
+            email_verification_url: config_raw.email_verification_url,
+            require_verified_email: config_raw.require_verified_email.unwrap_or(false),
+            magic_link_enabled: config_raw.magic_link_enabled.unwrap_or(false),
+            magic_link_url: config_raw.magic_link_url,
+            argon2_params,
+            totp_issuer: config_raw
+                .totp_issuer
+                .unwrap_or(DEFAULT_TOTP_ISSUER.to_string()),
+            webauthn_rp_id: config_raw
+                .webauthn_rp_id
+                .unwrap_or(DEFAULT_WEBAUTHN_RP_ID.to_string()),
+            webauthn_rp_name: config_raw
+                .webauthn_rp_name
+                .unwrap_or(DEFAULT_WEBAUTHN_RP_NAME.to_string()),
+            webauthn_origin: config_raw
+                .webauthn_origin
+                .unwrap_or(DEFAULT_WEBAUTHN_ORIGIN.to_string()),
+            trusted_proxies: config_raw.trusted_proxies.unwrap_or(vec![]),
         };
 
         return Ok(config);
@@ -182,7 +592,263 @@ impl CauthConfig {
                 serde_yml::to_value("The port you want the service to be running on..").unwrap(),
             );
         }
+
+        if !as_map.contains_key("session_lifetime") {
+            let _ = as_map.insert(
+                "session_lifetime".into(),
+                serde_yml::to_value(DEFAULT_SESSION_LIFETIME).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("session_idle_timeout") {
+            let _ = as_map.insert(
+                "session_idle_timeout".into(),
+                serde_yml::to_value(DEFAULT_SESSION_IDLE_TIMEOUT).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("session_pepper") {
+            // a fresh secret is generated, changing it later logs out every user
+            let _ = as_map.insert(
+                "session_pepper".into(),
+                serde_yml::to_value(generate_token()).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("access_token_lifetime") {
+            let _ = as_map.insert(
+                "access_token_lifetime".into(),
+                serde_yml::to_value(DEFAULT_ACCESS_TOKEN_LIFETIME).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("session_cookie_name") {
+            let _ = as_map.insert(
+                "session_cookie_name".into(),
+                serde_yml::to_value(DEFAULT_SESSION_COOKIE_NAME).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("jwt_enabled") {
+            let _ = as_map.insert(
+                "jwt_enabled".into(),
+                serde_yml::to_value(false).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("jwt_issuer") {
+            let _ = as_map.insert(
+                "jwt_issuer".into(),
+                serde_yml::to_value(DEFAULT_JWT_ISSUER).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("jwt_key_grace_period") {
+            let _ = as_map.insert(
+                "jwt_key_grace_period".into(),
+                serde_yml::to_value(DEFAULT_JWT_KEY_GRACE_PERIOD).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("oidc_groups_claim") {
+            let _ = as_map.insert(
+                "oidc_groups_claim".into(),
+                serde_yml::to_value(DEFAULT_OIDC_GROUPS_CLAIM).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("forward_auth_rules") {
+            let _ = as_map.insert(
+                "forward_auth_rules".into(),
+                serde_yml::to_value(Vec::<ForwardAuthRule>::new()).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("oidc_providers") {
+            let _ = as_map.insert(
+                "oidc_providers".into(),
+                serde_yml::to_value(Vec::<OidcProvider>::new()).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("notifier") {
+            let _ = as_map.insert(
+                "notifier".into(),
+                serde_yml::to_value(NotifierConfig::Stdout).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("require_verified_email") {
+            let _ = as_map.insert(
+                "require_verified_email".into(),
+                serde_yml::to_value(false).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("magic_link_enabled") {
+            let _ = as_map.insert(
+                "magic_link_enabled".into(),
+                serde_yml::to_value(false).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("argon2") {
+            let _ = as_map.insert(
+                "argon2".into(),
+                serde_yml::to_value(Argon2Config::default()).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("totp_issuer") {
+            let _ = as_map.insert(
+                "totp_issuer".into(),
+                serde_yml::to_value(DEFAULT_TOTP_ISSUER).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("webauthn_rp_id") {
+            let _ = as_map.insert(
+                "webauthn_rp_id".into(),
+                serde_yml::to_value(DEFAULT_WEBAUTHN_RP_ID).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("webauthn_rp_name") {
+            let _ = as_map.insert(
+                "webauthn_rp_name".into(),
+                serde_yml::to_value(DEFAULT_WEBAUTHN_RP_NAME).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("webauthn_origin") {
+            let _ = as_map.insert(
+                "webauthn_origin".into(),
+                serde_yml::to_value(DEFAULT_WEBAUTHN_ORIGIN).unwrap(),
+            );
+        }
+
+        if !as_map.contains_key("trusted_proxies") {
+            let _ = as_map.insert(
+                "trusted_proxies".into(),
+                serde_yml::to_value(Vec::<IpAddr>::new()).unwrap(),
+            );
+        }
         let as_string = serde_yml::to_string(&as_map).unwrap();
         let _ = file.write(as_string.as_bytes());
     }
 }
+
+#[cfg(test)]
+impl CauthConfig {
+    /// ## CauthConfig::for_tests
+    ///
+    /// Builds a config with the default settings, connected to a new schema of the database at
+    /// TEST_DATABASE_URL with every migration applied. The tests calling it are ignored by default
+    /// and panic when run without the variable, so they cannot pass without touching the database
+    ///
+    pub async fn for_tests() -> Self {
+        use sqlx::{postgres::PgConnectOptions, raw_sql, Connection, PgConnection};
+        use std::str::FromStr;
+
+        let url = std::env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL has to point to a PostgreSQL database to run the ignored tests");
+
+        let schema = format!("test_{}", generate_token().to_lowercase().replace(['-', '_'], ""));
+        let mut conn = PgConnection::connect(&url).await.unwrap();
+        raw_sql(&format!("CREATE SCHEMA {};", schema)).execute(&mut conn).await.unwrap();
+
+        let options = PgConnectOptions::from_str(&url).unwrap().options([("search_path", &schema)]);
+        let db_conn = PgPool::connect_with(options).await.unwrap();
+
+        let mut migrations = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        migrations.sort();
+
+        for migration in migrations {
+            let sql = std::fs::read_to_string(&migration).unwrap();
+            raw_sql(&sql).execute(&db_conn).await.unwrap();
+        }
+
+        return CauthConfig {
+            db_conn,
+            port: 8080,
+            session_lifetime: DEFAULT_SESSION_LIFETIME,
+            session_idle_timeout: DEFAULT_SESSION_IDLE_TIMEOUT,
+            session_pepper: generate_token(),
+            access_token_lifetime: DEFAULT_ACCESS_TOKEN_LIFETIME,
+            session_cookie_name: DEFAULT_SESSION_COOKIE_NAME.to_string(),
+            jwt_enabled: false,
+            jwt_issuer: DEFAULT_JWT_ISSUER.to_string(),
+            jwt_key_grace_period: DEFAULT_JWT_KEY_GRACE_PERIOD,
+            oidc_groups_claim: DEFAULT_OIDC_GROUPS_CLAIM.to_string(),
+            forward_auth_rules: vec![],
+            oidc_providers: vec![],
+            ldap: None,
+            notifier: NotifierConfig::Stdout,
+            password_reset_url: None,
+            email_verification_url: None,
+            require_verified_email: false,
+            magic_link_enabled: false,
+            magic_link_url: None,
+            argon2_params: Argon2Config::default().params().unwrap(),
+            totp_issuer: DEFAULT_TOTP_ISSUER.to_string(),
+            webauthn_rp_id: DEFAULT_WEBAUTHN_RP_ID.to_string(),
+            webauthn_rp_name: DEFAULT_WEBAUTHN_RP_NAME.to_string(),
+            webauthn_origin: DEFAULT_WEBAUTHN_ORIGIN.to_string(),
+            trusted_proxies: vec![],
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path_prefix: &str, permission: &str) -> ForwardAuthRule {
+        return ForwardAuthRule {
+            host: None,
+            path_prefix: path_prefix.to_string(),
+            permission: Some(permission.to_string()),
+        };
+    }
+
+    #[test]
+    fn normalizes_forwarded_uri() {
+        assert_eq!(normalize_forwarded_uri("/admin"), "/admin");
+        assert_eq!(normalize_forwarded_uri("/admin/?page=1"), "/admin");
+        assert_eq!(normalize_forwarded_uri("/%61dmin/users"), "/admin/users");
+        assert_eq!(normalize_forwarded_uri("/public/../admin"), "/admin");
+        assert_eq!(normalize_forwarded_uri("/public/%2e%2e/admin"), "/admin");
+        assert_eq!(normalize_forwarded_uri("//admin/./users"), "/admin/users");
+        assert_eq!(normalize_forwarded_uri("/../../admin"), "/admin");
+        assert_eq!(normalize_forwarded_uri("/public?next=/admin"), "/public");
+        assert_eq!(normalize_forwarded_uri(""), "/");
+    }
+
+    #[test]
+    fn matches_prefix_at_segment_boundary_only() {
+        let admin = rule("/admin", "wiki:admin");
+
+        assert!(admin.matches(None, "/admin"));
+        assert!(admin.matches(None, "/admin/"));
+        assert!(admin.matches(None, "/admin/users?id=1"));
+        assert!(!admin.matches(None, "/administrator"));
+        assert!(!admin.matches(None, "/admin-panel"));
+
+        assert!(rule("/admin/", "wiki:admin").matches(None, "/admin"));
+        assert!(rule("/", "wiki:read").matches(None, "/anything"));
+    }
+
+    #[test]
+    fn matches_encoded_and_dotted_paths() {
+        let admin = rule("/admin", "wiki:admin");
+
+        assert!(admin.matches(None, "/%61dmin/users"));
+        assert!(admin.matches(None, "/public/../admin"));
+        assert!(admin.matches(None, "/public/%2e%2e/admin"));
+        assert!(admin.matches(None, "//admin"));
+        assert!(!admin.matches(None, "/public?next=/admin"));
+    }
+}
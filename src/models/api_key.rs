@@ -0,0 +1,395 @@
+use argon2::password_hash::rand_core::{
+  OsRng,
+  RngCore
+};
+use chrono::{
+  DateTime,
+  Utc
+};
+use serde::{
+  Deserialize,
+  Serialize
+};
+use serde_json::json;
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  models::permission::Permission,
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    string::json_value_to_pretty_string,
+    token::{
+      generate_token,
+      hash_token,
+      TOKEN_INSERT_ATTEMPTS
+    }
+  }
+};
+
+/// Leading part of every API key, telling them apart from session tokens at a glance
+pub const API_KEY_MARKER: &str = "ak";
+
+/// Number of random bytes in the public prefix of a key
+const PREFIX_BYTES: usize = 4;
+
+/// Long-lived credential of a user, usually a service account,
+/// accepted everywhere a session token is
+#[derive(FromRow, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+  pub id: i32,
+  pub user_login: String,
+  pub name: String,
+  pub prefix: String,
+  #[serde(skip_serializing)]
+  pub key_hash: String,
+  /// Permissions the key is limited to, all of the owner's permissions when missing
+  pub permissions: Option<Vec<String>>,
+  pub expires_at: Option<DateTime<Utc>>,
+  pub created_at: DateTime<Utc>,
+  pub last_used_at: Option<DateTime<Utc>>
+}
+
+impl ToString for ApiKey {
+  fn to_string(&self) -> String {
+    let formatted = json_value_to_pretty_string(&serde_json::to_value(self).unwrap());
+
+    return formatted;
+  }
+}
+
+#[derive(Debug)]
+pub enum ApiKeyInsertError {
+  /// Returned when the owner do not exist or the name is too long
+  NameError,
+  /// Returned when one of the permissions the key is limited to do not exist
+  PermissionNotFound(String),
+  /// Returned when no unique key could be generated
+  TokenCollision
+}
+
+impl ToString for ApiKeyInsertError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NameError => "Either the owner do not exist or the key name is too long".to_string(),
+      Self::PermissionNotFound(name) => format!("Permission {} do not exist", name),
+      Self::TokenCollision => "Cannot generate a unique API key".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum ApiKeyAuthenticateError {
+  /// Returned when the key do not exist
+  NotFound,
+  /// Returned when the key is past its expiry date
+  Expired
+}
+
+impl ToString for ApiKeyAuthenticateError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotFound => "API key not found".to_string(),
+      Self::Expired => "API key has expired".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum ApiKeyRevokeError {
+  /// Returned when the user do not have a key with this id
+  NotFound
+}
+
+impl ToString for ApiKeyRevokeError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotFound => "API key not found".to_string()
+    }
+  }
+}
+
+impl ApiKey {
+  /// ## ApiKey::narrow_permissions
+  ///
+  /// Limits the requested permissions of a new key to the scopes of the session creating it,
+  /// all of the scopes when none are requested. Missing when the session is limited
+  /// and none of the requested permissions is among its scopes
+  ///
+  pub fn narrow_permissions(
+    requested: Option<&Vec<String>>,
+    scopes: Option<&Vec<String>>
+  ) -> Option<Option<Vec<String>>> {
+    let scopes = match scopes {
+      Some(scopes) => scopes,
+      None => return Some(requested.cloned())
+    };
+
+    let permissions = requested
+      .unwrap_or(scopes)
+      .iter()
+      .filter(|permission| scopes.contains(permission))
+      .cloned()
+      .collect::<Vec<_>>();
+
+    if permissions.is_empty() {
+      return None;
+    }
+
+    return Some(Some(permissions));
+  }
+
+  /// ## ApiKey::insert
+  ///
+  /// Creates a new API key for the user, returning it together with the plaintext key
+  /// in the `ak_<prefix>_<secret>` format. Only the key's digest is stored,
+  /// the prefix is kept to tell the keys apart
+  ///
+  /// Errors:
+  /// + when the owner do not exist or the name is too long
+  /// + when one of the permissions do not exist
+  /// + when no unique key could be generated
+  ///
+  pub async fn insert(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    name: &String,
+    permissions: Option<&Vec<String>>,
+    expires_at: Option<DateTime<Utc>>
+  ) -> Result<(Self, String), ApiKeyInsertError> {
+    if let Some(permissions) = permissions {
+      for permission in permissions {
+        if Permission::retrieve(conn, permission).await.is_err() {
+          return Err(ApiKeyInsertError::PermissionNotFound(permission.to_string()));
+        }
+      }
+    }
+
+    let sql = "
+      INSERT INTO
+        api_keys (user_login, name, prefix, key_hash, permissions, expires_at)
+      VALUES
+        ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT DO NOTHING
+      RETURNING *;
+    ";
+
+    for _ in 0..TOKEN_INSERT_ATTEMPTS {
+      let prefix = generate_prefix();
+      let key = format!("{}_{}_{}", API_KEY_MARKER, prefix, generate_token());
+
+      let result = query_as(sql)
+        .bind(&user_login)
+        .bind(&name)
+        .bind(&prefix)
+        .bind(hash_token(&key, &config.session_pepper))
+        .bind(&permissions)
+        .bind(&expires_at)
+        .fetch_optional(&mut *conn)
+        .await;
+
+      let api_key: Option<Self> = match result {
+        Ok(api_key) => api_key,
+        Err(err) => {
+          log_database_interaction(
+            "Inserting an API key into database.",
+            json!({ "user_login": user_login, "name": name }),
+            DatabaseOperationLogStatus::Err(err.to_string())
+          );
+
+          return Err(ApiKeyInsertError::NameError);
+        }
+      };
+
+      // no row is returned when the prefix or the key is already taken
+      if let Some(api_key) = api_key {
+        log_database_interaction::<String>(
+          "Inserting an API key into database.",
+          json!({ "user_login": user_login, "name": name, "prefix": prefix }),
+          DatabaseOperationLogStatus::Ok
+        );
+
+        return Ok((api_key, key));
+      }
+    }
+
+    return Err(ApiKeyInsertError::TokenCollision);
+  }
+
+  /// ## ApiKey::authenticate
+  ///
+  /// Retrieves the API key by its digest, refreshing the time it was last used at
+  ///
+  /// Errors:
+  /// + when the key do not exist
+  /// + when the key has expired
+  ///
+  pub async fn authenticate(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    key: &String
+  ) -> Result<Self, ApiKeyAuthenticateError> {
+    let sql = "SELECT * FROM api_keys WHERE key_hash = $1;";
+    let result: Option<Self> = query_as(sql)
+      .bind(hash_token(key, &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    let api_key = match result {
+      Some(api_key) => api_key,
+      None => return Err(ApiKeyAuthenticateError::NotFound)
+    };
+
+    if api_key.is_expired() {
+      return Err(ApiKeyAuthenticateError::Expired);
+    }
+
+    let sql = "UPDATE api_keys SET last_used_at = NOW() WHERE id = $1;";
+    let _ = query(sql)
+      .bind(&api_key.id)
+      .execute(&mut *conn)
+      .await;
+
+    return Ok(api_key);
+  }
+
+  /// ## ApiKey::list_by_user
+  ///
+  /// Lists every API key of a user, including the expired ones, oldest first
+  ///
+  pub async fn list_by_user(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) -> Vec<Self> {
+    let sql = "SELECT * FROM api_keys WHERE user_login = $1 ORDER BY created_at ASC;";
+    let keys = query_as(sql)
+      .bind(&user_login)
+      .fetch_all(&mut *conn)
+      .await
+      .unwrap();
+
+    return keys;
+  }
+
+  /// ## ApiKey::revoke
+  ///
+  /// Deletes an API key belonging to specified user
+  ///
+  /// Errors:
+  /// + when the user do not have a key with this id
+  ///
+  pub async fn revoke(
+    conn: &mut PgConnection,
+    user_login: &String,
+    id: i32
+  ) -> Result<(), ApiKeyRevokeError> {
+    let sql = "DELETE FROM api_keys WHERE id = $1 AND user_login = $2;";
+    let result = query(sql)
+      .bind(&id)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+
+    if result.rows_affected() == 0 {
+      return Err(ApiKeyRevokeError::NotFound);
+    }
+
+    return Ok(());
+  }
+
+  /// ## ApiKey::delete
+  ///
+  /// Deletes an API key of any user
+  ///
+  /// Errors:
+  /// + when a key with this id do not exist
+  ///
+  pub async fn delete(
+    conn: &mut PgConnection,
+    id: i32
+  ) -> Result<(), ApiKeyRevokeError> {
+    let sql = "DELETE FROM api_keys WHERE id = $1;";
+    let result = query(sql)
+      .bind(&id)
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+
+    if result.rows_affected() == 0 {
+      return Err(ApiKeyRevokeError::NotFound);
+    }
+
+    return Ok(());
+  }
+
+  /// ## ApiKey::is_expired
+  ///
+  /// Checks if the key is past its expiry date, keys without one never expire
+  ///
+  pub fn is_expired(self: &Self) -> bool {
+    return match self.expires_at {
+      Some(expires_at) => Utc::now() > expires_at,
+      None => false
+    };
+  }
+}
+
+/// Generates the public part of a key, hex encoded so it never contains the separator
+fn generate_prefix() -> String {
+  let mut bytes = [0u8; PREFIX_BYTES];
+  OsRng.fill_bytes(&mut bytes);
+
+  return bytes
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn permissions(names: &[&str]) -> Vec<String> {
+    return names.iter().map(|name| name.to_string()).collect();
+  }
+
+  #[test]
+  fn keeps_requested_permissions_of_unlimited_session() {
+    assert_eq!(ApiKey::narrow_permissions(None, None), Some(None));
+
+    let requested = permissions(&["users:get"]);
+    assert_eq!(ApiKey::narrow_permissions(Some(&requested), None), Some(Some(requested.clone())));
+  }
+
+  #[test]
+  fn limits_key_to_session_scopes() {
+    let scopes = permissions(&["openid", "users:get"]);
+
+    assert_eq!(ApiKey::narrow_permissions(None, Some(&scopes)), Some(Some(scopes.clone())));
+
+    let requested = permissions(&["users:get", "users:delete"]);
+    assert_eq!(
+      ApiKey::narrow_permissions(Some(&requested), Some(&scopes)),
+      Some(Some(permissions(&["users:get"])))
+    );
+  }
+
+  #[test]
+  fn refuses_key_without_permission_of_session() {
+    let scopes = permissions(&["openid"]);
+
+    assert_eq!(ApiKey::narrow_permissions(Some(&permissions(&["users:delete"])), Some(&scopes)), None);
+    assert_eq!(ApiKey::narrow_permissions(Some(&vec![]), Some(&scopes)), None);
+    assert_eq!(ApiKey::narrow_permissions(None, Some(&vec![])), None);
+  }
+}
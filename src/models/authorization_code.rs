@@ -0,0 +1,104 @@
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  util::token::{
+    generate_token,
+    hash_token
+  }
+};
+
+/// Time in seconds an authorization code has to be exchanged in
+pub const AUTHORIZATION_CODE_LIFETIME: i64 = 60;
+
+/// Code issued by the authorization endpoint once the user consents,
+/// exchanged by the client for tokens. Usable once
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct AuthorizationCode {
+  pub id: i32,
+  pub code_hash: String,
+  pub client_id: String,
+  pub user_login: String,
+  pub redirect_uri: String,
+  pub scopes: Vec<String>,
+  pub code_challenge: String,
+  /// OpenID Connect nonce the ID token is issued with
+  pub nonce: Option<String>,
+  pub created_at: DateTime<Utc>
+}
+
+impl AuthorizationCode {
+  /// ## AuthorizationCode::create
+  ///
+  /// Stores a new code for the client and user, returning the plaintext code.
+  /// Only the code's digest is stored, expired codes are removed on the way
+  ///
+  pub async fn create(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    client_id: &String,
+    user_login: &String,
+    redirect_uri: &String,
+    scopes: &Vec<String>,
+    code_challenge: &String,
+    nonce: Option<&String>
+  ) -> String {
+    let sql = "DELETE FROM oauth_authorization_codes WHERE created_at < $1;";
+    let _ = query(sql)
+      .bind(Utc::now() - Duration::seconds(AUTHORIZATION_CODE_LIFETIME))
+      .execute(&mut *conn)
+      .await;
+
+    let code = generate_token();
+
+    let sql = "
+      INSERT INTO
+        oauth_authorization_codes (code_hash, client_id, user_login, redirect_uri, scopes, code_challenge, nonce)
+      VALUES
+        ($1, $2, $3, $4, $5, $6, $7);
+    ";
+    let _ = query(sql)
+      .bind(hash_token(&code, &config.session_pepper))
+      .bind(&client_id)
+      .bind(&user_login)
+      .bind(&redirect_uri)
+      .bind(&scopes)
+      .bind(&code_challenge)
+      .bind(&nonce)
+      .execute(&mut *conn)
+      .await;
+
+    return code;
+  }
+
+  /// ## AuthorizationCode::take
+  ///
+  /// Deletes the code and returns it,
+  /// unless it do not exist or has expired
+  ///
+  pub async fn take(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    code: &String
+  ) -> Option<Self> {
+    let sql = "DELETE FROM oauth_authorization_codes WHERE code_hash = $1 RETURNING *;";
+    let result: Option<Self> = query_as(sql)
+      .bind(hash_token(code, &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return result.filter(|code| {
+      Utc::now() - code.created_at <= Duration::seconds(AUTHORIZATION_CODE_LIFETIME)
+    });
+  }
+}
@@ -0,0 +1,362 @@
+use argon2::password_hash::rand_core::{
+  OsRng,
+  RngCore
+};
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use serde_json::json;
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    token::{
+      generate_token,
+      hash_token,
+      TOKEN_INSERT_ATTEMPTS
+    }
+  }
+};
+
+/// Time in seconds the user has to approve a device authorization request in
+pub const DEVICE_CODE_LIFETIME: i64 = 60 * 10;
+
+/// Initial number of seconds the client has to wait between two polls
+pub const DEVICE_CODE_POLL_INTERVAL: i32 = 5;
+
+/// Characters of the user code, consonants only as suggested by RFC 8628,
+/// so the codes are easy to type and never spell words
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+/// Number of characters of the user code, displayed as two halves
+const USER_CODE_LENGTH: usize = 8;
+
+/// Device authorization request of a client running on a device without a browser,
+/// approved by the user on the verification page as described in RFC 8628
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct DeviceCode {
+  pub id: i32,
+  pub device_code_hash: String,
+  pub user_code_hash: String,
+  pub client_id: String,
+  pub scopes: Vec<String>,
+  pub user_login: Option<String>,
+  /// Whether the user approved the request, missing while it's pending
+  pub approved: Option<bool>,
+  pub poll_interval: i32,
+  pub last_polled_at: Option<DateTime<Utc>>,
+  pub expires_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>
+}
+
+/// Device authorization request together with the plaintext codes, returned on creation
+pub struct IssuedDeviceCode {
+  pub device_code: String,
+  pub user_code: String,
+  pub expires_in: i64,
+  pub interval: i32
+}
+
+#[derive(Debug)]
+pub enum DeviceCodeInsertError {
+  /// Returned when no unique codes could be generated
+  TokenCollision
+}
+
+impl ToString for DeviceCodeInsertError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::TokenCollision => "Cannot generate a unique device code".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum DeviceCodePollError {
+  /// Returned when the device code do not exist or was issued to another client
+  NotFound,
+  /// Returned while the user has not approved or denied the request yet
+  Pending,
+  /// Returned when the client polls faster than the interval, which is increased by 5 seconds
+  SlowDown,
+  /// Returned when the user denied the request
+  Denied,
+  /// Returned when the request was not approved in time
+  Expired
+}
+
+impl ToString for DeviceCodePollError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotFound => "The device code is invalid".to_string(),
+      Self::Pending => "The user has not approved the request yet".to_string(),
+      Self::SlowDown => "The device code is polled too often".to_string(),
+      Self::Denied => "The user denied the request".to_string(),
+      Self::Expired => "The device code has expired".to_string()
+    }
+  }
+}
+
+impl DeviceCode {
+  /// ## DeviceCode::create
+  ///
+  /// Stores a new device authorization request of the client,
+  /// returning the plaintext device code and user code. Only the digests of the codes are stored,
+  /// expired requests are removed on the way
+  ///
+  /// Errors:
+  /// + when no unique codes could be generated
+  ///
+  pub async fn create(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    client_id: &String,
+    scopes: &Vec<String>
+  ) -> Result<IssuedDeviceCode, DeviceCodeInsertError> {
+    let sql = "DELETE FROM oauth_device_codes WHERE expires_at < NOW();";
+    let _ = query(sql)
+      .execute(&mut *conn)
+      .await;
+
+    let sql = "
+      INSERT INTO
+        oauth_device_codes (device_code_hash, user_code_hash, client_id, scopes, poll_interval, expires_at)
+      VALUES
+        ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT DO NOTHING;
+    ";
+
+    for _ in 0..TOKEN_INSERT_ATTEMPTS {
+      let device_code = generate_token();
+      let user_code = generate_user_code();
+
+      let result = query(sql)
+        .bind(hash_token(&device_code, &config.session_pepper))
+        .bind(hash_token(&normalize_user_code(&user_code), &config.session_pepper))
+        .bind(&client_id)
+        .bind(&scopes)
+        .bind(DEVICE_CODE_POLL_INTERVAL)
+        .bind(Utc::now() + Duration::seconds(DEVICE_CODE_LIFETIME))
+        .execute(&mut *conn)
+        .await;
+
+      match result {
+        // no row is inserted when one of the codes is already taken
+        Ok(result) if result.rows_affected() == 0 => continue,
+        Ok(_) => (),
+        Err(err) => {
+          log_database_interaction(
+            "Inserting a device code into database.",
+            json!({ "client_id": client_id }),
+            DatabaseOperationLogStatus::Err(err.to_string())
+          );
+
+          return Err(DeviceCodeInsertError::TokenCollision);
+        }
+      };
+
+      log_database_interaction::<String>(
+        "Inserting a device code into database.",
+        json!({ "client_id": client_id }),
+        DatabaseOperationLogStatus::Ok
+      );
+
+      return Ok(IssuedDeviceCode {
+        device_code,
+        user_code,
+        expires_in: DEVICE_CODE_LIFETIME,
+        interval: DEVICE_CODE_POLL_INTERVAL
+      });
+    }
+
+    return Err(DeviceCodeInsertError::TokenCollision);
+  }
+
+  /// ## DeviceCode::retrieve_pending
+  ///
+  /// Selects the request with specified user code,
+  /// unless it was already decided on or has expired.
+  /// The code is matched ignoring case, spaces and dashes
+  ///
+  pub async fn retrieve_pending(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_code: &String
+  ) -> Option<Self> {
+    let sql = "
+      SELECT
+        *
+      FROM
+        oauth_device_codes
+      WHERE
+        user_code_hash = $1
+        AND
+        approved IS NULL
+        AND
+        expires_at > NOW();
+    ";
+    let result = query_as(sql)
+      .bind(hash_token(&normalize_user_code(user_code), &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return result;
+  }
+
+  /// ## DeviceCode::decide
+  ///
+  /// Records the user's decision on a pending request,
+  /// narrowing the scopes down to the granted ones when approved
+  ///
+  pub async fn decide(
+    self: &Self,
+    conn: &mut PgConnection,
+    user_login: &String,
+    approved: bool,
+    scopes: &Vec<String>
+  ) {
+    let sql = "
+      UPDATE
+        oauth_device_codes
+      SET
+        user_login = $2,
+        approved = $3,
+        scopes = $4
+      WHERE
+        id = $1
+        AND
+        approved IS NULL;
+    ";
+    let _ = query(sql)
+      .bind(&self.id)
+      .bind(&user_login)
+      .bind(&approved)
+      .bind(&scopes)
+      .execute(&mut *conn)
+      .await;
+  }
+
+  /// ## DeviceCode::poll
+  ///
+  /// Checks the state of the client's request, returning it once approved.
+  /// Approved, denied and expired requests are deleted, so the device code is usable once.
+  /// Tokens are only issued for the poll that actually deleted an approved request
+  ///
+  /// Errors:
+  /// + when the device code do not exist or was issued to another client
+  /// + when the request is still pending or polled faster than the interval
+  /// + when the user denied the request or it has expired
+  ///
+  pub async fn poll(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    client_id: &String,
+    device_code: &String
+  ) -> Result<Self, DeviceCodePollError> {
+    let sql = "SELECT * FROM oauth_device_codes WHERE device_code_hash = $1 AND client_id = $2;";
+    let result: Option<Self> = query_as(sql)
+      .bind(hash_token(device_code, &config.session_pepper))
+      .bind(&client_id)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    let code = match result {
+      Some(code) => code,
+      None => return Err(DeviceCodePollError::NotFound)
+    };
+
+    let now = Utc::now();
+
+    // approved requests expire too, the client has to poll before the code does
+    if now > code.expires_at {
+      code.delete(conn).await;
+
+      return Err(DeviceCodePollError::Expired);
+    }
+
+    let too_early = match code.last_polled_at {
+      Some(last_polled_at) => now - last_polled_at < Duration::seconds(code.poll_interval as i64),
+      None => false
+    };
+
+    if too_early {
+      let sql = "UPDATE oauth_device_codes SET poll_interval = poll_interval + 5, last_polled_at = NOW() WHERE id = $1;";
+      let _ = query(sql)
+        .bind(&code.id)
+        .execute(&mut *conn)
+        .await;
+
+      return Err(DeviceCodePollError::SlowDown);
+    }
+
+    return match code.approved {
+      None => {
+        let sql = "UPDATE oauth_device_codes SET last_polled_at = NOW() WHERE id = $1;";
+        let _ = query(sql)
+          .bind(&code.id)
+          .execute(&mut *conn)
+          .await;
+
+        Err(DeviceCodePollError::Pending)
+      },
+      Some(false) => {
+        code.delete(conn).await;
+
+        Err(DeviceCodePollError::Denied)
+      },
+      Some(true) => {
+        // the code is only consumed once when polled concurrently, the later poll finds nothing
+        let sql = "DELETE FROM oauth_device_codes WHERE id = $1 AND approved = TRUE RETURNING *;";
+        let consumed: Option<Self> = query_as(sql)
+          .bind(&code.id)
+          .fetch_optional(&mut *conn)
+          .await
+          .unwrap_or(None);
+
+        match consumed {
+          Some(code) => Ok(code),
+          None => Err(DeviceCodePollError::NotFound)
+        }
+      }
+    };
+  }
+
+  async fn delete(self: &Self, conn: &mut PgConnection) {
+    let sql = "DELETE FROM oauth_device_codes WHERE id = $1;";
+    let _ = query(sql)
+      .bind(&self.id)
+      .execute(&mut *conn)
+      .await;
+  }
+}
+
+/// Generates a user code in the `XXXX-XXXX` format
+fn generate_user_code() -> String {
+  let characters = (0..USER_CODE_LENGTH)
+    .map(|_| USER_CODE_ALPHABET[OsRng.next_u32() as usize % USER_CODE_ALPHABET.len()] as char)
+    .collect::<String>();
+
+  return format!("{}-{}", &characters[..USER_CODE_LENGTH / 2], &characters[USER_CODE_LENGTH / 2..]);
+}
+
+/// Uppercases the user code and removes the separators the user might have typed
+fn normalize_user_code(user_code: &str) -> String {
+  return user_code
+    .chars()
+    .filter(|character| character.is_ascii_alphanumeric())
+    .map(|character| character.to_ascii_uppercase())
+    .collect();
+}
@@ -0,0 +1,346 @@
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use serde_json::{
+  json,
+  Value
+};
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  models::user::User,
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    notifier::{
+      notify,
+      Notification,
+      NotifyError
+    },
+    token::{
+      generate_token,
+      hash_token,
+      TOKEN_INSERT_ATTEMPTS
+    }
+  }
+};
+
+/// Time in seconds an email verification token can be used for
+pub const EMAIL_VERIFICATION_TOKEN_LIFETIME: i64 = 60 * 60 * 24;
+
+/// Single-use token sent to the email of a user, proving they own it
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct EmailVerificationToken {
+  pub id: i32,
+  pub token_hash: String,
+  pub user_login: String,
+  /// Address the token was sent to
+  pub email: String,
+  pub expires_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>
+}
+
+#[derive(Debug)]
+pub enum EmailVerificationSendError {
+  /// Returned when the user has no email in their details
+  MissingEmail,
+  /// Returned when the email is already verified
+  AlreadyVerified,
+  /// Returned when no unique token could be generated
+  TokenCollision,
+  /// Returned when the notifier cannot deliver the token
+  Notify(NotifyError)
+}
+
+impl ToString for EmailVerificationSendError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::MissingEmail => "The user has no email to verify".to_string(),
+      Self::AlreadyVerified => "The email of the user is already verified".to_string(),
+      Self::TokenCollision => "Cannot generate a unique email verification token".to_string(),
+      Self::Notify(err) => err.to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum EmailVerificationConfirmError {
+  /// Returned when the token do not exist, has expired or was sent to an email the user no longer has
+  InvalidToken
+}
+
+impl ToString for EmailVerificationConfirmError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::InvalidToken => "The email verification token is invalid, was already used or has expired".to_string()
+    }
+  }
+}
+
+impl EmailVerificationToken {
+  /// ## EmailVerificationToken::send
+  ///
+  /// Stores a new verification token for the user's current email and delivers it through the notifier.
+  /// The token is stored outside of the caller's transaction, so it's usable once delivered.
+  /// Earlier tokens of the user and expired tokens are removed on the way
+  ///
+  /// Errors:
+  /// + when the user has no email or it's already verified
+  /// + when no unique token could be generated
+  /// + when the notifier cannot deliver the token
+  ///
+  pub async fn send(
+    config: &CauthConfig,
+    user: &User
+  ) -> Result<(), EmailVerificationSendError> {
+    let email = match user.details.get("email").and_then(Value::as_str) {
+      Some(email) if !email.is_empty() => email.to_string(),
+      _ => return Err(EmailVerificationSendError::MissingEmail)
+    };
+
+    if user.email_verified {
+      return Err(EmailVerificationSendError::AlreadyVerified);
+    }
+
+    // these will never error
+    let mut conn = config.db_conn
+      .acquire()
+      .await
+      .unwrap();
+
+    let token = Self::create(&mut conn, config, &user.login, &email).await?;
+
+    let link = config.email_verification_url
+      .as_ref()
+      .map(|url| url.replace("{token}", &token));
+
+    let instructions = match &link {
+      Some(link) => format!("open {}", link),
+      None => format!("use the following token: {}", token)
+    };
+
+    let notification = Notification {
+      kind: "email_verification".to_string(),
+      login: user.login.clone(),
+      email: Some(email),
+      subject: "Verify your email".to_string(),
+      body: format!(
+        "To verify the email of the account {}, {}\nThe token expires in {} hours, ignore this message if you did not create the account.",
+        user.login,
+        instructions,
+        EMAIL_VERIFICATION_TOKEN_LIFETIME / 60 / 60
+      ),
+      token,
+      link
+    };
+
+    return match notify(&config.notifier, &notification).await {
+      Ok(_) => Ok(()),
+      Err(err) => Err(EmailVerificationSendError::Notify(err))
+    };
+  }
+
+  async fn create(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    email: &String
+  ) -> Result<String, EmailVerificationSendError> {
+    let sql = "DELETE FROM email_verification_tokens WHERE user_login = $1 OR expires_at < NOW();";
+    let _ = query(sql)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await;
+
+    let sql = "
+      INSERT INTO
+        email_verification_tokens (token_hash, user_login, email, expires_at)
+      VALUES
+        ($1, $2, $3, $4)
+      ON CONFLICT DO NOTHING;
+    ";
+
+    for _ in 0..TOKEN_INSERT_ATTEMPTS {
+      let token = generate_token();
+
+      let result = query(sql)
+        .bind(hash_token(&token, &config.session_pepper))
+        .bind(&user_login)
+        .bind(&email)
+        .bind(Utc::now() + Duration::seconds(EMAIL_VERIFICATION_TOKEN_LIFETIME))
+        .execute(&mut *conn)
+        .await;
+
+      match result {
+        Ok(result) if result.rows_affected() == 0 => continue,
+        Ok(_) => (),
+        Err(err) => {
+          log_database_interaction(
+            "Inserting an email verification token into database.",
+            json!({ "user_login": user_login }),
+            DatabaseOperationLogStatus::Err(err.to_string())
+          );
+
+          return Err(EmailVerificationSendError::TokenCollision);
+        }
+      };
+
+      log_database_interaction::<String>(
+        "Inserting an email verification token into database.",
+        json!({ "user_login": user_login }),
+        DatabaseOperationLogStatus::Ok
+      );
+
+      return Ok(token);
+    }
+
+    return Err(EmailVerificationSendError::TokenCollision);
+  }
+
+  /// ## EmailVerificationToken::confirm
+  ///
+  /// Consumes the token and marks the email it was sent to as verified,
+  /// returning the login of the user
+  ///
+  /// Errors:
+  /// + when the token do not exist, was already used or has expired
+  /// + when the user changed their email since the token was sent
+  ///
+  pub async fn confirm(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    token: &String
+  ) -> Result<String, EmailVerificationConfirmError> {
+    let sql = "DELETE FROM email_verification_tokens WHERE token_hash = $1 RETURNING *;";
+    let result: Option<Self> = query_as(sql)
+      .bind(hash_token(token, &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    let verification = match result {
+      Some(verification) if Utc::now() <= verification.expires_at => verification,
+      _ => return Err(EmailVerificationConfirmError::InvalidToken)
+    };
+
+    if !User::verify_email(conn, &verification.user_login, &verification.email).await {
+      return Err(EmailVerificationConfirmError::InvalidToken);
+    }
+
+    return Ok(verification.user_login);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::NotifierConfig;
+
+  async fn insert_user(config: &CauthConfig, login: &str, email: &str) -> User {
+    let sql = "INSERT INTO users (login, password_hash, details) VALUES ($1, '', $2) RETURNING *;";
+
+    return query_as(sql)
+      .bind(login)
+      .bind(json!({ "email": email }))
+      .fetch_one(&config.db_conn)
+      .await
+      .unwrap();
+  }
+
+  async fn is_verified(config: &CauthConfig, login: &str) -> bool {
+    let mut conn = config.db_conn.acquire().await.unwrap();
+
+    return User::retrieve(&mut conn, &login.to_string()).await.ok().unwrap().email_verified;
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn confirms_delivered_token_only_once() {
+    let mut config = CauthConfig::for_tests().await;
+    let path = std::env::temp_dir().join(format!("authoric-notifications-{}.jsonl", generate_token()));
+    config.notifier = NotifierConfig::File { path: path.to_string_lossy().to_string() };
+
+    let user = insert_user(&config, "alice", "alice@example.com").await;
+    EmailVerificationToken::send(&config, &user).await.unwrap();
+
+    let notification: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(notification["email"], "alice@example.com");
+    let token = notification["token"].as_str().unwrap().to_string();
+
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    assert_eq!(EmailVerificationToken::confirm(&mut conn, &config, &token).await.unwrap(), "alice");
+    assert!(is_verified(&config, "alice").await);
+
+    assert!(matches!(
+      EmailVerificationToken::confirm(&mut conn, &config, &token).await,
+      Err(EmailVerificationConfirmError::InvalidToken)
+    ));
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn rejects_expired_token() {
+    let config = CauthConfig::for_tests().await;
+    insert_user(&config, "alice", "alice@example.com").await;
+
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let token = EmailVerificationToken::create(&mut conn, &config, &"alice".to_string(), &"alice@example.com".to_string())
+      .await
+      .unwrap();
+
+    let sql = "UPDATE email_verification_tokens SET expires_at = NOW() - INTERVAL '1 second';";
+    query(sql).execute(&mut *conn).await.unwrap();
+
+    assert!(matches!(
+      EmailVerificationToken::confirm(&mut conn, &config, &token).await,
+      Err(EmailVerificationConfirmError::InvalidToken)
+    ));
+    assert!(!is_verified(&config, "alice").await);
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn rejects_token_of_replaced_email() {
+    let config = CauthConfig::for_tests().await;
+    insert_user(&config, "alice", "alice@example.com").await;
+
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let token = EmailVerificationToken::create(&mut conn, &config, &"alice".to_string(), &"alice@example.com".to_string())
+      .await
+      .unwrap();
+
+    let sql = "UPDATE users SET details = $1 WHERE login = 'alice';";
+    query(sql).bind(json!({ "email": "mallory@example.com" })).execute(&mut *conn).await.unwrap();
+
+    assert!(matches!(
+      EmailVerificationToken::confirm(&mut conn, &config, &token).await,
+      Err(EmailVerificationConfirmError::InvalidToken)
+    ));
+    assert!(!is_verified(&config, "alice").await);
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn replaces_earlier_tokens_of_user() {
+    let config = CauthConfig::for_tests().await;
+    insert_user(&config, "alice", "alice@example.com").await;
+
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let (login, email) = ("alice".to_string(), "alice@example.com".to_string());
+    let earlier = EmailVerificationToken::create(&mut conn, &config, &login, &email).await.unwrap();
+    let latest = EmailVerificationToken::create(&mut conn, &config, &login, &email).await.unwrap();
+
+    assert!(EmailVerificationToken::confirm(&mut conn, &config, &earlier).await.is_err());
+    assert!(EmailVerificationToken::confirm(&mut conn, &config, &latest).await.is_ok());
+  }
+}
@@ -1,11 +1,13 @@
 pub mod user_register;
 pub mod user_login;
 pub mod user_delete;
+pub mod user_password_change;
 
 pub use crate::models::event::{
     user_register::UserRegisterEvent,
     user_login::UserLoginEvent,
-    user_delete::UserDeleteEvent
+    user_delete::UserDeleteEvent,
+    user_password_change::UserPasswordChangeEvent
 };
 use sqlx::prelude::FromRow;
 use serde::Serialize;
@@ -1,10 +1,4 @@
-use std::{
-    time::{
-        self,
-        UNIX_EPOCH
-    },
-    error::Error
-};
+use std::error::Error;
 use serde_json::Value;
 use sqlx::{
     prelude::FromRow,
@@ -12,12 +6,14 @@ use sqlx::{
     query_as,
     PgConnection
 };
-use crypto::{
-    digest::Digest,
-    sha3::Sha3
-};
 use crate::{
-    util::string::json_value_to_pretty_string,
+    util::{
+        string::json_value_to_pretty_string,
+        token::{
+            generate_token,
+            TOKEN_INSERT_ATTEMPTS
+        }
+    },
     models::{
         user::{
             User,
@@ -49,6 +45,8 @@ pub enum UserDeleteEventCommitError {
 pub enum UserDeleteEventInsertError {
     /// Returned when user with login specified in the event's data is not found
     UserNotFound,
+    /// Returned when the event cannot be stored, e.g. when no unique key could be generated
+    CannotInsert,
 }
 
 pub enum UserDeleteEventCancelError {
@@ -114,32 +112,31 @@ impl UserDeleteEvent {
             }
         };
 
-        let time_since_epoch = time::SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let key_raw = format!("{}{}", user_login, time_since_epoch);
-        
-        let mut hasher = Sha3::keccak256();
-        hasher.input_str(key_raw.as_str());
-        let key = hasher.result_str();
-        
         let sql = "
             INSERT INTO 
                 user_delete_events (key, user_login)
             VALUES
                 ($1, $2)
+            ON CONFLICT (key) DO NOTHING
             RETURNING id, key;
         ";
 
-        let result = query_as(sql)
-            .bind(&key)
-            .bind(&user_login)
-            .fetch_one(db_conn)
-            .await
-            .unwrap();
+        for _ in 0..TOKEN_INSERT_ATTEMPTS {
+            let result = query_as(sql)
+                .bind(generate_token())
+                .bind(&user_login)
+                .fetch_optional(&mut *db_conn)
+                .await;
+
+            match result {
+                Ok(Some(credentials)) => return Ok(credentials),
+                // no row is returned when the key is already taken
+                Ok(None) => continue,
+                Err(_) => return Err(UserDeleteEventInsertError::CannotInsert)
+            };
+        }
 
-        return Ok(result);
+        return Err(UserDeleteEventInsertError::CannotInsert);
     }
 
     /// UserDeleteEvent::commit
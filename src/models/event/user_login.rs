@@ -1,10 +1,4 @@
-use std::{
-    time::{
-        self,
-        UNIX_EPOCH
-    },
-    error::Error
-};
+use std::error::Error;
 use serde_json::Value;
 use sqlx::{
     prelude::FromRow,
@@ -12,19 +6,28 @@ use sqlx::{
     query_as,
     PgConnection
 };
-use crypto::{
-    digest::Digest,
-    sha3::Sha3
-};
 use crate::{
-    util::string::json_value_to_pretty_string,
+    config::CauthConfig,
+    util::{
+        string::json_value_to_pretty_string,
+        token::{
+            generate_token,
+            TOKEN_INSERT_ATTEMPTS
+        }
+    },
     models::{
         user::{
             User,
+            UserSecondFactorError,
             UserVerifyPasswordError
         },
         login_session::{
-            LoginSession,
+            LoginSessionInsertError,
+            SessionMetadata
+        },
+        refresh_token::{
+            RefreshToken,
+            TokenPair
         },
         event::EventCredentials
     }
@@ -47,13 +50,23 @@ pub enum UserLoginEventCommitError {
     Unauthorized,
     /// Returned when the event with specified id cannot be found
     NotFound,
+    /// Returned when no unique token could be generated
+    TokenCollision,
+    /// Returned when the JWT access token cannot be signed
+    CannotSign(String),
 }
 
 pub enum UserLoginEventInsertError {
     /// Returned when user with login specified in the event's data is not found
     UserNotFound,
     /// Returned when the provided password is invalid
-    Unauthorized
+    Unauthorized,
+    /// Returned when the user has two-factor authentication enabled and no code was provided
+    MfaRequired,
+    /// Returned when the provided two-factor authentication code is invalid
+    InvalidMfaCode,
+    /// Returned when the event cannot be stored, e.g. when no unique key could be generated
+    CannotInsert
 }
 
 pub enum UserLoginEventCancelError {
@@ -101,14 +114,18 @@ impl UserLoginEvent {
     /// Errors:
     /// + when the user does not exist
     /// + when the password is invalid
+    /// + when the second factor is required but missing or invalid
     ///
     pub async fn insert(
         db_conn: &mut PgConnection,
+        config: &CauthConfig,
         user_login: &String,
-        password: &String
+        password: &String,
+        code: Option<&String>
     ) -> Result<EventCredentials, UserLoginEventInsertError> {
         let result = User::verify_password(
             db_conn,
+            config,
             user_login,
             password
         )
@@ -122,49 +139,82 @@ impl UserLoginEvent {
             }
         };
 
-        let time_since_epoch = time::SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let key_raw = format!("{}{}", user_login, time_since_epoch);
-        
-        let mut hasher = Sha3::keccak256();
-        hasher.input_str(key_raw.as_str());
-        let key = hasher.result_str();
-        
+        let result = User::verify_second_factor(
+            db_conn,
+            user_login,
+            code
+        )
+        .await;
+
+        match result {
+            Ok(_) => (),
+            Err(err) => match err {
+                UserSecondFactorError::Required => return Err(UserLoginEventInsertError::MfaRequired),
+                UserSecondFactorError::Invalid => return Err(UserLoginEventInsertError::InvalidMfaCode)
+            }
+        };
+
+        return Self::insert_authenticated(db_conn, user_login).await;
+    }
+
+    /// UserLoginEvent::insert_authenticated
+    ///
+    /// Inserts a new UserLogin event of a user authenticated by other means than the password,
+    /// e.g. with a magic link, returning it's key and id
+    ///
+    /// Errors:
+    /// + when no unique key could be generated
+    ///
+    pub async fn insert_authenticated(
+        db_conn: &mut PgConnection,
+        user_login: &String
+    ) -> Result<EventCredentials, UserLoginEventInsertError> {
         let sql = "
             INSERT INTO 
                 user_login_events (key, user_login)
             VALUES
                 ($1, $2)
+            ON CONFLICT (key) DO NOTHING
             RETURNING id, key;
         ";
 
-        let result = query_as(sql)
-            .bind(&key)
-            .bind(&user_login)
-            .fetch_one(db_conn)
-            .await
-            .unwrap();
+        for _ in 0..TOKEN_INSERT_ATTEMPTS {
+            let result = query_as(sql)
+                .bind(generate_token())
+                .bind(&user_login)
+                .fetch_optional(&mut *db_conn)
+                .await;
 
-        return Ok(result);
+            match result {
+                Ok(Some(credentials)) => return Ok(credentials),
+                // no row is returned when the key is already taken
+                Ok(None) => continue,
+                Err(_) => return Err(UserLoginEventInsertError::CannotInsert)
+            };
+        }
+
+        return Err(UserLoginEventInsertError::CannotInsert);
     }
 
     /// UserLoginEvent::commit
     ///
-    /// Commits the changes in single UserLogin event to the database, returning token
-    /// of the created session
+    /// Commits the changes in single UserLogin event to the database, returning access token
+    /// of the created session and a refresh token
     ///
     /// Errors:
     /// + when the event is not found
     /// + when the key is invalid
     /// + when the user with specified login is not found 
+    /// + when no unique token could be generated
+    /// + when the JWT access token cannot be signed
     ///
     pub async fn commit(
         db_conn: &mut PgConnection,
+        config: &CauthConfig,
         id: &i32,
-        key: &String
-    ) -> Result<String, UserLoginEventCommitError>
+        key: &String,
+        metadata: &SessionMetadata
+    ) -> Result<TokenPair, UserLoginEventCommitError>
     {
         let retrieved = Self::retrieve(
             db_conn,
@@ -181,11 +231,21 @@ impl UserLoginEvent {
             return Err(UserLoginEventCommitError::Unauthorized);
         }
 
-        let token = LoginSession::insert(
+        let result = RefreshToken::issue(
             db_conn,
-            event.user_login
-        ).await
-        .unwrap();
+            config,
+            &event.user_login,
+            metadata
+        ).await;
+
+        let pair = match result {
+            Ok(pair) => pair,
+            Err(err) => match err {
+                LoginSessionInsertError::UserNotFound => return Err(UserLoginEventCommitError::NotFound),
+                LoginSessionInsertError::TokenCollision => return Err(UserLoginEventCommitError::TokenCollision),
+                LoginSessionInsertError::CannotSign(err) => return Err(UserLoginEventCommitError::CannotSign(err))
+            }
+        };
         
         let _ = Self::cancel(
             db_conn,
@@ -194,7 +254,7 @@ impl UserLoginEvent {
         )
         .await;
 
-        return Ok(token);
+        return Ok(pair);
     }
 
     /// UserLoginEvent::cancel
@@ -0,0 +1,279 @@
+use sqlx::{
+    prelude::FromRow,
+    query,
+    query_as,
+    PgConnection
+};
+use crate::{
+    config::CauthConfig,
+    util::token::{
+        generate_token,
+        TOKEN_INSERT_ATTEMPTS
+    },
+    models::{
+        user::{
+            User,
+            UserVerifyPasswordError,
+            hash_password
+        },
+        login_session::LoginSession,
+        event::EventCredentials
+    }
+};
+
+#[derive(FromRow)]
+pub struct UserPasswordChangeEvent {
+    id: i32,
+    key: String,
+    user_login: String,
+    password_hash: String,
+    /// Session kept when the other sessions of the user are revoked,
+    /// missing when no session is revoked
+    kept_session_id: Option<i32>
+}
+
+pub enum UserPasswordChangeEventRetrieveError {
+    /// Returned when the event with specified id cannot be found
+    NotFound
+}
+
+pub enum UserPasswordChangeEventInsertError {
+    /// Returned when the user of the session is not found
+    UserNotFound,
+    /// Returned when the current password is invalid
+    Unauthorized,
+    /// Returned when the new password cannot be hashed
+    CannotHash(String),
+    /// Returned when the event cannot be stored, e.g. when no unique key could be generated
+    CannotInsert
+}
+
+pub enum UserPasswordChangeEventCommitError {
+    /// Returned when the key is invalid
+    Unauthorized,
+    /// Returned when the event with specified id or its user cannot be found
+    NotFound
+}
+
+pub enum UserPasswordChangeEventCancelError {
+    /// Returned when the key is invalid
+    Unauthorized,
+    /// Returned when the event with specified id cannot be found
+    NotFound
+}
+
+impl UserPasswordChangeEvent {
+    /// ## UserPasswordChangeEvent::retrieve
+    ///
+    /// Retrieves UserPasswordChange event with specifed id
+    ///
+    /// Errors:
+    /// + When the event is not found
+    ///
+    pub async fn retrieve(
+        db_conn: &mut PgConnection,
+        id: &i32
+    ) -> Result<UserPasswordChangeEvent, UserPasswordChangeEventRetrieveError> {
+        let sql = "
+        SELECT
+            *
+        FROM
+            user_password_change_events
+        WHERE
+            id = $1;
+        ";
+        let result = query_as(sql)
+            .bind(&id)
+            .fetch_one(db_conn)
+            .await;
+
+        return match result {
+            Ok(event) => Ok(event),
+            Err(_) => return Err(UserPasswordChangeEventRetrieveError::NotFound),
+        };
+    }
+
+    /// UserPasswordChangeEvent::insert
+    ///
+    /// Inserts a new UserPasswordChange event of the session's user into database,
+    /// returning it's key and id. The new password is hashed right away
+    ///
+    /// Errors:
+    /// + when the user does not exist
+    /// + when the current password is invalid
+    /// + when the new password cannot be hashed
+    ///
+    pub async fn insert(
+        db_conn: &mut PgConnection,
+        config: &CauthConfig,
+        session: &LoginSession,
+        current_password: &String,
+        new_password: &String,
+        revoke_other_sessions: bool
+    ) -> Result<EventCredentials, UserPasswordChangeEventInsertError> {
+        let result = User::verify_password(
+            db_conn,
+            config,
+            &session.user_login,
+            current_password
+        )
+        .await;
+
+        match result {
+            Ok(_) => (),
+            Err(err) => match err {
+                UserVerifyPasswordError::NotFound => return Err(UserPasswordChangeEventInsertError::UserNotFound),
+                UserVerifyPasswordError::Unauthorized => return Err(UserPasswordChangeEventInsertError::Unauthorized)
+            }
+        };
+
+        let password_hash = match hash_password(new_password.clone(), &config.argon2_params) {
+            Ok(hash) => hash,
+            Err(err) => return Err(UserPasswordChangeEventInsertError::CannotHash(err))
+        };
+
+        let kept_session_id = match revoke_other_sessions {
+            true => Some(session.id),
+            false => None
+        };
+
+        let sql = "
+            INSERT INTO
+                user_password_change_events (key, user_login, password_hash, kept_session_id)
+            VALUES
+                ($1, $2, $3, $4)
+            ON CONFLICT (key) DO NOTHING
+            RETURNING id, key;
+        ";
+
+        for _ in 0..TOKEN_INSERT_ATTEMPTS {
+            let result = query_as(sql)
+                .bind(generate_token())
+                .bind(&session.user_login)
+                .bind(&password_hash)
+                .bind(&kept_session_id)
+                .fetch_optional(&mut *db_conn)
+                .await;
+
+            match result {
+                Ok(Some(credentials)) => return Ok(credentials),
+                // no row is returned when the key is already taken
+                Ok(None) => continue,
+                Err(_) => return Err(UserPasswordChangeEventInsertError::CannotInsert)
+            };
+        }
+
+        return Err(UserPasswordChangeEventInsertError::CannotInsert);
+    }
+
+    /// UserPasswordChangeEvent::commit
+    ///
+    /// Commits the changes in single UserPasswordChange event to the database,
+    /// revoking the other sessions of the user when requested
+    ///
+    /// Errors:
+    /// + when the event is not found
+    /// + when the key is invalid
+    /// + when the user was deleted in the meantime
+    ///
+    pub async fn commit(
+        db_conn: &mut PgConnection,
+        id: &i32,
+        key: &String
+    ) -> Result<(), UserPasswordChangeEventCommitError>
+    {
+        let retrieved = Self::retrieve(
+            db_conn,
+            id
+        )
+        .await;
+
+        let event = match retrieved {
+            Ok(event) => event,
+            Err(_) => return Err(UserPasswordChangeEventCommitError::NotFound)
+        };
+
+        if *key != event.key {
+            return Err(UserPasswordChangeEventCommitError::Unauthorized);
+        }
+
+        let result = User::update_password_unhashed(
+            db_conn,
+            &event.user_login,
+            &event.password_hash
+        )
+        .await;
+
+        if result.is_ok() {
+            if let Some(session_id) = event.kept_session_id {
+                LoginSession::revoke_all_except(
+                    db_conn,
+                    &event.user_login,
+                    session_id
+                )
+                .await;
+            }
+        }
+
+        let _ = Self::cancel(
+            db_conn,
+            &event.id,
+            key
+        )
+        .await;
+
+        return match result {
+            Ok(_) => Ok(()),
+            Err(_) => return Err(UserPasswordChangeEventCommitError::NotFound)
+        };
+    }
+
+    /// UserPasswordChangeEvent::cancel
+    ///
+    /// Deletes UserPasswordChange event with specified id from the database
+    ///
+    /// Errors:
+    /// + When the event is not found
+    /// + When the key is invalid
+    ///
+    pub async fn cancel(
+        db_conn: &mut PgConnection,
+        id: &i32,
+        key: &String
+    ) -> Result<(), UserPasswordChangeEventCancelError>
+    {
+        let retrieved = Self::retrieve(
+            db_conn,
+            id
+        )
+        .await;
+
+        let event = match retrieved {
+            Ok(event) => event,
+            Err(_) => return Err(UserPasswordChangeEventCancelError::NotFound)
+        };
+
+        if *key != event.key {
+            return Err(UserPasswordChangeEventCancelError::Unauthorized);
+        }
+
+        let sql = "
+        DELETE FROM
+            user_password_change_events
+        WHERE
+            id = $1;
+        ";
+
+        let result = query(sql)
+            .bind(&id)
+            .execute(db_conn)
+            .await
+            .unwrap();
+
+        if result.rows_affected() == 0 {
+            return Err(UserPasswordChangeEventCancelError::NotFound);
+        } else {
+            return Ok(());
+        }
+    }
+}
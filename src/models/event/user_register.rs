@@ -1,10 +1,4 @@
-use std::{
-    time::{
-        self,
-        UNIX_EPOCH
-    },
-    error::Error
-};
+use std::error::Error;
 use serde_json::Value;
 use sqlx::{
     prelude::FromRow,
@@ -12,12 +6,15 @@ use sqlx::{
     query_as,
     PgConnection
 };
-use crypto::{
-    digest::Digest,
-    sha3::Sha3
-};
 use crate::{
-    util::string::json_value_to_pretty_string,
+    config::CauthConfig,
+    util::{
+        string::json_value_to_pretty_string,
+        token::{
+            generate_token,
+            TOKEN_INSERT_ATTEMPTS
+        }
+    },
     models::{
         user::{
             User,
@@ -45,7 +42,9 @@ pub enum UserRegisterEventInsertError {
     /// Returned when the mentioned user already exists
     AlreadyExists,
     /// Returned when the password cannot be hashed
-    CannotHash(String)
+    CannotHash(String),
+    /// Returned when the event cannot be stored, e.g. when no unique key could be generated
+    CannotInsert
 }
 
 pub enum UserRegisterEventCommitError {
@@ -101,6 +100,7 @@ impl UserRegisterEvent {
     /// + when the user with specified login already exists
     pub async fn insert(
         db_conn: &mut PgConnection,
+        config: &CauthConfig,
         user_login: &String,
         password: &String,
         details: &serde_json::Value
@@ -118,17 +118,7 @@ impl UserRegisterEvent {
             Err(_) => ()
         };
 
-        let time_since_epoch = time::SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let key_raw = format!("{}{}", user_login, time_since_epoch);
-        
-        let mut hasher = Sha3::keccak256();
-        hasher.input_str(key_raw.as_str());
-        let key = hasher.result_str();
-
-        let password_hash = match hash_password(password.clone()) {
+        let password_hash = match hash_password(password.clone(), &config.argon2_params) {
             Ok(hash) => hash,
             Err(err) => return Err(UserRegisterEventInsertError::CannotHash(err))
         };
@@ -138,24 +128,34 @@ impl UserRegisterEvent {
                 user_register_events (key, user_login, password_hash, details)
             VALUES
                 ($1, $2, $3, $4)
+            ON CONFLICT (key) DO NOTHING
             RETURNING id, key;
         ";
 
-        let result = query_as(sql)
-            .bind(&key)
-            .bind(&user_login)
-            .bind(&password_hash)
-            .bind(&details)
-            .fetch_one(db_conn)
-            .await
-            .unwrap();
+        for _ in 0..TOKEN_INSERT_ATTEMPTS {
+            let result = query_as(sql)
+                .bind(generate_token())
+                .bind(&user_login)
+                .bind(&password_hash)
+                .bind(&details)
+                .fetch_optional(&mut *db_conn)
+                .await;
+
+            match result {
+                Ok(Some(credentials)) => return Ok(credentials),
+                // no row is returned when the key is already taken
+                Ok(None) => continue,
+                Err(_) => return Err(UserRegisterEventInsertError::CannotInsert)
+            };
+        }
 
-        return Ok(result);
+        return Err(UserRegisterEventInsertError::CannotInsert);
     }
 
     /// UserRegisterEvent::commit
     ///
-    /// Commits the changes in single UserRegister event to the database
+    /// Commits the changes in single UserRegister event to the database,
+    /// returning the login of the registered user
     ///
     /// Errors:
     /// + when the event is not found
@@ -165,7 +165,7 @@ impl UserRegisterEvent {
         db_conn: &mut PgConnection,
         id: &i32,
         key: &String
-    ) -> Result<(), UserRegisterEventCommitError>
+    ) -> Result<String, UserRegisterEventCommitError>
     {
         let retrieved = Self::retrieve(
             db_conn,
@@ -182,7 +182,7 @@ impl UserRegisterEvent {
             return Err(UserRegisterEventCommitError::Unauthorized);
         }
 
-        let token = User::insert_unhashed(
+        User::insert_unhashed(
             db_conn,
             &event.user_login,
             &event.password_hash,
@@ -199,7 +199,7 @@ impl UserRegisterEvent {
         )
         .await;
 
-        return Ok(token);
+        return Ok(event.user_login);
     }
 
     /// UserRegisterEvent::cancel
@@ -0,0 +1,284 @@
+use chrono::{
+  DateTime,
+  Utc
+};
+use serde::{
+  Deserialize,
+  Serialize
+};
+use serde_json::{
+  json,
+  Map,
+  Value
+};
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::OidcProvider,
+  models::user::User,
+  util::logging::{
+    log_database_interaction,
+    DatabaseOperationLogStatus
+  }
+};
+
+/// Link between a user and its account at an upstream identity provider
+#[derive(FromRow, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct FederatedIdentity {
+  pub id: i32,
+  pub provider: String,
+  /// Subject of the user at the provider, the "sub" claim
+  pub subject: String,
+  pub user_login: String,
+  pub created_at: DateTime<Utc>,
+  pub last_login_at: DateTime<Utc>
+}
+
+#[derive(Debug)]
+pub enum FederatedSignInError {
+  /// Returned when the provider did not send the subject or the login claim
+  MissingClaim(String),
+  /// Returned when no user is linked to the subject and provisioning is disabled
+  NotProvisioned,
+  /// Returned when a user with the same login exists but linking is disabled
+  LoginTaken(String)
+}
+
+impl ToString for FederatedSignInError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::MissingClaim(claim) => format!("The identity provider did not send the {} claim", claim),
+      Self::NotProvisioned => "No user is linked to this account and new users are not created".to_string(),
+      Self::LoginTaken(login) => format!("User {} already exist and is not linked to this account", login)
+    }
+  }
+}
+
+impl FederatedIdentity {
+  /// ## FederatedIdentity::retrieve
+  ///
+  /// Selects the link of the provider's subject
+  ///
+  pub async fn retrieve(
+    conn: &mut PgConnection,
+    provider: &String,
+    subject: &String
+  ) -> Option<Self> {
+    let sql = "SELECT * FROM federated_identities WHERE provider = $1 AND subject = $2;";
+    let identity = query_as(sql)
+      .bind(&provider)
+      .bind(&subject)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return identity;
+  }
+
+  /// ## FederatedIdentity::sign_in
+  ///
+  /// Finds the user of the verified claims from the provider, linking an existing user
+  /// or creating a new one on the first sign in, as allowed by the provider's config.
+  /// The mapped claims and groups of the user are synchronized on every sign in
+  ///
+  /// Errors:
+  /// + when the provider did not send the subject or the login claim
+  /// + when there is no linked user and it cannot be linked or created
+  ///
+  pub async fn sign_in(
+    conn: &mut PgConnection,
+    provider: &OidcProvider,
+    claims: &Map<String, Value>
+  ) -> Result<User, FederatedSignInError> {
+    let subject = match claims.get("sub").and_then(Value::as_str) {
+      Some(subject) => subject.to_string(),
+      None => return Err(FederatedSignInError::MissingClaim("sub".to_string()))
+    };
+
+    let user_login = match Self::retrieve(conn, &provider.name, &subject).await {
+      Some(identity) => {
+        let sql = "UPDATE federated_identities SET last_login_at = NOW() WHERE id = $1;";
+        let _ = query(sql)
+          .bind(&identity.id)
+          .execute(&mut *conn)
+          .await;
+
+        identity.user_login
+      },
+      None => {
+        let login = Self::resolve_user(conn, provider, claims).await?;
+        Self::link(conn, &provider.name, &subject, &login).await;
+
+        login
+      }
+    };
+
+    let user = match User::retrieve(conn, &user_login).await {
+      Ok(user) => user,
+      Err(_) => return Err(FederatedSignInError::NotProvisioned)
+    };
+
+    Self::sync_details(conn, provider, &user, claims).await;
+    Self::sync_groups(conn, provider, &user, claims).await;
+
+    return Ok(user);
+  }
+
+  /// Finds or creates the user the subject is linked to on its first sign in
+  async fn resolve_user(
+    conn: &mut PgConnection,
+    provider: &OidcProvider,
+    claims: &Map<String, Value>
+  ) -> Result<String, FederatedSignInError> {
+    let login = match claims.get(provider.login_claim()).and_then(Value::as_str) {
+      Some(login) if !login.is_empty() => login.to_string(),
+      _ => return Err(FederatedSignInError::MissingClaim(provider.login_claim().to_string()))
+    };
+
+    if let Ok(user) = User::retrieve(conn, &login).await {
+      return Self::link_existing(provider, user);
+    }
+
+    if !provider.auto_provision.unwrap_or(true) {
+      return Err(FederatedSignInError::NotProvisioned);
+    }
+
+    return match User::insert_federated(conn, &login, &json!({})).await {
+      Ok(_) => Ok(login),
+      Err(_) => Err(FederatedSignInError::LoginTaken(login))
+    };
+  }
+
+  /// Links the existing user with the claimed login when the provider is trusted with it,
+  /// service accounts are never linked
+  fn link_existing(provider: &OidcProvider, user: User) -> Result<String, FederatedSignInError> {
+    return match provider.link_existing_users.unwrap_or(false) && !user.service_account {
+      true => Ok(user.login),
+      false => Err(FederatedSignInError::LoginTaken(user.login))
+    };
+  }
+
+  async fn link(
+    conn: &mut PgConnection,
+    provider: &String,
+    subject: &String,
+    user_login: &String
+  ) {
+    let sql = "INSERT INTO federated_identities (provider, subject, user_login) VALUES ($1, $2, $3);";
+    let result = query(sql)
+      .bind(&provider)
+      .bind(&subject)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await;
+
+    let status = match result {
+      Ok(_) => DatabaseOperationLogStatus::Ok,
+      Err(err) => DatabaseOperationLogStatus::Err(err.to_string())
+    };
+
+    log_database_interaction(
+      "Linking a user to an identity provider.",
+      json!({ "provider": provider, "user_login": user_login }),
+      status
+    );
+  }
+
+  /// Copies the mapped claims into the user details
+  async fn sync_details(
+    conn: &mut PgConnection,
+    provider: &OidcProvider,
+    user: &User,
+    claims: &Map<String, Value>
+  ) {
+    let mapping = match &provider.claim_mapping {
+      Some(mapping) => mapping,
+      None => return
+    };
+
+    let details = mapping
+      .iter()
+      .filter_map(|(claim, detail)| Some((detail.clone(), claims.get(claim)?.clone())))
+      .collect::<Map<String, Value>>();
+
+    user.merge_details(conn, details).await;
+  }
+
+  /// Grants the groups mapped from the upstream groups and revokes the mapped ones the user lost
+  async fn sync_groups(
+    conn: &mut PgConnection,
+    provider: &OidcProvider,
+    user: &User,
+    claims: &Map<String, Value>
+  ) {
+    let mapping = match &provider.group_mapping {
+      Some(mapping) => mapping,
+      None => return
+    };
+
+    let upstream_groups = match claims.get(provider.groups_claim()) {
+      Some(Value::Array(groups)) => groups
+        .iter()
+        .filter_map(Value::as_str)
+        .map(|group| group.to_string())
+        .collect(),
+      Some(Value::String(group)) => vec![group.clone()],
+      _ => vec![]
+    };
+
+    user.sync_mapped_groups(conn, mapping, &upstream_groups).await;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn provider(link_existing_users: Option<bool>) -> OidcProvider {
+    return serde_json::from_value(json!({
+      "name": "corp",
+      "issuer": "https://idp.example.com",
+      "client_id": "authoric",
+      "client_secret": "secret",
+      "link_existing_users": link_existing_users
+    }))
+    .unwrap();
+  }
+
+  fn user(service_account: bool) -> User {
+    return User {
+      login: "alice".to_string(),
+      password_hash: String::new(),
+      details: json!({}),
+      service_account,
+      email_verified: false
+    };
+  }
+
+  #[test]
+  fn links_existing_user_when_enabled() {
+    assert_eq!(FederatedIdentity::link_existing(&provider(Some(true)), user(false)).unwrap(), "alice");
+  }
+
+  #[test]
+  fn refuses_existing_user_by_default() {
+    for link_existing_users in [None, Some(false)] {
+      match FederatedIdentity::link_existing(&provider(link_existing_users), user(false)) {
+        Err(FederatedSignInError::LoginTaken(login)) => assert_eq!(login, "alice"),
+        _ => panic!("the existing user was linked")
+      }
+    }
+  }
+
+  #[test]
+  fn never_links_service_accounts() {
+    assert!(matches!(
+      FederatedIdentity::link_existing(&provider(Some(true)), user(true)),
+      Err(FederatedSignInError::LoginTaken(_))
+    ));
+  }
+}
@@ -0,0 +1,168 @@
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  util::token::{
+    generate_token,
+    hash_token
+  }
+};
+
+/// Time in seconds the user has to sign in at the upstream provider in
+pub const FEDERATED_LOGIN_STATE_LIFETIME: i64 = 60 * 10;
+
+/// Cookie holding the digest of the state, binding the sign in to the browser it was started in
+pub const FEDERATED_LOGIN_STATE_COOKIE: &str = "authoric_oidc_state";
+
+/// Sign in started with an upstream identity provider,
+/// matched with the provider's callback by the state parameter
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct FederatedLoginState {
+  pub id: i32,
+  pub state_hash: String,
+  pub provider: String,
+  /// Nonce the provider's ID token has to carry
+  pub nonce: String,
+  /// PKCE verifier of the challenge sent to the provider
+  pub code_verifier: String,
+  pub redirect_to: String,
+  pub created_at: DateTime<Utc>
+}
+
+impl FederatedLoginState {
+  /// ## FederatedLoginState::create
+  ///
+  /// Stores a new sign in with the provider, returning it together with the plaintext state.
+  /// Only the state's digest is stored, expired sign ins are removed on the way
+  ///
+  pub async fn create(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    provider: &String,
+    redirect_to: &String
+  ) -> (Self, String) {
+    let sql = "DELETE FROM federated_login_states WHERE created_at < $1;";
+    let _ = query(sql)
+      .bind(Utc::now() - Duration::seconds(FEDERATED_LOGIN_STATE_LIFETIME))
+      .execute(&mut *conn)
+      .await;
+
+    let state = generate_token();
+
+    let sql = "
+      INSERT INTO
+        federated_login_states (state_hash, provider, nonce, code_verifier, redirect_to)
+      VALUES
+        ($1, $2, $3, $4, $5)
+      RETURNING *;
+    ";
+    let login_state = query_as(sql)
+      .bind(hash_token(&state, &config.session_pepper))
+      .bind(&provider)
+      .bind(generate_token())
+      .bind(generate_token())
+      .bind(&redirect_to)
+      .fetch_one(&mut *conn)
+      .await
+      .unwrap();
+
+    return (login_state, state);
+  }
+
+  /// ## FederatedLoginState::take
+  ///
+  /// Deletes the sign in with specified state and returns it,
+  /// unless it do not exist, was started with another provider or has expired
+  ///
+  pub async fn take(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    provider: &String,
+    state: &String
+  ) -> Option<Self> {
+    let sql = "DELETE FROM federated_login_states WHERE state_hash = $1 RETURNING *;";
+    let result: Option<Self> = query_as(sql)
+      .bind(hash_token(state, &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return result.filter(|login_state| login_state.is_valid_for(provider));
+  }
+
+  /// ## FederatedLoginState::is_bound_to
+  ///
+  /// Checks that the state comes back to the browser the sign in was started in,
+  /// i.e. the state cookie holds the digest of it
+  ///
+  pub fn is_bound_to(config: &CauthConfig, state: &String, cookie: Option<&str>) -> bool {
+    return cookie.is_some_and(|cookie| cookie == hash_token(state, &config.session_pepper));
+  }
+
+  /// ## FederatedLoginState::is_valid_for
+  ///
+  /// Checks that the sign in was started with specified provider and has not expired
+  ///
+  pub fn is_valid_for(&self, provider: &String) -> bool {
+    return &self.provider == provider
+      && Utc::now() - self.created_at <= Duration::seconds(FEDERATED_LOGIN_STATE_LIFETIME);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn login_state(provider: &str, age: i64) -> FederatedLoginState {
+    return FederatedLoginState {
+      id: 1,
+      state_hash: "hash".to_string(),
+      provider: provider.to_string(),
+      nonce: "nonce".to_string(),
+      code_verifier: "verifier".to_string(),
+      redirect_to: "/".to_string(),
+      created_at: Utc::now() - Duration::seconds(age)
+    };
+  }
+
+  #[test]
+  fn accepts_fresh_sign_in_of_same_provider() {
+    assert!(login_state("corp", 0).is_valid_for(&"corp".to_string()));
+    assert!(login_state("corp", FEDERATED_LOGIN_STATE_LIFETIME - 5).is_valid_for(&"corp".to_string()));
+  }
+
+  #[test]
+  fn rejects_state_of_another_provider() {
+    assert!(!login_state("corp", 0).is_valid_for(&"other".to_string()));
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn binds_state_to_cookie_of_same_browser() {
+    let config = CauthConfig::for_tests().await;
+
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let (login_state, state) = FederatedLoginState::create(&mut conn, &config, &"corp".to_string(), &"/".to_string()).await;
+    let (other_state, _) = FederatedLoginState::create(&mut conn, &config, &"corp".to_string(), &"/".to_string()).await;
+
+    assert!(FederatedLoginState::is_bound_to(&config, &state, Some(&login_state.state_hash)));
+    // the attacker's own sign in, completed in the victim's browser
+    assert!(!FederatedLoginState::is_bound_to(&config, &state, Some(&other_state.state_hash)));
+    assert!(!FederatedLoginState::is_bound_to(&config, &state, Some(&state)));
+    assert!(!FederatedLoginState::is_bound_to(&config, &state, None));
+  }
+
+  #[test]
+  fn rejects_expired_sign_in() {
+    assert!(!login_state("corp", FEDERATED_LOGIN_STATE_LIFETIME + 5).is_valid_for(&"corp".to_string()));
+  }
+}
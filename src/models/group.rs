@@ -134,7 +134,7 @@ impl Group {
       g.description,
       ARRAY_REMOVE(ARRAY_AGG(gp.permission_name), NULL) AS permissions
     FROM groups g
-    INNER JOIN groups_permissions gp ON gp.group_name = g.name
+    LEFT JOIN groups_permissions gp ON gp.group_name = g.name
     GROUP BY g.name
     ORDER BY g.name {}
     OFFSET {} ROWS
@@ -168,7 +168,7 @@ impl Group {
       ARRAY_REMOVE(ARRAY_AGG(gp.permission_name), NULL) AS permissions
     FROM 
       groups g
-    INNER JOIN
+    LEFT JOIN
       groups_permissions gp
     ON
       gp.group_name = g.name
@@ -1,13 +1,8 @@
-use std::{
-  fmt::Debug,
-  time::{
-    self,
-    UNIX_EPOCH
-  }
-};
-use crypto::{
-  digest::Digest,
-  sha3::Sha3
+use std::fmt::Debug;
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
 };
 use serde::{
   Deserialize,
@@ -19,18 +14,58 @@ use sqlx::{
   FromRow,PgConnection
 };
 use crate::{
-  models::user::{
-    User,
-    UserRetrieveError
+  config::CauthConfig,
+  models::{
+    api_key::{
+      ApiKey,
+      ApiKeyAuthenticateError
+    },
+    refresh_token::RefreshToken,
+    user::{
+      User,
+      UserRetrieveError
+    }
   },
-  util::string::json_value_to_pretty_string
+  util::{
+    string::json_value_to_pretty_string,
+    token::{
+      generate_token,
+      hash_token,
+      TOKEN_INSERT_ATTEMPTS
+    }
+  }
 };
 
 #[derive(FromRow, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct LoginSession {
   pub id: i32,
   pub user_login: String,
-  pub token: String
+  pub token_hash: String,
+  pub created_at: DateTime<Utc>,
+  pub last_seen_at: DateTime<Utc>,
+  pub refresh_family: Option<String>,
+  pub user_agent: Option<String>,
+  pub ip: Option<String>,
+  /// OAuth client the session was issued to, missing for first-party logins
+  pub client_id: Option<String>,
+  /// Permissions the session is limited to, all of the user's permissions when missing
+  pub scopes: Option<Vec<String>>
+}
+
+/// OAuth client and scopes a session is issued for,
+/// the default is an unrestricted first-party session
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct TokenScope {
+  pub client_id: Option<String>,
+  pub scopes: Option<Vec<String>>
+}
+
+/// Information about the client creating a login session,
+/// shown to the user when listing their sessions
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct SessionMetadata {
+  pub user_agent: Option<String>,
+  pub ip: Option<String>
 }
 
 impl ToString for LoginSession {
@@ -44,13 +79,16 @@ impl ToString for LoginSession {
 #[derive(Debug)]
 pub enum LoginSessionRetrieveError {
   /// Returned when the session is not found
-  NotFound
+  NotFound,
+  /// Returned when the session exceeded its lifetime or idle timeout
+  Expired
 }
 
 impl ToString for LoginSessionRetrieveError {
   fn to_string(&self) -> String {
     return match self {
-      Self::NotFound => "Login session not found".to_string()
+      Self::NotFound => "Login session not found".to_string(),
+      Self::Expired => "Login session has expired".to_string()
     }
   }
 }
@@ -59,15 +97,18 @@ impl ToString for LoginSessionRetrieveError {
 pub enum LoginSessionInsertError {
   /// Returned when the user attached to the session does not exist
   UserNotFound,
-  /// Returned when the token hash cannot be created
-  CannotHash(String)
+  /// Returned when no unique token could be generated
+  TokenCollision,
+  /// Returned when the JWT access token issued with the session cannot be signed
+  CannotSign(String)
 }
 
 impl ToString for LoginSessionInsertError {
   fn to_string(&self) -> String {
     return match self {
       Self::UserNotFound => "Mentioned user not found".to_string(),
-      Self::CannotHash(err) => format!("Cannot hash the token. Details;\n{}", err)
+      Self::TokenCollision => "Cannot generate a unique session token".to_string(),
+      Self::CannotSign(err) => format!("Cannot sign the JWT access token: {}", err)
     }
   }
 }
@@ -89,16 +130,24 @@ impl ToString for LoginSessionDeleteError {
 #[derive(Debug)]
 pub enum LoginSessionGetUserError {
     /// Returned when the session do not exist
-    NotFound
+    NotFound,
+    /// Returned when the session exceeded its lifetime or idle timeout
+    Expired
 }
 
 impl LoginSession {
   /// ## LoginSession::retrieve
   /// 
   /// Selects a user's login session with specified token from the database
+  /// by the token's digest, refreshing the time it was last seen at
+  /// 
+  /// Errors:
+  /// + when the session do not exist
+  /// + when the session has expired, in which case it is also deleted
   /// 
   pub async fn retrieve(
     conn: &mut PgConnection,
+    config: &CauthConfig,
     token: &String
   ) -> Result<Self, LoginSessionRetrieveError> {
     let sql = "
@@ -107,94 +156,172 @@ impl LoginSession {
       FROM
         login_sessions
       WHERE
-        token = $1
+        token_hash = $1
       ;
     ";
 
     let q = query_as(&sql)
-      .bind(&token);
+      .bind(hash_token(token, &config.session_pepper));
 
-    let raw: LoginSession = match q.fetch_one(&mut *conn).await {
-      Ok(raw) => raw,
+    let session: LoginSession = match q.fetch_one(&mut *conn).await {
+      Ok(session) => session,
       Err(_) => return Err(LoginSessionRetrieveError::NotFound)
     };
 
-    let session = LoginSession {
-      id: raw.id,
-      user_login: raw.user_login,
-      token: raw.token
-    };
+    if session.is_expired(config) {
+      // the pool is used instead of the passed connection so the removal
+      // is not rolled back together with the caller's transaction
+      let sql = "DELETE FROM login_sessions WHERE id = $1;";
+      let _ = query(sql)
+        .bind(&session.id)
+        .execute(&config.db_conn)
+        .await;
+
+      return Err(LoginSessionRetrieveError::Expired);
+    }
+
+    let sql = "UPDATE login_sessions SET last_seen_at = NOW() WHERE id = $1;";
+    let _ = query(sql)
+      .bind(&session.id)
+      .execute(&mut *conn)
+      .await;
+
+    return Ok(session);
+  }
+
+  /// ## LoginSession::retrieve_first_party
+  ///
+  /// Selects a login session like `LoginSession::retrieve`, rejecting the sessions issued to OAuth clients.
+  /// Routes managing the account itself use it, so a client granted some scopes cannot take the account over
+  ///
+  /// Errors:
+  /// + when the session do not exist or was issued to an OAuth client
+  /// + when the session has expired
+  ///
+  pub async fn retrieve_first_party(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    token: &String
+  ) -> Result<Self, LoginSessionRetrieveError> {
+    let session = Self::retrieve(conn, config, token).await?;
+
+    if session.client_id.is_some() {
+      return Err(LoginSessionRetrieveError::NotFound);
+    }
 
     return Ok(session);
   }
+
+  /// ## LoginSession::is_expired
+  /// 
+  /// Checks if the session exceeded either its absolute lifetime
+  /// or the idle timeout set in the config.
+  /// Sessions issued with a refresh token or to an OAuth client use the access token lifetime
+  /// 
+  pub fn is_expired(self: &Self, config: &CauthConfig) -> bool {
+    return Utc::now() > self.expires_at(config);
+  }
+
+  /// ## LoginSession::expires_at
+  /// 
+  /// Computes when the session expires if it stays unused,
+  /// e.g. the earlier of its absolute lifetime and the idle timeout
+  /// 
+  pub fn expires_at(self: &Self, config: &CauthConfig) -> DateTime<Utc> {
+    let lifetime = match self.refresh_family.is_some() || self.client_id.is_some() {
+      true => Duration::seconds(config.access_token_lifetime),
+      false => Duration::seconds(config.session_lifetime)
+    };
+    let idle_timeout = Duration::seconds(config.session_idle_timeout);
+
+    return (self.created_at + lifetime).min(self.last_seen_at + idle_timeout);
+  }
   
   /// ## LoginSession::insert
   /// 
-  /// Inserts a new login session with provided data into the database <br>
+  /// Inserts a new login session with provided data into the database,
+  /// returning a randomly generated token of the session.
+  /// Only the token's digest is stored.
+  /// Sessions belonging to a refresh token family are revoked together with it <br>
+  /// Sessions issued to an OAuth client are limited to the granted scopes
   /// 
   /// Errors:
   /// + when referenced user do not exist.
+  /// + when no unique token could be generated
   /// 
   pub async fn insert(
     conn: &mut PgConnection,
+    config: &CauthConfig,
     user_login: String,
+    refresh_family: Option<&String>,
+    scope: &TokenScope,
+    metadata: &SessionMetadata
   ) -> Result<String, LoginSessionInsertError> {
     let sql = "
       INSERT INTO
-        login_sessions (user_login, token)
+        login_sessions (user_login, token_hash, refresh_family, user_agent, ip, client_id, scopes)
       VALUES
-        ($1, $2)
-      RETURNING token;
+        ($1, $2, $3, $4, $5, $6, $7)
+      ON CONFLICT (token_hash) DO NOTHING
+      RETURNING id;
     ";
-    
-    let time_since_epoch = match time::SystemTime::now().duration_since(UNIX_EPOCH) {
-      Ok(time) => time,
-      Err(err) => return Err(LoginSessionInsertError::CannotHash(err.to_string()))
-    }
-    .as_secs();
-
-    let to_hash = format!("{}{}", &user_login, time_since_epoch);
 
-    let mut hasher = Sha3::keccak256();
-    hasher.input_str(to_hash.as_str());
-    let token = hasher.result_str();
-
-    let result = query_as(sql)
-      .bind(&user_login)
-      .bind(&token)
-      .fetch_one(&mut *conn)
-      .await;
-
-    let row: (String,) = match result {
-      Ok(row) => row,
-      Err(_) => return Err(LoginSessionInsertError::UserNotFound)
-    };
-    let token = row.0;
+    for _ in 0..TOKEN_INSERT_ATTEMPTS {
+      let token = generate_token();
+
+      let result = query_as(sql)
+        .bind(&user_login)
+        .bind(hash_token(&token, &config.session_pepper))
+        .bind(&refresh_family)
+        .bind(&metadata.user_agent)
+        .bind(&metadata.ip)
+        .bind(&scope.client_id)
+        .bind(&scope.scopes)
+        .fetch_optional(&mut *conn)
+        .await;
+
+      let row: Option<(i32,)> = match result {
+        Ok(row) => row,
+        Err(_) => return Err(LoginSessionInsertError::UserNotFound)
+      };
+
+      // no row is returned when the token is already taken
+      if row.is_some() {
+        return Ok(token);
+      }
+    }
 
-    return Ok(token);
+    return Err(LoginSessionInsertError::TokenCollision);
   }
 
 
   /// ## LoginSession::delete
   /// 
-  /// Deletes a user's login session from the database (e.g. logs out the user)
+  /// Deletes a user's login session from the database (e.g. logs out the user),
+  /// revoking the refresh token family it was issued with
+  /// 
+  /// Errors:
+  /// + When the session is not found
   /// 
   pub async fn delete(
     conn: &mut PgConnection,
     session_id: i32
   ) -> Result<(), LoginSessionDeleteError> {
-    let sql = "DELETE FROM login_sessions WHERE id = $1";
-    let result = query(sql)
+    let sql = "DELETE FROM login_sessions WHERE id = $1 RETURNING refresh_family;";
+    let result = query_as(sql)
       .bind(&session_id)
-      .execute(&mut *conn)
+      .fetch_optional(&mut *conn)
       .await;
 
-    let rows_affected = result
-      .unwrap()
-      .rows_affected();
+    let row: Option<(Option<String>,)> = result.unwrap();
 
-    if rows_affected == 0 {
-      return Err(LoginSessionDeleteError::NotFound)
+    let refresh_family = match row {
+      Some((refresh_family,)) => refresh_family,
+      None => return Err(LoginSessionDeleteError::NotFound)
+    };
+
+    if let Some(family) = refresh_family {
+      RefreshToken::revoke_family(conn, &family).await;
     }
 
     return Ok(());
@@ -202,70 +329,233 @@ impl LoginSession {
 
   /// ## LoginSession::delete_by_token
   /// 
-  /// Deletes a user login session from the database
+  /// Deletes a user login session from the database,
+  /// revoking the refresh token family it was issued with
   /// 
   /// Errors:
   /// + When the session is not found
   /// 
   pub async fn delete_by_token(
     conn: &mut PgConnection,
+    config: &CauthConfig,
     token: &String
   ) -> Result<(), LoginSessionDeleteError> {
-    let sql = "DELETE FROM login_sessions WHERE token = $1;";
-    let result = query(sql)
-      .bind(&token)
-      .execute(&mut *conn)
+    let sql = "DELETE FROM login_sessions WHERE token_hash = $1 RETURNING refresh_family;";
+    let result = query_as(sql)
+      .bind(hash_token(token, &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await;
+
+    let row: Option<(Option<String>,)> = result.unwrap();
+
+    let refresh_family = match row {
+      Some((refresh_family,)) => refresh_family,
+      None => return Err(LoginSessionDeleteError::NotFound)
+    };
+
+    if let Some(family) = refresh_family {
+      RefreshToken::revoke_family(conn, &family).await;
+    }
+
+    return Ok(());
+  }
+
+  /// ## LoginSession::delete_by_client_token
+  /// 
+  /// Deletes a login session issued to specified OAuth client,
+  /// revoking the refresh token family it was issued with
+  /// 
+  /// Errors:
+  /// + When the session is not found or was issued to another client
+  /// 
+  pub async fn delete_by_client_token(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    token: &String,
+    client_id: &String
+  ) -> Result<(), LoginSessionDeleteError> {
+    let sql = "DELETE FROM login_sessions WHERE token_hash = $1 AND client_id = $2 RETURNING refresh_family;";
+    let row: Option<(Option<String>,)> = query_as(sql)
+      .bind(hash_token(token, &config.session_pepper))
+      .bind(&client_id)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    let refresh_family = match row {
+      Some((refresh_family,)) => refresh_family,
+      None => return Err(LoginSessionDeleteError::NotFound)
+    };
+
+    if let Some(family) = refresh_family {
+      RefreshToken::revoke_family(conn, &family).await;
+    }
+
+    return Ok(());
+  }
+
+  /// ## LoginSession::list_by_user
+  /// 
+  /// Lists the active login sessions of a user, oldest first
+  /// 
+  pub async fn list_by_user(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String
+  ) -> Vec<Self> {
+    let sql = "SELECT * FROM login_sessions WHERE user_login = $1 ORDER BY created_at ASC;";
+    let sessions: Vec<Self> = query_as(sql)
+      .bind(&user_login)
+      .fetch_all(&mut *conn)
+      .await
+      .unwrap();
+
+    return sessions
+      .into_iter()
+      .filter(|session| !session.is_expired(config))
+      .collect();
+  }
+
+  /// ## LoginSession::revoke
+  /// 
+  /// Deletes a login session belonging to specified user,
+  /// revoking the refresh token family it was issued with
+  /// 
+  /// Errors:
+  /// + When the user do not have a session with this id
+  /// 
+  pub async fn revoke(
+    conn: &mut PgConnection,
+    user_login: &String,
+    session_id: i32
+  ) -> Result<(), LoginSessionDeleteError> {
+    let sql = "DELETE FROM login_sessions WHERE id = $1 AND user_login = $2 RETURNING refresh_family;";
+    let result = query_as(sql)
+      .bind(&session_id)
+      .bind(&user_login)
+      .fetch_optional(&mut *conn)
       .await;
 
-    let rows_affected = result
-      .unwrap()
-      .rows_affected();
+    let row: Option<(Option<String>,)> = result.unwrap();
+
+    let refresh_family = match row {
+      Some((refresh_family,)) => refresh_family,
+      None => return Err(LoginSessionDeleteError::NotFound)
+    };
 
-    if rows_affected == 0 {
-      return Err(LoginSessionDeleteError::NotFound)
+    if let Some(family) = refresh_family {
+      RefreshToken::revoke_family(conn, &family).await;
     }
 
     return Ok(());
   }
 
+  /// ## LoginSession::revoke_others
+  /// 
+  /// Deletes every login session and refresh token of the session's owner
+  /// except the session itself and its refresh token family (e.g. logs out everywhere else)
+  /// 
+  pub async fn revoke_others(
+    self: &Self,
+    conn: &mut PgConnection
+  ) {
+    Self::revoke_all_except(conn, &self.user_login, self.id).await;
+  }
+
+  /// ## LoginSession::revoke_all_except
+  /// 
+  /// Deletes every login session and refresh token of a user
+  /// except the session with specified id and its refresh token family.
+  /// Every session is deleted when the kept one no longer exist
+  /// 
+  pub async fn revoke_all_except(
+    conn: &mut PgConnection,
+    user_login: &String,
+    session_id: i32
+  ) {
+    let sql = "
+      DELETE FROM
+        refresh_tokens
+      WHERE
+        user_login = $1
+        AND
+        family IS DISTINCT FROM (SELECT refresh_family FROM login_sessions WHERE id = $2 AND user_login = $1);
+    ";
+    let _ = query(sql)
+      .bind(&user_login)
+      .bind(&session_id)
+      .execute(&mut *conn)
+      .await;
+
+    let sql = "DELETE FROM login_sessions WHERE user_login = $1 AND id <> $2;";
+    let _ = query(sql)
+      .bind(&user_login)
+      .bind(&session_id)
+      .execute(&mut *conn)
+      .await;
+  }
+
+
+  /// ## LoginSession::revoke_all
+  /// 
+  /// Deletes every login session and refresh token of a user without deleting the user,
+  /// returning the number of deleted sessions
+  /// 
+  pub async fn revoke_all(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) -> u64 {
+    let sql = "DELETE FROM login_sessions WHERE user_login = $1;";
+    let result = query(sql)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+
+    let sql = "DELETE FROM refresh_tokens WHERE user_login = $1;";
+    let _ = query(sql)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await;
+
+    return result.rows_affected();
+  }
 
   /// ## LoginSession::get_user
   ///
-  /// Retrieve a user associated with provided session token
+  /// Retrieve a user associated with provided session token.
+  /// API keys are accepted in place of the session token
   ///
   /// Errors:
-  /// + When a session with specified token do not exist
-  /// + When session with provided token is not commited
+  /// + When a session or API key with specified token do not exist
+  /// + When the session or API key has expired
   ///
   pub async fn get_user(
     conn: &mut PgConnection,
+    config: &CauthConfig,
     token: &String
   ) -> Result<User, LoginSessionGetUserError> {
-    let sql = "
-    SELECT
-      u.login,
-      u.password_hash,
-      u.details
-    FROM
-      users u
-    INNER JOIN
-      login_sessions ls
-    ON
-      u.login = ls.user_login
-    WHERE
-      ls.token = $1
-      AND
-      ls.status = 'Commited';
-    ";
-    let result = query_as(&sql)
-      .bind(&token)
-      .fetch_one(&mut *conn)
-      .await;
+    let (user, _) = Self::get_user_and_scopes(conn, config, token, false).await?;
 
-    let user = match result {
-      Ok(result) => result,
-      Err(_) => return Err(LoginSessionGetUserError::NotFound)
-    };
+    return Ok(user);
+  }
+
+  /// ## LoginSession::get_first_party_user
+  ///
+  /// Retrieve a user associated with provided session token like `LoginSession::get_user`,
+  /// rejecting the sessions issued to OAuth clients
+  ///
+  /// Errors:
+  /// + When a session or API key with specified token do not exist
+  /// + When the session was issued to an OAuth client
+  /// + When the session or API key has expired
+  ///
+  pub async fn get_first_party_user(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    token: &String
+  ) -> Result<User, LoginSessionGetUserError> {
+    let (user, _) = Self::get_user_and_scopes(conn, config, token, true).await?;
 
     return Ok(user);
   }
@@ -273,20 +563,34 @@ impl LoginSession {
   /// ## LoginSession::has_permission
   ///
   /// Check if the user associated with provided token 
-  /// has provided permission
+  /// has provided permission, rejecting expired sessions.
+  /// Sessions and API keys limited to a set of permissions also have to include it
   pub async fn has_permission(
     conn: &mut PgConnection,
+    config: &CauthConfig,
     token: &String,
     permission_name: &str
   ) -> bool {
-    let user = match Self::get_user(
+    let (user, scopes) = match Self::get_user_and_scopes(
       conn,
-      &token
+      config,
+      &token,
+      false
     ).await {
-      Ok(user) => user,
+      Ok(result) => result,
       Err(_) => return false
     };
 
+    if user.is_restricted(config) {
+      return false;
+    }
+
+    if let Some(scopes) = scopes {
+      if !scopes.iter().any(|scope| scope == permission_name) {
+        return false;
+      }
+    }
+
     return user
       .has_permission(
         conn,
@@ -294,4 +598,152 @@ impl LoginSession {
       )
       .await;
   }
+
+  /// ## LoginSession::permissions
+  ///
+  /// Lists the permissions granted through provided token,
+  /// e.g. the user's permissions narrowed down to the scopes of the session or API key
+  ///
+  pub async fn permissions(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    token: &String
+  ) -> Vec<String> {
+    let (user, scopes) = match Self::get_user_and_scopes(conn, config, token, false).await {
+      Ok(result) => result,
+      Err(_) => return vec![]
+    };
+
+    if user.is_restricted(config) {
+      return vec![];
+    }
+
+    return user
+      .permissions(conn)
+      .await
+      .into_iter()
+      .filter(|permission| match &scopes {
+        Some(scopes) => scopes.contains(permission),
+        None => true
+      })
+      .collect();
+  }
+
+  async fn get_user_and_scopes(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    token: &String,
+    first_party_only: bool
+  ) -> Result<(User, Option<Vec<String>>), LoginSessionGetUserError> {
+    let (user_login, scopes) = match Self::retrieve(conn, config, token).await {
+      Ok(session) if first_party_only && session.client_id.is_some() => return Err(LoginSessionGetUserError::NotFound),
+      Ok(session) => (session.user_login, session.scopes),
+      Err(LoginSessionRetrieveError::Expired) => return Err(LoginSessionGetUserError::Expired),
+      Err(LoginSessionRetrieveError::NotFound) => match ApiKey::authenticate(conn, config, token).await {
+        Ok(api_key) => (api_key.user_login, api_key.permissions),
+        Err(err) => match err {
+          ApiKeyAuthenticateError::NotFound => return Err(LoginSessionGetUserError::NotFound),
+          ApiKeyAuthenticateError::Expired => return Err(LoginSessionGetUserError::Expired)
+        }
+      }
+    };
+
+    let user = match User::retrieve(conn, &user_login).await {
+      Ok(user) => user,
+      Err(_) => return Err(LoginSessionGetUserError::NotFound)
+    };
+
+    return Ok((user, scopes));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Inserts alice with a first-party session and a session issued to an OAuth client,
+  /// returning both tokens
+  async fn insert_sessions(config: &CauthConfig, conn: &mut PgConnection) -> (String, String) {
+    query("INSERT INTO users (login, password_hash, details) VALUES ('alice', '', '{}');")
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+    query("INSERT INTO oauth_clients (client_id, secret_hash, name) VALUES ('client', '', 'Client');")
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+
+    let first_party = LoginSession::insert(
+      conn,
+      config,
+      "alice".to_string(),
+      None,
+      &TokenScope::default(),
+      &SessionMetadata::default()
+    )
+    .await
+    .unwrap();
+
+    let scope = TokenScope {
+      client_id: Some("client".to_string()),
+      scopes: Some(vec!["openid".to_string()])
+    };
+    let client = LoginSession::insert(conn, config, "alice".to_string(), None, &scope, &SessionMetadata::default())
+      .await
+      .unwrap();
+
+    return (first_party, client);
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn first_party_lookup_rejects_client_sessions() {
+    let config = CauthConfig::for_tests().await;
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let (first_party, client) = insert_sessions(&config, &mut conn).await;
+
+    assert!(LoginSession::retrieve(&mut conn, &config, &client).await.is_ok());
+    assert!(matches!(
+      LoginSession::retrieve_first_party(&mut conn, &config, &client).await,
+      Err(LoginSessionRetrieveError::NotFound)
+    ));
+    assert!(matches!(
+      LoginSession::get_first_party_user(&mut conn, &config, &client).await,
+      Err(LoginSessionGetUserError::NotFound)
+    ));
+
+    assert_eq!(LoginSession::retrieve_first_party(&mut conn, &config, &first_party).await.unwrap().user_login, "alice");
+    assert_eq!(LoginSession::get_first_party_user(&mut conn, &config, &first_party).await.unwrap().login, "alice");
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn client_revokes_only_its_own_tokens() {
+    let config = CauthConfig::for_tests().await;
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let (first_party, client) = insert_sessions(&config, &mut conn).await;
+    let (client_id, other_id) = ("client".to_string(), "other".to_string());
+
+    assert!(LoginSession::delete_by_client_token(&mut conn, &config, &first_party, &client_id).await.is_err());
+    assert!(LoginSession::delete_by_client_token(&mut conn, &config, &client, &other_id).await.is_err());
+    assert!(LoginSession::retrieve(&mut conn, &config, &first_party).await.is_ok());
+    assert!(LoginSession::retrieve(&mut conn, &config, &client).await.is_ok());
+
+    assert!(LoginSession::delete_by_client_token(&mut conn, &config, &client, &client_id).await.is_ok());
+    assert!(LoginSession::retrieve(&mut conn, &config, &client).await.is_err());
+
+    let scope = TokenScope {
+      client_id: Some(client_id.clone()),
+      scopes: None
+    };
+    let pair = RefreshToken::issue_scoped(&mut conn, &config, &"alice".to_string(), &scope, &SessionMetadata::default())
+      .await
+      .unwrap();
+
+    assert!(!RefreshToken::revoke_by_token(&mut conn, &config, &pair.refresh_token, &other_id).await);
+    assert!(LoginSession::retrieve(&mut conn, &config, &pair.token).await.is_ok());
+
+    assert!(RefreshToken::revoke_by_token(&mut conn, &config, &pair.refresh_token, &client_id).await);
+    assert!(LoginSession::retrieve(&mut conn, &config, &pair.token).await.is_err());
+  }
 }
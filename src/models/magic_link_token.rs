@@ -0,0 +1,263 @@
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use serde_json::json;
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  models::user::{
+    User,
+    UserSecondFactorError
+  },
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    notifier::Notification,
+    token::{
+      generate_token,
+      hash_token,
+      TOKEN_INSERT_ATTEMPTS
+    }
+  }
+};
+
+/// Time in seconds a magic link can be used for
+pub const MAGIC_LINK_LIFETIME: i64 = 60 * 15;
+
+/// Maximum number of magic links sent to one address within the rate limit window
+pub const MAGIC_LINK_RATE_LIMIT: i64 = 5;
+
+/// Time in seconds the magic links sent to an address are counted for
+pub const MAGIC_LINK_RATE_WINDOW: i64 = 60 * 60;
+
+/// Single-use login token sent to the email of a user
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct MagicLinkToken {
+  pub id: i32,
+  pub token_hash: String,
+  pub user_login: String,
+  /// Address the link was sent to
+  pub email: String,
+  pub used_at: Option<DateTime<Utc>>,
+  pub expires_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>
+}
+
+#[derive(Debug)]
+pub enum MagicLinkTokenInsertError {
+  /// Returned when no unique token could be generated
+  TokenCollision
+}
+
+impl ToString for MagicLinkTokenInsertError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::TokenCollision => "Cannot generate a unique magic link token".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum MagicLinkRedeemError {
+  /// Returned when the token do not exist, was already used or has expired
+  InvalidToken,
+  /// Returned when the user has two-factor authentication enabled and no code was provided
+  MfaRequired,
+  /// Returned when the provided two-factor authentication code is invalid
+  InvalidMfaCode
+}
+
+impl ToString for MagicLinkRedeemError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::InvalidToken => "The magic link is invalid, was already used or has expired".to_string(),
+      Self::MfaRequired => "Two-factor authentication is enabled, provide a code".to_string(),
+      Self::InvalidMfaCode => "The two-factor authentication code is invalid".to_string()
+    }
+  }
+}
+
+impl MagicLinkToken {
+  /// ## MagicLinkToken::is_rate_limited
+  ///
+  /// Checks if the address already got the maximum number of magic links within the rate limit window
+  ///
+  pub async fn is_rate_limited(
+    conn: &mut PgConnection,
+    email: &String
+  ) -> bool {
+    let sql = "SELECT COUNT(*) FROM magic_link_tokens WHERE email = $1 AND created_at > $2;";
+    let (count,): (i64,) = query_as(sql)
+      .bind(&email)
+      .bind(Utc::now() - Duration::seconds(MAGIC_LINK_RATE_WINDOW))
+      .fetch_one(&mut *conn)
+      .await
+      .unwrap();
+
+    return count >= MAGIC_LINK_RATE_LIMIT;
+  }
+
+  /// ## MagicLinkToken::create
+  ///
+  /// Stores a new magic link token of the user sent to specified address, returning the plaintext token.
+  /// Only the token's digest is stored, tokens older than the rate limit window are removed on the way
+  ///
+  /// Errors:
+  /// + when no unique token could be generated
+  ///
+  pub async fn create(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    email: &String
+  ) -> Result<String, MagicLinkTokenInsertError> {
+    let sql = "DELETE FROM magic_link_tokens WHERE created_at < $1;";
+    let _ = query(sql)
+      .bind(Utc::now() - Duration::seconds(MAGIC_LINK_RATE_WINDOW.max(MAGIC_LINK_LIFETIME)))
+      .execute(&mut *conn)
+      .await;
+
+    let sql = "
+      INSERT INTO
+        magic_link_tokens (token_hash, user_login, email, expires_at)
+      VALUES
+        ($1, $2, $3, $4)
+      ON CONFLICT DO NOTHING;
+    ";
+
+    for _ in 0..TOKEN_INSERT_ATTEMPTS {
+      let token = generate_token();
+
+      let result = query(sql)
+        .bind(hash_token(&token, &config.session_pepper))
+        .bind(&user_login)
+        .bind(&email)
+        .bind(Utc::now() + Duration::seconds(MAGIC_LINK_LIFETIME))
+        .execute(&mut *conn)
+        .await;
+
+      match result {
+        Ok(result) if result.rows_affected() == 0 => continue,
+        Ok(_) => (),
+        Err(err) => {
+          log_database_interaction(
+            "Inserting a magic link token into database.",
+            json!({ "user_login": user_login }),
+            DatabaseOperationLogStatus::Err(err.to_string())
+          );
+
+          return Err(MagicLinkTokenInsertError::TokenCollision);
+        }
+      };
+
+      log_database_interaction::<String>(
+        "Inserting a magic link token into database.",
+        json!({ "user_login": user_login }),
+        DatabaseOperationLogStatus::Ok
+      );
+
+      return Ok(token);
+    }
+
+    return Err(MagicLinkTokenInsertError::TokenCollision);
+  }
+
+  /// ## MagicLinkToken::redeem
+  ///
+  /// Marks the token as used, returning the login of its user.
+  /// Users with two-factor authentication enabled also have to provide a code,
+  /// the token stays usable when the code is missing or invalid.
+  /// Redeeming the token proves the user owns the address, so it's verified as well
+  ///
+  /// Errors:
+  /// + when the token do not exist, was already used or has expired
+  /// + when the second factor is required but missing or invalid
+  ///
+  pub async fn redeem(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    token: &String,
+    code: Option<&String>
+  ) -> Result<String, MagicLinkRedeemError> {
+    let sql = "SELECT * FROM magic_link_tokens WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW();";
+    let result: Option<Self> = query_as(sql)
+      .bind(hash_token(token, &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    let magic_link = match result {
+      Some(magic_link) => magic_link,
+      None => return Err(MagicLinkRedeemError::InvalidToken)
+    };
+
+    match User::verify_second_factor(conn, &magic_link.user_login, code).await {
+      Ok(_) => (),
+      Err(err) => match err {
+        UserSecondFactorError::Required => return Err(MagicLinkRedeemError::MfaRequired),
+        UserSecondFactorError::Invalid => return Err(MagicLinkRedeemError::InvalidMfaCode)
+      }
+    };
+
+    // the token is only marked once, when redeemed concurrently
+    let sql = "UPDATE magic_link_tokens SET used_at = NOW() WHERE id = $1 AND used_at IS NULL;";
+    let result = query(sql)
+      .bind(&magic_link.id)
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+
+    if result.rows_affected() == 0 {
+      return Err(MagicLinkRedeemError::InvalidToken);
+    }
+
+    User::verify_email(conn, &magic_link.user_login, &magic_link.email).await;
+
+    return Ok(magic_link.user_login);
+  }
+
+  /// ## MagicLinkToken::notification
+  ///
+  /// Builds the message delivering the token to the address,
+  /// linking to the configured magic link page when there is one
+  ///
+  pub fn notification(
+    config: &CauthConfig,
+    user: &User,
+    email: &String,
+    token: &String
+  ) -> Notification {
+    let link = config.magic_link_url
+      .as_ref()
+      .map(|url| url.replace("{token}", token));
+
+    let instructions = match &link {
+      Some(link) => format!("open {}", link),
+      None => format!("use the following token: {}", token)
+    };
+
+    return Notification {
+      kind: "magic_link".to_string(),
+      login: user.login.clone(),
+      email: Some(email.clone()),
+      subject: "Your login link".to_string(),
+      body: format!(
+        "To log in to the account {}, {}\nThe link can be used once and expires in {} minutes, ignore this message if you did not request it.",
+        user.login,
+        instructions,
+        MAGIC_LINK_LIFETIME / 60
+      ),
+      token: token.clone(),
+      link
+    };
+  }
+}
@@ -2,6 +2,21 @@ pub mod permission;
 pub mod group;
 pub mod user;
 pub mod login_session;
+pub mod refresh_token;
+pub mod signing_key;
+pub mod oauth_client;
+pub mod api_key;
+pub mod authorization_code;
+pub mod device_code;
+pub mod federated_identity;
+pub mod federated_login_state;
+pub mod password_reset_token;
+pub mod email_verification_token;
+pub mod magic_link_token;
+pub mod user_totp;
+pub mod recovery_code;
+pub mod webauthn_challenge;
+pub mod webauthn_credential;
 pub mod event;
 
 use serde::Deserialize;
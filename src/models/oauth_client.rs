@@ -0,0 +1,282 @@
+use chrono::{
+  DateTime,
+  Utc
+};
+use serde::{
+  Deserialize,
+  Serialize
+};
+use serde_json::json;
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    string::json_value_to_pretty_string,
+    token::{
+      generate_token,
+      hash_token
+    }
+  }
+};
+
+/// Client allowed to use the OAuth endpoints, e.g. an app logging its users in
+/// or a gateway introspecting or revoking tokens.
+/// Public clients have no secret and can only use the authorization code grant
+#[derive(FromRow, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct OAuthClient {
+  pub id: i32,
+  pub client_id: String,
+  #[serde(skip_serializing)]
+  pub secret_hash: Option<String>,
+  pub name: String,
+  pub created_at: DateTime<Utc>,
+  /// Exact uris the authorization codes can be sent to
+  pub redirect_uris: Vec<String>,
+  /// User the client_credentials grant issues tokens for
+  pub service_account: Option<String>
+}
+
+impl ToString for OAuthClient {
+  fn to_string(&self) -> String {
+    let formatted = json_value_to_pretty_string(&serde_json::to_value(self).unwrap());
+
+    return formatted;
+  }
+}
+
+#[derive(Debug)]
+pub enum OAuthClientInsertError {
+  /// Returned when the name is too long, the service account do not exist
+  /// or the generated client id is already taken
+  NameError
+}
+
+impl ToString for OAuthClientInsertError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NameError => "Either the client name is too long, the service account do not exist or the generated client id is already taken".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum OAuthClientRetrieveError {
+  /// Returned when a client with specified id do not exist
+  NotFound
+}
+
+impl ToString for OAuthClientRetrieveError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotFound => "OAuth client not found".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum OAuthClientAuthenticateError {
+  /// Returned when the client do not exist or the secret do not match
+  InvalidClient
+}
+
+impl ToString for OAuthClientAuthenticateError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::InvalidClient => "Client authentication failed".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum OAuthClientDeleteError {
+  /// Returned when a client with specified id do not exist
+  NotFound
+}
+
+impl ToString for OAuthClientDeleteError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotFound => "OAuth client not found".to_string()
+    }
+  }
+}
+
+impl OAuthClient {
+  /// ## OAuthClient::list
+  ///
+  /// Lists every registered client, oldest first
+  ///
+  pub async fn list(
+    conn: &mut PgConnection
+  ) -> Vec<Self> {
+    let sql = "SELECT * FROM oauth_clients ORDER BY created_at ASC;";
+    let clients = query_as(sql)
+      .fetch_all(&mut *conn)
+      .await
+      .unwrap();
+
+    return clients;
+  }
+
+  /// ## OAuthClient::retrieve
+  ///
+  /// Retrieves the client with specified client id
+  ///
+  /// Errors:
+  /// + when the client do not exist
+  ///
+  pub async fn retrieve(
+    conn: &mut PgConnection,
+    client_id: &String
+  ) -> Result<Self, OAuthClientRetrieveError> {
+    let sql = "SELECT * FROM oauth_clients WHERE client_id = $1;";
+    let result = query_as(sql)
+      .bind(&client_id)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return match result {
+      Some(client) => Ok(client),
+      None => Err(OAuthClientRetrieveError::NotFound)
+    };
+  }
+
+  /// ## OAuthClient::insert
+  ///
+  /// Registers a new client with a random client id and, unless it's public, a random secret,
+  /// returning the client together with the plaintext secret.
+  /// Only the secret's digest is stored
+  ///
+  /// Errors:
+  /// + when the name is too long
+  /// + when the service account do not exist
+  ///
+  pub async fn insert(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    name: &String,
+    redirect_uris: &Vec<String>,
+    public: bool,
+    service_account: Option<&String>
+  ) -> Result<(Self, Option<String>), OAuthClientInsertError> {
+    let secret = match public {
+      true => None,
+      false => Some(generate_token())
+    };
+
+    let sql = "
+      INSERT INTO
+        oauth_clients (client_id, secret_hash, name, redirect_uris, service_account)
+      VALUES
+        ($1, $2, $3, $4, $5)
+      RETURNING *;
+    ";
+    let result = query_as(sql)
+      .bind(generate_token())
+      .bind(secret.as_ref().map(|secret| hash_token(secret, &config.session_pepper)))
+      .bind(&name)
+      .bind(&redirect_uris)
+      .bind(&service_account)
+      .fetch_one(&mut *conn)
+      .await;
+
+    let client: Self = match result {
+      Ok(client) => client,
+      Err(err) => {
+        log_database_interaction(
+          "Inserting an OAuth client into database.",
+          json!({ "name": name }),
+          DatabaseOperationLogStatus::Err(err.to_string())
+        );
+
+        return Err(OAuthClientInsertError::NameError);
+      }
+    };
+
+    log_database_interaction::<String>(
+      "Inserting an OAuth client into database.",
+      json!({ "name": name, "client_id": client.client_id }),
+      DatabaseOperationLogStatus::Ok
+    );
+
+    return Ok((client, secret));
+  }
+
+  /// ## OAuthClient::is_public
+  ///
+  /// Checks if the client has no secret, e.g. cannot authenticate itself
+  ///
+  pub fn is_public(self: &Self) -> bool {
+    return self.secret_hash.is_none();
+  }
+
+  /// ## OAuthClient::allows_redirect_uri
+  ///
+  /// Checks if the uri exactly matches one of the registered redirect uris
+  ///
+  pub fn allows_redirect_uri(self: &Self, redirect_uri: &str) -> bool {
+    return self.redirect_uris.iter().any(|uri| uri == redirect_uri);
+  }
+
+  /// ## OAuthClient::authenticate
+  ///
+  /// Retrieves the client with specified id when the secret matches
+  ///
+  /// Errors:
+  /// + when the client do not exist or the secret is wrong
+  ///
+  pub async fn authenticate(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    client_id: &String,
+    client_secret: &String
+  ) -> Result<Self, OAuthClientAuthenticateError> {
+    let sql = "SELECT * FROM oauth_clients WHERE client_id = $1 AND secret_hash = $2;";
+    let result = query_as(sql)
+      .bind(&client_id)
+      .bind(hash_token(client_secret, &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return match result {
+      Some(client) => Ok(client),
+      None => Err(OAuthClientAuthenticateError::InvalidClient)
+    };
+  }
+
+  /// ## OAuthClient::delete
+  ///
+  /// Deletes the client with specified client id
+  ///
+  /// Errors:
+  /// + when the client do not exist
+  ///
+  pub async fn delete(
+    conn: &mut PgConnection,
+    client_id: &String
+  ) -> Result<(), OAuthClientDeleteError> {
+    let sql = "DELETE FROM oauth_clients WHERE client_id = $1;";
+    let result = query(sql)
+      .bind(&client_id)
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+
+    if result.rows_affected() == 0 {
+      return Err(OAuthClientDeleteError::NotFound);
+    }
+
+    return Ok(());
+  }
+}
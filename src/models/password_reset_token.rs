@@ -0,0 +1,240 @@
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use serde_json::{
+  json,
+  Value
+};
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  models::user::User,
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    notifier::Notification,
+    token::{
+      generate_token,
+      hash_token,
+      TOKEN_INSERT_ATTEMPTS
+    }
+  }
+};
+
+/// Time in seconds a password reset token can be used for
+pub const PASSWORD_RESET_TOKEN_LIFETIME: i64 = 60 * 60;
+
+/// Maximum number of password reset tokens sent to one user within the rate limit window
+pub const PASSWORD_RESET_RATE_LIMIT: i64 = 5;
+
+/// Time in seconds the password reset tokens sent to a user are counted for
+pub const PASSWORD_RESET_RATE_WINDOW: i64 = 60 * 60;
+
+/// Single-use token sent to a user who forgot their password
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct PasswordResetToken {
+  pub id: i32,
+  pub token_hash: String,
+  pub user_login: String,
+  pub expires_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>
+}
+
+#[derive(Debug)]
+pub enum PasswordResetTokenInsertError {
+  /// Returned when no unique token could be generated
+  TokenCollision
+}
+
+impl ToString for PasswordResetTokenInsertError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::TokenCollision => "Cannot generate a unique password reset token".to_string()
+    }
+  }
+}
+
+impl PasswordResetToken {
+  /// ## PasswordResetToken::is_rate_limited
+  ///
+  /// Checks if the user already got the maximum number of password reset tokens within the rate limit window
+  ///
+  pub async fn is_rate_limited(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) -> bool {
+    let sql = "SELECT COUNT(*) FROM password_reset_tokens WHERE user_login = $1 AND created_at > $2;";
+    let (count,): (i64,) = query_as(sql)
+      .bind(&user_login)
+      .bind(Utc::now() - Duration::seconds(PASSWORD_RESET_RATE_WINDOW))
+      .fetch_one(&mut *conn)
+      .await
+      .unwrap();
+
+    return count >= PASSWORD_RESET_RATE_LIMIT;
+  }
+
+  /// ## PasswordResetToken::create
+  ///
+  /// Stores a new password reset token of the user, returning the plaintext token.
+  /// Only the token's digest is stored. Earlier tokens of the user expire, they're kept
+  /// for the rate limit until they're older than its window and removed on the way
+  ///
+  /// Errors:
+  /// + when no unique token could be generated
+  ///
+  pub async fn create(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String
+  ) -> Result<String, PasswordResetTokenInsertError> {
+    let sql = "DELETE FROM password_reset_tokens WHERE created_at < $1 AND expires_at < NOW();";
+    let _ = query(sql)
+      .bind(Utc::now() - Duration::seconds(PASSWORD_RESET_RATE_WINDOW))
+      .execute(&mut *conn)
+      .await;
+
+    let sql = "UPDATE password_reset_tokens SET expires_at = NOW() WHERE user_login = $1 AND expires_at > NOW();";
+    let _ = query(sql)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await;
+
+    let sql = "
+      INSERT INTO
+        password_reset_tokens (token_hash, user_login, expires_at)
+      VALUES
+        ($1, $2, $3)
+      ON CONFLICT DO NOTHING;
+    ";
+
+    for _ in 0..TOKEN_INSERT_ATTEMPTS {
+      let token = generate_token();
+
+      let result = query(sql)
+        .bind(hash_token(&token, &config.session_pepper))
+        .bind(&user_login)
+        .bind(Utc::now() + Duration::seconds(PASSWORD_RESET_TOKEN_LIFETIME))
+        .execute(&mut *conn)
+        .await;
+
+      match result {
+        Ok(result) if result.rows_affected() == 0 => continue,
+        Ok(_) => (),
+        Err(err) => {
+          log_database_interaction(
+            "Inserting a password reset token into database.",
+            json!({ "user_login": user_login }),
+            DatabaseOperationLogStatus::Err(err.to_string())
+          );
+
+          return Err(PasswordResetTokenInsertError::TokenCollision);
+        }
+      };
+
+      log_database_interaction::<String>(
+        "Inserting a password reset token into database.",
+        json!({ "user_login": user_login }),
+        DatabaseOperationLogStatus::Ok
+      );
+
+      return Ok(token);
+    }
+
+    return Err(PasswordResetTokenInsertError::TokenCollision);
+  }
+
+  /// ## PasswordResetToken::consume
+  ///
+  /// Deletes the token and returns it, unless it do not exist or has expired.
+  /// The token can only be consumed once
+  ///
+  pub async fn consume(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    token: &String
+  ) -> Option<Self> {
+    let sql = "DELETE FROM password_reset_tokens WHERE token_hash = $1 RETURNING *;";
+    let result: Option<Self> = query_as(sql)
+      .bind(hash_token(token, &config.session_pepper))
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return result.filter(|reset_token| Utc::now() <= reset_token.expires_at);
+  }
+
+  /// ## PasswordResetToken::notification
+  ///
+  /// Builds the message delivering the token to the user,
+  /// linking to the configured password reset page when there is one
+  ///
+  pub fn notification(
+    config: &CauthConfig,
+    user: &User,
+    token: &String
+  ) -> Notification {
+    let link = config.password_reset_url
+      .as_ref()
+      .map(|url| url.replace("{token}", token));
+
+    let instructions = match &link {
+      Some(link) => format!("open {}", link),
+      None => format!("use the following token: {}", token)
+    };
+
+    return Notification {
+      kind: "password_reset".to_string(),
+      login: user.login.clone(),
+      email: user.details.get("email").and_then(Value::as_str).map(|email| email.to_string()),
+      subject: "Reset your password".to_string(),
+      body: format!(
+        "A password reset was requested for the account {}.\nTo choose a new password, {}\nThe token expires in {} minutes, ignore this message if you did not request it.",
+        user.login,
+        instructions,
+        PASSWORD_RESET_TOKEN_LIFETIME / 60
+      ),
+      token: token.clone(),
+      link
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn limits_tokens_sent_to_a_user() {
+    let config = CauthConfig::for_tests().await;
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let login = "alice".to_string();
+    User::insert(&mut conn, &config, &login, &"correct horse".to_string(), &json!({})).await.unwrap();
+
+    let mut tokens = vec![];
+    for _ in 0..PASSWORD_RESET_RATE_LIMIT {
+      assert!(!PasswordResetToken::is_rate_limited(&mut conn, &login).await);
+      tokens.push(PasswordResetToken::create(&mut conn, &config, &login).await.unwrap());
+    }
+
+    assert!(PasswordResetToken::is_rate_limited(&mut conn, &login).await);
+    assert!(!PasswordResetToken::is_rate_limited(&mut conn, &"bob".to_string()).await);
+
+    // only the newest token can be used
+    let newest = tokens.pop().unwrap();
+    for token in &tokens {
+      assert!(PasswordResetToken::consume(&mut conn, &config, token).await.is_none());
+    }
+    assert_eq!(PasswordResetToken::consume(&mut conn, &config, &newest).await.unwrap().user_login, login);
+  }
+}
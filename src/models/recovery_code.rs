@@ -0,0 +1,183 @@
+use argon2::{
+  password_hash::Encoding,
+  Argon2,
+  Params,
+  PasswordHash,
+  PasswordVerifier
+};
+use chrono::{
+  DateTime,
+  Utc
+};
+use serde_json::json;
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  models::user::hash_password,
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    totp::{
+      generate_recovery_code,
+      normalize_recovery_code
+    }
+  }
+};
+
+/// Number of recovery codes generated at once
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Single-use code accepted in place of a TOTP code, for users who lost their authenticator
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct RecoveryCode {
+  pub id: i32,
+  pub user_login: String,
+  pub code_hash: String,
+  pub created_at: DateTime<Utc>
+}
+
+#[derive(Debug)]
+pub enum RecoveryCodeGenerateError {
+  /// Returned when a code cannot be hashed
+  CannotHash(String),
+  /// Returned when the user do not exist
+  UserNotFound
+}
+
+impl ToString for RecoveryCodeGenerateError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::CannotHash(err) => format!("Recovery code hashing error: {}.", err),
+      Self::UserNotFound => "Mentioned user not found".to_string()
+    }
+  }
+}
+
+impl RecoveryCode {
+  /// ## RecoveryCode::generate
+  ///
+  /// Replaces the recovery codes of the user with a new set,
+  /// returning the plaintext codes. Only their argon2 hashes are stored
+  ///
+  /// Errors:
+  /// + when a code cannot be hashed
+  /// + when the user do not exist
+  ///
+  pub async fn generate(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) -> Result<Vec<String>, RecoveryCodeGenerateError> {
+    Self::delete_all(conn, user_login).await;
+
+    let mut codes = vec![];
+    let sql = "INSERT INTO recovery_codes (user_login, code_hash) VALUES ($1, $2);";
+
+    for _ in 0..RECOVERY_CODE_COUNT {
+      let code = generate_recovery_code();
+      // the codes are hashed without the separator, as they are normalized before verifying.
+      // they are random, so the default cost is enough regardless of the configured one
+      let code_hash = match hash_password(normalize_recovery_code(&code).unwrap(), &Params::default()) {
+        Ok(code_hash) => code_hash,
+        Err(err) => return Err(RecoveryCodeGenerateError::CannotHash(err))
+      };
+
+      let result = query(sql)
+        .bind(&user_login)
+        .bind(&code_hash)
+        .execute(&mut *conn)
+        .await;
+
+      if let Err(err) = result {
+        log_database_interaction(
+          "Inserting recovery codes into database.",
+          json!({ "login": user_login }),
+          DatabaseOperationLogStatus::Err(err.to_string())
+        );
+
+        return Err(RecoveryCodeGenerateError::UserNotFound);
+      }
+
+      codes.push(code);
+    }
+
+    log_database_interaction::<String>(
+      "Inserting recovery codes into database.",
+      json!({ "login": user_login }),
+      DatabaseOperationLogStatus::Ok
+    );
+
+    return Ok(codes);
+  }
+
+  /// ## RecoveryCode::redeem
+  ///
+  /// Checks the code against the user's unused recovery codes, deleting the matching one.
+  /// Returns whether a code matched
+  ///
+  pub async fn redeem(
+    conn: &mut PgConnection,
+    user_login: &String,
+    code: &String
+  ) -> bool {
+    let code = match normalize_recovery_code(code) {
+      Some(code) => code,
+      None => return false
+    };
+
+    let sql = "SELECT * FROM recovery_codes WHERE user_login = $1;";
+    let result = query_as(sql)
+      .bind(&user_login)
+      .fetch_all(&mut *conn)
+      .await;
+
+    let recovery_codes: Vec<Self> = match result {
+      Ok(recovery_codes) => recovery_codes,
+      Err(_) => return false
+    };
+
+    let matching = recovery_codes
+      .iter()
+      .find(|recovery_code| {
+        match PasswordHash::parse(&recovery_code.code_hash, Encoding::B64) {
+          Ok(hash) => Argon2::default().verify_password(code.as_bytes(), &hash).is_ok(),
+          Err(_) => false
+        }
+      });
+
+    let matching = match matching {
+      Some(matching) => matching,
+      None => return false
+    };
+
+    // the code is only deleted once when redeemed concurrently, the later redemption fails
+    let sql = "DELETE FROM recovery_codes WHERE id = $1 RETURNING id;";
+    let deleted: Vec<(i32,)> = query_as(sql)
+      .bind(&matching.id)
+      .fetch_all(&mut *conn)
+      .await
+      .unwrap_or_default();
+
+    return deleted.len() == 1;
+  }
+
+  /// ## RecoveryCode::delete_all
+  ///
+  /// Deletes every recovery code of the user
+  ///
+  pub async fn delete_all(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) {
+    let sql = "DELETE FROM recovery_codes WHERE user_login = $1;";
+    let _ = query(sql)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await;
+  }
+}
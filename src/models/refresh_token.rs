@@ -0,0 +1,352 @@
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use serde::Serialize;
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  models::{
+    login_session::{
+      LoginSession,
+      LoginSessionInsertError,
+      SessionMetadata,
+      TokenScope
+    },
+    signing_key::SigningKey
+  },
+  util::token::{
+    generate_token,
+    hash_token,
+    TOKEN_INSERT_ATTEMPTS
+  }
+};
+
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct RefreshToken {
+  pub id: i32,
+  pub family: String,
+  pub user_login: String,
+  pub token_hash: String,
+  pub used: bool,
+  pub family_created_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+  /// OAuth client the family was issued to, missing for first-party logins
+  pub client_id: Option<String>,
+  pub scopes: Option<Vec<String>>
+}
+
+/// Access token and refresh token returned to the client after logging in or refreshing
+#[derive(Serialize)]
+pub struct TokenPair {
+  pub token: String,
+  pub refresh_token: String,
+  /// Number of seconds the access token is valid for
+  pub expires_in: i64,
+  /// Signed JWT version of the access token, issued when enabled in the config
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub jwt: Option<String>
+}
+
+#[derive(Debug)]
+pub enum RefreshTokenRotateError {
+  /// Returned when the refresh token do not exist
+  NotFound,
+  /// Returned when the refresh token was already used, the whole family is revoked
+  Reused,
+  /// Returned when the refresh token family exceeded its lifetime or idle timeout
+  Expired,
+  /// Returned when no unique token could be generated
+  TokenCollision,
+  /// Returned when the JWT access token cannot be signed
+  CannotSign(String)
+}
+
+impl ToString for RefreshTokenRotateError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotFound => "Refresh token not found".to_string(),
+      Self::Reused => "Refresh token was already used, the login has been revoked".to_string(),
+      Self::Expired => "Refresh token has expired".to_string(),
+      Self::TokenCollision => "Cannot generate a unique token".to_string(),
+      Self::CannotSign(err) => format!("Cannot sign the JWT access token: {}", err)
+    }
+  }
+}
+
+impl RefreshToken {
+  /// ## RefreshToken::issue
+  ///
+  /// Starts a new refresh token family for the user,
+  /// returning a short-lived access token and a refresh token
+  ///
+  /// Errors:
+  /// + when referenced user do not exist
+  /// + when no unique token could be generated
+  /// + when the JWT access token cannot be signed
+  ///
+  pub async fn issue(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    metadata: &SessionMetadata
+  ) -> Result<TokenPair, LoginSessionInsertError> {
+    return Self::issue_scoped(conn, config, user_login, &TokenScope::default(), metadata).await;
+  }
+
+  /// ## RefreshToken::issue_scoped
+  ///
+  /// Starts a new refresh token family issued to an OAuth client,
+  /// every token of the family is limited to the granted scopes
+  ///
+  /// Errors:
+  /// + when referenced user do not exist
+  /// + when no unique token could be generated
+  /// + when the JWT access token cannot be signed
+  ///
+  pub async fn issue_scoped(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    scope: &TokenScope,
+    metadata: &SessionMetadata
+  ) -> Result<TokenPair, LoginSessionInsertError> {
+    let family = generate_token();
+
+    return Self::insert_pair(conn, config, user_login, &family, Utc::now(), scope, metadata).await;
+  }
+
+  /// ## RefreshToken::rotate
+  ///
+  /// Exchanges a refresh token for a new token pair of the same family.
+  /// Presenting an already used refresh token revokes the whole family,
+  /// as it means the token was leaked.
+  /// Families issued to an OAuth client can only be rotated by the same client
+  ///
+  /// Errors:
+  /// + when the refresh token do not exist or belongs to another client
+  /// + when the refresh token was already used
+  /// + when the family has expired
+  /// + when no unique token could be generated
+  ///
+  pub async fn rotate(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    refresh_token: &String,
+    client_id: Option<&String>,
+    metadata: &SessionMetadata
+  ) -> Result<TokenPair, RefreshTokenRotateError> {
+    let sql = "SELECT * FROM refresh_tokens WHERE token_hash = $1;";
+    let result = query_as(sql)
+      .bind(hash_token(refresh_token, &config.session_pepper))
+      .fetch_one(&mut *conn)
+      .await;
+
+    let current: RefreshToken = match result {
+      Ok(current) => current,
+      Err(_) => return Err(RefreshTokenRotateError::NotFound)
+    };
+
+    if current.client_id.as_ref() != client_id {
+      return Err(RefreshTokenRotateError::NotFound);
+    }
+
+    if current.used {
+      Self::revoke_family(conn, &current.family).await;
+
+      return Err(RefreshTokenRotateError::Reused);
+    }
+
+    if current.is_expired(config) {
+      Self::revoke_family(conn, &current.family).await;
+
+      return Err(RefreshTokenRotateError::Expired);
+    }
+
+    // the token is only marked once when rotated concurrently, the later rotation is handled as reuse
+    let sql = "UPDATE refresh_tokens SET used = TRUE WHERE id = $1 AND used = FALSE RETURNING id;";
+    let marked: Option<(i32,)> = query_as(sql)
+      .bind(&current.id)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap_or(None);
+
+    if marked.is_none() {
+      Self::revoke_family(conn, &current.family).await;
+
+      return Err(RefreshTokenRotateError::Reused);
+    }
+
+    // the previous access token is replaced by the new one
+    let sql = "DELETE FROM login_sessions WHERE refresh_family = $1;";
+    let _ = query(sql)
+      .bind(&current.family)
+      .execute(&mut *conn)
+      .await;
+
+    let scope = TokenScope {
+      client_id: current.client_id.clone(),
+      scopes: current.scopes.clone()
+    };
+
+    let result = Self::insert_pair(
+      conn,
+      config,
+      &current.user_login,
+      &current.family,
+      current.family_created_at,
+      &scope,
+      metadata
+    )
+    .await;
+
+    return match result {
+      Ok(pair) => Ok(pair),
+      Err(err) => match err {
+        LoginSessionInsertError::UserNotFound => Err(RefreshTokenRotateError::NotFound),
+        LoginSessionInsertError::TokenCollision => Err(RefreshTokenRotateError::TokenCollision),
+        LoginSessionInsertError::CannotSign(err) => Err(RefreshTokenRotateError::CannotSign(err))
+      }
+    };
+  }
+
+  /// ## RefreshToken::revoke_family
+  ///
+  /// Deletes every refresh token and login session of the family
+  ///
+  pub async fn revoke_family(
+    conn: &mut PgConnection,
+    family: &String
+  ) {
+    let sql = "DELETE FROM refresh_tokens WHERE family = $1;";
+    let _ = query(sql)
+      .bind(&family)
+      .execute(&mut *conn)
+      .await;
+
+    let sql = "DELETE FROM login_sessions WHERE refresh_family = $1;";
+    let _ = query(sql)
+      .bind(&family)
+      .execute(&mut *conn)
+      .await;
+  }
+
+  /// ## RefreshToken::revoke_by_token
+  ///
+  /// Revokes the whole family of a refresh token issued to specified OAuth client,
+  /// returning whether such token existed
+  ///
+  pub async fn revoke_by_token(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    refresh_token: &String,
+    client_id: &String
+  ) -> bool {
+    let sql = "SELECT family FROM refresh_tokens WHERE token_hash = $1 AND client_id = $2;";
+    let result: Option<(String,)> = query_as(sql)
+      .bind(hash_token(refresh_token, &config.session_pepper))
+      .bind(&client_id)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return match result {
+      Some((family,)) => {
+        Self::revoke_family(conn, &family).await;
+
+        true
+      },
+      None => false
+    };
+  }
+
+  /// ## RefreshToken::is_expired
+  ///
+  /// Checks if the family exceeded the session lifetime
+  /// or the token stayed unused longer than the idle timeout
+  ///
+  pub fn is_expired(self: &Self, config: &CauthConfig) -> bool {
+    let now = Utc::now();
+    let lifetime = Duration::seconds(config.session_lifetime);
+    let idle_timeout = Duration::seconds(config.session_idle_timeout);
+
+    return now - self.family_created_at > lifetime || now - self.created_at > idle_timeout;
+  }
+
+  async fn insert_pair(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    family: &String,
+    family_created_at: DateTime<Utc>,
+    scope: &TokenScope,
+    metadata: &SessionMetadata
+  ) -> Result<TokenPair, LoginSessionInsertError> {
+    let token = LoginSession::insert(
+      conn,
+      config,
+      user_login.to_string(),
+      Some(family),
+      scope,
+      metadata
+    )
+    .await?;
+
+    let sql = "
+      INSERT INTO
+        refresh_tokens (family, user_login, token_hash, family_created_at, client_id, scopes)
+      VALUES
+        ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT (token_hash) DO NOTHING
+      RETURNING id;
+    ";
+
+    for _ in 0..TOKEN_INSERT_ATTEMPTS {
+      let refresh_token = generate_token();
+
+      let result = query_as(sql)
+        .bind(&family)
+        .bind(&user_login)
+        .bind(hash_token(&refresh_token, &config.session_pepper))
+        .bind(&family_created_at)
+        .bind(&scope.client_id)
+        .bind(&scope.scopes)
+        .fetch_optional(&mut *conn)
+        .await;
+
+      let row: Option<(i32,)> = match result {
+        Ok(row) => row,
+        Err(_) => return Err(LoginSessionInsertError::UserNotFound)
+      };
+
+      // no row is returned when the token is already taken
+      if row.is_none() {
+        continue;
+      }
+
+      let jwt = if config.jwt_enabled {
+        match SigningKey::sign_access_token(conn, config, user_login, scope.scopes.as_ref()).await {
+          Ok(jwt) => Some(jwt),
+          Err(err) => return Err(LoginSessionInsertError::CannotSign(err.to_string()))
+        }
+      } else {
+        None
+      };
+
+      return Ok(TokenPair {
+        token,
+        refresh_token,
+        expires_in: config.access_token_lifetime,
+        jwt
+      });
+    }
+
+    return Err(LoginSessionInsertError::TokenCollision);
+  }
+}
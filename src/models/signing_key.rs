@@ -0,0 +1,325 @@
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use base64::{
+  engine::general_purpose::URL_SAFE_NO_PAD,
+  Engine
+};
+use jsonwebtoken::{
+  encode,
+  Algorithm,
+  EncodingKey,
+  Header
+};
+use ring::{
+  rand::SystemRandom,
+  signature::{
+    Ed25519KeyPair,
+    KeyPair
+  }
+};
+use serde::{
+  Deserialize,
+  Serialize
+};
+use serde_json::{
+  json,
+  Value
+};
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  models::user::User,
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    token::generate_token
+  }
+};
+
+/// Ed25519 key used to sign JWT access tokens and ID tokens
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct SigningKey {
+  pub id: i32,
+  pub kid: String,
+  pub private_key: Vec<u8>,
+  pub public_key: Vec<u8>,
+  pub created_at: DateTime<Utc>,
+  pub retired_at: Option<DateTime<Utc>>
+}
+
+/// Claims of a JWT access token
+#[derive(Deserialize, Serialize)]
+pub struct AccessTokenClaims {
+  pub iss: String,
+  /// login of the user
+  pub sub: String,
+  pub iat: i64,
+  pub exp: i64,
+  pub groups: Vec<String>,
+  /// every permission the user has through it's groups
+  pub permissions: Vec<String>
+}
+
+#[derive(Debug)]
+pub enum SigningKeyError {
+  /// Returned when a new key pair cannot be generated or stored
+  CannotGenerate(String),
+  /// Returned when the token cannot be signed
+  CannotSign(String),
+  /// Returned when the user the token is signed for do not exist
+  UserNotFound
+}
+
+impl ToString for SigningKeyError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::CannotGenerate(err) => format!("Cannot generate a signing key: {}", err),
+      Self::CannotSign(err) => format!("Cannot sign the token: {}", err),
+      Self::UserNotFound => "Mentioned user not found".to_string()
+    }
+  }
+}
+
+impl SigningKey {
+  /// ## SigningKey::active
+  ///
+  /// Retrieves the newest key that wasn't retired, generating one when there is none
+  ///
+  pub async fn active(
+    conn: &mut PgConnection
+  ) -> Result<Self, SigningKeyError> {
+    let sql = "SELECT * FROM signing_keys WHERE retired_at IS NULL ORDER BY created_at DESC LIMIT 1;";
+    let result: Option<Self> = query_as(sql)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return match result {
+      Some(key) => Ok(key),
+      None => Self::generate(conn).await
+    };
+  }
+
+  /// ## SigningKey::generate
+  ///
+  /// Generates a new Ed25519 key pair and stores it in the database
+  ///
+  pub async fn generate(
+    conn: &mut PgConnection
+  ) -> Result<Self, SigningKeyError> {
+    let document = match Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()) {
+      Ok(document) => document,
+      Err(err) => return Err(SigningKeyError::CannotGenerate(err.to_string()))
+    };
+    let key_pair = match Ed25519KeyPair::from_pkcs8(document.as_ref()) {
+      Ok(key_pair) => key_pair,
+      Err(err) => return Err(SigningKeyError::CannotGenerate(err.to_string()))
+    };
+
+    let sql = "
+      INSERT INTO
+        signing_keys (kid, private_key, public_key)
+      VALUES
+        ($1, $2, $3)
+      RETURNING *;
+    ";
+    let result = query_as(sql)
+      .bind(generate_token())
+      .bind(document.as_ref())
+      .bind(key_pair.public_key().as_ref())
+      .fetch_one(&mut *conn)
+      .await;
+
+    let key: Self = match result {
+      Ok(key) => key,
+      Err(err) => {
+        log_database_interaction(
+          "Inserting a signing key into database.",
+          json!({}),
+          DatabaseOperationLogStatus::Err(err.to_string())
+        );
+
+        return Err(SigningKeyError::CannotGenerate(err.to_string()));
+      }
+    };
+
+    log_database_interaction::<String>(
+      "Inserting a signing key into database.",
+      json!({ "kid": key.kid }),
+      DatabaseOperationLogStatus::Ok
+    );
+
+    return Ok(key);
+  }
+
+  /// ## SigningKey::rotate
+  ///
+  /// Retires every active key and generates a new one.
+  /// Retired keys stay published for the grace period set in the config,
+  /// so tokens signed with them can still be verified
+  ///
+  pub async fn rotate(
+    conn: &mut PgConnection
+  ) -> Result<Self, SigningKeyError> {
+    let sql = "UPDATE signing_keys SET retired_at = NOW() WHERE retired_at IS NULL;";
+    let _ = query(sql)
+      .execute(&mut *conn)
+      .await;
+
+    return Self::generate(conn).await;
+  }
+
+  /// ## SigningKey::published
+  ///
+  /// Lists the keys tokens can be verified with,
+  /// e.g. the active keys and the keys still in their grace period
+  ///
+  pub async fn published(
+    conn: &mut PgConnection,
+    config: &CauthConfig
+  ) -> Vec<Self> {
+    let grace_period_start = Utc::now() - Duration::seconds(config.jwt_key_grace_period);
+
+    let sql = "
+      SELECT
+        *
+      FROM
+        signing_keys
+      WHERE
+        retired_at IS NULL
+        OR
+        retired_at > $1
+      ORDER BY
+        created_at DESC;
+    ";
+    let keys = query_as(sql)
+      .bind(&grace_period_start)
+      .fetch_all(&mut *conn)
+      .await
+      .unwrap();
+
+    return keys;
+  }
+
+  /// ## SigningKey::to_jwk
+  ///
+  /// Formats the public key as a JSON Web Key
+  ///
+  pub fn to_jwk(self: &Self) -> Value {
+    return json!({
+      "kty": "OKP",
+      "crv": "Ed25519",
+      "alg": "EdDSA",
+      "use": "sig",
+      "kid": self.kid,
+      "x": URL_SAFE_NO_PAD.encode(&self.public_key)
+    });
+  }
+
+  /// ## SigningKey::sign_access_token
+  ///
+  /// Signs a JWT access token of the user with the active key,
+  /// embedding the user's groups and effective permissions,
+  /// narrowed down to the scopes the access token is limited to.
+  /// The token expires together with the access token it's issued with
+  ///
+  /// Errors:
+  /// + when the user do not exist
+  /// + when the key cannot be generated or the token cannot be signed
+  ///
+  pub async fn sign_access_token(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    scopes: Option<&Vec<String>>
+  ) -> Result<String, SigningKeyError> {
+    let user = match User::retrieve(conn, user_login).await {
+      Ok(user) => user,
+      Err(_) => return Err(SigningKeyError::UserNotFound)
+    };
+    let key = Self::active(conn).await?;
+
+    let now = Utc::now().timestamp();
+    let claims = AccessTokenClaims {
+      iss: config.jwt_issuer.clone(),
+      sub: user.login.clone(),
+      iat: now,
+      exp: now + config.access_token_lifetime,
+      groups: user.groups(conn).await,
+      permissions: match user.is_restricted(config) {
+        true => vec![],
+        false => user
+          .permissions(conn)
+          .await
+          .into_iter()
+          .filter(|permission| match scopes {
+            Some(scopes) => scopes.contains(permission),
+            None => true
+          })
+          .collect()
+      }
+    };
+
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = Some(key.kid.clone());
+
+    return match encode(&header, &claims, &EncodingKey::from_ed_der(&key.private_key)) {
+      Ok(token) => Ok(token),
+      Err(err) => Err(SigningKeyError::CannotSign(err.to_string()))
+    };
+  }
+
+  /// ## SigningKey::sign_id_token
+  ///
+  /// Signs an OpenID Connect ID token of the user for the client with the active key,
+  /// carrying the claims of the granted scopes and the nonce of the authentication request.
+  /// The token expires together with the access token it's issued with
+  ///
+  /// Errors:
+  /// + when the user do not exist
+  /// + when the key cannot be generated or the token cannot be signed
+  ///
+  pub async fn sign_id_token(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    client_id: &String,
+    nonce: Option<&String>,
+    scopes: &Vec<String>
+  ) -> Result<String, SigningKeyError> {
+    let user = match User::retrieve(conn, user_login).await {
+      Ok(user) => user,
+      Err(_) => return Err(SigningKeyError::UserNotFound)
+    };
+    let key = Self::active(conn).await?;
+
+    let now = Utc::now().timestamp();
+    let mut claims = user.openid_claims(conn, config, scopes).await;
+    claims.insert("iss".to_string(), json!(config.jwt_issuer));
+    claims.insert("aud".to_string(), json!(client_id));
+    claims.insert("iat".to_string(), json!(now));
+    claims.insert("exp".to_string(), json!(now + config.access_token_lifetime));
+
+    if let Some(nonce) = nonce {
+      claims.insert("nonce".to_string(), json!(nonce));
+    }
+
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = Some(key.kid.clone());
+
+    return match encode(&header, &claims, &EncodingKey::from_ed_der(&key.private_key)) {
+      Ok(token) => Ok(token),
+      Err(err) => Err(SigningKeyError::CannotSign(err.to_string()))
+    };
+  }
+}
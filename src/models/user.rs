@@ -1,30 +1,59 @@
 use crate::{
+    config::{CauthConfig, LdapConfig},
     models::{
         login_session::{
             LoginSession, LoginSessionDeleteError, LoginSessionInsertError,
-            LoginSessionRetrieveError,
+            LoginSessionRetrieveError, SessionMetadata,
         },
+        refresh_token::{RefreshToken, TokenPair},
+        recovery_code::RecoveryCode,
+        user_totp::{UserTotp, UserTotpVerifyError},
         Order,
     },
-    util::logging::{log_database_interaction, DatabaseOperationLogStatus},
+    util::{
+        ldap::{self, LdapBindError},
+        logging::{log_database_interaction, DatabaseOperationLogStatus},
+        password::{password_hash_format, verify_password_hash},
+    },
 };
 use crate::{util::string::json_value_to_pretty_string, web::ServerResponse};
 use actix_web::http::StatusCode;
 use argon2::{
-    password_hash::{self, rand_core::OsRng, SaltString},
-    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{rand_core::OsRng, SaltString},
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use sqlx::{prelude::FromRow, query, query_as, PgConnection};
+use std::collections::HashMap;
 
 use super::Group;
 
+/// OpenID Connect scopes, granted on top of the permissions a client asks for
+pub const OPENID_SCOPES: [&str; 4] = ["openid", "profile", "email", "groups"];
+
+/// Claims of the ID token that cannot be overwritten by the user details
+const RESERVED_CLAIMS: [&str; 9] = [
+    "iss",
+    "sub",
+    "aud",
+    "exp",
+    "iat",
+    "nonce",
+    "auth_time",
+    "preferred_username",
+    "email_verified",
+];
+
 #[derive(FromRow, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct User {
     pub login: String,
     pub password_hash: String,
     pub details: Value,
+    /// Service accounts cannot log in with a password, they authenticate with API keys only
+    pub service_account: bool,
+    /// Whether the user proved owning the email of their details
+    pub email_verified: bool,
 }
 
 impl ToString for User {
@@ -65,17 +94,43 @@ pub enum UserInsertError {
     NameError,
     /// Returned when the provided password cannot be hashed
     CannotHash(String),
+    /// Returned when the provided password hash is malformed or its format is not supported
+    UnsupportedHash,
+    /// Returned when the database refuses the user for another reason
+    CannotInsert(String),
 }
 
 impl ToString for UserInsertError {
     fn to_string(&self) -> String {
         return match self {
       Self::NameError => "Either the provided login is too long, this user already exist or one of the provided groups do not exist.".to_string(),
-      Self::CannotHash(err) => format!("Password hashing error: {}.", err)
+      Self::CannotHash(err) => format!("Password hashing error: {}.", err),
+      Self::UnsupportedHash => "The password hash is malformed or its format is not supported, use argon2, bcrypt, scrypt or PBKDF2-SHA256.".to_string(),
+      Self::CannotInsert(err) => format!("The user cannot be stored: {}.", err)
     };
     }
 }
 
+#[derive(Debug)]
+pub enum UserUpdatePasswordError {
+    /// Returned when the user with specified login do not exist
+    NotFound,
+    /// Returned when the provided password cannot be hashed
+    CannotHash(String),
+    /// Returned when the database refuses the new password hash
+    CannotUpdate(String),
+}
+
+impl ToString for UserUpdatePasswordError {
+    fn to_string(&self) -> String {
+        return match self {
+            Self::NotFound => "This user cannot be found".to_string(),
+            Self::CannotHash(err) => format!("Password hashing error: {}.", err),
+            Self::CannotUpdate(err) => format!("The password cannot be stored: {}.", err),
+        };
+    }
+}
+
 pub enum UserDeleteError {
     /// Returned when the user with specified login do not exist
     NotFound,
@@ -100,8 +155,14 @@ pub enum UserLoginError {
     NotFound,
     /// Returned when the credentials are invalid
     InvalidCredentials,
-    /// Returned when the token hash cannot be created
-    CannotHash(String),
+    /// Returned when no unique session token could be generated
+    TokenCollision,
+    /// Returned when the JWT access token cannot be signed
+    CannotSign(String),
+    /// Returned when the user has two-factor authentication enabled and no code was provided
+    MfaRequired,
+    /// Returned when the provided two-factor authentication code is invalid
+    InvalidMfaCode,
 }
 
 pub enum UserGrantError {
@@ -146,6 +207,23 @@ pub enum UserVerifyPasswordError {
     Unauthorized,
 }
 
+#[derive(Debug)]
+pub enum UserSecondFactorError {
+    /// Returned when the user has two-factor authentication enabled and no code was provided
+    Required,
+    /// Returned when the provided code is invalid or was already used
+    Invalid,
+}
+
+impl ToString for UserSecondFactorError {
+    fn to_string(&self) -> String {
+        return match self {
+            Self::Required => "A two-factor authentication code is required".to_string(),
+            Self::Invalid => "The two-factor authentication code is invalid".to_string(),
+        };
+    }
+}
+
 impl User {
     /// ## User::list
     ///
@@ -201,11 +279,12 @@ impl User {
     ///
     pub async fn insert(
         conn: &mut PgConnection,
+        config: &CauthConfig,
         login: &String,
         password: &String,
         details: &Value,
     ) -> Result<(), UserInsertError> {
-        let password_hash = match hash_password(password.to_string()) {
+        let password_hash = match hash_password(password.to_string(), &config.argon2_params) {
             Ok(hash) => hash,
             Err(err) => {
                 log_database_interaction(
@@ -221,12 +300,33 @@ impl User {
         return Self::insert_unhashed(conn, login, &password_hash, details).await;
     }
 
+    /// ## User::insert_unhashed
+    ///
+    /// Inserts a user with an already hashed password, e.g. a user imported from another application.
+    /// Besides argon2, bcrypt, scrypt and PBKDF2-SHA256 hashes are accepted,
+    /// they are replaced by argon2 hashes when their users log in
+    ///
+    /// Errors:
+    /// + when the hash is malformed or its format is not supported
+    /// + when a user with provided login already exist
+    /// + when the login is longer than 255 chars
+    ///
     pub async fn insert_unhashed(
         conn: &mut PgConnection,
         login: &String,
         password: &String,
         details: &Value,
     ) -> Result<(), UserInsertError> {
+        if password_hash_format(password).is_none() {
+            log_database_interaction(
+                "Inserting a user into database.",
+                json!({ "login": login, "details": details }),
+                DatabaseOperationLogStatus::Err("Password hash format is not supported."),
+            );
+
+            return Err(UserInsertError::UnsupportedHash);
+        }
+
         let sql = "
       INSERT INTO
         users (login, password_hash, details)
@@ -244,7 +344,7 @@ impl User {
 
         match result {
             Ok(_) => (),
-            Err(_) => {
+            Err(err) if is_name_error(&err) => {
                 log_database_interaction(
                     "Inserting a user into database.",
                     json!({ "login": login, "details": details }),
@@ -253,6 +353,15 @@ impl User {
 
                 return Err(UserInsertError::NameError);
             }
+            Err(err) => {
+                log_database_interaction(
+                    "Inserting a user into database.",
+                    json!({ "login": login, "details": details }),
+                    DatabaseOperationLogStatus::Err(err.to_string()),
+                );
+
+                return Err(UserInsertError::CannotInsert(err.to_string()));
+            }
         };
 
         log_database_interaction::<String>(
@@ -264,6 +373,307 @@ impl User {
         return Ok(());
     }
 
+    /// ## User::insert_service_account
+    ///
+    /// Inserts a service account, e.g. a user for machine clients
+    /// which has no password and authenticates with API keys only
+    ///
+    /// Errors:
+    /// + when a user with provided login already exist
+    /// + when the login is longer than 255 chars
+    ///
+    pub async fn insert_service_account(
+        conn: &mut PgConnection,
+        login: &String,
+        details: &Value,
+    ) -> Result<(), UserInsertError> {
+        let sql = "
+      INSERT INTO
+        users (login, password_hash, details, service_account)
+      VALUES
+        ($1, '', $2, TRUE)
+      ;
+    ";
+
+        let result = query(sql)
+            .bind(&login)
+            .bind(&details)
+            .execute(&mut *conn)
+            .await;
+
+        match result {
+            Ok(_) => (),
+            Err(_) => {
+                log_database_interaction(
+                    "Inserting a service account into database.",
+                    json!({ "login": login, "details": details }),
+                    DatabaseOperationLogStatus::Err("User with this login already exist."),
+                );
+
+                return Err(UserInsertError::NameError);
+            }
+        };
+
+        log_database_interaction::<String>(
+            "Inserting a service account into database.",
+            json!({ "login": login, "details": details }),
+            DatabaseOperationLogStatus::Ok,
+        );
+
+        return Ok(());
+    }
+
+    /// ## User::insert_federated
+    ///
+    /// Inserts a user provisioned by an upstream identity provider,
+    /// which has no password and signs in through the provider only
+    ///
+    /// Errors:
+    /// + when a user with provided login already exist
+    /// + when the login is longer than 255 chars
+    ///
+    pub async fn insert_federated(
+        conn: &mut PgConnection,
+        login: &String,
+        details: &Value,
+    ) -> Result<(), UserInsertError> {
+        let sql = "
+      INSERT INTO
+        users (login, password_hash, details)
+      VALUES
+        ($1, '', $2)
+      ;
+    ";
+
+        let result = query(sql)
+            .bind(&login)
+            .bind(&details)
+            .execute(&mut *conn)
+            .await;
+
+        match result {
+            Ok(_) => (),
+            Err(_) => {
+                log_database_interaction(
+                    "Inserting a federated user into database.",
+                    json!({ "login": login, "details": details }),
+                    DatabaseOperationLogStatus::Err("User with this login already exist."),
+                );
+
+                return Err(UserInsertError::NameError);
+            }
+        };
+
+        log_database_interaction::<String>(
+            "Inserting a federated user into database.",
+            json!({ "login": login, "details": details }),
+            DatabaseOperationLogStatus::Ok,
+        );
+
+        return Ok(());
+    }
+
+    /// ## User::update_details
+    ///
+    /// Replaces the details of a user with specified login,
+    /// the email has to be verified again when it changes
+    ///
+    pub async fn update_details(conn: &mut PgConnection, login: &String, details: &Value) {
+        let sql = "
+      UPDATE
+        users
+      SET
+        details = $2,
+        email_verified = email_verified AND details->>'email' IS NOT DISTINCT FROM $2->>'email'
+      WHERE
+        login = $1;
+    ";
+        let _ = query(sql)
+            .bind(&login)
+            .bind(&details)
+            .execute(&mut *conn)
+            .await;
+    }
+
+    /// ## User::update_password
+    ///
+    /// Replaces the password of a user with specified login, hashing the new one
+    ///
+    /// Errors:
+    /// + when the user do not exist
+    /// + when the password cannot be hashed
+    ///
+    pub async fn update_password(
+        conn: &mut PgConnection,
+        config: &CauthConfig,
+        login: &String,
+        password: &String,
+    ) -> Result<(), UserUpdatePasswordError> {
+        let password_hash = match hash_password(password.to_string(), &config.argon2_params) {
+            Ok(hash) => hash,
+            Err(err) => {
+                log_database_interaction(
+                    "Updating the password of a user.",
+                    json!({ "login": login }),
+                    DatabaseOperationLogStatus::Err("Password cannot be hashed."),
+                );
+
+                return Err(UserUpdatePasswordError::CannotHash(err.to_string()));
+            }
+        };
+
+        return Self::update_password_unhashed(conn, login, &password_hash).await;
+    }
+
+    pub async fn update_password_unhashed(
+        conn: &mut PgConnection,
+        login: &String,
+        password_hash: &String,
+    ) -> Result<(), UserUpdatePasswordError> {
+        let sql = "UPDATE users SET password_hash = $2 WHERE login = $1 AND NOT service_account;";
+        let result = query(sql)
+            .bind(&login)
+            .bind(&password_hash)
+            .execute(&mut *conn)
+            .await;
+
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                log_database_interaction(
+                    "Updating the password of a user.",
+                    json!({ "login": login }),
+                    DatabaseOperationLogStatus::Err(err.to_string()),
+                );
+
+                return Err(UserUpdatePasswordError::CannotUpdate(err.to_string()));
+            }
+        };
+
+        if result.rows_affected() == 0 {
+            log_database_interaction(
+                "Updating the password of a user.",
+                json!({ "login": login }),
+                DatabaseOperationLogStatus::Err("User with this login do not exist."),
+            );
+
+            return Err(UserUpdatePasswordError::NotFound);
+        }
+
+        log_database_interaction::<String>(
+            "Updating the password of a user.",
+            json!({ "login": login }),
+            DatabaseOperationLogStatus::Ok,
+        );
+
+        return Ok(());
+    }
+
+    /// ## User::merge_details
+    ///
+    /// Sets specified members of the user details, keeping the other ones
+    ///
+    pub async fn merge_details(self: &Self, conn: &mut PgConnection, values: Map<String, Value>) {
+        if values.is_empty() {
+            return;
+        }
+
+        let mut details = match &self.details {
+            Value::Object(details) => details.clone(),
+            _ => Map::new(),
+        };
+
+        details.extend(values);
+
+        Self::update_details(conn, &self.login, &Value::Object(details)).await;
+    }
+
+    /// ## User::sync_mapped_groups
+    ///
+    /// Grants the groups mapped from the external groups of the user
+    /// and revokes the mapped ones the user is no longer a member of externally.
+    /// Groups that are not a target of the mapping are left untouched
+    ///
+    pub async fn sync_mapped_groups(
+        self: &Self,
+        conn: &mut PgConnection,
+        mapping: &HashMap<String, String>,
+        external_groups: &Vec<String>,
+    ) {
+        let current_groups = self.groups(conn).await;
+
+        let mut managed_groups = mapping.values().collect::<Vec<_>>();
+        managed_groups.sort();
+        managed_groups.dedup();
+
+        for group in managed_groups {
+            let granted = mapping
+                .iter()
+                .any(|(external, local)| local == group && external_groups.contains(external));
+
+            match (granted, current_groups.contains(group)) {
+                (true, false) => {
+                    let _ = Self::grant_group(conn, &self.login, group).await;
+                }
+                (false, true) => {
+                    let _ = Self::revoke_group(conn, &self.login, group).await;
+                }
+                _ => (),
+            };
+        }
+    }
+
+    /// ## User::list_by_email
+    ///
+    /// Selects the users with specified email in their details, except service accounts
+    ///
+    pub async fn list_by_email(conn: &mut PgConnection, email: &String) -> Vec<Self> {
+        let sql = "SELECT * FROM users WHERE details->>'email' = $1 AND NOT service_account ORDER BY login;";
+        let users = query_as(sql)
+            .bind(&email)
+            .fetch_all(&mut *conn)
+            .await
+            .unwrap();
+
+        return users;
+    }
+
+    /// ## User::verify_email
+    ///
+    /// Marks the email of a user as verified, unless the user no longer has this email.
+    /// Returns whether the email was verified
+    ///
+    pub async fn verify_email(conn: &mut PgConnection, login: &String, email: &String) -> bool {
+        let sql = "UPDATE users SET email_verified = TRUE WHERE login = $1 AND details->>'email' = $2;";
+        let result = query(sql)
+            .bind(&login)
+            .bind(&email)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        if result.rows_affected() == 0 {
+            return false;
+        }
+
+        log_database_interaction::<String>(
+            "Verifying the email of a user.",
+            json!({ "login": login }),
+            DatabaseOperationLogStatus::Ok,
+        );
+
+        return true;
+    }
+
+    /// ## User::is_restricted
+    ///
+    /// Checks if the user is denied every permission because the config requires a verified email
+    /// and the user has not verified theirs. Service accounts are never restricted
+    ///
+    pub fn is_restricted(self: &Self, config: &CauthConfig) -> bool {
+        return config.require_verified_email && !self.service_account && !self.email_verified;
+    }
+
     /// ## User::delete
     ///
     /// Deletes a user and all of it's related data from the database
@@ -275,6 +685,42 @@ impl User {
         let sql = "DELETE FROM login_sessions WHERE user_login = $1";
         let _ = query(sql).bind(&login).execute(&mut *conn).await;
 
+        let sql = "DELETE FROM webauthn_credentials WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM webauthn_challenges WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM recovery_codes WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM user_totp WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM refresh_tokens WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM api_keys WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM oauth_authorization_codes WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM oauth_device_codes WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM federated_identities WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM password_reset_tokens WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM email_verification_tokens WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
+        let sql = "DELETE FROM magic_link_tokens WHERE user_login = $1";
+        let _ = query(sql).bind(&login).execute(&mut *conn).await;
+
         let sql = "DELETE FROM users WHERE login = $1";
         let result = query(sql).bind(&login).execute(&mut *conn).await.unwrap();
 
@@ -299,18 +745,24 @@ impl User {
 
     /// ## User::login
     ///
-    /// Logs in the user with specified session_status, returning the token of created login session
+    /// Logs in the user, returning the access token of created login session and a refresh token.
+    /// Users with two-factor authentication enabled also have to provide a code.
+    /// The client's metadata is stored with the session
     ///
     /// Errors:
     /// + When the user do not exist
     /// + When the credentials are invalid
+    /// + When the second factor is required but missing or invalid
     ///
     pub async fn login(
         conn: &mut PgConnection,
+        config: &CauthConfig,
         login: &String,
         password: &String,
-    ) -> Result<String, UserLoginError> {
-        let result = Self::verify_password(conn, login, password).await;
+        code: Option<&String>,
+        metadata: &SessionMetadata,
+    ) -> Result<TokenPair, UserLoginError> {
+        let result = Self::verify_password(conn, config, login, password).await;
 
         match result {
             Ok(_) => (),
@@ -336,19 +788,44 @@ impl User {
             },
         }
 
-        let result = LoginSession::insert(conn, login.to_string()).await;
+        match Self::verify_second_factor(conn, login, code).await {
+            Ok(_) => (),
+            Err(err) => {
+                log_database_interaction(
+                    "Inserting user login session to the database.",
+                    json!({ "login": login }),
+                    DatabaseOperationLogStatus::Err(err.to_string()),
+                );
+
+                return match err {
+                    UserSecondFactorError::Required => Err(UserLoginError::MfaRequired),
+                    UserSecondFactorError::Invalid => Err(UserLoginError::InvalidMfaCode),
+                };
+            }
+        }
+
+        let result = RefreshToken::issue(conn, config, login, metadata).await;
 
-        let token = match result {
-            Ok(token) => token,
+        let pair = match result {
+            Ok(pair) => pair,
             Err(err) => match err {
-                LoginSessionInsertError::CannotHash(e) => {
+                LoginSessionInsertError::TokenCollision => {
+                    log_database_interaction(
+                        "Inserting user login session to the database.",
+                        json!({ "login": login }),
+                        DatabaseOperationLogStatus::Err("Cannot generate a unique token."),
+                    );
+
+                    return Err(UserLoginError::TokenCollision);
+                }
+                LoginSessionInsertError::CannotSign(e) => {
                     log_database_interaction(
                         "Inserting user login session to the database.",
                         json!({ "login": login }),
-                        DatabaseOperationLogStatus::Err("Cannot hash the token."),
+                        DatabaseOperationLogStatus::Err("Cannot sign the JWT access token."),
                     );
 
-                    return Err(UserLoginError::CannotHash(e));
+                    return Err(UserLoginError::CannotSign(e));
                 }
                 LoginSessionInsertError::UserNotFound => {
                     log_database_interaction(
@@ -368,7 +845,7 @@ impl User {
             DatabaseOperationLogStatus::Ok,
         );
 
-        return Ok(token);
+        return Ok(pair);
     }
 
     /// ## User::has_permission
@@ -410,6 +887,119 @@ impl User {
         return true;
     }
 
+    /// ## User::groups
+    ///
+    /// Lists names of the groups granted to the user
+    ///
+    pub async fn groups(self: &Self, conn: &mut PgConnection) -> Vec<String> {
+        let sql = "SELECT group_name FROM users_groups WHERE user_login = $1 ORDER BY group_name;";
+        let rows: Vec<(String,)> = query_as(sql)
+            .bind(&self.login)
+            .fetch_all(&mut *conn)
+            .await
+            .unwrap();
+
+        return rows.into_iter().map(|(name,)| name).collect();
+    }
+
+    /// ## User::permissions
+    ///
+    /// Lists names of every permission the user has through it's groups
+    ///
+    pub async fn permissions(self: &Self, conn: &mut PgConnection) -> Vec<String> {
+        let sql = "
+      SELECT DISTINCT
+        gp.permission_name
+      FROM
+        users_groups ug
+      INNER JOIN
+        groups_permissions gp
+      ON
+        ug.group_name = gp.group_name
+      WHERE
+        ug.user_login = $1
+      ORDER BY
+        gp.permission_name;
+    ";
+        let rows: Vec<(String,)> = query_as(sql)
+            .bind(&self.login)
+            .fetch_all(&mut *conn)
+            .await
+            .unwrap();
+
+        return rows.into_iter().map(|(name,)| name).collect();
+    }
+
+    /// ## User::grant_scopes
+    ///
+    /// Narrows the scopes a client asks for down to the ones the user can grant,
+    /// e.g. the user's own permissions and the OpenID Connect scopes
+    ///
+    pub async fn grant_scopes(
+        self: &Self,
+        conn: &mut PgConnection,
+        scopes: &Vec<String>,
+    ) -> Vec<String> {
+        let permissions = self.permissions(conn).await;
+
+        return scopes
+            .iter()
+            .filter(|scope| permissions.contains(scope) || OPENID_SCOPES.contains(&scope.as_str()))
+            .cloned()
+            .collect();
+    }
+
+    /// ## User::openid_claims
+    ///
+    /// Builds the OpenID Connect claims of the user for the granted scopes:
+    /// + `sub` and `preferred_username` - the login, always present
+    /// + `profile` - every member of the user details except the email
+    /// + `email` - the `email` member of the user details and whether it's verified
+    /// + `groups` - names of the user's groups, under the claim set in the config
+    ///
+    pub async fn openid_claims(
+        self: &Self,
+        conn: &mut PgConnection,
+        config: &CauthConfig,
+        scopes: &Vec<String>,
+    ) -> Map<String, Value> {
+        let mut claims = Map::new();
+        let has_scope = |scope: &str| scopes.iter().any(|granted| granted == scope);
+        let details = match &self.details {
+            Value::Object(details) => details.clone(),
+            _ => Map::new(),
+        };
+
+        if has_scope("profile") {
+            for (name, value) in details.iter() {
+                if name == "email"
+                    || name == &config.oidc_groups_claim
+                    || RESERVED_CLAIMS.contains(&name.as_str())
+                {
+                    continue;
+                }
+
+                claims.insert(name.clone(), value.clone());
+            }
+        }
+
+        if has_scope("email") {
+            if let Some(email) = details.get("email") {
+                claims.insert("email".to_string(), email.clone());
+                claims.insert("email_verified".to_string(), json!(self.email_verified));
+            }
+        }
+
+        if has_scope("groups") {
+            claims.insert(config.oidc_groups_claim.clone(), json!(self.groups(conn).await));
+        }
+
+        claims.insert("sub".to_string(), json!(self.login));
+        claims.insert("preferred_username".to_string(), json!(self.login));
+
+        return claims;
+    }
+
     /// ## User::grant_group
     ///
     /// Grants user a group with specified name
@@ -527,30 +1117,217 @@ impl User {
     ///
     pub async fn verify_password(
         db_conn: &mut PgConnection,
+        config: &CauthConfig,
+        login: &String,
+        password: &String,
+    ) -> Result<(), UserVerifyPasswordError> {
+        let user = Self::retrieve(db_conn, &login).await.ok();
+
+        if let Some(user) = &user {
+            // service accounts never log in with a password, not even through the directory
+            if user.service_account {
+                return Err(UserVerifyPasswordError::Unauthorized);
+            }
+        }
+
+        if let Some(user) = &user {
+            if user.accepts_local_password(config.ldap.as_ref(), password) {
+                user.rehash_password(db_conn, config, password).await;
+                return Ok(());
+            }
+        }
+
+        let ldap = match &config.ldap {
+            Some(ldap) => ldap,
+            None => {
+                return match &user {
+                    Some(_) => Err(UserVerifyPasswordError::Unauthorized),
+                    None => Err(UserVerifyPasswordError::NotFound),
+                }
+            }
+        };
+
+        // a directory entry sharing the login of a local user, e.g. root, cannot sign in as them
+        if let Some(user) = &user {
+            if !user.accepts_directory_password(ldap) {
+                return Err(UserVerifyPasswordError::Unauthorized);
+            }
+        }
+
+        return Self::verify_ldap_password(db_conn, ldap, user, login, password).await;
+    }
+
+    /// Checks the password against the stored hash, unless the directory replaces the local passwords
+    fn accepts_local_password(self: &Self, ldap: Option<&LdapConfig>, password: &String) -> bool {
+        if ldap.is_some_and(|ldap| ldap.exclusive.unwrap_or(false)) {
+            return false;
+        }
+
+        return self.verify_local_password(password);
+    }
+
+    /// Checks if the user can sign in with the directory: users created by the directory have no local password,
+    /// the others only when the directory replaces the local passwords or is trusted with existing users
+    fn accepts_directory_password(self: &Self, ldap: &LdapConfig) -> bool {
+        return self.password_hash.is_empty()
+            || ldap.exclusive.unwrap_or(false)
+            || ldap.link_existing_users.unwrap_or(false);
+    }
+
+    /// Checks the password against the stored hash, dispatching on its format as imported users keep theirs.
+    /// Users provisioned by an upstream provider or the directory have none
+    fn verify_local_password(self: &Self, password: &String) -> bool {
+        if self.password_hash.is_empty() {
+            return false;
+        }
+
+        return verify_password_hash(&self.password_hash, password);
+    }
+
+    /// Replaces the stored hash with one created with the configured Argon2 parameters
+    /// when it was created with weaker ones, the password has to be verified beforehand
+    async fn rehash_password(
+        self: &Self,
+        db_conn: &mut PgConnection,
+        config: &CauthConfig,
+        password: &String,
+    ) {
+        if !password_needs_rehash(&self.password_hash, &config.argon2_params) {
+            return;
+        }
+
+        let password_hash = match hash_password(password.to_string(), &config.argon2_params) {
+            Ok(hash) => hash,
+            Err(_) => return,
+        };
+
+        // the login goes on with the old hash, it's replaced on a later login
+        if let Err(err) = Self::update_password_unhashed(db_conn, &self.login, &password_hash).await {
+            log::error!("Cannot rehash the password of {}: {}", self.login, err.to_string());
+        }
+    }
+
+    /// Binds to the LDAP directory as the user, creating the user on its first login
+    /// and synchronizing the mapped attributes and groups
+    async fn verify_ldap_password(
+        db_conn: &mut PgConnection,
+        ldap: &LdapConfig,
+        user: Option<Self>,
         login: &String,
         password: &String,
     ) -> Result<(), UserVerifyPasswordError> {
-        let user = match Self::retrieve(db_conn, &login).await {
-            Ok(user) => user,
-            Err(_) => return Err(UserVerifyPasswordError::NotFound),
+        let unauthorized = match &user {
+            Some(_) => UserVerifyPasswordError::Unauthorized,
+            None => UserVerifyPasswordError::NotFound,
         };
 
-        let password_hash =
-            &PasswordHash::parse(user.password_hash.as_str(), password_hash::Encoding::B64)
-                .unwrap();
+        let entry = match ldap::bind(ldap, login, password).await {
+            Ok(entry) => entry,
+            Err(err) => {
+                if let LdapBindError::Unavailable(_) = err {
+                    log::error!("{}", err.to_string());
+                }
+
+                return Err(unauthorized);
+            }
+        };
+
+        let user = match user {
+            Some(user) => user,
+            None => {
+                if !ldap.auto_provision.unwrap_or(true) {
+                    return Err(UserVerifyPasswordError::NotFound);
+                }
+
+                if Self::insert_federated(db_conn, login, &json!({})).await.is_err() {
+                    return Err(UserVerifyPasswordError::NotFound);
+                }
+
+                match Self::retrieve(db_conn, login).await {
+                    Ok(user) => user,
+                    Err(_) => return Err(UserVerifyPasswordError::NotFound),
+                }
+            }
+        };
+
+        if let Some(mapping) = &ldap.attribute_mapping {
+            let mut details = Map::new();
+
+            for (attribute, detail) in mapping {
+                let value = match entry.attributes.get(attribute) {
+                    Some(values) if values.len() == 1 => json!(values[0]),
+                    Some(values) => json!(values),
+                    None => continue,
+                };
+
+                details.insert(detail.clone(), value);
+            }
+
+            user.merge_details(db_conn, details).await;
+        }
+
+        if let (Some(mapping), Some(_)) = (&ldap.group_mapping, &ldap.group_base_dn) {
+            user.sync_mapped_groups(db_conn, mapping, &entry.groups).await;
+        }
+
+        return Ok(());
+    }
+
+    /// ## User::verify_second_factor
+    ///
+    /// Checks the two-factor authentication code of a user,
+    /// passing when the user has no second factor enabled.
+    /// A recovery code is accepted in place of the TOTP code and can only be used once
+    ///
+    /// Errors:
+    /// + when the second factor is enabled and no code was provided
+    /// + when the code is invalid or was already used
+    ///
+    pub async fn verify_second_factor(
+        db_conn: &mut PgConnection,
+        login: &String,
+        code: Option<&String>,
+    ) -> Result<(), UserSecondFactorError> {
+        if !UserTotp::is_enabled(db_conn, login).await {
+            return Ok(());
+        }
+
+        let code = match code {
+            Some(code) => code,
+            None => return Err(UserSecondFactorError::Required),
+        };
 
-        return match Argon2::default().verify_password(password.as_bytes(), password_hash) {
+        return match UserTotp::verify(db_conn, login, code).await {
             Ok(_) => Ok(()),
-            Err(_) => return Err(UserVerifyPasswordError::Unauthorized),
+            Err(err) => match err {
+                UserTotpVerifyError::NotEnrolled => Ok(()),
+                UserTotpVerifyError::InvalidCode => {
+                    if RecoveryCode::redeem(db_conn, login, code).await {
+                        return Ok(());
+                    }
+
+                    Err(UserSecondFactorError::Invalid)
+                }
+            },
         };
     }
 }
 
-pub fn hash_password(password: String) -> Result<String, String> {
+/// Checks if the database refused a user because of its login,
+/// e.g. the login is taken (unique_violation) or too long (string_data_right_truncation)
+fn is_name_error(err: &sqlx::Error) -> bool {
+    return err
+        .as_database_error()
+        .and_then(|err| err.code())
+        .is_some_and(|code| code == "23505" || code == "22001");
+}
+
+pub fn hash_password(password: String, params: &Params) -> Result<String, String> {
     let pwd = password.as_bytes();
     let salt = SaltString::generate(&mut OsRng);
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone());
 
-    let password_hash = match Argon2::default().hash_password(pwd, &salt) {
+    let password_hash = match argon2.hash_password(pwd, &salt) {
         Ok(hash) => hash,
         Err(err) => return Err(err.to_string()),
     }
@@ -558,3 +1335,146 @@ pub fn hash_password(password: String) -> Result<String, String> {
 
     return Ok(password_hash);
 }
+
+/// ## password_needs_rehash
+///
+/// Checks if the hash was created with another algorithm or version than Argon2id v19,
+/// or with any cost parameter lower than the specified ones
+///
+pub fn password_needs_rehash(password_hash: &str, params: &Params) -> bool {
+    let password_hash = match PasswordHash::new(password_hash) {
+        Ok(hash) => hash,
+        Err(_) => return true,
+    };
+
+    if password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    return match Params::try_from(&password_hash) {
+        Ok(current) => {
+            current.m_cost() < params.m_cost()
+                || current.t_cost() < params.t_cost()
+                || current.p_cost() < params.p_cost()
+        }
+        Err(_) => true,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(password: &str) -> User {
+        let params = Params::new(256, 1, 1, None).unwrap();
+
+        return User {
+            login: "alice".to_string(),
+            password_hash: hash_password(password.to_string(), &params).unwrap(),
+            details: json!({}),
+            service_account: false,
+            email_verified: false,
+        };
+    }
+
+    fn ldap(exclusive: Option<bool>) -> LdapConfig {
+        return ldap_linking(exclusive, None);
+    }
+
+    fn ldap_linking(exclusive: Option<bool>, link_existing_users: Option<bool>) -> LdapConfig {
+        return serde_json::from_value(json!({
+            "url": "ldap://127.0.0.1:389",
+            "bind_dn_templates": ["uid={login},ou=people,dc=example,dc=com"],
+            "exclusive": exclusive,
+            "link_existing_users": link_existing_users,
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn accepts_local_password_without_directory() {
+        let user = user("local password");
+
+        assert!(user.accepts_local_password(None, &"local password".to_string()));
+        assert!(!user.accepts_local_password(None, &"wrong password".to_string()));
+    }
+
+    #[test]
+    fn tries_local_password_before_shared_directory() {
+        let user = user("local password");
+
+        for exclusive in [None, Some(false)] {
+            assert!(user.accepts_local_password(Some(&ldap(exclusive)), &"local password".to_string()));
+            assert!(!user.accepts_local_password(Some(&ldap(exclusive)), &"wrong password".to_string()));
+        }
+    }
+
+    #[test]
+    fn ignores_local_password_in_exclusive_mode() {
+        let user = user("local password");
+
+        assert!(!user.accepts_local_password(Some(&ldap(Some(true))), &"local password".to_string()));
+    }
+
+    #[test]
+    fn provisioned_users_have_no_local_password() {
+        let mut user = user("local password");
+        user.password_hash = String::new();
+
+        assert!(!user.accepts_local_password(None, &String::new()));
+    }
+
+    #[test]
+    fn local_users_do_not_fall_back_to_directory() {
+        let user = user("local password");
+
+        assert!(!user.accepts_directory_password(&ldap_linking(None, None)));
+        assert!(!user.accepts_directory_password(&ldap_linking(Some(false), Some(false))));
+    }
+
+    #[test]
+    fn directory_users_sign_in_with_directory() {
+        let mut user = user("local password");
+        user.password_hash = String::new();
+
+        assert!(user.accepts_directory_password(&ldap_linking(None, None)));
+    }
+
+    #[test]
+    fn local_users_use_directory_when_opted_in() {
+        let user = user("local password");
+
+        assert!(user.accepts_directory_password(&ldap_linking(None, Some(true))));
+        assert!(user.accepts_directory_password(&ldap_linking(Some(true), None)));
+    }
+
+    /// scrypt hash with a 64 byte digest, longer than the 100 characters the column used to hold
+    const LONG_SCRYPT_HASH: &str = "$scrypt$ln=10,r=8,p=1$MDEyMzQ1Njc4OWFiY2RlZg$6g3umF+uVrJsObaTZhIbbTlgrvOEFcCItdwSjtPF67O6y9Bv3Wwmz9isfvUwH/G4zXJYS65bOgwHS3m025dyrg";
+
+    #[tokio::test]
+    #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+    async fn inserts_user_with_long_imported_hash() {
+        let config = CauthConfig::for_tests().await;
+        let mut conn = config.db_conn.acquire().await.unwrap();
+        let login = "alice".to_string();
+
+        User::insert_unhashed(&mut conn, &login, &LONG_SCRYPT_HASH.to_string(), &json!({}))
+            .await
+            .unwrap();
+
+        let user = User::retrieve(&mut conn, &login).await.ok().unwrap();
+        assert_eq!(user.password_hash, LONG_SCRYPT_HASH);
+        assert!(user.verify_local_password(&"correct horse".to_string()));
+
+        assert!(matches!(
+            User::insert_unhashed(&mut conn, &login, &LONG_SCRYPT_HASH.to_string(), &json!({})).await,
+            Err(UserInsertError::NameError)
+        ));
+        assert!(matches!(
+            User::insert_unhashed(&mut conn, &"a".repeat(256), &LONG_SCRYPT_HASH.to_string(), &json!({})).await,
+            Err(UserInsertError::NameError)
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use chrono::{
+  DateTime,
+  Utc
+};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  models::recovery_code::{
+    RecoveryCode,
+    RecoveryCodeGenerateError
+  },
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    totp::{
+      generate_secret,
+      matching_step,
+      provisioning_uri
+    }
+  }
+};
+
+/// TOTP second factor of a user as described in RFC 6238
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct UserTotp {
+  pub user_login: String,
+  pub secret: String,
+  pub confirmed: bool,
+  pub last_used_step: Option<i64>,
+  pub created_at: DateTime<Utc>
+}
+
+/// Secret of a pending enrollment, shown to the user once
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+  pub secret: String,
+  /// `otpauth://` URI to be scanned by the authenticator app
+  pub uri: String
+}
+
+#[derive(Debug)]
+pub enum UserTotpEnrollError {
+  /// Returned when the user already has a confirmed second factor
+  AlreadyEnabled,
+  /// Returned when the user do not exist
+  UserNotFound
+}
+
+impl ToString for UserTotpEnrollError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::AlreadyEnabled => "Two-factor authentication is already enabled".to_string(),
+      Self::UserNotFound => "Mentioned user not found".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum UserTotpVerifyError {
+  /// Returned when the user did not enroll a second factor
+  NotEnrolled,
+  /// Returned when the code is wrong or was already used
+  InvalidCode
+}
+
+impl ToString for UserTotpVerifyError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotEnrolled => "Two-factor authentication is not enrolled".to_string(),
+      Self::InvalidCode => "The code is invalid".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum UserTotpConfirmError {
+  /// Returned when the user did not enroll a second factor
+  NotEnrolled,
+  /// Returned when the code is wrong
+  InvalidCode,
+  /// Returned when the recovery codes cannot be generated
+  CannotGenerateRecoveryCodes(RecoveryCodeGenerateError)
+}
+
+impl ToString for UserTotpConfirmError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotEnrolled => "Two-factor authentication is not enrolled".to_string(),
+      Self::InvalidCode => "The code is invalid".to_string(),
+      Self::CannotGenerateRecoveryCodes(err) => err.to_string()
+    }
+  }
+}
+
+impl UserTotp {
+  /// ## UserTotp::retrieve
+  ///
+  /// Retrieves the second factor of a user, confirmed or not
+  ///
+  pub async fn retrieve(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) -> Option<Self> {
+    let sql = "SELECT * FROM user_totp WHERE user_login = $1;";
+    let result = query_as(sql)
+      .bind(&user_login)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return result;
+  }
+
+  /// ## UserTotp::is_enabled
+  ///
+  /// Checks if the user has a confirmed second factor
+  ///
+  pub async fn is_enabled(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) -> bool {
+    return match Self::retrieve(conn, user_login).await {
+      Some(totp) => totp.confirmed,
+      None => false
+    };
+  }
+
+  /// ## UserTotp::enroll
+  ///
+  /// Generates a new secret for the user, replacing a pending enrollment.
+  /// The second factor is not required until it is confirmed with a code
+  ///
+  /// Errors:
+  /// + when the user already has a confirmed second factor
+  /// + when the user do not exist
+  ///
+  pub async fn enroll(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String
+  ) -> Result<TotpEnrollment, UserTotpEnrollError> {
+    if Self::is_enabled(conn, user_login).await {
+      return Err(UserTotpEnrollError::AlreadyEnabled);
+    }
+
+    let secret = generate_secret();
+
+    let sql = "
+      INSERT INTO
+        user_totp (user_login, secret)
+      VALUES
+        ($1, $2)
+      ON CONFLICT (user_login) DO UPDATE SET
+        secret = EXCLUDED.secret,
+        last_used_step = NULL,
+        created_at = NOW();
+    ";
+    let result = query(sql)
+      .bind(&user_login)
+      .bind(&secret)
+      .execute(&mut *conn)
+      .await;
+
+    if let Err(err) = result {
+      log_database_interaction(
+        "Enrolling a TOTP second factor.",
+        json!({ "login": user_login }),
+        DatabaseOperationLogStatus::Err(err.to_string())
+      );
+
+      return Err(UserTotpEnrollError::UserNotFound);
+    }
+
+    log_database_interaction::<String>(
+      "Enrolling a TOTP second factor.",
+      json!({ "login": user_login }),
+      DatabaseOperationLogStatus::Ok
+    );
+
+    let uri = provisioning_uri(&secret, &config.totp_issuer, user_login);
+
+    return Ok(TotpEnrollment {
+      secret,
+      uri
+    });
+  }
+
+  /// ## UserTotp::confirm
+  ///
+  /// Confirms a pending enrollment with the first code of the authenticator app,
+  /// from then on the code is required to log in.
+  /// Returns a fresh set of recovery codes, shown to the user once
+  ///
+  /// Errors:
+  /// + when the user did not enroll a second factor
+  /// + when the code is invalid
+  /// + when the recovery codes cannot be generated
+  ///
+  pub async fn confirm(
+    conn: &mut PgConnection,
+    user_login: &String,
+    code: &String
+  ) -> Result<Vec<String>, UserTotpConfirmError> {
+    let totp = match Self::retrieve(conn, user_login).await {
+      Some(totp) => totp,
+      None => return Err(UserTotpConfirmError::NotEnrolled)
+    };
+
+    if let Err(_) = totp.accept(conn, code).await {
+      return Err(UserTotpConfirmError::InvalidCode);
+    }
+
+    let sql = "UPDATE user_totp SET confirmed = TRUE WHERE user_login = $1;";
+    let _ = query(sql)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await;
+
+    return match RecoveryCode::generate(conn, user_login).await {
+      Ok(codes) => Ok(codes),
+      Err(err) => Err(UserTotpConfirmError::CannotGenerateRecoveryCodes(err))
+    };
+  }
+
+  /// ## UserTotp::verify
+  ///
+  /// Checks a code against the confirmed second factor of the user
+  ///
+  /// Errors:
+  /// + when the user has no confirmed second factor
+  /// + when the code is invalid or was already used
+  ///
+  pub async fn verify(
+    conn: &mut PgConnection,
+    user_login: &String,
+    code: &String
+  ) -> Result<(), UserTotpVerifyError> {
+    let totp = match Self::retrieve(conn, user_login).await {
+      Some(totp) if totp.confirmed => totp,
+      _ => return Err(UserTotpVerifyError::NotEnrolled)
+    };
+
+    return totp.accept(conn, code).await;
+  }
+
+  /// ## UserTotp::disable
+  ///
+  /// Removes the second factor of the user, confirmed or not, together with the recovery codes
+  ///
+  /// Errors:
+  /// + when the user did not enroll a second factor
+  ///
+  pub async fn disable(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) -> Result<(), UserTotpVerifyError> {
+    let sql = "DELETE FROM user_totp WHERE user_login = $1;";
+    let result = query(sql)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+
+    RecoveryCode::delete_all(conn, user_login).await;
+
+    if result.rows_affected() == 0 {
+      return Err(UserTotpVerifyError::NotEnrolled);
+    }
+
+    return Ok(());
+  }
+
+  /// Checks the code and remembers its time step, rejecting codes of already used steps
+  async fn accept(
+    self: &Self,
+    conn: &mut PgConnection,
+    code: &String
+  ) -> Result<(), UserTotpVerifyError> {
+    let step = match matching_step(&self.secret, code, Utc::now().timestamp()) {
+      Some(step) => step,
+      None => return Err(UserTotpVerifyError::InvalidCode)
+    };
+
+    if self.last_used_step.is_some_and(|last_used_step| step <= last_used_step) {
+      return Err(UserTotpVerifyError::InvalidCode);
+    }
+
+    // checked again in the update, as a concurrent login may have used the step since the retrieval
+    let sql = "
+      UPDATE
+        user_totp
+      SET
+        last_used_step = $1
+      WHERE
+        user_login = $2 AND (last_used_step IS NULL OR last_used_step < $1);
+    ";
+    let result = query(sql)
+      .bind(&step)
+      .bind(&self.user_login)
+      .execute(&mut *conn)
+      .await;
+
+    return match result {
+      Ok(result) if result.rows_affected() > 0 => Ok(()),
+      _ => Err(UserTotpVerifyError::InvalidCode)
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    models::user::User,
+    util::totp::{
+      code_at,
+      TOTP_PERIOD
+    }
+  };
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn accepts_code_of_a_step_once_across_concurrent_logins() {
+    let config = CauthConfig::for_tests().await;
+
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let login = "alice".to_string();
+    User::insert(&mut conn, &config, &login, &"correct horse".to_string(), &json!({})).await.ok().unwrap();
+    let enrollment = UserTotp::enroll(&mut conn, &config, &login).await.ok().unwrap();
+
+    let code = code_at(&enrollment.secret, Utc::now().timestamp() / TOTP_PERIOD).unwrap();
+
+    // both logins retrieved the second factor before either of them used the code
+    let first = UserTotp::retrieve(&mut conn, &login).await.unwrap();
+    let second = UserTotp::retrieve(&mut conn, &login).await.unwrap();
+
+    assert!(first.accept(&mut conn, &code).await.is_ok());
+    assert!(matches!(second.accept(&mut conn, &code).await, Err(UserTotpVerifyError::InvalidCode)));
+    assert!(matches!(first.accept(&mut conn, &code).await, Err(UserTotpVerifyError::InvalidCode)));
+  }
+}
@@ -0,0 +1,84 @@
+use chrono::{
+  DateTime,
+  Duration,
+  Utc
+};
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::util::token::generate_token;
+
+/// Time in seconds a ceremony has to be finished in
+pub const WEBAUTHN_CHALLENGE_LIFETIME: i64 = 60 * 5;
+
+/// Ceremony registering a new credential
+pub const CEREMONY_REGISTRATION: &str = "registration";
+/// Ceremony logging in with an existing credential
+pub const CEREMONY_AUTHENTICATION: &str = "authentication";
+
+/// Random challenge a WebAuthn ceremony was started with, usable once
+#[derive(FromRow, Clone, PartialEq, Eq)]
+pub struct WebauthnChallenge {
+  pub id: i32,
+  pub challenge: String,
+  pub ceremony: String,
+  pub user_login: Option<String>,
+  pub created_at: DateTime<Utc>
+}
+
+impl WebauthnChallenge {
+  /// ## WebauthnChallenge::create
+  ///
+  /// Stores a new challenge for the ceremony, returning it base64url encoded.
+  /// Expired challenges are removed on the way
+  ///
+  pub async fn create(
+    conn: &mut PgConnection,
+    ceremony: &str,
+    user_login: Option<&String>
+  ) -> String {
+    let sql = "DELETE FROM webauthn_challenges WHERE created_at < $1;";
+    let _ = query(sql)
+      .bind(Utc::now() - Duration::seconds(WEBAUTHN_CHALLENGE_LIFETIME))
+      .execute(&mut *conn)
+      .await;
+
+    let challenge = generate_token();
+
+    let sql = "INSERT INTO webauthn_challenges (challenge, ceremony, user_login) VALUES ($1, $2, $3);";
+    let _ = query(sql)
+      .bind(&challenge)
+      .bind(&ceremony)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await;
+
+    return challenge;
+  }
+
+  /// ## WebauthnChallenge::take
+  ///
+  /// Deletes the challenge of the ceremony and returns it,
+  /// unless it do not exist or has expired
+  ///
+  pub async fn take(
+    conn: &mut PgConnection,
+    challenge: &String,
+    ceremony: &str
+  ) -> Option<Self> {
+    let sql = "DELETE FROM webauthn_challenges WHERE challenge = $1 AND ceremony = $2 RETURNING *;";
+    let result: Option<Self> = query_as(sql)
+      .bind(&challenge)
+      .bind(&ceremony)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return result.filter(|challenge| {
+      Utc::now() - challenge.created_at <= Duration::seconds(WEBAUTHN_CHALLENGE_LIFETIME)
+    });
+  }
+}
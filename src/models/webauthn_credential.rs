@@ -0,0 +1,559 @@
+use base64::{
+  engine::general_purpose::URL_SAFE_NO_PAD,
+  Engine
+};
+use chrono::{
+  DateTime,
+  Utc
+};
+use serde::{
+  Deserialize,
+  Serialize
+};
+use serde_json::{
+  json,
+  Value
+};
+use sqlx::{
+  query,
+  query_as,
+  FromRow,
+  PgConnection
+};
+use crate::{
+  config::CauthConfig,
+  models::{
+    login_session::{
+      LoginSessionInsertError,
+      SessionMetadata
+    },
+    refresh_token::{
+      RefreshToken,
+      TokenPair
+    },
+    webauthn_challenge::{
+      WebauthnChallenge,
+      CEREMONY_AUTHENTICATION,
+      CEREMONY_REGISTRATION,
+      WEBAUTHN_CHALLENGE_LIFETIME
+    }
+  },
+  util::{
+    logging::{
+      log_database_interaction,
+      DatabaseOperationLogStatus
+    },
+    webauthn::{
+      decode_base64url,
+      parse_attestation_object,
+      parse_authenticator_data,
+      parse_client_data,
+      sign_count_increased,
+      verify_assertion,
+      COSE_ALG_EDDSA,
+      COSE_ALG_ES256
+    }
+  }
+};
+
+/// WebAuthn credential (passkey or security key) registered by a user
+#[derive(FromRow, Serialize, Clone, PartialEq, Eq)]
+pub struct WebauthnCredential {
+  pub id: i32,
+  pub user_login: String,
+  #[serde(skip_serializing)]
+  pub credential_id: Vec<u8>,
+  pub algorithm: i32,
+  #[serde(skip_serializing)]
+  pub public_key: Vec<u8>,
+  pub sign_count: i64,
+  pub name: String,
+  pub created_at: DateTime<Utc>,
+  pub last_used_at: Option<DateTime<Utc>>
+}
+
+/// `PublicKeyCredential` returned by `navigator.credentials.create()`
+#[derive(Deserialize)]
+pub struct RegistrationCredential {
+  /// base64url encoded credential id
+  pub id: String,
+  pub response: AttestationResponse
+}
+
+#[derive(Deserialize)]
+pub struct AttestationResponse {
+  #[serde(rename = "clientDataJSON")]
+  pub client_data_json: String,
+  #[serde(rename = "attestationObject")]
+  pub attestation_object: String
+}
+
+/// `PublicKeyCredential` returned by `navigator.credentials.get()`
+#[derive(Deserialize)]
+pub struct AuthenticationCredential {
+  /// base64url encoded credential id
+  pub id: String,
+  pub response: AssertionResponse
+}
+
+#[derive(Deserialize)]
+pub struct AssertionResponse {
+  #[serde(rename = "clientDataJSON")]
+  pub client_data_json: String,
+  #[serde(rename = "authenticatorData")]
+  pub authenticator_data: String,
+  pub signature: String
+}
+
+#[derive(Debug)]
+pub enum WebauthnRegisterError {
+  /// Returned when the challenge do not exist, has expired or belongs to another user
+  ChallengeNotFound,
+  /// Returned when the response of the authenticator is malformed or do not match the relying party
+  InvalidResponse(String),
+  /// Returned when the credential is already registered
+  CredentialExists
+}
+
+impl ToString for WebauthnRegisterError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::ChallengeNotFound => "The challenge do not exist or has expired".to_string(),
+      Self::InvalidResponse(err) => format!("Invalid authenticator response: {}", err),
+      Self::CredentialExists => "This credential is already registered".to_string()
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum WebauthnAuthenticateError {
+  /// Returned when the credential is not registered
+  CredentialNotFound,
+  /// Returned when the challenge do not exist, has expired or belongs to another user
+  ChallengeNotFound,
+  /// Returned when the response of the authenticator is malformed or do not match the relying party
+  InvalidResponse(String),
+  /// Returned when the signature do not match the credential's public key
+  InvalidSignature,
+  /// Returned when the authenticator only checked the user's presence, not their identity
+  UserNotVerified,
+  /// Returned when the signature counter did not increase, which means the authenticator may be cloned
+  CounterRegression,
+  /// Returned when no unique token could be generated
+  TokenCollision,
+  /// Returned when the JWT access token cannot be signed
+  CannotSign(String)
+}
+
+impl ToString for WebauthnAuthenticateError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::CredentialNotFound => "This credential is not registered".to_string(),
+      Self::ChallengeNotFound => "The challenge do not exist or has expired".to_string(),
+      Self::InvalidResponse(err) => format!("Invalid authenticator response: {}", err),
+      Self::InvalidSignature => "The signature is invalid".to_string(),
+      Self::UserNotVerified => "The authenticator did not verify the user, e.g. with a PIN or biometrics".to_string(),
+      Self::CounterRegression => "The signature counter did not increase, the authenticator may be cloned".to_string(),
+      Self::TokenCollision => "Cannot generate a unique token".to_string(),
+      Self::CannotSign(err) => format!("Cannot sign the JWT access token: {}", err)
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum WebauthnCredentialDeleteError {
+  /// Returned when the user do not have a credential with this id
+  NotFound
+}
+
+impl ToString for WebauthnCredentialDeleteError {
+  fn to_string(&self) -> String {
+    return match self {
+      Self::NotFound => "WebAuthn credential not found".to_string()
+    }
+  }
+}
+
+impl WebauthnCredential {
+  /// ## WebauthnCredential::list_by_user
+  ///
+  /// Lists the credentials of a user, oldest first
+  ///
+  pub async fn list_by_user(
+    conn: &mut PgConnection,
+    user_login: &String
+  ) -> Vec<Self> {
+    let sql = "SELECT * FROM webauthn_credentials WHERE user_login = $1 ORDER BY created_at ASC;";
+    let credentials = query_as(sql)
+      .bind(&user_login)
+      .fetch_all(&mut *conn)
+      .await
+      .unwrap();
+
+    return credentials;
+  }
+
+  /// ## WebauthnCredential::delete
+  ///
+  /// Deletes a credential belonging to specified user
+  ///
+  /// Errors:
+  /// + when the user do not have a credential with this id
+  ///
+  pub async fn delete(
+    conn: &mut PgConnection,
+    user_login: &String,
+    id: i32
+  ) -> Result<(), WebauthnCredentialDeleteError> {
+    let sql = "DELETE FROM webauthn_credentials WHERE id = $1 AND user_login = $2;";
+    let result = query(sql)
+      .bind(&id)
+      .bind(&user_login)
+      .execute(&mut *conn)
+      .await
+      .unwrap();
+
+    if result.rows_affected() == 0 {
+      return Err(WebauthnCredentialDeleteError::NotFound);
+    }
+
+    return Ok(());
+  }
+
+  /// ## WebauthnCredential::start_registration
+  ///
+  /// Starts the registration ceremony for the user,
+  /// returning the options passed to `navigator.credentials.create()`
+  ///
+  pub async fn start_registration(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String
+  ) -> Value {
+    let challenge = WebauthnChallenge::create(conn, CEREMONY_REGISTRATION, Some(user_login)).await;
+    let exclude_credentials = Self::list_by_user(conn, user_login)
+      .await
+      .iter()
+      .map(|credential| credential.descriptor())
+      .collect::<Vec<_>>();
+
+    return json!({
+      "publicKey": {
+        "rp": {
+          "id": config.webauthn_rp_id,
+          "name": config.webauthn_rp_name
+        },
+        "user": {
+          "id": URL_SAFE_NO_PAD.encode(user_login.as_bytes()),
+          "name": user_login,
+          "displayName": user_login
+        },
+        "challenge": challenge,
+        "pubKeyCredParams": [
+          { "type": "public-key", "alg": COSE_ALG_ES256 },
+          { "type": "public-key", "alg": COSE_ALG_EDDSA }
+        ],
+        "timeout": WEBAUTHN_CHALLENGE_LIFETIME * 1000,
+        "attestation": "none",
+        "excludeCredentials": exclude_credentials,
+        "authenticatorSelection": {
+          "residentKey": "preferred",
+          "userVerification": "required"
+        }
+      }
+    });
+  }
+
+  /// ## WebauthnCredential::finish_registration
+  ///
+  /// Verifies the response of the authenticator and stores the new credential.
+  /// Attestation statements are not verified, the credential is trusted like with `none` attestation
+  ///
+  /// Errors:
+  /// + when the challenge do not exist, has expired or belongs to another user
+  /// + when the response is malformed or do not match the relying party
+  /// + when the credential is already registered
+  ///
+  pub async fn finish_registration(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: &String,
+    credential: &RegistrationCredential,
+    name: &String
+  ) -> Result<Self, WebauthnRegisterError> {
+    let invalid = |err: String| WebauthnRegisterError::InvalidResponse(err);
+
+    let client_data_json = decode_base64url(&credential.response.client_data_json).map_err(invalid)?;
+    let client_data = parse_client_data(&client_data_json, "webauthn.create", &config.webauthn_origin)
+      .map_err(invalid)?;
+
+    match WebauthnChallenge::take(conn, &client_data.challenge, CEREMONY_REGISTRATION).await {
+      Some(challenge) if challenge.user_login.as_ref() == Some(user_login) => (),
+      _ => return Err(WebauthnRegisterError::ChallengeNotFound)
+    };
+
+    let attestation_object = decode_base64url(&credential.response.attestation_object).map_err(invalid)?;
+    let authenticator_data = parse_attestation_object(&attestation_object).map_err(invalid)?;
+    let authenticator_data = parse_authenticator_data(&authenticator_data, &config.webauthn_rp_id)
+      .map_err(invalid)?;
+
+    let attested = match authenticator_data.attested_credential {
+      Some(attested) => attested,
+      None => return Err(invalid("The authenticator did not return a credential".to_string()))
+    };
+
+    if decode_base64url(&credential.id).map_err(invalid)? != attested.credential_id {
+      return Err(invalid("The credential id do not match the attested credential".to_string()));
+    }
+
+    let sql = "
+      INSERT INTO
+        webauthn_credentials (user_login, credential_id, algorithm, public_key, sign_count, name)
+      VALUES
+        ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT (credential_id) DO NOTHING
+      RETURNING *;
+    ";
+    let result: Option<Self> = query_as(sql)
+      .bind(&user_login)
+      .bind(&attested.credential_id)
+      .bind(&attested.algorithm)
+      .bind(&attested.public_key)
+      .bind(authenticator_data.sign_count as i64)
+      .bind(&name)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap();
+
+    return match result {
+      Some(credential) => {
+        log_database_interaction::<String>(
+          "Inserting a WebAuthn credential into database.",
+          json!({ "login": user_login, "name": name }),
+          DatabaseOperationLogStatus::Ok
+        );
+
+        Ok(credential)
+      },
+      None => Err(WebauthnRegisterError::CredentialExists)
+    };
+  }
+
+  /// ## WebauthnCredential::start_authentication
+  ///
+  /// Starts the authentication ceremony, returning the options passed to `navigator.credentials.get()`.
+  /// When the login is known only the user's credentials are allowed,
+  /// otherwise the authenticator offers its discoverable credentials (passkeys)
+  ///
+  pub async fn start_authentication(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    user_login: Option<&String>
+  ) -> Value {
+    let challenge = WebauthnChallenge::create(conn, CEREMONY_AUTHENTICATION, user_login).await;
+    let allow_credentials = match user_login {
+      Some(user_login) => Self::list_by_user(conn, user_login)
+        .await
+        .iter()
+        .map(|credential| credential.descriptor())
+        .collect::<Vec<_>>(),
+      None => vec![]
+    };
+
+    return json!({
+      "publicKey": {
+        "rpId": config.webauthn_rp_id,
+        "challenge": challenge,
+        "timeout": WEBAUTHN_CHALLENGE_LIFETIME * 1000,
+        "allowCredentials": allow_credentials,
+        "userVerification": "required"
+      }
+    });
+  }
+
+  /// ## WebauthnCredential::finish_authentication
+  ///
+  /// Verifies the assertion of the authenticator and logs in the credential's owner,
+  /// returning the same token pair as a password login
+  ///
+  /// Errors:
+  /// + when the credential is not registered
+  /// + when the challenge do not exist, has expired or was issued for another user
+  /// + when the response is malformed, do not match the relying party or the signature is invalid
+  /// + when the authenticator did not verify the user, as the credential replaces the password
+  /// + when the signature counter did not increase
+  /// + when the session cannot be created
+  ///
+  pub async fn finish_authentication(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    credential: &AuthenticationCredential,
+    metadata: &SessionMetadata
+  ) -> Result<TokenPair, WebauthnAuthenticateError> {
+    let invalid = |err: String| WebauthnAuthenticateError::InvalidResponse(err);
+
+    let credential_id = decode_base64url(&credential.id).map_err(invalid)?;
+    let sql = "SELECT * FROM webauthn_credentials WHERE credential_id = $1;";
+    let stored: Self = match query_as(sql)
+      .bind(&credential_id)
+      .fetch_optional(&mut *conn)
+      .await
+      .unwrap() {
+      Some(stored) => stored,
+      None => return Err(WebauthnAuthenticateError::CredentialNotFound)
+    };
+
+    let client_data_json = decode_base64url(&credential.response.client_data_json).map_err(invalid)?;
+    let client_data = parse_client_data(&client_data_json, "webauthn.get", &config.webauthn_origin)
+      .map_err(invalid)?;
+
+    match WebauthnChallenge::take(conn, &client_data.challenge, CEREMONY_AUTHENTICATION).await {
+      Some(challenge) if challenge.user_login.is_none() || challenge.user_login.as_ref() == Some(&stored.user_login) => (),
+      _ => return Err(WebauthnAuthenticateError::ChallengeNotFound)
+    };
+
+    let raw_authenticator_data = decode_base64url(&credential.response.authenticator_data).map_err(invalid)?;
+    let authenticator_data = parse_authenticator_data(&raw_authenticator_data, &config.webauthn_rp_id)
+      .map_err(invalid)?;
+    let signature = decode_base64url(&credential.response.signature).map_err(invalid)?;
+
+    let verified = verify_assertion(
+      stored.algorithm,
+      &stored.public_key,
+      &raw_authenticator_data,
+      &client_data_json,
+      &signature
+    );
+
+    if !verified {
+      return Err(WebauthnAuthenticateError::InvalidSignature);
+    }
+
+    // presence alone would let anyone holding the authenticator log in
+    if !authenticator_data.user_verified {
+      return Err(WebauthnAuthenticateError::UserNotVerified);
+    }
+
+    if !sign_count_increased(stored.sign_count as u32, authenticator_data.sign_count) {
+      return Err(WebauthnAuthenticateError::CounterRegression);
+    }
+
+    let sign_count = authenticator_data.sign_count as i64;
+
+    let sql = "UPDATE webauthn_credentials SET sign_count = $1, last_used_at = NOW() WHERE id = $2;";
+    let _ = query(sql)
+      .bind(&sign_count)
+      .bind(&stored.id)
+      .execute(&mut *conn)
+      .await;
+
+    return match RefreshToken::issue(conn, config, &stored.user_login, metadata).await {
+      Ok(pair) => Ok(pair),
+      Err(err) => match err {
+        LoginSessionInsertError::UserNotFound => Err(WebauthnAuthenticateError::CredentialNotFound),
+        LoginSessionInsertError::TokenCollision => Err(WebauthnAuthenticateError::TokenCollision),
+        LoginSessionInsertError::CannotSign(err) => Err(WebauthnAuthenticateError::CannotSign(err))
+      }
+    };
+  }
+
+  /// `PublicKeyCredentialDescriptor` of the credential
+  fn descriptor(self: &Self) -> Value {
+    return json!({
+      "type": "public-key",
+      "id": URL_SAFE_NO_PAD.encode(&self.credential_id)
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    models::user::User,
+    util::webauthn::tests::{
+      client_data_json_with_challenge,
+      SoftwareAuthenticator,
+      CREDENTIAL_ID,
+      ORIGIN,
+      RP_ID
+    }
+  };
+
+  async fn authenticate(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    authenticator: &SoftwareAuthenticator,
+    sign_count: u32,
+    user_verified: bool
+  ) -> Result<TokenPair, WebauthnAuthenticateError> {
+    let options = WebauthnCredential::start_authentication(conn, config, Some(&"alice".to_string())).await;
+    let challenge = options["publicKey"]["challenge"].as_str().unwrap();
+
+    let client_data_json = client_data_json_with_challenge("webauthn.get", ORIGIN, challenge);
+    let (authenticator_data, signature) = authenticator.get_assertion(sign_count, user_verified, &client_data_json);
+    let credential = AuthenticationCredential {
+      id: URL_SAFE_NO_PAD.encode(CREDENTIAL_ID),
+      response: AssertionResponse {
+        client_data_json: URL_SAFE_NO_PAD.encode(&client_data_json),
+        authenticator_data: URL_SAFE_NO_PAD.encode(&authenticator_data),
+        signature: URL_SAFE_NO_PAD.encode(&signature)
+      }
+    };
+
+    return WebauthnCredential::finish_authentication(conn, config, &credential, &SessionMetadata::default()).await;
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a database, run with TEST_DATABASE_URL set and --ignored"]
+  async fn registers_and_authenticates_verified_users_only() {
+    let mut config = CauthConfig::for_tests().await;
+    config.webauthn_rp_id = RP_ID.to_string();
+    config.webauthn_origin = ORIGIN.to_string();
+
+    let mut conn = config.db_conn.acquire().await.unwrap();
+    let login = "alice".to_string();
+    User::insert(&mut conn, &config, &login, &"correct horse".to_string(), &json!({})).await.ok().unwrap();
+
+    let authenticator = SoftwareAuthenticator::es256();
+    let options = WebauthnCredential::start_registration(&mut conn, &config, &login).await;
+    assert_eq!(options["publicKey"]["authenticatorSelection"]["userVerification"], "required");
+    let challenge = options["publicKey"]["challenge"].as_str().unwrap();
+
+    let credential = RegistrationCredential {
+      id: URL_SAFE_NO_PAD.encode(CREDENTIAL_ID),
+      response: AttestationResponse {
+        client_data_json: URL_SAFE_NO_PAD.encode(client_data_json_with_challenge("webauthn.create", ORIGIN, challenge)),
+        attestation_object: URL_SAFE_NO_PAD.encode(authenticator.make_credential())
+      }
+    };
+    let registered = WebauthnCredential::finish_registration(&mut conn, &config, &login, &credential, &"key".to_string())
+      .await
+      .unwrap();
+    assert_eq!(registered.sign_count, 0);
+
+    assert!(authenticate(&mut conn, &config, &authenticator, 1, true).await.is_ok());
+
+    // a security key without a PIN only proves someone touched it
+    assert!(matches!(
+      authenticate(&mut conn, &config, &authenticator, 2, false).await,
+      Err(WebauthnAuthenticateError::UserNotVerified)
+    ));
+
+    assert!(authenticate(&mut conn, &config, &authenticator, 5, true).await.is_ok());
+
+    // a cloned authenticator lagging behind the stored counter
+    assert!(matches!(
+      authenticate(&mut conn, &config, &authenticator, 5, true).await,
+      Err(WebauthnAuthenticateError::CounterRegression)
+    ));
+    assert!(matches!(
+      authenticate(&mut conn, &config, &authenticator, 3, true).await,
+      Err(WebauthnAuthenticateError::CounterRegression)
+    ));
+
+    let stored = WebauthnCredential::list_by_user(&mut conn, &login).await;
+    assert_eq!(stored[0].sign_count, 5);
+    assert!(stored[0].last_used_at.is_some());
+  }
+}
@@ -0,0 +1,424 @@
+use ldap3::{dn_escape, ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::{collections::HashMap, time::Duration};
+
+use crate::config::LdapConfig;
+
+/// Maximum time in seconds connecting to the directory can take
+const CONNECT_TIMEOUT: u64 = 5;
+
+/// Result code of a bind with a wrong DN or password
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Default filter of the group search
+const DEFAULT_GROUP_FILTER: &str = "(|(member={dn})(uniqueMember={dn})(memberUid={login}))";
+
+/// Entry of the user found in the directory after a successful bind
+pub struct LdapEntry {
+    /// Values of the mapped attributes of the entry
+    pub attributes: HashMap<String, Vec<String>>,
+    /// Names of the directory groups the user is a member of
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum LdapBindError {
+    /// Returned when no DN template accepts the login and password
+    InvalidCredentials,
+    /// Returned when the directory is unreachable or fails
+    Unavailable(String),
+}
+
+impl ToString for LdapBindError {
+    fn to_string(&self) -> String {
+        return match self {
+            Self::InvalidCredentials => "Invalid LDAP credentials".to_string(),
+            Self::Unavailable(err) => format!("LDAP directory is unavailable: {}", err),
+        };
+    }
+}
+
+/// ## bind
+///
+/// Verifies the login and password with a simple bind as every DN template in turn,
+/// then reads the mapped attributes and the groups of the user with the bound identity
+///
+/// Errors:
+/// + when the password is empty or no DN template accepts the credentials
+/// + when the directory is unreachable or fails
+///
+pub async fn bind(config: &LdapConfig, login: &str, password: &str) -> Result<LdapEntry, LdapBindError> {
+    // an empty password would make an unauthenticated bind, which always succeeds
+    if password.is_empty() {
+        return Err(LdapBindError::InvalidCredentials);
+    }
+
+    let settings = LdapConnSettings::new()
+        .set_starttls(config.starttls.unwrap_or(false))
+        .set_conn_timeout(Duration::from_secs(CONNECT_TIMEOUT));
+
+    let (conn, mut ldap) = match LdapConnAsync::with_settings(settings, &config.url).await {
+        Ok(connection) => connection,
+        Err(err) => return Err(LdapBindError::Unavailable(err.to_string())),
+    };
+    ldap3::drive!(conn);
+
+    let result = bind_and_read(config, &mut ldap, login, password).await;
+    let _ = ldap.unbind().await;
+
+    return result;
+}
+
+async fn bind_and_read(
+    config: &LdapConfig,
+    ldap: &mut Ldap,
+    login: &str,
+    password: &str,
+) -> Result<LdapEntry, LdapBindError> {
+    let mut bound_dn = None;
+
+    for template in &config.bind_dn_templates {
+        let dn = template.replace("{login}", &dn_escape(login));
+
+        match ldap.simple_bind(&dn, password).await {
+            Ok(result) if result.rc == 0 => {
+                bound_dn = Some(dn);
+                break;
+            }
+            Ok(result) if result.rc == INVALID_CREDENTIALS => continue,
+            Ok(result) => return Err(LdapBindError::Unavailable(result.to_string())),
+            Err(err) => return Err(LdapBindError::Unavailable(err.to_string())),
+        };
+    }
+
+    let dn = match bound_dn {
+        Some(dn) => dn,
+        None => return Err(LdapBindError::InvalidCredentials),
+    };
+
+    let mut entry = LdapEntry {
+        attributes: HashMap::new(),
+        groups: vec![],
+    };
+
+    if let Some(mapping) = &config.attribute_mapping {
+        let attributes = mapping.keys().map(|attribute| attribute.as_str()).collect::<Vec<_>>();
+        let result = ldap.search(&dn, Scope::Base, "(objectClass=*)", attributes).await;
+
+        match result.and_then(|result| result.success()) {
+            Ok((entries, _)) => {
+                if let Some(found) = entries.into_iter().next() {
+                    entry.attributes = SearchEntry::construct(found).attrs;
+                }
+            }
+            Err(err) => return Err(LdapBindError::Unavailable(err.to_string())),
+        };
+    }
+
+    if let Some(group_base_dn) = &config.group_base_dn {
+        let name_attribute = config.group_name_attribute.as_deref().unwrap_or("cn");
+        let filter = config
+            .group_filter
+            .as_deref()
+            .unwrap_or(DEFAULT_GROUP_FILTER)
+            .replace("{dn}", &ldap_escape(dn.as_str()))
+            .replace("{login}", &ldap_escape(login));
+
+        let result = ldap
+            .search(group_base_dn, Scope::Subtree, &filter, vec![name_attribute])
+            .await;
+
+        match result.and_then(|result| result.success()) {
+            Ok((entries, _)) => {
+                entry.groups = entries
+                    .into_iter()
+                    .filter_map(|found| {
+                        SearchEntry::construct(found)
+                            .attrs
+                            .remove(name_attribute)
+                            .and_then(|names| names.into_iter().next())
+                    })
+                    .collect();
+            }
+            Err(err) => return Err(LdapBindError::Unavailable(err.to_string())),
+        };
+    }
+
+    return Ok(entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    const PEOPLE: &str = "ou=people,dc=example,dc=com";
+    const GROUPS: &str = "ou=groups,dc=example,dc=com";
+    const LOGINS: [&str; 2] = ["alice", "*)(uid=*"];
+
+    /// Reads a BER element, returning its tag, its content and the bytes after it
+    fn read_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+        let (&tag, rest) = data.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+
+        let (length, rest) = match first {
+            0..=0x7f => (first as usize, rest),
+            _ => {
+                let bytes = (first & 0x7f) as usize;
+                let length = rest.get(..bytes)?.iter().fold(0, |length, byte| length << 8 | *byte as usize);
+                (length, &rest[bytes..])
+            }
+        };
+
+        return Some((tag, rest.get(..length)?, &rest[length..]));
+    }
+
+    fn element(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![tag];
+
+        match content.len() {
+            length @ 0..=0x7f => encoded.push(length as u8),
+            length => {
+                encoded.push(0x84);
+                encoded.extend_from_slice(&(length as u32).to_be_bytes());
+            }
+        };
+
+        encoded.extend_from_slice(content);
+        return encoded;
+    }
+
+    fn message(id: &[u8], operation: Vec<u8>) -> Vec<u8> {
+        return element(0x30, &[element(0x02, id), operation].concat());
+    }
+
+    fn result(tag: u8, code: u8) -> Vec<u8> {
+        return element(tag, &[element(0x0a, &[code]), element(0x04, b""), element(0x04, b"")].concat());
+    }
+
+    fn search_entry(dn: &str, attributes: &[(&str, &[&str])]) -> Vec<u8> {
+        let attributes = attributes
+            .iter()
+            .map(|(name, values)| {
+                let values = values.iter().map(|value| element(0x04, value.as_bytes())).collect::<Vec<_>>();
+                element(0x30, &[element(0x04, name.as_bytes()), element(0x31, &values.concat())].concat())
+            })
+            .collect::<Vec<_>>();
+
+        return element(0x64, &[element(0x04, dn.as_bytes()), element(0x30, &attributes.concat())].concat());
+    }
+
+    /// Directory answering simple binds and searches like slapd would, recording what it was sent.
+    /// Only the logins in `LOGINS` under the people OU can bind, with the password "secret"
+    #[derive(Clone, Default)]
+    struct StubDirectory {
+        /// Bind DNs in the order they were tried
+        binds: Arc<Mutex<Vec<String>>>,
+        /// Raw BER filters of the searches
+        filters: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl StubDirectory {
+        /// Starts the directory on a free port, returning its url
+        async fn start() -> (Self, String) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ldap://{}", listener.local_addr().unwrap());
+            let directory = StubDirectory::default();
+
+            let served = directory.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(served.clone().serve(stream));
+                }
+            });
+
+            return (directory, url);
+        }
+
+        async fn serve(self, mut stream: TcpStream) {
+            let mut buffer = vec![];
+            let mut bound_dn = None;
+
+            loop {
+                let mut chunk = [0u8; 4096];
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                };
+
+                while let Some((_, content, rest)) = read_element(&buffer) {
+                    let consumed = buffer.len() - rest.len();
+                    let (_, id, operation) = read_element(content).unwrap();
+                    let (tag, operation, _) = read_element(operation).unwrap();
+
+                    let response = match tag {
+                        // bind request
+                        0x60 => {
+                            let (_, _version, operation) = read_element(operation).unwrap();
+                            let (_, dn, operation) = read_element(operation).unwrap();
+                            let (_, password, _) = read_element(operation).unwrap();
+                            let dn = String::from_utf8(dn.to_vec()).unwrap();
+                            self.binds.lock().unwrap().push(dn.clone());
+
+                            let known = LOGINS.iter().any(|login| dn == format!("uid={},{}", dn_escape(*login), PEOPLE));
+                            match (known, password) {
+                                (true, b"secret") => {
+                                    bound_dn = Some(dn);
+                                    message(id, result(0x61, 0))
+                                }
+                                _ => message(id, result(0x61, INVALID_CREDENTIALS as u8)),
+                            }
+                        }
+                        // search request
+                        0x63 => {
+                            let (_, base, mut operation) = read_element(operation).unwrap();
+                            for _ in 0..5 {
+                                operation = read_element(operation).unwrap().2;
+                            }
+                            let (filter_tag, filter, _) = read_element(operation).unwrap();
+                            self.filters.lock().unwrap().push(element(filter_tag, filter));
+
+                            let base = String::from_utf8(base.to_vec()).unwrap();
+                            let entries = if Some(&base) == bound_dn.as_ref() {
+                                vec![search_entry(&base, &[("mail", &["alice@example.com"]), ("cn", &["Alice"])])]
+                            } else if base == GROUPS && bound_dn.is_some() {
+                                vec![
+                                    search_entry(&format!("cn=admins,{}", GROUPS), &[("cn", &["admins"])]),
+                                    search_entry(&format!("cn=staff,{}", GROUPS), &[("cn", &["staff"])]),
+                                ]
+                            } else {
+                                vec![]
+                            };
+
+                            let entries = entries.into_iter().map(|entry| message(id, entry));
+                            [entries.collect::<Vec<_>>().concat(), message(id, result(0x65, 0))].concat()
+                        }
+                        // unbind request
+                        0x42 => return,
+                        _ => message(id, result(tag + 1, 53)),
+                    };
+
+                    if stream.write_all(&response).await.is_err() {
+                        return;
+                    }
+                    buffer.drain(..consumed);
+                }
+            }
+        }
+    }
+
+    fn config(url: &str, extra: serde_json::Value) -> LdapConfig {
+        let mut config = json!({
+            "url": url,
+            "bind_dn_templates": [
+                "uid={login},ou=robots,dc=example,dc=com",
+                format!("uid={{login}},{}", PEOPLE),
+            ],
+        });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+
+        return serde_json::from_value(config).unwrap();
+    }
+
+    #[tokio::test]
+    async fn binds_with_matching_template_and_reads_entry() {
+        let (directory, url) = StubDirectory::start().await;
+        let config = config(
+            &url,
+            json!({
+                "attribute_mapping": { "mail": "email" },
+                "group_base_dn": GROUPS,
+            }),
+        );
+
+        let entry = bind(&config, "alice", "secret").await.unwrap();
+
+        assert_eq!(
+            *directory.binds.lock().unwrap(),
+            vec![
+                "uid=alice,ou=robots,dc=example,dc=com".to_string(),
+                format!("uid=alice,{}", PEOPLE),
+            ]
+        );
+        assert_eq!(entry.attributes.get("mail"), Some(&vec!["alice@example.com".to_string()]));
+        assert_eq!(entry.groups, vec!["admins".to_string(), "staff".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_password() {
+        let (directory, url) = StubDirectory::start().await;
+
+        assert!(matches!(
+            bind(&config(&url, json!({})), "alice", "wrong").await,
+            Err(LdapBindError::InvalidCredentials)
+        ));
+        assert_eq!(directory.binds.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_password_without_binding() {
+        let (directory, url) = StubDirectory::start().await;
+
+        assert!(matches!(
+            bind(&config(&url, json!({})), "alice", "").await,
+            Err(LdapBindError::InvalidCredentials)
+        ));
+        assert!(directory.binds.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_unreachable_directory() {
+        // the port is released before the bind, nothing listens on it
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("ldap://{}", listener.local_addr().unwrap())
+        };
+
+        assert!(matches!(
+            bind(&config(&url, json!({})), "alice", "secret").await,
+            Err(LdapBindError::Unavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn escapes_login_in_bind_dn() {
+        let (directory, url) = StubDirectory::start().await;
+
+        let result = bind(&config(&url, json!({})), "alice,ou=people", "secret").await;
+
+        assert!(matches!(result, Err(LdapBindError::InvalidCredentials)));
+        assert_eq!(
+            directory.binds.lock().unwrap().last().unwrap(),
+            &format!("uid=alice\\2cou\\3dpeople,{}", PEOPLE)
+        );
+    }
+
+    #[tokio::test]
+    async fn escapes_login_and_dn_in_group_filter() {
+        let (directory, url) = StubDirectory::start().await;
+        let config = config(
+            &url,
+            json!({
+                "group_base_dn": GROUPS,
+                "group_filter": "(&(member={dn})(memberUid={login}))",
+            }),
+        );
+
+        // unescaped, the login would close the filter and match every group
+        let login = "*)(uid=*";
+        let entry = bind(&config, login, "secret").await.unwrap();
+        assert_eq!(entry.groups.len(), 2);
+
+        let dn = format!("uid={},{}", dn_escape(login), PEOPLE);
+        let equality = |attribute: &str, value: &str| {
+            element(0xa3, &[element(0x04, attribute.as_bytes()), element(0x04, value.as_bytes())].concat())
+        };
+        let expected = element(0xa0, &[equality("member", &dn), equality("memberUid", login)].concat());
+
+        assert_eq!(*directory.filters.lock().unwrap(), vec![expected]);
+    }
+}
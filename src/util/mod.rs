@@ -1,4 +1,10 @@
 pub mod io;
+pub mod ldap;
 pub mod logging;
+pub mod notifier;
+pub mod oidc;
+pub mod password;
 pub mod string;
-
+pub mod token;
+pub mod totp;
+pub mod webauthn;
@@ -0,0 +1,319 @@
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use reqwest::Client;
+use serde::Serialize;
+use std::{fs::OpenOptions, io::Write, time::Duration};
+
+use crate::config::{NotifierConfig, SmtpEncryption};
+
+/// Maximum time in seconds delivering a notification can take
+const DELIVERY_TIMEOUT: u64 = 10;
+
+/// Message carrying a one-time token to a user
+#[derive(Serialize)]
+pub struct Notification {
+    /// Kind of the notification, e.g. password_reset
+    pub kind: String,
+    pub login: String,
+    /// Email detail of the user, the recipient of SMTP notifications
+    pub email: Option<String>,
+    pub subject: String,
+    pub body: String,
+    pub token: String,
+    /// Link the token is embedded in, when the page it points to is configured
+    pub link: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum NotifyError {
+    /// Returned when the user has no address the notifier can deliver to
+    MissingRecipient,
+    /// Returned when the notification cannot be delivered
+    Failed(String),
+}
+
+impl ToString for NotifyError {
+    fn to_string(&self) -> String {
+        return match self {
+            Self::MissingRecipient => "The user has no address to notify".to_string(),
+            Self::Failed(err) => format!("Cannot deliver the notification: {}", err),
+        };
+    }
+}
+
+/// ## notify
+///
+/// Delivers the notification through the configured channel
+///
+/// Errors:
+/// + when an email is sent and the user has no valid email
+/// + when the file, webhook or mail server fails
+///
+pub async fn notify(config: &NotifierConfig, notification: &Notification) -> Result<(), NotifyError> {
+    return match config {
+        NotifierConfig::Stdout => {
+            println!("{}", serde_json::to_string(notification).unwrap());
+            Ok(())
+        }
+        NotifierConfig::File { path } => write_to_file(path, notification),
+        NotifierConfig::Webhook { url, secret } => post_to_webhook(url, secret, notification).await,
+        NotifierConfig::Smtp {
+            host,
+            port,
+            username,
+            password,
+            from,
+            encryption,
+        } => {
+            let transport = match smtp_transport(host, *port, username, password, *encryption) {
+                Ok(transport) => transport,
+                Err(err) => return Err(err),
+            };
+
+            send_email(&transport, from, notification).await
+        }
+    };
+}
+
+fn write_to_file(path: &str, notification: &Notification) -> Result<(), NotifyError> {
+    let mut file = match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => file,
+        Err(err) => return Err(NotifyError::Failed(err.to_string())),
+    };
+
+    let line = format!("{}\n", serde_json::to_string(notification).unwrap());
+
+    return match file.write_all(line.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(NotifyError::Failed(err.to_string())),
+    };
+}
+
+async fn post_to_webhook(
+    url: &str,
+    secret: &Option<String>,
+    notification: &Notification,
+) -> Result<(), NotifyError> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(DELIVERY_TIMEOUT))
+        .build()
+        .unwrap_or_default();
+
+    let mut request = client.post(url).json(notification);
+
+    if let Some(secret) = secret {
+        request = request.bearer_auth(secret);
+    }
+
+    return match request.send().await.and_then(|response| response.error_for_status()) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(NotifyError::Failed(err.to_string())),
+    };
+}
+
+fn smtp_transport(
+    host: &str,
+    port: Option<u16>,
+    username: &Option<String>,
+    password: &Option<String>,
+    encryption: Option<SmtpEncryption>,
+) -> Result<AsyncSmtpTransport<Tokio1Executor>, NotifyError> {
+    let builder = match encryption.unwrap_or(SmtpEncryption::Starttls) {
+        SmtpEncryption::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+        SmtpEncryption::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+        SmtpEncryption::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)),
+    };
+
+    let mut builder = match builder {
+        Ok(builder) => builder.timeout(Some(Duration::from_secs(DELIVERY_TIMEOUT))),
+        Err(err) => return Err(NotifyError::Failed(err.to_string())),
+    };
+
+    if let Some(port) = port {
+        builder = builder.port(port);
+    }
+
+    if let (Some(username), Some(password)) = (username, password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    return Ok(builder.build());
+}
+
+async fn send_email(
+    transport: &AsyncSmtpTransport<Tokio1Executor>,
+    from: &str,
+    notification: &Notification,
+) -> Result<(), NotifyError> {
+    let recipient = match notification.email.as_ref().map(|email| email.parse()) {
+        Some(Ok(recipient)) => recipient,
+        _ => return Err(NotifyError::MissingRecipient),
+    };
+
+    let sender = match from.parse() {
+        Ok(sender) => sender,
+        Err(err) => return Err(NotifyError::Failed(format!("invalid sender: {}", err))),
+    };
+
+    let message = Message::builder()
+        .from(sender)
+        .to(recipient)
+        .subject(notification.subject.clone())
+        .header(ContentType::TEXT_PLAIN)
+        .body(notification.body.clone());
+
+    let message = match message {
+        Ok(message) => message,
+        Err(err) => return Err(NotifyError::Failed(err.to_string())),
+    };
+
+    return match transport.send(message).await {
+        Ok(_) => Ok(()),
+        Err(err) => Err(NotifyError::Failed(err.to_string())),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    /// Envelope and content of an email received by the sink
+    #[derive(Clone, Default)]
+    struct ReceivedEmail {
+        mail_from: String,
+        rcpt_to: Vec<String>,
+        data: String,
+    }
+
+    /// SMTP server accepting every email without delivering it, returning the port it listens on
+    async fn start_smtp_sink(received: Arc<Mutex<Vec<ReceivedEmail>>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let received = received.clone();
+
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    let mut email = ReceivedEmail::default();
+                    let mut in_data = false;
+
+                    let _ = writer.write_all(b"220 sink ESMTP\r\n").await;
+
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let reply: &[u8] = if in_data {
+                            if line != "." {
+                                email.data.push_str(&line);
+                                email.data.push('\n');
+                                continue;
+                            }
+
+                            in_data = false;
+                            received.lock().unwrap().push(email.clone());
+                            b"250 queued\r\n"
+                        } else if line.starts_with("EHLO") {
+                            b"250-sink\r\n250 8BITMIME\r\n"
+                        } else if let Some(from) = line.strip_prefix("MAIL FROM:") {
+                            email.mail_from = from.to_string();
+                            b"250 OK\r\n"
+                        } else if let Some(to) = line.strip_prefix("RCPT TO:") {
+                            email.rcpt_to.push(to.to_string());
+                            b"250 OK\r\n"
+                        } else if line == "DATA" {
+                            in_data = true;
+                            b"354 end with .\r\n"
+                        } else if line == "QUIT" {
+                            let _ = writer.write_all(b"221 bye\r\n").await;
+                            return;
+                        } else {
+                            b"250 OK\r\n"
+                        };
+
+                        if writer.write_all(reply).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        return port;
+    }
+
+    fn smtp_config(port: u16) -> NotifierConfig {
+        return NotifierConfig::Smtp {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            username: None,
+            password: None,
+            from: "Authoric <no-reply@example.com>".to_string(),
+            encryption: Some(SmtpEncryption::None),
+        };
+    }
+
+    fn notification(email: Option<&str>) -> Notification {
+        return Notification {
+            kind: "email_verification".to_string(),
+            login: "alice".to_string(),
+            email: email.map(|email| email.to_string()),
+            subject: "Verify your email".to_string(),
+            body: "To verify the email of the account alice, use the following token: t0k3n".to_string(),
+            token: "t0k3n".to_string(),
+            link: None,
+        };
+    }
+
+    #[tokio::test]
+    async fn sends_email_through_smtp() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let port = start_smtp_sink(received.clone()).await;
+
+        notify(&smtp_config(port), &notification(Some("alice@example.com")))
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].mail_from.contains("<no-reply@example.com>"));
+        assert_eq!(received[0].rcpt_to.len(), 1);
+        assert!(received[0].rcpt_to[0].contains("<alice@example.com>"));
+        assert!(received[0].data.contains("Subject: Verify your email"));
+        assert!(received[0].data.contains("To: alice@example.com"));
+        assert!(received[0].data.contains("use the following token: t0k3n"));
+    }
+
+    #[tokio::test]
+    async fn refuses_email_without_valid_recipient() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let port = start_smtp_sink(received.clone()).await;
+
+        for email in [None, Some("not an email")] {
+            assert!(matches!(
+                notify(&smtp_config(port), &notification(email)).await,
+                Err(NotifyError::MissingRecipient)
+            ));
+        }
+
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_unreachable_mail_server() {
+        // the port is released before sending, nothing listens on it
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+
+        assert!(matches!(
+            notify(&smtp_config(port), &notification(Some("alice@example.com"))).await,
+            Err(NotifyError::Failed(_))
+        ));
+    }
+}
@@ -0,0 +1,532 @@
+use jsonwebtoken::{
+    decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation,
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+use crate::config::OidcProvider;
+
+/// Maximum time in seconds a request to an upstream provider can take
+const REQUEST_TIMEOUT: u64 = 10;
+
+/// Endpoints of an upstream identity provider, read from its discovery document
+#[derive(Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: Option<String>,
+    pub jwks_uri: String,
+}
+
+/// Tokens returned by the token endpoint of the provider
+#[derive(Deserialize)]
+pub struct ProviderTokens {
+    pub access_token: String,
+    pub id_token: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum OidcError {
+    /// Returned when the discovery document cannot be fetched or do not match the issuer
+    Discovery(String),
+    /// Returned when the provider refuses to exchange the authorization code
+    TokenExchange(String),
+    /// Returned when the ID token is missing, has an invalid signature or claims
+    InvalidIdToken(String),
+    /// Returned when the userinfo endpoint fails or returns claims of another user
+    UserInfo(String),
+}
+
+impl ToString for OidcError {
+    fn to_string(&self) -> String {
+        return match self {
+            Self::Discovery(err) => format!("Cannot discover the identity provider: {}", err),
+            Self::TokenExchange(err) => format!("Cannot exchange the authorization code: {}", err),
+            Self::InvalidIdToken(err) => format!("The ID token is invalid: {}", err),
+            Self::UserInfo(err) => format!("Cannot retrieve the user info: {}", err),
+        };
+    }
+}
+
+fn client() -> Client {
+    return Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT))
+        .build()
+        .unwrap_or_default();
+}
+
+impl ProviderMetadata {
+    /// ## ProviderMetadata::discover
+    ///
+    /// Fetches the discovery document of the provider as described in OpenID Connect Discovery
+    ///
+    /// Errors:
+    /// + when the document cannot be fetched or parsed
+    /// + when the issuer of the document is not the configured one
+    ///
+    pub async fn discover(provider: &OidcProvider) -> Result<Self, OidcError> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            provider.issuer.trim_end_matches('/')
+        );
+
+        let response = match client().get(&url).send().await {
+            Ok(response) => response,
+            Err(err) => return Err(OidcError::Discovery(err.to_string())),
+        };
+
+        let metadata = match response.error_for_status() {
+            Ok(response) => response.json::<Self>().await,
+            Err(err) => return Err(OidcError::Discovery(err.to_string())),
+        };
+
+        return match metadata {
+            Ok(metadata) if metadata.issuer.trim_end_matches('/') == provider.issuer.trim_end_matches('/') => {
+                Ok(metadata)
+            }
+            Ok(metadata) => Err(OidcError::Discovery(format!(
+                "The document was issued by {}",
+                metadata.issuer
+            ))),
+            Err(err) => Err(OidcError::Discovery(err.to_string())),
+        };
+    }
+
+    /// ## ProviderMetadata::authorization_url
+    ///
+    /// Builds the url the user is sent to for signing in at the provider,
+    /// using the authorization code flow with PKCE
+    ///
+    pub fn authorization_url(
+        &self,
+        provider: &OidcProvider,
+        redirect_uri: &str,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> String {
+        let scope = provider.scopes().join(" ");
+        let params = [
+            ("response_type", "code"),
+            ("client_id", provider.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("scope", scope.as_str()),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", code_challenge),
+            ("code_challenge_method", "S256"),
+        ];
+
+        let mut url = self.authorization_endpoint.clone();
+
+        for (name, value) in params {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(name);
+            url.push('=');
+            url.push_str(&utf8_percent_encode(value, NON_ALPHANUMERIC).to_string());
+        }
+
+        return url;
+    }
+
+    /// ## ProviderMetadata::exchange_code
+    ///
+    /// Exchanges the authorization code from the provider's callback for tokens,
+    /// authenticating with the client secret
+    ///
+    /// Errors:
+    /// + when the provider is unreachable or refuses the code
+    ///
+    pub async fn exchange_code(
+        &self,
+        provider: &OidcProvider,
+        redirect_uri: &str,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<ProviderTokens, OidcError> {
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", code_verifier),
+        ];
+
+        let response = client()
+            .post(&self.token_endpoint)
+            .basic_auth(&provider.client_id, Some(&provider.client_secret))
+            .form(&form)
+            .send()
+            .await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => return Err(OidcError::TokenExchange(err.to_string())),
+        };
+
+        return match response.error_for_status() {
+            Ok(response) => match response.json::<ProviderTokens>().await {
+                Ok(tokens) => Ok(tokens),
+                Err(err) => Err(OidcError::TokenExchange(err.to_string())),
+            },
+            Err(err) => Err(OidcError::TokenExchange(err.to_string())),
+        };
+    }
+
+    /// ## ProviderMetadata::verify_id_token
+    ///
+    /// Verifies the ID token with the provider's published keys, returning its claims.
+    /// The token has to be issued by the provider for the configured client, unexpired,
+    /// and carry the nonce the sign in was started with
+    ///
+    /// Errors:
+    /// + when the token is malformed, signed with an unknown key or a symmetric algorithm
+    /// + when the issuer, audience, expiry or nonce do not match
+    ///
+    pub async fn verify_id_token(
+        &self,
+        provider: &OidcProvider,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<Map<String, Value>, OidcError> {
+        let header = match decode_header(id_token) {
+            Ok(header) => header,
+            Err(err) => return Err(OidcError::InvalidIdToken(err.to_string())),
+        };
+
+        // the client secret is never used as a verification key
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(OidcError::InvalidIdToken(
+                "Symmetric signing algorithms are not supported".to_string(),
+            ));
+        }
+
+        let keys = match client().get(&self.jwks_uri).send().await {
+            Ok(response) => response.json::<JwkSet>().await,
+            Err(err) => return Err(OidcError::InvalidIdToken(err.to_string())),
+        };
+        let keys = match keys {
+            Ok(keys) => keys,
+            Err(err) => return Err(OidcError::InvalidIdToken(err.to_string())),
+        };
+
+        let jwk = match &header.kid {
+            Some(kid) => keys.find(kid),
+            None if keys.keys.len() == 1 => keys.keys.first(),
+            None => None,
+        };
+        let key = match jwk.map(DecodingKey::from_jwk) {
+            Some(Ok(key)) => key,
+            Some(Err(err)) => return Err(OidcError::InvalidIdToken(err.to_string())),
+            None => {
+                return Err(OidcError::InvalidIdToken(
+                    "The signing key is not published by the provider".to_string(),
+                ))
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = match decode::<Map<String, Value>>(id_token, &key, &validation) {
+            Ok(token) => token.claims,
+            Err(err) => return Err(OidcError::InvalidIdToken(err.to_string())),
+        };
+
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            return Err(OidcError::InvalidIdToken(
+                "The nonce do not match the sign in".to_string(),
+            ));
+        }
+
+        return Ok(claims);
+    }
+
+    /// ## ProviderMetadata::userinfo
+    ///
+    /// Fetches the claims of the user from the provider's userinfo endpoint,
+    /// returning no claims when the provider has none
+    ///
+    /// Errors:
+    /// + when the endpoint fails
+    /// + when the claims belong to another subject than the ID token
+    ///
+    pub async fn userinfo(
+        &self,
+        access_token: &str,
+        subject: &str,
+    ) -> Result<Map<String, Value>, OidcError> {
+        let endpoint = match &self.userinfo_endpoint {
+            Some(endpoint) => endpoint,
+            None => return Ok(Map::new()),
+        };
+
+        let response = match client().get(endpoint).bearer_auth(access_token).send().await {
+            Ok(response) => response,
+            Err(err) => return Err(OidcError::UserInfo(err.to_string())),
+        };
+
+        let claims = match response.error_for_status() {
+            Ok(response) => response.json::<Map<String, Value>>().await,
+            Err(err) => return Err(OidcError::UserInfo(err.to_string())),
+        };
+
+        return match claims {
+            Ok(claims) if claims.get("sub").and_then(Value::as_str) == Some(subject) => Ok(claims),
+            Ok(_) => Err(OidcError::UserInfo(
+                "The claims belong to another subject".to_string(),
+            )),
+            Err(err) => Err(OidcError::UserInfo(err.to_string())),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use serde_json::json;
+    use std::net::TcpListener;
+
+    const CLIENT_ID: &str = "authoric";
+    const NONCE: &str = "expected-nonce";
+
+    /// Identity provider serving its discovery document, keys, token and userinfo endpoints on localhost
+    struct MockIdp {
+        issuer: String,
+        signing_key: EncodingKey,
+    }
+
+    impl MockIdp {
+        /// Starts the provider, its token endpoint returns `id_token` and its userinfo endpoint `userinfo`
+        fn start(id_token: impl Fn(&MockIdp) -> String, userinfo: Value) -> (Self, OidcProvider) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+            let point = key_pair.public_key().as_ref();
+
+            let idp = MockIdp {
+                issuer: issuer.clone(),
+                signing_key: EncodingKey::from_ec_der(pkcs8.as_ref()),
+            };
+
+            let discovery = json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "userinfo_endpoint": format!("{}/userinfo", issuer),
+                "jwks_uri": format!("{}/jwks", issuer),
+            });
+            let jwks = json!({
+                "keys": [{
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "kid": "mock",
+                    "alg": "ES256",
+                    "use": "sig",
+                    "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                    "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+                }]
+            });
+            let tokens = json!({ "access_token": "upstream-access-token", "id_token": id_token(&idp) });
+
+            let server = HttpServer::new(move || {
+                let (discovery, jwks, tokens, userinfo) =
+                    (discovery.clone(), jwks.clone(), tokens.clone(), userinfo.clone());
+
+                App::new()
+                    .route(
+                        "/.well-known/openid-configuration",
+                        web::get().to(move || {
+                            let discovery = discovery.clone();
+                            async move { HttpResponse::Ok().json(discovery) }
+                        }),
+                    )
+                    .route(
+                        "/jwks",
+                        web::get().to(move || {
+                            let jwks = jwks.clone();
+                            async move { HttpResponse::Ok().json(jwks) }
+                        }),
+                    )
+                    .route(
+                        "/token",
+                        web::post().to(move |form: web::Form<Vec<(String, String)>>| {
+                            let tokens = tokens.clone();
+                            async move {
+                                match form.iter().any(|(name, value)| name == "code" && value == "valid-code") {
+                                    true => HttpResponse::Ok().json(tokens),
+                                    false => HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" })),
+                                }
+                            }
+                        }),
+                    )
+                    .route(
+                        "/userinfo",
+                        web::get().to(move || {
+                            let userinfo = userinfo.clone();
+                            async move { HttpResponse::Ok().json(userinfo) }
+                        }),
+                    )
+            })
+            .workers(1)
+            .listen(listener)
+            .unwrap()
+            .run();
+            actix_web::rt::spawn(server);
+
+            let provider = serde_json::from_value(json!({
+                "name": "mock",
+                "issuer": issuer,
+                "client_id": CLIENT_ID,
+                "client_secret": "secret",
+            }))
+            .unwrap();
+
+            return (idp, provider);
+        }
+
+        fn id_token(&self, claims: Value) -> String {
+            let mut header = Header::new(Algorithm::ES256);
+            header.kid = Some("mock".to_string());
+
+            return encode(&header, &claims, &self.signing_key).unwrap();
+        }
+
+        fn claims(&self, nonce: &str, expires_in: i64) -> Value {
+            let now = chrono::Utc::now().timestamp();
+
+            return json!({
+                "iss": self.issuer,
+                "aud": CLIENT_ID,
+                "sub": "upstream-subject",
+                "iat": now,
+                "exp": now + expires_in,
+                "nonce": nonce,
+                "preferred_username": "alice",
+            });
+        }
+    }
+
+    /// Runs the callback's steps against the provider, returning the verified claims
+    async fn sign_in(provider: &OidcProvider, code: &str) -> Result<Map<String, Value>, OidcError> {
+        let metadata = ProviderMetadata::discover(provider).await?;
+        let tokens = metadata
+            .exchange_code(provider, "https://auth.example.com/callback", code, "verifier")
+            .await?;
+        let id_token = tokens.id_token.ok_or(OidcError::InvalidIdToken("missing".to_string()))?;
+
+        let mut claims = metadata.verify_id_token(provider, &id_token, NONCE).await?;
+        let subject = claims["sub"].as_str().unwrap().to_string();
+        for (name, value) in metadata.userinfo(&tokens.access_token, &subject).await? {
+            claims.entry(name).or_insert(value);
+        }
+
+        return Ok(claims);
+    }
+
+    #[actix_web::test]
+    async fn signs_in_with_valid_id_token() {
+        let userinfo = json!({ "sub": "upstream-subject", "groups": ["admins"] });
+        let (_, provider) = MockIdp::start(|idp| idp.id_token(idp.claims(NONCE, 300)), userinfo);
+
+        let claims = sign_in(&provider, "valid-code").await.unwrap();
+
+        assert_eq!(claims["sub"], "upstream-subject");
+        assert_eq!(claims["preferred_username"], "alice");
+        assert_eq!(claims["groups"], json!(["admins"]));
+    }
+
+    #[actix_web::test]
+    async fn rejects_nonce_mismatch() {
+        let userinfo = json!({ "sub": "upstream-subject" });
+        let (_, provider) = MockIdp::start(|idp| idp.id_token(idp.claims("other-nonce", 300)), userinfo);
+
+        assert!(matches!(sign_in(&provider, "valid-code").await, Err(OidcError::InvalidIdToken(_))));
+    }
+
+    #[actix_web::test]
+    async fn rejects_expired_id_token() {
+        let userinfo = json!({ "sub": "upstream-subject" });
+        let (_, provider) = MockIdp::start(|idp| idp.id_token(idp.claims(NONCE, -3600)), userinfo);
+
+        match sign_in(&provider, "valid-code").await {
+            Err(OidcError::InvalidIdToken(err)) => assert!(err.contains("Expired"), "{}", err),
+            _ => panic!("the expired ID token was accepted"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn rejects_id_token_of_another_client_or_issuer() {
+        let userinfo = json!({ "sub": "upstream-subject" });
+        let (_, provider) = MockIdp::start(
+            |idp| {
+                let mut claims = idp.claims(NONCE, 300);
+                claims["aud"] = json!("another-client");
+                idp.id_token(claims)
+            },
+            userinfo.clone(),
+        );
+        assert!(matches!(sign_in(&provider, "valid-code").await, Err(OidcError::InvalidIdToken(_))));
+
+        let (_, provider) = MockIdp::start(
+            |idp| {
+                let mut claims = idp.claims(NONCE, 300);
+                claims["iss"] = json!("https://evil.example.com");
+                idp.id_token(claims)
+            },
+            userinfo,
+        );
+        assert!(matches!(sign_in(&provider, "valid-code").await, Err(OidcError::InvalidIdToken(_))));
+    }
+
+    #[actix_web::test]
+    async fn rejects_symmetric_id_token() {
+        let userinfo = json!({ "sub": "upstream-subject" });
+        let (_, provider) = MockIdp::start(
+            |idp| {
+                let key = EncodingKey::from_secret(b"secret");
+                encode(&Header::new(Algorithm::HS256), &idp.claims(NONCE, 300), &key).unwrap()
+            },
+            userinfo,
+        );
+
+        match sign_in(&provider, "valid-code").await {
+            Err(OidcError::InvalidIdToken(err)) => assert!(err.contains("Symmetric"), "{}", err),
+            _ => panic!("the symmetric ID token was accepted"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn rejects_refused_code_and_foreign_userinfo() {
+        let userinfo = json!({ "sub": "another-subject" });
+        let (_, provider) = MockIdp::start(|idp| idp.id_token(idp.claims(NONCE, 300)), userinfo);
+
+        assert!(matches!(sign_in(&provider, "invalid-code").await, Err(OidcError::TokenExchange(_))));
+        assert!(matches!(sign_in(&provider, "valid-code").await, Err(OidcError::UserInfo(_))));
+    }
+
+    #[actix_web::test]
+    async fn rejects_discovery_of_another_issuer() {
+        let userinfo = json!({ "sub": "upstream-subject" });
+        let (_, mut provider) = MockIdp::start(|idp| idp.id_token(idp.claims(NONCE, 300)), userinfo);
+        provider.issuer = provider.issuer.replace("127.0.0.1", "localhost");
+
+        assert!(matches!(ProviderMetadata::discover(&provider).await, Err(OidcError::Discovery(_))));
+    }
+}
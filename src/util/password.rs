@@ -0,0 +1,376 @@
+use argon2::{
+    password_hash::PasswordHash,
+    Argon2, PasswordVerifier,
+};
+use base64::{
+    alphabet,
+    engine::{general_purpose::STANDARD, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use crypto::{
+    bcrypt::bcrypt,
+    hmac::Hmac,
+    pbkdf2::pbkdf2,
+    scrypt::{scrypt, ScryptParams},
+    sha2::Sha256,
+    util::fixed_time_eq,
+};
+
+/// Base64 variant of bcrypt hashes, its salts and digests have unused trailing bits
+const BCRYPT_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::BCRYPT,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::RequireNone)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// Maximum number of password bytes bcrypt takes into account, including the terminating null byte
+const BCRYPT_MAX_PASSWORD_BYTES: usize = 72;
+
+/// Maximum length in bytes of the salts and digests of imported hashes
+const MAX_IMPORTED_HASH_BYTES: usize = 64;
+
+/// Format of a stored password hash. Passwords are hashed with Argon2,
+/// the other formats come from users imported from other applications
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordHashFormat {
+    /// PHC string, e.g. $argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>
+    Argon2,
+    /// Modular crypt string, e.g. $2b$12$<salt><hash>
+    Bcrypt,
+    /// PHC string, e.g. $scrypt$ln=17,r=8,p=1$<salt>$<hash>
+    Scrypt,
+    /// PHC string, e.g. $pbkdf2-sha256$i=600000,l=32$<salt>$<hash>
+    Pbkdf2Sha256,
+    /// Django's format, e.g. pbkdf2_sha256$600000$<salt>$<base64 hash>
+    DjangoPbkdf2Sha256,
+}
+
+/// ## password_hash_format
+///
+/// Detects the format of a password hash, missing when the hash is malformed
+/// or its format is not supported
+///
+pub fn password_hash_format(password_hash: &str) -> Option<PasswordHashFormat> {
+    if BcryptHash::parse(password_hash).is_some() {
+        return Some(PasswordHashFormat::Bcrypt);
+    }
+
+    if DjangoPbkdf2Hash::parse(password_hash).is_some() {
+        return Some(PasswordHashFormat::DjangoPbkdf2Sha256);
+    }
+
+    let parsed = match PasswordHash::new(password_hash) {
+        Ok(parsed) => parsed,
+        Err(_) => return None,
+    };
+
+    return match parsed.algorithm.as_str() {
+        "argon2d" | "argon2i" | "argon2id" => Some(PasswordHashFormat::Argon2),
+        "scrypt" if ScryptHash::parse(&parsed).is_some() => Some(PasswordHashFormat::Scrypt),
+        "pbkdf2-sha256" if Pbkdf2Hash::parse(&parsed).is_some() => {
+            Some(PasswordHashFormat::Pbkdf2Sha256)
+        }
+        _ => None,
+    };
+}
+
+/// ## verify_password_hash
+///
+/// Checks a password against a hash in any of the supported formats.
+/// Malformed and unsupported hashes never match
+///
+pub fn verify_password_hash(password_hash: &str, password: &str) -> bool {
+    let format = match password_hash_format(password_hash) {
+        Some(format) => format,
+        None => return false,
+    };
+
+    return match format {
+        PasswordHashFormat::Argon2 => match PasswordHash::new(password_hash) {
+            Ok(parsed) => Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok(),
+            Err(_) => false,
+        },
+        PasswordHashFormat::Bcrypt => match BcryptHash::parse(password_hash) {
+            Some(hash) => hash.verify(password),
+            None => false,
+        },
+        PasswordHashFormat::Scrypt => match PasswordHash::new(password_hash) {
+            Ok(parsed) => ScryptHash::parse(&parsed).is_some_and(|hash| hash.verify(password)),
+            Err(_) => false,
+        },
+        PasswordHashFormat::Pbkdf2Sha256 => match PasswordHash::new(password_hash) {
+            Ok(parsed) => Pbkdf2Hash::parse(&parsed).is_some_and(|hash| hash.verify(password)),
+            Err(_) => false,
+        },
+        PasswordHashFormat::DjangoPbkdf2Sha256 => match DjangoPbkdf2Hash::parse(password_hash) {
+            Some(hash) => hash.verify(password),
+            None => false,
+        },
+    };
+}
+
+struct BcryptHash {
+    cost: u32,
+    salt: Vec<u8>,
+    digest: Vec<u8>,
+}
+
+impl BcryptHash {
+    /// Parses $2a$, $2b$ and $2y$ hashes, they only differ in bugs of old implementations
+    fn parse(password_hash: &str) -> Option<Self> {
+        let rest = ["$2a$", "$2b$", "$2y$"]
+            .iter()
+            .find_map(|prefix| password_hash.strip_prefix(prefix))?;
+
+        let (cost, rest) = rest.split_once('$')?;
+        if cost.len() != 2 || rest.len() != 53 || !rest.is_ascii() {
+            return None;
+        }
+
+        let cost = cost.parse::<u32>().ok().filter(|cost| (4..=31).contains(cost))?;
+        let salt = BCRYPT_BASE64.decode(&rest[..22]).ok()?;
+        let digest = BCRYPT_BASE64.decode(&rest[22..]).ok()?;
+
+        if salt.len() != 16 || digest.len() != 23 {
+            return None;
+        }
+
+        return Some(BcryptHash { cost, salt, digest });
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        // the password is hashed as a null-terminated string, truncated to its first 72 bytes
+        let mut key = password.as_bytes().to_vec();
+        key.push(0);
+        key.truncate(BCRYPT_MAX_PASSWORD_BYTES);
+
+        let mut output = [0u8; 24];
+        bcrypt(self.cost, &self.salt, &key, &mut output);
+
+        // only 23 of the 24 bytes are encoded in the hash
+        return fixed_time_eq(&output[..23], &self.digest);
+    }
+}
+
+struct ScryptHash {
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: Vec<u8>,
+    digest: Vec<u8>,
+}
+
+impl ScryptHash {
+    /// Reads the ln, r and p parameters, rejecting the ones scrypt would panic on
+    fn parse(parsed: &PasswordHash) -> Option<Self> {
+        let log_n = u8::try_from(parsed.params.get_decimal("ln")?).ok()?;
+        let r = parsed.params.get_decimal("r")?;
+        let p = parsed.params.get_decimal("p")?;
+
+        if log_n == 0 || r == 0 || p == 0 || u32::from(log_n) >= r.saturating_mul(16).min(64) {
+            return None;
+        }
+
+        let r128 = (r as usize).checked_mul(128)?;
+        r128.checked_mul(1usize.checked_shl(log_n.into())?)?;
+        r128.checked_mul(p as usize)?;
+
+        if u64::from(p) > (u64::from(u32::MAX) * 32) / (r128 as u64) {
+            return None;
+        }
+
+        let (salt, digest) = decode_phc_salt_and_digest(parsed)?;
+
+        return Some(ScryptHash { log_n, r, p, salt, digest });
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        let params = ScryptParams::new(self.log_n, self.r, self.p);
+        let mut output = vec![0u8; self.digest.len()];
+        scrypt(password.as_bytes(), &self.salt, &params, &mut output);
+
+        return fixed_time_eq(&output, &self.digest);
+    }
+}
+
+struct Pbkdf2Hash {
+    iterations: u32,
+    salt: Vec<u8>,
+    digest: Vec<u8>,
+}
+
+impl Pbkdf2Hash {
+    fn parse(parsed: &PasswordHash) -> Option<Self> {
+        let iterations = parsed.params.get_decimal("i").filter(|iterations| *iterations > 0)?;
+        let (salt, digest) = decode_phc_salt_and_digest(parsed)?;
+
+        // the optional output length has to match the digest
+        if let Some(length) = parsed.params.get_decimal("l") {
+            if length as usize != digest.len() {
+                return None;
+            }
+        }
+
+        return Some(Pbkdf2Hash { iterations, salt, digest });
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        return verify_pbkdf2_sha256(password, &self.salt, self.iterations, &self.digest);
+    }
+}
+
+struct DjangoPbkdf2Hash {
+    iterations: u32,
+    salt: String,
+    digest: Vec<u8>,
+}
+
+impl DjangoPbkdf2Hash {
+    fn parse(password_hash: &str) -> Option<Self> {
+        let mut parts = password_hash.split('$');
+
+        if parts.next()? != "pbkdf2_sha256" {
+            return None;
+        }
+
+        let iterations = parts.next()?.parse::<u32>().ok().filter(|iterations| *iterations > 0)?;
+        let salt = parts.next().filter(|salt| !salt.is_empty())?.to_string();
+        let digest = STANDARD.decode(parts.next()?).ok()?;
+
+        if parts.next().is_some() || digest.is_empty() || digest.len() > MAX_IMPORTED_HASH_BYTES {
+            return None;
+        }
+
+        return Some(DjangoPbkdf2Hash { iterations, salt, digest });
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        // django uses the salt as is, not decoded
+        return verify_pbkdf2_sha256(password, self.salt.as_bytes(), self.iterations, &self.digest);
+    }
+}
+
+fn decode_phc_salt_and_digest(parsed: &PasswordHash) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut buf = [0u8; MAX_IMPORTED_HASH_BYTES];
+    let salt = parsed.salt?.decode_b64(&mut buf).ok()?.to_vec();
+    let digest = parsed.hash?.as_bytes().to_vec();
+
+    if digest.len() > MAX_IMPORTED_HASH_BYTES {
+        return None;
+    }
+
+    return Some((salt, digest));
+}
+
+fn verify_pbkdf2_sha256(password: &str, salt: &[u8], iterations: u32, digest: &[u8]) -> bool {
+    let mut mac = Hmac::new(Sha256::new(), password.as_bytes());
+    let mut output = vec![0u8; digest.len()];
+    pbkdf2(&mut mac, salt, iterations, &mut output);
+
+    return fixed_time_eq(&output, digest);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARGON2_HASH: &str =
+        "$argon2id$v=19$m=256,t=1,p=1$czRsdHlzNGx0eXM0bHR5IQ$DtvAS7NgaJsWWOgyngyGbA6XZsBBELHscEnzgy0BIYI";
+    const SCRYPT_HASH: &str =
+        "$scrypt$ln=10,r=8,p=1$aIu5MdCjri5Tz5WnWAB/eg$Y91BgBAZs98GCCnkgXLTXPdtNNMAH5vxttxdY/hAw1E";
+    const PBKDF2_HASH: &str =
+        "$pbkdf2-sha256$i=1000,l=32$aIu5MdCjri5Tz5WnWAB/eg$jvDdMQ+ZJYUWaAwF2CwTHNzQxd7p550VTVgBRnYbkzY";
+    const DJANGO_HASH: &str = "pbkdf2_sha256$1000$s4ltyS4lt$ke6bSeB9NG3eOhRmS7Mtw3Jz6PwJghPDtuI9dtid0GU=";
+    const PASSWORD: &str = "correct horse";
+
+    // test vectors from openwall's crypt_blowfish
+    const BCRYPT_HASH: &str = "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW";
+    const BCRYPT_PASSWORD: &str = "U*U";
+    const BCRYPT_LONG_HASH: &str = "$2a$05$abcdefghijklmnopqrstuu5s2v8.iXieOjg/.AySBTTZIIVFJeBui";
+    const BCRYPT_LONG_PASSWORD: &str =
+        "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789chars after 72 are ignored";
+
+    fn scrypt_params(params: &str) -> Option<ScryptHash> {
+        let password_hash = format!("$scrypt${}$aIu5MdCjri5Tz5WnWAB/eg$Y91BgBAZs98GCCnkgXLTXPdtNNMAH5vxttxdY/hAw1E", params);
+        let parsed = PasswordHash::new(&password_hash).unwrap();
+        return ScryptHash::parse(&parsed);
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(password_hash_format(ARGON2_HASH), Some(PasswordHashFormat::Argon2));
+        assert_eq!(password_hash_format(BCRYPT_HASH), Some(PasswordHashFormat::Bcrypt));
+        assert_eq!(password_hash_format(SCRYPT_HASH), Some(PasswordHashFormat::Scrypt));
+        assert_eq!(password_hash_format(PBKDF2_HASH), Some(PasswordHashFormat::Pbkdf2Sha256));
+        assert_eq!(password_hash_format(DJANGO_HASH), Some(PasswordHashFormat::DjangoPbkdf2Sha256));
+    }
+
+    #[test]
+    fn verifies_known_answers() {
+        for password_hash in [ARGON2_HASH, SCRYPT_HASH, PBKDF2_HASH, DJANGO_HASH] {
+            assert!(verify_password_hash(password_hash, PASSWORD), "{}", password_hash);
+            assert!(!verify_password_hash(password_hash, "correct horse battery"), "{}", password_hash);
+        }
+
+        assert!(verify_password_hash(BCRYPT_HASH, BCRYPT_PASSWORD));
+        assert!(!verify_password_hash(BCRYPT_HASH, "U*U*"));
+    }
+
+    #[test]
+    fn bcrypt_ignores_bytes_after_72() {
+        assert!(verify_password_hash(BCRYPT_LONG_HASH, BCRYPT_LONG_PASSWORD));
+        assert!(verify_password_hash(BCRYPT_LONG_HASH, &BCRYPT_LONG_PASSWORD[..72]));
+        assert!(verify_password_hash(BCRYPT_LONG_HASH, &format!("{}something else", &BCRYPT_LONG_PASSWORD[..72])));
+        assert!(!verify_password_hash(BCRYPT_LONG_HASH, &BCRYPT_LONG_PASSWORD[..71]));
+    }
+
+    #[test]
+    fn rejects_malformed_hashes() {
+        let malformed = [
+            "",
+            "password",
+            "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOe",
+            "$2a$5$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW",
+            "$2a$32$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW",
+            "$2x$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW",
+            "$2a$05$CCCCCCCCCCCCCCCCCCCCC!E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW",
+            "$scrypt$ln=10,r=8$aIu5MdCjri5Tz5WnWAB/eg$Y91BgBAZs98GCCnkgXLTXPdtNNMAH5vxttxdY/hAw1E",
+            "$scrypt$ln=10,r=8,p=1$aIu5MdCjri5Tz5WnWAB/eg",
+            "$pbkdf2-sha256$i=0,l=32$aIu5MdCjri5Tz5WnWAB/eg$jvDdMQ+ZJYUWaAwF2CwTHNzQxd7p550VTVgBRnYbkzY",
+            "$pbkdf2-sha256$i=1000,l=16$aIu5MdCjri5Tz5WnWAB/eg$jvDdMQ+ZJYUWaAwF2CwTHNzQxd7p550VTVgBRnYbkzY",
+            "$pbkdf2-sha1$i=1000$aIu5MdCjri5Tz5WnWAB/eg$jvDdMQ+ZJYUWaAwF2CwTHNzQxd7p550VTVgBRnYbkzY",
+            "pbkdf2_sha256$0$s4ltyS4lt$ke6bSeB9NG3eOhRmS7Mtw3Jz6PwJghPDtuI9dtid0GU=",
+            "pbkdf2_sha256$1000$$ke6bSeB9NG3eOhRmS7Mtw3Jz6PwJghPDtuI9dtid0GU=",
+            "pbkdf2_sha256$1000$s4ltyS4lt$not base64!",
+            "pbkdf2_sha256$1000$s4ltyS4lt$ke6bSeB9NG3eOhRmS7Mtw3Jz6PwJghPDtuI9dtid0GU=$",
+            "pbkdf2_sha1$1000$s4ltyS4lt$ke6bSeB9NG3eOhRmS7Mtw3Jz6PwJghPDtuI9dtid0GU=",
+            "$md5$rounds=1000$salt$hash",
+        ];
+
+        for password_hash in malformed {
+            assert_eq!(password_hash_format(password_hash), None, "{}", password_hash);
+            assert!(!verify_password_hash(password_hash, PASSWORD), "{}", password_hash);
+        }
+    }
+
+    #[test]
+    fn scrypt_parameter_bounds() {
+        assert!(scrypt_params("ln=10,r=8,p=1").is_some());
+        assert!(scrypt_params("ln=0,r=8,p=1").is_none());
+        assert!(scrypt_params("ln=10,r=0,p=1").is_none());
+        assert!(scrypt_params("ln=10,r=8,p=0").is_none());
+        assert!(scrypt_params("ln=256,r=8,p=1").is_none());
+
+        // n has to be below 2^(128 * r / 8)
+        assert!(scrypt_params("ln=15,r=1,p=1").is_some());
+        assert!(scrypt_params("ln=16,r=1,p=1").is_none());
+        assert!(scrypt_params("ln=63,r=8,p=1").is_none());
+
+        // r * p has to stay below 2^30
+        assert!(scrypt_params("ln=1,r=4294967295,p=4294967295").is_none());
+    }
+}
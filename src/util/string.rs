@@ -139,3 +139,25 @@ fn json_value_to_pretty_string_with_prefix(value: &serde_json::Value, indent_tim
 
   return String::new();
 }
+
+/// ## escape_html
+///
+/// Escapes the characters with a special meaning in HTML,
+/// so user provided values can be embedded in pages and attributes
+///
+pub fn escape_html(value: &str) -> String {
+  let mut escaped = String::with_capacity(value.len());
+
+  for c in value.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      _ => escaped.push(c)
+    }
+  }
+
+  return escaped;
+}
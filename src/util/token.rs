@@ -0,0 +1,67 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use crypto::{hmac::Hmac, mac::Mac, sha2::Sha256};
+use ring::digest::{digest, SHA256};
+
+/// Number of random bytes in every generated token (256 bits)
+const TOKEN_BYTES: usize = 32;
+
+/// How many times a token is regenerated when it collides with an existing one
+pub const TOKEN_INSERT_ATTEMPTS: usize = 5;
+
+/// ## generate_token
+///
+/// Generates a random, URL-safe token from the operating system's secure random number generator.
+/// Used for login session tokens and event keys.
+///
+/// Panics when the operating system's random number generator is unavailable.
+///
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+
+    return URL_SAFE_NO_PAD.encode(bytes);
+}
+
+/// ## hash_token
+///
+/// Computes a hex encoded HMAC-SHA256 digest of a token keyed with the server-side pepper.
+/// Only these digests are stored in the database, so leaked rows cannot be used as tokens.
+///
+pub fn hash_token(token: &str, pepper: &str) -> String {
+    let mut hmac = Hmac::new(Sha256::new(), pepper.as_bytes());
+    hmac.input(token.as_bytes());
+
+    return hmac
+        .result()
+        .code()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+}
+
+/// ## verify_code_verifier
+///
+/// Checks a PKCE code verifier against the S256 challenge it was derived from, as described in RFC 7636.
+/// Verifiers have to be 43 to 128 unreserved characters long
+///
+pub fn verify_code_verifier(verifier: &str, challenge: &str) -> bool {
+    let valid_format = (43..=128).contains(&verifier.len())
+        && verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c));
+
+    if !valid_format {
+        return false;
+    }
+
+    return code_challenge(verifier) == challenge;
+}
+
+/// ## code_challenge
+///
+/// Derives the S256 PKCE challenge of a code verifier, as described in RFC 7636
+///
+pub fn code_challenge(verifier: &str) -> String {
+    return URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes()));
+}
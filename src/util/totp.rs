@@ -0,0 +1,118 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use crypto::{hmac::Hmac, mac::Mac, sha1::Sha1};
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+/// Number of random bytes in a TOTP secret (160 bits, as recommended by RFC 4226)
+const SECRET_BYTES: usize = 20;
+
+/// Length of a time step in seconds
+pub const TOTP_PERIOD: i64 = 30;
+
+/// Number of digits of a code
+const TOTP_DIGITS: u32 = 6;
+
+/// How many time steps before and after the current one are accepted, to allow for clock drift
+const TOTP_SKEW: i64 = 1;
+
+/// Number of characters of a recovery code, without the separator
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// ## generate_secret
+///
+/// Generates a random TOTP secret, encoded in base32 as expected by authenticator apps
+///
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+
+    return BASE32_NOPAD.encode(&bytes);
+}
+
+/// ## provisioning_uri
+///
+/// Builds the `otpauth://` URI authenticator apps are enrolled with (usually shown as a QR code)
+///
+pub fn provisioning_uri(secret: &str, issuer: &str, account: &str) -> String {
+    let issuer = utf8_percent_encode(issuer, NON_ALPHANUMERIC).to_string();
+    let account = utf8_percent_encode(account, NON_ALPHANUMERIC).to_string();
+
+    return format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer, account, secret, issuer, TOTP_DIGITS, TOTP_PERIOD
+    );
+}
+
+/// ## code_at
+///
+/// Computes the code of a base32 encoded secret for specified time step as described in RFC 6238,
+/// returning None when the secret is not valid base32
+///
+pub fn code_at(secret: &str, step: i64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let mut hmac = Hmac::new(Sha1::new(), &key);
+    hmac.input(&(step as u64).to_be_bytes());
+    let digest = hmac.result();
+    let digest = digest.code();
+
+    // dynamic truncation from RFC 4226
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    return Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    ));
+}
+
+/// ## matching_step
+///
+/// Finds the time step around `now` (in seconds since the epoch) the code was generated for.
+/// The step is stored after a successful login so the same code cannot be used twice
+///
+pub fn matching_step(secret: &str, code: &str, now: i64) -> Option<i64> {
+    let current = now / TOTP_PERIOD;
+
+    return (current - TOTP_SKEW..=current + TOTP_SKEW)
+        .find(|step| code_at(secret, *step).as_deref() == Some(code.trim()));
+}
+
+/// ## generate_recovery_code
+///
+/// Generates a random single-use recovery code formatted as `xxxxx-xxxxx`
+///
+pub fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; RECOVERY_CODE_LENGTH];
+    OsRng.fill_bytes(&mut bytes);
+
+    let code = BASE32_NOPAD.encode(&bytes).to_lowercase();
+    let (first, second) = code[..RECOVERY_CODE_LENGTH].split_at(RECOVERY_CODE_LENGTH / 2);
+
+    return format!("{}-{}", first, second);
+}
+
+/// ## normalize_recovery_code
+///
+/// Strips the separator, whitespace and case from a recovery code typed by the user,
+/// returning None when it cannot be a recovery code (e.g. it's a TOTP code)
+///
+pub fn normalize_recovery_code(code: &str) -> Option<String> {
+    let normalized: String = code
+        .chars()
+        .filter(|char| *char != '-' && !char.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+
+    if normalized.len() != RECOVERY_CODE_LENGTH {
+        return None;
+    }
+
+    return Some(normalized);
+}
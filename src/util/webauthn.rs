@@ -0,0 +1,515 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::value::Value;
+use ring::{
+    digest::{digest, SHA256},
+    signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1, ED25519},
+};
+use serde::Deserialize;
+
+/// COSE identifier of ECDSA with P-256 and SHA-256
+pub const COSE_ALG_ES256: i32 = -7;
+/// COSE identifier of EdDSA (Ed25519)
+pub const COSE_ALG_EDDSA: i32 = -8;
+
+/// User present flag of the authenticator data
+const FLAG_USER_PRESENT: u8 = 0x01;
+/// User verified flag of the authenticator data, set after a PIN or biometric check
+const FLAG_USER_VERIFIED: u8 = 0x04;
+/// Attested credential data included flag of the authenticator data
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// Length of the authenticator data without the attested credential data and extensions
+const AUTHENTICATOR_DATA_MIN_LENGTH: usize = 37;
+
+/// `clientDataJSON` collected by the browser during a ceremony
+#[derive(Deserialize)]
+pub struct ClientData {
+    #[serde(rename = "type")]
+    pub ceremony_type: String,
+    /// base64url encoded challenge the ceremony was started with
+    pub challenge: String,
+    pub origin: String,
+}
+
+/// Authenticator data returned by both ceremonies
+pub struct AuthenticatorData {
+    pub sign_count: u32,
+    /// Whether the authenticator verified the user, not only their presence
+    pub user_verified: bool,
+    /// Only present in the registration ceremony
+    pub attested_credential: Option<AttestedCredential>,
+}
+
+/// Credential created by the authenticator in the registration ceremony
+pub struct AttestedCredential {
+    pub credential_id: Vec<u8>,
+    pub algorithm: i32,
+    /// Public key in the format expected by ring, e.g. an uncompressed point for ES256
+    pub public_key: Vec<u8>,
+}
+
+/// ## parse_client_data
+///
+/// Decodes `clientDataJSON` and checks its type and origin.
+/// The challenge is checked by the caller against the stored ones
+///
+pub fn parse_client_data(
+    client_data_json: &[u8],
+    ceremony_type: &str,
+    origin: &str,
+) -> Result<ClientData, String> {
+    let client_data: ClientData = match serde_json::from_slice(client_data_json) {
+        Ok(client_data) => client_data,
+        Err(err) => return Err(format!("Cannot parse the client data: {}", err)),
+    };
+
+    if client_data.ceremony_type != ceremony_type {
+        return Err(format!("Expected a {} ceremony", ceremony_type));
+    }
+
+    if client_data.origin != origin {
+        return Err(format!("Origin {} is not allowed", client_data.origin));
+    }
+
+    return Ok(client_data);
+}
+
+/// ## parse_attestation_object
+///
+/// Extracts the authenticator data from an attestation object.
+/// The attestation statement is not verified, every format is treated as `none`
+///
+pub fn parse_attestation_object(attestation_object: &[u8]) -> Result<Vec<u8>, String> {
+    let value: Value = match ciborium::de::from_reader(attestation_object) {
+        Ok(value) => value,
+        Err(err) => return Err(format!("Cannot parse the attestation object: {}", err)),
+    };
+
+    return map_get(&value, &Value::Text("authData".to_string()))
+        .and_then(|auth_data| auth_data.as_bytes())
+        .cloned()
+        .ok_or("The attestation object has no authenticator data".to_string());
+}
+
+/// ## parse_authenticator_data
+///
+/// Parses the authenticator data and checks that it was created for the relying party
+/// and the user was present
+///
+pub fn parse_authenticator_data(data: &[u8], rp_id: &str) -> Result<AuthenticatorData, String> {
+    if data.len() < AUTHENTICATOR_DATA_MIN_LENGTH {
+        return Err("The authenticator data is too short".to_string());
+    }
+
+    let rp_id_hash = &data[..32];
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    if rp_id_hash != digest(&SHA256, rp_id.as_bytes()).as_ref() {
+        return Err("The credential was not created for this relying party".to_string());
+    }
+
+    if flags & FLAG_USER_PRESENT == 0 {
+        return Err("The user was not present".to_string());
+    }
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+        Some(parse_attested_credential(&data[AUTHENTICATOR_DATA_MIN_LENGTH..])?)
+    } else {
+        None
+    };
+
+    return Ok(AuthenticatorData {
+        sign_count,
+        user_verified: flags & FLAG_USER_VERIFIED != 0,
+        attested_credential,
+    });
+}
+
+/// ## verify_assertion
+///
+/// Verifies the signature of an assertion, made over the authenticator data
+/// followed by the SHA-256 hash of `clientDataJSON`
+///
+pub fn verify_assertion(
+    algorithm: i32,
+    public_key: &[u8],
+    authenticator_data: &[u8],
+    client_data_json: &[u8],
+    signature: &[u8],
+) -> bool {
+    let mut signed = authenticator_data.to_vec();
+    signed.extend_from_slice(digest(&SHA256, client_data_json).as_ref());
+
+    return match algorithm {
+        COSE_ALG_ES256 => UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, public_key)
+            .verify(&signed, signature)
+            .is_ok(),
+        COSE_ALG_EDDSA => UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(&signed, signature)
+            .is_ok(),
+        _ => false,
+    };
+}
+
+/// ## sign_count_increased
+///
+/// Checks that the signature counter reported in an assertion increased since the last one.
+/// Authenticators without a counter always report 0
+///
+pub fn sign_count_increased(stored_sign_count: u32, sign_count: u32) -> bool {
+    return (sign_count == 0 && stored_sign_count == 0) || sign_count > stored_sign_count;
+}
+
+/// ## decode_base64url
+///
+/// Decodes the base64url values WebAuthn clients send, with or without padding
+///
+pub fn decode_base64url(value: &str) -> Result<Vec<u8>, String> {
+    return URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|err| format!("Invalid base64url value: {}", err));
+}
+
+fn parse_attested_credential(data: &[u8]) -> Result<AttestedCredential, String> {
+    // 16 bytes of AAGUID followed by the length of the credential id
+    if data.len() < 18 {
+        return Err("The attested credential data is too short".to_string());
+    }
+
+    let id_length = u16::from_be_bytes([data[16], data[17]]) as usize;
+
+    if data.len() < 18 + id_length {
+        return Err("The attested credential data is too short".to_string());
+    }
+
+    let credential_id = data[18..18 + id_length].to_vec();
+    let cose_key: Value = match ciborium::de::from_reader(&data[18 + id_length..]) {
+        Ok(cose_key) => cose_key,
+        Err(err) => return Err(format!("Cannot parse the credential public key: {}", err)),
+    };
+
+    let (algorithm, public_key) = parse_cose_key(&cose_key)?;
+
+    return Ok(AttestedCredential {
+        credential_id,
+        algorithm,
+        public_key,
+    });
+}
+
+/// Converts a COSE_Key into the algorithm and the raw public key used by ring
+fn parse_cose_key(cose_key: &Value) -> Result<(i32, Vec<u8>), String> {
+    let integer = |label: i64| {
+        label_integer(cose_key, label).ok_or(format!("The public key has no parameter {}", label))
+    };
+    let bytes = |label: i64| {
+        map_get(cose_key, &Value::Integer(label.into()))
+            .and_then(|value| value.as_bytes())
+            .cloned()
+            .ok_or(format!("The public key has no parameter {}", label))
+    };
+
+    let algorithm = integer(3)?;
+
+    return match algorithm {
+        // EC2 key on the P-256 curve
+        COSE_ALG_ES256 if integer(1)? == 2 && integer(-1)? == 1 => {
+            let mut point = vec![0x04];
+            point.extend(bytes(-2)?);
+            point.extend(bytes(-3)?);
+
+            Ok((algorithm, point))
+        }
+        // OKP key on the Ed25519 curve
+        COSE_ALG_EDDSA if integer(1)? == 1 && integer(-1)? == 6 => Ok((algorithm, bytes(-2)?)),
+        _ => Err(format!("Unsupported public key algorithm {}", algorithm)),
+    };
+}
+
+fn label_integer(map: &Value, label: i64) -> Option<i32> {
+    let value = map_get(map, &Value::Integer(label.into()))?.as_integer()?;
+
+    return i32::try_from(i128::from(value)).ok();
+}
+
+fn map_get<'a>(map: &'a Value, key: &Value) -> Option<&'a Value> {
+    return map
+        .as_map()?
+        .iter()
+        .find(|(entry_key, _)| entry_key == key)
+        .map(|(_, value)| value);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+
+    pub(crate) const RP_ID: &str = "auth.example.com";
+    pub(crate) const ORIGIN: &str = "https://auth.example.com";
+    pub(crate) const CREDENTIAL_ID: &[u8] = b"software-credential";
+
+    /// Authenticator holding a single credential in memory, signing like a security key would
+    pub(crate) enum SoftwareAuthenticator {
+        Es256(EcdsaKeyPair),
+        EdDsa(Ed25519KeyPair),
+    }
+
+    impl SoftwareAuthenticator {
+        pub(crate) fn es256() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+
+            return SoftwareAuthenticator::Es256(key_pair);
+        }
+
+        fn eddsa() -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+
+            return SoftwareAuthenticator::EdDsa(Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap());
+        }
+
+        fn cose_key(&self) -> Value {
+            let int = |value: i64| Value::Integer(value.into());
+
+            return match self {
+                SoftwareAuthenticator::Es256(key_pair) => {
+                    let point = key_pair.public_key().as_ref();
+
+                    Value::Map(vec![
+                        (int(1), int(2)),
+                        (int(3), int(COSE_ALG_ES256.into())),
+                        (int(-1), int(1)),
+                        (int(-2), Value::Bytes(point[1..33].to_vec())),
+                        (int(-3), Value::Bytes(point[33..].to_vec())),
+                    ])
+                }
+                SoftwareAuthenticator::EdDsa(key_pair) => Value::Map(vec![
+                    (int(1), int(1)),
+                    (int(3), int(COSE_ALG_EDDSA.into())),
+                    (int(-1), int(6)),
+                    (int(-2), Value::Bytes(key_pair.public_key().as_ref().to_vec())),
+                ]),
+            };
+        }
+
+        fn authenticator_data(&self, rp_id: &str, flags: u8, sign_count: u32, attested: bool) -> Vec<u8> {
+            let mut data = digest(&SHA256, rp_id.as_bytes()).as_ref().to_vec();
+            data.push(flags | if attested { FLAG_ATTESTED_CREDENTIAL_DATA } else { 0 });
+            data.extend_from_slice(&sign_count.to_be_bytes());
+
+            if attested {
+                data.extend_from_slice(&[0u8; 16]);
+                data.extend_from_slice(&(CREDENTIAL_ID.len() as u16).to_be_bytes());
+                data.extend_from_slice(CREDENTIAL_ID);
+                ciborium::ser::into_writer(&self.cose_key(), &mut data).unwrap();
+            }
+
+            return data;
+        }
+
+        /// `navigator.credentials.create`, with a `none` attestation
+        pub(crate) fn make_credential(&self) -> Vec<u8> {
+            let attestation_object = Value::Map(vec![
+                (Value::Text("fmt".to_string()), Value::Text("none".to_string())),
+                (Value::Text("attStmt".to_string()), Value::Map(vec![])),
+                (
+                    Value::Text("authData".to_string()),
+                    Value::Bytes(self.authenticator_data(RP_ID, FLAG_USER_PRESENT, 0, true)),
+                ),
+            ]);
+
+            let mut encoded = vec![];
+            ciborium::ser::into_writer(&attestation_object, &mut encoded).unwrap();
+
+            return encoded;
+        }
+
+        /// `navigator.credentials.get`, returning the authenticator data and its signature.
+        /// Security keys without a PIN only report the user's presence
+        pub(crate) fn get_assertion(
+            &self,
+            sign_count: u32,
+            user_verified: bool,
+            client_data_json: &[u8],
+        ) -> (Vec<u8>, Vec<u8>) {
+            let flags = FLAG_USER_PRESENT | if user_verified { FLAG_USER_VERIFIED } else { 0 };
+            let authenticator_data = self.authenticator_data(RP_ID, flags, sign_count, false);
+            let mut signed = authenticator_data.clone();
+            signed.extend_from_slice(digest(&SHA256, client_data_json).as_ref());
+
+            let signature = match self {
+                SoftwareAuthenticator::Es256(key_pair) => key_pair
+                    .sign(&SystemRandom::new(), &signed)
+                    .unwrap()
+                    .as_ref()
+                    .to_vec(),
+                SoftwareAuthenticator::EdDsa(key_pair) => key_pair.sign(&signed).as_ref().to_vec(),
+            };
+
+            return (authenticator_data, signature);
+        }
+    }
+
+    fn client_data_json(ceremony_type: &str, origin: &str) -> Vec<u8> {
+        return client_data_json_with_challenge(ceremony_type, origin, "Y2hhbGxlbmdl");
+    }
+
+    pub(crate) fn client_data_json_with_challenge(ceremony_type: &str, origin: &str, challenge: &str) -> Vec<u8> {
+        return serde_json::to_vec(&serde_json::json!({
+            "type": ceremony_type,
+            "challenge": challenge,
+            "origin": origin,
+        }))
+        .unwrap();
+    }
+
+    fn foreign_client_data_json() -> Vec<u8> {
+        return client_data_json("webauthn.get", "https://evil.example.com");
+    }
+
+    /// Runs the registration ceremony the way the credential model does
+    fn register(authenticator: &SoftwareAuthenticator) -> AttestedCredential {
+        let client_data = parse_client_data(&client_data_json("webauthn.create", ORIGIN), "webauthn.create", ORIGIN)
+            .unwrap();
+        assert_eq!(client_data.challenge, "Y2hhbGxlbmdl");
+
+        let auth_data = parse_attestation_object(&authenticator.make_credential()).unwrap();
+        let authenticator_data = parse_authenticator_data(&auth_data, RP_ID).unwrap();
+
+        return authenticator_data.attested_credential.unwrap();
+    }
+
+    #[test]
+    fn registers_and_asserts_es256_credential() {
+        let authenticator = SoftwareAuthenticator::es256();
+        let credential = register(&authenticator);
+
+        assert_eq!(credential.credential_id, CREDENTIAL_ID);
+        assert_eq!(credential.algorithm, COSE_ALG_ES256);
+        assert_eq!(credential.public_key.len(), 65);
+
+        let client_data_json = client_data_json("webauthn.get", ORIGIN);
+        let (authenticator_data, signature) = authenticator.get_assertion(1, true, &client_data_json);
+
+        let parsed = parse_authenticator_data(&authenticator_data, RP_ID).unwrap();
+        assert_eq!(parsed.sign_count, 1);
+        assert!(parsed.attested_credential.is_none());
+        assert!(verify_assertion(
+            credential.algorithm,
+            &credential.public_key,
+            &authenticator_data,
+            &client_data_json,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn registers_and_asserts_eddsa_credential() {
+        let authenticator = SoftwareAuthenticator::eddsa();
+        let credential = register(&authenticator);
+
+        assert_eq!(credential.algorithm, COSE_ALG_EDDSA);
+
+        let client_data_json = client_data_json("webauthn.get", ORIGIN);
+        let (authenticator_data, signature) = authenticator.get_assertion(7, true, &client_data_json);
+
+        assert!(verify_assertion(
+            credential.algorithm,
+            &credential.public_key,
+            &authenticator_data,
+            &client_data_json,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn rejects_tampered_assertion() {
+        let authenticator = SoftwareAuthenticator::es256();
+        let credential = register(&authenticator);
+        let other_credential = register(&SoftwareAuthenticator::es256());
+
+        let client_data_json = client_data_json("webauthn.get", ORIGIN);
+        let (authenticator_data, signature) = authenticator.get_assertion(1, true, &client_data_json);
+
+        let mut tampered_data = authenticator_data.clone();
+        tampered_data[36] = 2;
+        let foreign_client_data_json = foreign_client_data_json();
+
+        let verify = |public_key: &[u8], data: &[u8], client_data_json: &[u8]| {
+            verify_assertion(credential.algorithm, public_key, data, client_data_json, &signature)
+        };
+
+        assert!(!verify(&other_credential.public_key, &authenticator_data, &client_data_json));
+        assert!(!verify(&credential.public_key, &tampered_data, &client_data_json));
+        assert!(!verify(&credential.public_key, &authenticator_data, &foreign_client_data_json));
+        assert!(!verify_assertion(
+            COSE_ALG_EDDSA,
+            &credential.public_key,
+            &authenticator_data,
+            &client_data_json,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn rejects_foreign_ceremonies() {
+        let authenticator = SoftwareAuthenticator::es256();
+
+        assert!(parse_client_data(&client_data_json("webauthn.get", ORIGIN), "webauthn.create", ORIGIN).is_err());
+        assert!(parse_client_data(&foreign_client_data_json(), "webauthn.get", ORIGIN).is_err());
+        assert!(parse_client_data(b"not json", "webauthn.get", ORIGIN).is_err());
+
+        let other_rp = authenticator.authenticator_data("evil.example.com", FLAG_USER_PRESENT, 1, false);
+        assert!(parse_authenticator_data(&other_rp, RP_ID).is_err());
+
+        let user_absent = authenticator.authenticator_data(RP_ID, 0, 1, false);
+        assert!(parse_authenticator_data(&user_absent, RP_ID).is_err());
+
+        let truncated = authenticator.authenticator_data(RP_ID, FLAG_USER_PRESENT, 0, true);
+        assert!(parse_authenticator_data(&truncated[..AUTHENTICATOR_DATA_MIN_LENGTH + 10], RP_ID).is_err());
+        assert!(parse_authenticator_data(&truncated[..20], RP_ID).is_err());
+    }
+
+    #[test]
+    fn reports_user_verification() {
+        let authenticator = SoftwareAuthenticator::es256();
+        let client_data_json = client_data_json("webauthn.get", ORIGIN);
+
+        let (verified, _) = authenticator.get_assertion(1, true, &client_data_json);
+        assert!(parse_authenticator_data(&verified, RP_ID).unwrap().user_verified);
+
+        // a security key without a PIN, touched by whoever holds it
+        let (present_only, _) = authenticator.get_assertion(1, false, &client_data_json);
+        assert!(!parse_authenticator_data(&present_only, RP_ID).unwrap().user_verified);
+    }
+
+    #[test]
+    fn rejects_sign_count_regression() {
+        let authenticator = SoftwareAuthenticator::es256();
+        let client_data_json = client_data_json("webauthn.get", ORIGIN);
+
+        let mut stored_sign_count = 0;
+        for sign_count in [1, 2, 5] {
+            let (authenticator_data, _) = authenticator.get_assertion(sign_count, true, &client_data_json);
+            let parsed = parse_authenticator_data(&authenticator_data, RP_ID).unwrap();
+
+            assert!(sign_count_increased(stored_sign_count, parsed.sign_count));
+            stored_sign_count = parsed.sign_count;
+        }
+
+        // a cloned authenticator replays or lags behind the counter
+        assert!(!sign_count_increased(stored_sign_count, 5));
+        assert!(!sign_count_increased(stored_sign_count, 4));
+        assert!(!sign_count_increased(stored_sign_count, 0));
+
+        // authenticators without a counter always report 0
+        assert!(sign_count_increased(0, 0));
+    }
+}
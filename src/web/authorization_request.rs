@@ -0,0 +1,274 @@
+use actix_web::{
+  http::{
+    header::{
+      HeaderValue,
+      CACHE_CONTROL,
+      LOCATION
+    },
+    StatusCode
+  },
+  HttpRequest
+};
+use percent_encoding::{
+  utf8_percent_encode,
+  NON_ALPHANUMERIC
+};
+use serde::Deserialize;
+use sqlx::PgConnection;
+use crate::{
+  config::CauthConfig,
+  models::{
+    login_session::LoginSession,
+    oauth_client::OAuthClient,
+    user::User
+  },
+  util::token::hash_token,
+  web::{
+    consent_page::error_page,
+    session_token::SessionToken,
+    ServerResponse
+  }
+};
+
+/// ## AuthorizationRequest
+///
+/// Parameters of an authorization code request as described in RFC 6749,
+/// sent to GET /oauth/authorize and carried over by the consent page.
+/// PKCE with the S256 method is required from every client
+///
+#[derive(Deserialize)]
+pub struct AuthorizationRequest {
+  pub response_type: Option<String>,
+  pub client_id: Option<String>,
+  pub redirect_uri: Option<String>,
+  /// Space separated permission names the client asks for
+  pub scope: Option<String>,
+  pub state: Option<String>,
+  pub code_challenge: Option<String>,
+  pub code_challenge_method: Option<String>,
+  /// OpenID Connect nonce, sent back in the ID token
+  pub nonce: Option<String>
+}
+
+/// ## SignInForm
+///
+/// Fields of the consent and device verification pages the user signs in with,
+/// either the credentials or the CSRF token of a session cookie
+///
+#[derive(Deserialize)]
+pub struct SignInForm {
+  pub login: Option<String>,
+  pub password: Option<String>,
+  /// TOTP or recovery code, when the second factor is enabled
+  pub code: Option<String>,
+  pub csrf_token: Option<String>
+}
+
+/// Authorization request whose client and redirect uri were checked
+pub struct ValidAuthorizationRequest {
+  pub client: OAuthClient,
+  pub redirect_uri: String,
+  pub scopes: Vec<String>,
+  pub code_challenge: String
+}
+
+pub enum AuthorizationRequestError {
+  /// Returned when the client or the redirect uri is invalid,
+  /// the user cannot be sent back to the client so an error page is shown
+  Unredirectable(String),
+  /// Returned when the request is invalid, the error is sent to the client's redirect uri
+  Redirect(String, &'static str, String)
+}
+
+impl AuthorizationRequest {
+  /// ## AuthorizationRequest::validate
+  ///
+  /// Checks the client, the redirect uri and the parameters of the request
+  ///
+  /// Errors:
+  /// + when the client do not exist or the redirect uri is not registered for it
+  /// + when the response type is not `code` or the PKCE challenge is missing
+  ///
+  pub async fn validate(
+    self: &Self,
+    conn: &mut PgConnection
+  ) -> Result<ValidAuthorizationRequest, AuthorizationRequestError> {
+    let client_id = match &self.client_id {
+      Some(client_id) => client_id,
+      None => return Err(AuthorizationRequestError::Unredirectable("The client_id parameter is missing".to_string()))
+    };
+
+    let client = match OAuthClient::retrieve(conn, client_id).await {
+      Ok(client) => client,
+      Err(err) => return Err(AuthorizationRequestError::Unredirectable(err.to_string()))
+    };
+
+    let redirect_uri = match &self.redirect_uri {
+      Some(redirect_uri) if client.allows_redirect_uri(redirect_uri) => redirect_uri.clone(),
+      _ => return Err(AuthorizationRequestError::Unredirectable(
+        "The redirect_uri is missing or not registered for this client".to_string()
+      ))
+    };
+
+    if self.response_type.as_deref() != Some("code") {
+      return Err(AuthorizationRequestError::Redirect(
+        redirect_uri,
+        "unsupported_response_type",
+        "Only the code response type is supported".to_string()
+      ));
+    }
+
+    let code_challenge = match (&self.code_challenge, self.code_challenge_method.as_deref()) {
+      (Some(code_challenge), Some("S256")) => code_challenge.clone(),
+      _ => return Err(AuthorizationRequestError::Redirect(
+        redirect_uri,
+        "invalid_request",
+        "A PKCE code_challenge with the S256 method is required".to_string()
+      ))
+    };
+
+    return Ok(ValidAuthorizationRequest {
+      client,
+      redirect_uri,
+      scopes: self.scopes(),
+      code_challenge
+    });
+  }
+
+  /// ## AuthorizationRequest::scopes
+  ///
+  /// Splits the requested scope into permission names
+  ///
+  pub fn scopes(self: &Self) -> Vec<String> {
+    return match &self.scope {
+      Some(scope) => scope
+        .split_whitespace()
+        .map(|scope| scope.to_string())
+        .collect(),
+      None => vec![]
+    };
+  }
+
+  /// ## AuthorizationRequest::error_response
+  ///
+  /// Either shows the error page or sends the error back to the client
+  ///
+  pub fn error_response(self: &Self, error: AuthorizationRequestError) -> ServerResponse {
+    return match error {
+      AuthorizationRequestError::Unredirectable(message) => ServerResponse::html(
+        StatusCode::BAD_REQUEST,
+        error_page(&message)
+      ),
+      AuthorizationRequestError::Redirect(redirect_uri, error, description) => self.redirect(
+        &redirect_uri,
+        vec![("error", error.to_string()), ("error_description", description)]
+      )
+    };
+  }
+
+  /// ## AuthorizationRequest::redirect
+  ///
+  /// Sends the user back to the client's redirect uri
+  /// with provided parameters and the request's state
+  ///
+  pub fn redirect(self: &Self, redirect_uri: &String, params: Vec<(&str, String)>) -> ServerResponse {
+    let mut location = redirect_uri.clone();
+    let mut params = params;
+
+    if let Some(state) = &self.state {
+      params.push(("state", state.clone()));
+    }
+
+    for (name, value) in params {
+      location.push(if location.contains('?') { '&' } else { '?' });
+      location.push_str(name);
+      location.push('=');
+      location.push_str(&utf8_percent_encode(&value, NON_ALPHANUMERIC).to_string());
+    }
+
+    let location = match HeaderValue::from_str(&location) {
+      Ok(location) => location,
+      Err(_) => return ServerResponse::html(
+        StatusCode::BAD_REQUEST,
+        error_page("The redirect_uri cannot be used in a redirect")
+      )
+    };
+
+    return ServerResponse::new(StatusCode::FOUND, None)
+      .with_header(LOCATION, location)
+      .with_header(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+  }
+}
+
+/// ## cookie_session
+///
+/// Retrieves the first-party login session of the browser from the session cookie,
+/// together with its token
+///
+pub async fn cookie_session(
+  req: &HttpRequest,
+  conn: &mut PgConnection,
+  config: &CauthConfig
+) -> Option<(LoginSession, String)> {
+  let token = SessionToken::from_cookie(req)?;
+  let session = LoginSession::retrieve(conn, config, &token).await.ok()?;
+
+  if session.client_id.is_some() {
+    return None;
+  }
+
+  return Some((session, token));
+}
+
+/// ## consent_csrf_token
+///
+/// Derives the token the consent page is submitted with from the session token,
+/// so other sites cannot make a signed in user consent
+///
+pub fn consent_csrf_token(config: &CauthConfig, session_token: &str) -> String {
+  return hash_token(&format!("consent:{}", session_token), &config.session_pepper);
+}
+
+/// ## sign_in
+///
+/// Signs in the user submitting a consent or device verification page, returning the login.
+/// The credentials from the form are used when present, the session cookie otherwise
+///
+/// Errors:
+/// + when the credentials or the second factor are invalid, a consumed recovery code stays consumed once committed
+/// + when there is no session cookie or the CSRF token do not match it
+///
+pub async fn sign_in(
+  req: &HttpRequest,
+  conn: &mut PgConnection,
+  config: &CauthConfig,
+  form: &SignInForm
+) -> Result<String, String> {
+  if let (Some(login), Some(password)) = (&form.login, &form.password) {
+    if !login.is_empty() {
+      if User::verify_password(conn, config, login, password).await.is_err() {
+        return Err("Invalid login or password".to_string());
+      }
+
+      let code = form.code
+        .as_ref()
+        .filter(|code| !code.is_empty());
+
+      return match User::verify_second_factor(conn, login, code).await {
+        Ok(_) => Ok(login.clone()),
+        Err(err) => Err(err.to_string())
+      };
+    }
+  }
+
+  let (session, token) = match cookie_session(req, conn, config).await {
+    Some(session) => session,
+    None => return Err("Sign in to continue".to_string())
+  };
+
+  if form.csrf_token.as_deref() != Some(consent_csrf_token(config, &token).as_str()) {
+    return Err("The page has expired, sign in again".to_string());
+  }
+
+  return Ok(session.user_login);
+}
@@ -0,0 +1,131 @@
+use actix_web::{
+  http::{
+    header::{
+      HeaderValue,
+      AUTHORIZATION,
+      WWW_AUTHENTICATE
+    },
+    StatusCode
+  },
+  HttpRequest
+};
+use base64::{
+  engine::general_purpose::STANDARD,
+  Engine
+};
+use serde_json::json;
+use sqlx::PgConnection;
+use crate::{
+  config::CauthConfig,
+  models::oauth_client::OAuthClient,
+  web::ServerResponse
+};
+
+/// ## ClientCredentials
+///
+/// Credentials an OAuth client authenticates with, read in order from:
+/// + the `Authorization: Basic <base64(client_id:client_secret)>` header
+/// + the `client_id` and `client_secret` fields of the form body
+///
+pub struct ClientCredentials {
+  pub client_id: String,
+  pub client_secret: String
+}
+
+impl ClientCredentials {
+  /// ## ClientCredentials::extract
+  ///
+  /// Reads the credentials from the request,
+  /// falling back to the ones sent in the form body
+  ///
+  pub fn extract(
+    req: &HttpRequest,
+    client_id: &Option<String>,
+    client_secret: &Option<String>
+  ) -> Option<Self> {
+    if let Some(credentials) = Self::from_header(req) {
+      return Some(credentials);
+    }
+
+    return match (client_id, client_secret) {
+      (Some(client_id), Some(client_secret)) => Some(ClientCredentials {
+        client_id: client_id.clone(),
+        client_secret: client_secret.clone()
+      }),
+      _ => None
+    };
+  }
+
+  /// ## ClientCredentials::from_header
+  ///
+  /// Reads the credentials from the `Authorization: Basic` header
+  ///
+  pub fn from_header(req: &HttpRequest) -> Option<Self> {
+    let header = req
+      .headers()
+      .get(AUTHORIZATION)?
+      .to_str()
+      .ok()?;
+    let (scheme, encoded) = header.split_once(' ')?;
+
+    if !scheme.eq_ignore_ascii_case("basic") {
+      return None;
+    }
+
+    let decoded = STANDARD.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (client_id, client_secret) = decoded.split_once(':')?;
+
+    return Some(ClientCredentials {
+      client_id: client_id.to_string(),
+      client_secret: client_secret.to_string()
+    });
+  }
+}
+
+/// ## authenticate_client
+///
+/// Authenticates the client of a token or device authorization request.
+/// Confidential clients authenticate with their secret,
+/// public clients only identify themselves with the client id
+///
+pub async fn authenticate_client(
+  req: &HttpRequest,
+  conn: &mut PgConnection,
+  config: &CauthConfig,
+  client_id: &Option<String>,
+  client_secret: &Option<String>
+) -> Option<OAuthClient> {
+  if let Some(credentials) = ClientCredentials::extract(req, client_id, client_secret) {
+    return OAuthClient::authenticate(
+      conn,
+      config,
+      &credentials.client_id,
+      &credentials.client_secret
+    )
+    .await
+    .ok();
+  }
+
+  let client = OAuthClient::retrieve(conn, client_id.as_ref()?).await.ok()?;
+
+  return match client.is_public() {
+    true => Some(client),
+    false => None
+  };
+}
+
+/// ## invalid_client_error
+///
+/// Response sent when the client authentication fails, as described in RFC 6749
+///
+pub fn invalid_client_error() -> ServerResponse {
+  return ServerResponse::new(
+    StatusCode::UNAUTHORIZED,
+    Some(json!({
+      "error": "invalid_client",
+      "error_description": "Client authentication failed"
+    }))
+  )
+  .with_header(WWW_AUTHENTICATE, HeaderValue::from_static("Basic realm=\"authoric\""));
+}
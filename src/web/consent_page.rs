@@ -0,0 +1,209 @@
+use crate::{
+  util::string::escape_html,
+  web::authorization_request::AuthorizationRequest
+};
+
+const STYLE: &str = "
+  body { font-family: sans-serif; background: #f4f4f5; display: flex; justify-content: center; padding-top: 10vh; }
+  main { background: white; padding: 2rem; border-radius: 8px; width: 22rem; box-shadow: 0 1px 4px rgba(0, 0, 0, 0.1); }
+  input { display: block; width: 100%; box-sizing: border-box; margin: 0.25rem 0 0.75rem; padding: 0.5rem; }
+  button { padding: 0.5rem 1rem; margin-right: 0.5rem; }
+  .error { color: #b91c1c; }
+";
+
+/// Data shown on the consent page of the authorization endpoint
+pub struct ConsentPage<'a> {
+  pub request: &'a AuthorizationRequest,
+  pub client_name: &'a str,
+  /// Permissions the client asks for
+  pub scopes: &'a Vec<String>,
+  /// Login of the user signed in with the session cookie,
+  /// the page asks for credentials when missing
+  pub user: Option<&'a str>,
+  pub csrf_token: Option<String>,
+  pub error: Option<&'a str>
+}
+
+impl ConsentPage<'_> {
+  /// ## ConsentPage::render
+  ///
+  /// Renders the page asking the user to sign in and allow the client access,
+  /// submitted back to POST /oauth/authorize with the original request
+  ///
+  pub fn render(self: &Self) -> String {
+    let hidden = |name: &str, value: &Option<String>| match value {
+      Some(value) => format!(
+        "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+        name,
+        escape_html(value)
+      ),
+      None => String::new()
+    };
+
+    let fields = [
+      hidden("response_type", &self.request.response_type),
+      hidden("client_id", &self.request.client_id),
+      hidden("redirect_uri", &self.request.redirect_uri),
+      hidden("scope", &self.request.scope),
+      hidden("state", &self.request.state),
+      hidden("code_challenge", &self.request.code_challenge),
+      hidden("code_challenge_method", &self.request.code_challenge_method),
+      hidden("nonce", &self.request.nonce),
+      hidden("csrf_token", &self.csrf_token)
+    ]
+    .join("");
+
+    return page(&format!(
+      "<h1>Sign in</h1>
+      <p><b>{}</b> wants to access your account with:</p>
+      <ul>{}</ul>
+      {}
+      <form method=\"post\" action=\"/oauth/authorize\">
+        {}
+        {}
+        <button name=\"decision\" value=\"approve\">Allow</button>
+        <button name=\"decision\" value=\"deny\">Deny</button>
+      </form>",
+      escape_html(self.client_name),
+      scope_list(self.scopes),
+      error_message(self.error),
+      fields,
+      sign_in_fields(self.user)
+    ));
+  }
+}
+
+/// Data shown on the verification page of the device authorization grant
+pub struct DevicePage<'a> {
+  /// Code shown on the device, the page asks for it when missing
+  pub user_code: Option<&'a str>,
+  /// Name of the client and the permissions it asks for, known once the code is valid
+  pub request: Option<(&'a str, &'a Vec<String>)>,
+  /// Login of the user signed in with the session cookie,
+  /// the page asks for credentials when missing
+  pub user: Option<&'a str>,
+  pub csrf_token: Option<String>,
+  pub error: Option<&'a str>
+}
+
+impl DevicePage<'_> {
+  /// ## DevicePage::render
+  ///
+  /// Renders the page asking the user for the code shown on the device,
+  /// then to sign in and allow the client access.
+  /// Submitted to POST /oauth/device
+  ///
+  pub fn render(self: &Self) -> String {
+    let (user_code, (client_name, scopes)) = match (self.user_code, self.request) {
+      (Some(user_code), Some(request)) => (user_code, request),
+      _ => return page(&format!(
+        "<h1>Connect a device</h1>
+        <p>Enter the code shown on your device</p>
+        {}
+        <form method=\"get\" action=\"/oauth/device\">
+          <label>Code<input name=\"user_code\" value=\"{}\" autocomplete=\"off\"></label>
+          <button>Continue</button>
+        </form>",
+        error_message(self.error),
+        escape_html(self.user_code.unwrap_or(""))
+      ))
+    };
+
+    let csrf_token = match &self.csrf_token {
+      Some(csrf_token) => format!("<input type=\"hidden\" name=\"csrf_token\" value=\"{}\">", escape_html(csrf_token)),
+      None => String::new()
+    };
+
+    return page(&format!(
+      "<h1>Connect a device</h1>
+      <p><b>{}</b> wants to access your account with:</p>
+      <ul>{}</ul>
+      <p>Only continue if your device shows the code <b>{}</b></p>
+      {}
+      <form method=\"post\" action=\"/oauth/device\">
+        <input type=\"hidden\" name=\"user_code\" value=\"{}\">
+        {}
+        {}
+        <button name=\"decision\" value=\"approve\">Allow</button>
+        <button name=\"decision\" value=\"deny\">Deny</button>
+      </form>",
+      escape_html(client_name),
+      scope_list(scopes),
+      escape_html(user_code),
+      error_message(self.error),
+      escape_html(user_code),
+      csrf_token,
+      sign_in_fields(self.user)
+    ));
+  }
+}
+
+/// ## message_page
+///
+/// Renders a page with a title and a message, e.g. after the user decided on a device request
+///
+pub fn message_page(title: &str, message: &str) -> String {
+  return page(&format!(
+    "<h1>{}</h1><p>{}</p>",
+    escape_html(title),
+    escape_html(message)
+  ));
+}
+
+/// ## error_page
+///
+/// Renders the page shown when the request cannot be redirected back to the client,
+/// e.g. the client or the redirect uri is invalid
+///
+pub fn error_page(message: &str) -> String {
+  return page(&format!(
+    "<h1>Authorization failed</h1><p class=\"error\">{}</p>",
+    escape_html(message)
+  ));
+}
+
+fn scope_list(scopes: &Vec<String>) -> String {
+  return match scopes.is_empty() {
+    true => "<li>only your login, no permissions</li>".to_string(),
+    false => scopes
+      .iter()
+      .map(|scope| format!("<li>{}</li>", escape_html(scope)))
+      .collect::<Vec<_>>()
+      .join("")
+  };
+}
+
+fn sign_in_fields(user: Option<&str>) -> String {
+  return match user {
+    Some(user) => format!("<p>Signed in as <b>{}</b></p>", escape_html(user)),
+    None => "
+      <label>Login<input name=\"login\" autocomplete=\"username\"></label>
+      <label>Password<input name=\"password\" type=\"password\" autocomplete=\"current-password\"></label>
+      <label>Two-factor code, if enabled<input name=\"code\" autocomplete=\"one-time-code\"></label>
+    ".to_string()
+  };
+}
+
+fn error_message(error: Option<&str>) -> String {
+  return match error {
+    Some(error) => format!("<p class=\"error\">{}</p>", escape_html(error)),
+    None => String::new()
+  };
+}
+
+fn page(content: &str) -> String {
+  return format!(
+    "<!DOCTYPE html>
+    <html>
+      <head>
+        <meta charset=\"utf-8\">
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+        <title>authoric</title>
+        <style>{}</style>
+      </head>
+      <body><main>{}</main></body>
+    </html>",
+    STYLE,
+    content
+  );
+}
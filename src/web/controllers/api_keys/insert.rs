@@ -0,0 +1,138 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Path,
+        Data
+    }
+};
+use chrono::{
+    Duration,
+    Utc
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        api_key::{
+            ApiKey,
+            ApiKeyInsertError
+        },
+        login_session::LoginSession
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+type PathData = String;
+
+#[derive(Deserialize)]
+struct JsonData {
+    name: String,
+    permissions: Option<Vec<String>>,
+    /// Number of seconds the key is valid for, never expires when missing
+    expires_in: Option<i64>
+}
+
+fn ok(api_key: &ApiKey, key: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "id": api_key.id,
+            "name": api_key.name,
+            "prefix": api_key.prefix,
+            "key": key,
+            "permissions": api_key.permissions,
+            "expires_at": api_key.expires_at,
+            "created_at": api_key.created_at
+        }))
+    );
+}
+
+fn invalid_expiry_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_EXPIRY",
+            "details": "The key has to be valid for a positive number of seconds"
+        }))
+    );
+}
+
+fn insert_error(error: ApiKeyInsertError) -> ServerResponse {
+    let (status, code) = match error {
+        ApiKeyInsertError::NameError => (StatusCode::BAD_REQUEST, "NAME_ERROR"),
+        ApiKeyInsertError::PermissionNotFound(_) => (StatusCode::BAD_REQUEST, "PERMISSION_NOT_FOUND"),
+        ApiKeyInsertError::TokenCollision => (StatusCode::INTERNAL_SERVER_ERROR, "TOKEN_COLLISION")
+    };
+
+    return ServerResponse::new(
+        status,
+        Some(json!({
+            "code": code,
+            "details": error.to_string()
+        }))
+    );
+}
+
+#[post("/api-keys/users/{login}")]
+pub async fn controller(
+    session: SessionToken,
+    path: Path<PathData>,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    let expires_at = match json.expires_in {
+        Some(expires_in) if expires_in <= 0 => return invalid_expiry_error(),
+        Some(expires_in) => Some(Utc::now() + Duration::seconds(expires_in)),
+        None => None
+    };
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let permitted = LoginSession::has_permission(
+        &mut db_conn,
+        &data,
+        &session.token,
+        "authoric:service-accounts:manage"
+    )
+    .await;
+
+    if !permitted {
+        return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        );
+    }
+
+    let result = ApiKey::insert(
+        &mut db_conn,
+        &data,
+        &path.into_inner(),
+        &json.name,
+        json.permissions.as_ref(),
+        expires_at
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok((api_key, key)) => return ok(&api_key, key),
+        Err(error) => return insert_error(error)
+    };
+}
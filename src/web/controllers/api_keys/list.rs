@@ -0,0 +1,90 @@
+use actix_web::{
+    Responder,
+    get,
+    http::StatusCode,
+    web::{
+        Path,
+        Data
+    }
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        api_key::ApiKey,
+        login_session::LoginSession,
+        user::User
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+type PathData = String;
+
+fn ok(api_keys: Vec<ApiKey>) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(api_keys))
+    );
+}
+
+fn not_found_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NOT_FOUND",
+            "details": "The user with specified login do not exist"
+        }))
+    );
+}
+
+#[get("/api-keys/users/{login}")]
+pub async fn controller(
+    session: SessionToken,
+    path: Path<PathData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let permitted = LoginSession::has_permission(
+        &mut db_conn,
+        &data,
+        &session.token,
+        "authoric:service-accounts:manage"
+    )
+    .await;
+
+    if !permitted {
+        return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        );
+    }
+
+    let login = path.into_inner();
+
+    if let Err(_) = User::retrieve(&mut db_conn, &login).await {
+        return not_found_error();
+    }
+
+    let api_keys = ApiKey::list_by_user(
+        &mut db_conn,
+        &login
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok(api_keys);
+}
@@ -0,0 +1,193 @@
+use actix_web::{
+    cookie::{
+        time::Duration,
+        Cookie,
+        SameSite
+    },
+    get,
+    HttpRequest,
+    Responder,
+    http::{
+        header::{
+            HeaderValue,
+            CACHE_CONTROL,
+            LOCATION,
+            SET_COOKIE
+        },
+        StatusCode
+    },
+    web::{
+        Data,
+        Path,
+        Query
+    }
+};
+use serde::Deserialize;
+use crate::{
+    config::CauthConfig,
+    models::{
+        federated_identity::FederatedIdentity,
+        federated_login_state::{
+            FederatedLoginState,
+            FEDERATED_LOGIN_STATE_COOKIE
+        },
+        login_session::{
+            LoginSession,
+            TokenScope
+        }
+    },
+    util::oidc::ProviderMetadata,
+    web::{
+        consent_page::error_page,
+        session_metadata,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct QueryData {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>
+}
+
+fn error(status: StatusCode, message: &str) -> ServerResponse {
+    return ServerResponse::html(status, error_page(message));
+}
+
+/// Redirect uri of the upstream identity providers, verifying the user's sign in at the provider.
+/// Only accepted in the browser the sign in was started in, as told by the state cookie.
+/// The user is linked or created on the first sign in, then sent to the path the sign in was started with
+/// carrying a new login session in the session cookie
+#[get("/login/oidc/{provider}/callback")]
+pub async fn controller(
+    path: Path<String>,
+    query: Query<QueryData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    let provider = match data.oidc_provider(&path) {
+        Some(provider) => provider,
+        None => return error(StatusCode::NOT_FOUND, "Identity provider with specified name do not exist")
+    };
+
+    if let Some(err) = &query.error {
+        return error(
+            StatusCode::UNAUTHORIZED,
+            &format!("The identity provider refused the sign in: {}", query.error_description.as_ref().unwrap_or(err))
+        );
+    }
+
+    let (code, state) = match (&query.code, &query.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => return error(StatusCode::BAD_REQUEST, "The code and state parameters are required")
+    };
+
+    // otherwise an attacker could sign the victim in to the attacker's account with their own callback url
+    let state_cookie = req.cookie(FEDERATED_LOGIN_STATE_COOKIE);
+    if !FederatedLoginState::is_bound_to(&data, state, state_cookie.as_ref().map(|cookie| cookie.value())) {
+        return error(StatusCode::BAD_REQUEST, "The sign in was started in another browser, try again");
+    }
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let login_state = match FederatedLoginState::take(&mut db_conn, &data, &provider.name, state).await {
+        Some(login_state) => login_state,
+        None => return error(StatusCode::BAD_REQUEST, "The sign in has expired, try again")
+    };
+
+    let metadata = match ProviderMetadata::discover(provider).await {
+        Ok(metadata) => metadata,
+        Err(err) => return error(StatusCode::BAD_GATEWAY, &err.to_string())
+    };
+
+    let tokens = match metadata.exchange_code(
+        provider,
+        &data.oidc_redirect_uri(provider),
+        code,
+        &login_state.code_verifier
+    )
+    .await {
+        Ok(tokens) => tokens,
+        Err(err) => return error(StatusCode::BAD_GATEWAY, &err.to_string())
+    };
+
+    let id_token = match &tokens.id_token {
+        Some(id_token) => id_token,
+        None => return error(StatusCode::BAD_GATEWAY, "The identity provider did not return an ID token")
+    };
+
+    let mut claims = match metadata.verify_id_token(provider, id_token, &login_state.nonce).await {
+        Ok(claims) => claims,
+        Err(err) => return error(StatusCode::UNAUTHORIZED, &err.to_string())
+    };
+
+    // providers often send the profile and groups claims from the userinfo endpoint only
+    let subject = claims.get("sub").and_then(|sub| sub.as_str()).unwrap_or_default().to_string();
+    match metadata.userinfo(&tokens.access_token, &subject).await {
+        Ok(userinfo) => {
+            for (name, value) in userinfo {
+                claims.entry(name).or_insert(value);
+            }
+        },
+        Err(err) => return error(StatusCode::BAD_GATEWAY, &err.to_string())
+    };
+
+    let user = match FederatedIdentity::sign_in(&mut db_conn, provider, &claims).await {
+        Ok(user) => user,
+        Err(err) => return error(StatusCode::FORBIDDEN, &err.to_string())
+    };
+
+    let token = match LoginSession::insert(
+        &mut db_conn,
+        &data,
+        user.login.clone(),
+        None,
+        &TokenScope::default(),
+        &session_metadata(&req)
+    )
+    .await {
+        Ok(token) => token,
+        Err(err) => return error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string())
+    };
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    let cookie = Cookie::build(data.session_cookie_name.clone(), token)
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::seconds(data.session_lifetime))
+        .finish();
+
+    let mut state_cookie = Cookie::build(FEDERATED_LOGIN_STATE_COOKIE, "")
+        .path("/login/oidc/")
+        .finish();
+    state_cookie.make_removal();
+
+    let (cookie, state_cookie, location) = match (
+        HeaderValue::from_str(&cookie.to_string()),
+        HeaderValue::from_str(&state_cookie.to_string()),
+        HeaderValue::from_str(&login_state.redirect_to)
+    ) {
+        (Ok(cookie), Ok(state_cookie), Ok(location)) => (cookie, state_cookie, location),
+        _ => return error(StatusCode::INTERNAL_SERVER_ERROR, "Cannot send the session cookie")
+    };
+
+    return ServerResponse::new(StatusCode::FOUND, None)
+        .with_header(SET_COOKIE, cookie)
+        .with_header(SET_COOKIE, state_cookie)
+        .with_header(LOCATION, location)
+        .with_header(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+}
@@ -0,0 +1,29 @@
+use actix_web::{
+    get,
+    Responder,
+    http::StatusCode,
+    web::Data
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    web::ServerResponse
+};
+
+/// Lists names of the upstream identity providers users can sign in with
+#[get("/login/oidc")]
+pub async fn controller(
+    data: Data<CauthConfig>
+) -> impl Responder {
+    let providers = data.oidc_providers
+        .iter()
+        .map(|provider| provider.name.clone())
+        .collect::<Vec<_>>();
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "providers": providers
+        }))
+    );
+}
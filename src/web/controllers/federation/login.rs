@@ -0,0 +1,151 @@
+use actix_web::{
+    cookie::{
+        time::Duration,
+        Cookie,
+        SameSite
+    },
+    get,
+    Responder,
+    http::{
+        header::{
+            HeaderValue,
+            CACHE_CONTROL,
+            LOCATION,
+            SET_COOKIE
+        },
+        StatusCode
+    },
+    web::{
+        Data,
+        Path,
+        Query
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::federated_login_state::{
+        FederatedLoginState,
+        FEDERATED_LOGIN_STATE_COOKIE,
+        FEDERATED_LOGIN_STATE_LIFETIME
+    },
+    util::{
+        oidc::ProviderMetadata,
+        token::code_challenge
+    },
+    web::ServerResponse
+};
+
+#[derive(Deserialize)]
+pub struct QueryData {
+    /// Path the user is sent to once signed in
+    redirect_to: Option<String>
+}
+
+fn not_found_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::NOT_FOUND,
+        Some(json!({
+            "code": "NOT_FOUND",
+            "details": "Identity provider with specified name do not exist"
+        }))
+    );
+}
+
+fn invalid_redirect_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_REDIRECT",
+            "details": "redirect_to has to be a path on this host"
+        }))
+    );
+}
+
+fn provider_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_GATEWAY,
+        Some(json!({
+            "code": "PROVIDER_ERROR",
+            "details": details
+        }))
+    );
+}
+
+/// Starts signing in with an upstream identity provider,
+/// sending the user to the provider's authorization endpoint.
+/// The state is bound to the browser with a cookie, so the callback cannot be completed in another one
+#[get("/login/oidc/{provider}")]
+pub async fn controller(
+    path: Path<String>,
+    query: Query<QueryData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    let provider = match data.oidc_provider(&path) {
+        Some(provider) => provider,
+        None => return not_found_error()
+    };
+
+    // only local paths are accepted, so the sign in cannot be used as an open redirect
+    let redirect_to = query.redirect_to.clone().unwrap_or("/".to_string());
+    if !redirect_to.starts_with('/') || redirect_to.starts_with("//") || redirect_to.contains('\\') {
+        return invalid_redirect_error();
+    }
+
+    let metadata = match ProviderMetadata::discover(provider).await {
+        Ok(metadata) => metadata,
+        Err(err) => return provider_error(err.to_string())
+    };
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let (login_state, state) = FederatedLoginState::create(
+        &mut db_conn,
+        &data,
+        &provider.name,
+        &redirect_to
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    let url = metadata.authorization_url(
+        provider,
+        &data.oidc_redirect_uri(provider),
+        &state,
+        &login_state.nonce,
+        &code_challenge(&login_state.code_verifier)
+    );
+
+    let location = match HeaderValue::from_str(&url) {
+        Ok(location) => location,
+        Err(_) => return provider_error("The authorization endpoint cannot be used in a redirect".to_string())
+    };
+
+    // lax, as the provider sends the user back with a top-level navigation
+    let cookie = Cookie::build(FEDERATED_LOGIN_STATE_COOKIE, login_state.state_hash)
+        .path("/login/oidc/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::seconds(FEDERATED_LOGIN_STATE_LIFETIME))
+        .finish();
+
+    // the digest is hex encoded, so it's always a valid header
+    let cookie = HeaderValue::from_str(&cookie.to_string()).unwrap();
+
+    return ServerResponse::new(StatusCode::FOUND, None)
+        .with_header(SET_COOKIE, cookie)
+        .with_header(LOCATION, location)
+        .with_header(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+}
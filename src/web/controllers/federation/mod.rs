@@ -0,0 +1,3 @@
+pub mod list;
+pub mod login;
+pub mod callback;
@@ -0,0 +1,156 @@
+use actix_web::{
+    get,
+    HttpRequest,
+    Responder,
+    http::{
+        header::{
+            HeaderName,
+            HeaderValue
+        },
+        StatusCode
+    },
+    web::{
+        Query,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::login_session::{
+        LoginSession,
+        LoginSessionGetUserError
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct QueryData {
+    permission: Option<String>
+}
+
+fn ok(login: &String, permissions: &Vec<String>) -> ServerResponse {
+    let headers = HeaderValue::from_str(login)
+        .and_then(|login| Ok((login, HeaderValue::from_str(&permissions.join(","))?)));
+
+    let (login, permissions) = match headers {
+        Ok(headers) => headers,
+        Err(_) => return invalid_header_error()
+    };
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    )
+    .with_header(HeaderName::from_static("x-auth-user"), login)
+    .with_header(HeaderName::from_static("x-auth-permissions"), permissions);
+}
+
+fn unauthorized_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "UNAUTHORIZED",
+            "details": "The session do not exist"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session has expired"
+        }))
+    );
+}
+
+fn forbidden_error(permission: &String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::FORBIDDEN,
+        Some(json!({
+            "code": "FORBIDDEN",
+            "details": format!("The user do not have the \"{}\" permission", permission)
+        }))
+    );
+}
+
+fn invalid_header_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "INVALID_HEADER",
+            "details": "The user's login or permissions cannot be sent in a header"
+        }))
+    );
+}
+
+/// Checks requests forwarded by a reverse proxy (nginx auth_request, Traefik ForwardAuth, Caddy forward_auth).
+/// The required permission is taken from the query, or from the forward_auth_rules in the config
+/// matching X-Forwarded-Host and X-Forwarded-Uri
+#[get("/forward-auth")]
+pub async fn controller(
+    session: SessionToken,
+    query: Query<QueryData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    let forwarded_header = |name: &str| req
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok());
+
+    let permission = match &query.permission {
+        Some(permission) => Some(permission.clone()),
+        None => data
+            .forward_auth_rule(
+                forwarded_header("x-forwarded-host"),
+                forwarded_header("x-forwarded-uri").unwrap_or("/")
+            )
+            .and_then(|rule| rule.permission.clone())
+    };
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let user = match LoginSession::get_user(&mut db_conn, &data, &session.token).await {
+        Ok(user) => user,
+        Err(error) => match error {
+            LoginSessionGetUserError::NotFound => return unauthorized_error(),
+            LoginSessionGetUserError::Expired => return expired_error()
+        }
+    };
+
+    if let Some(permission) = &permission {
+        let permitted = LoginSession::has_permission(
+            &mut db_conn,
+            &data,
+            &session.token,
+            permission
+        )
+        .await;
+
+        if !permitted {
+            return forbidden_error(permission);
+        }
+    }
+
+    let permissions = LoginSession::permissions(&mut db_conn, &data, &session.token).await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok(&user.login, &permissions);
+}
@@ -3,12 +3,10 @@ use actix_web::{
     Responder,
     http::StatusCode, 
     web::{
-        Query,
         Data,
         Path
     }
 };
-use serde::Deserialize;
 use serde_json::json;
 use crate::{
     config::CauthConfig,
@@ -19,14 +17,12 @@ use crate::{
         },
         login_session::LoginSession
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-struct QueryData {
-    session_token: String
-}
-
 type PathData = (String, String);
 
 fn ok() -> ServerResponse {
@@ -58,7 +54,7 @@ fn permission_not_found_error() -> ServerResponse {
 
 #[post("/groups/{name}/{permission_name}")]
 pub async fn controller(
-    query: Query<QueryData>,
+    session: SessionToken,
     data: Data<CauthConfig>,
     path: Path<PathData>
 ) -> impl Responder {
@@ -70,7 +66,8 @@ pub async fn controller(
 
     let permitted = LoginSession::has_permission(
         &mut db_conn,
-        &query.session_token,
+        &data,
+        &session.token,
         "authoric:group:update"
     )
     .await;
@@ -1,6 +1,14 @@
 pub mod permissions;
 pub mod groups;
 pub mod users;
+pub mod sessions;
+pub mod well_known;
+pub mod oauth;
+pub mod forward_auth;
+pub mod webauthn;
+pub mod api_keys;
+pub mod service_accounts;
+pub mod federation;
 
 pub use self::{
     permissions::{
@@ -15,6 +23,47 @@ pub use self::{
         grant_permission::controller as GrantPermissionGroupController,
         revoke_permission::controller as RevokePermissionGroupController
     },
+    sessions::{
+        list::controller as ListSessionsController,
+        delete::controller as DeleteSessionController,
+        revoke_user::controller as RevokeUserSessionsController
+    },
+    well_known::{
+        jwks::controller as JwksController,
+        openid_configuration::controller as OpenIdConfigurationController
+    },
+    oauth::{
+        introspect::controller as IntrospectOAuthController,
+        revoke::controller as RevokeOAuthController,
+        authorize::controller as AuthorizeOAuthController,
+        authorize_submit::controller as AuthorizeSubmitOAuthController,
+        token::controller as TokenOAuthController,
+        device_authorization::controller as DeviceAuthorizationOAuthController,
+        device::controller as DeviceOAuthController,
+        device_submit::controller as DeviceSubmitOAuthController,
+        userinfo::controller as UserinfoOAuthController,
+        clients::{
+            insert::controller as InsertOAuthClientController,
+            list::controller as ListOAuthClientsController,
+            delete::controller as DeleteOAuthClientController
+        }
+    },
+    forward_auth::get::controller as ForwardAuthController,
+    webauthn::{
+        login::controller as WebauthnLoginController,
+        login_finish::controller as WebauthnLoginFinishController
+    },
+    api_keys::{
+        insert::controller as InsertApiKeyController,
+        list::controller as ListApiKeysController,
+        delete::controller as DeleteApiKeyController
+    },
+    service_accounts::insert::controller as InsertServiceAccountController,
+    federation::{
+        list::controller as ListFederationProvidersController,
+        login::controller as LoginFederationController,
+        callback::controller as CallbackFederationController
+    },
     users::{
         insert::controller as InsertUserController,
         delete::controller as DeleteUserController,
@@ -22,8 +71,44 @@ pub use self::{
         get_permission::controller as GetPermissionUserController,
         login::controller as LoginUserController,
         logout::controller as LogoutUserController,
+        refresh::controller as RefreshUserController,
         grant_group::controller as GrantGroupUserController,
         revoke_group::controller as RevokeGroupUserController,
+        change_password::controller as ChangePasswordUserController,
+        password_reset::{
+            request::controller as RequestPasswordResetUserController,
+            confirm::controller as ConfirmPasswordResetUserController
+        },
+        email_verification::{
+            request::controller as RequestEmailVerificationUserController,
+            confirm::controller as ConfirmEmailVerificationUserController
+        },
+        magic_link::{
+            request::controller as RequestMagicLinkUserController,
+            redeem::controller as RedeemMagicLinkUserController
+        },
+        sessions::{
+            list::controller as ListSessionsUserController,
+            delete::controller as DeleteSessionUserController,
+            delete_others::controller as DeleteOtherSessionsUserController
+        },
+        totp::{
+            enroll::controller as EnrollTotpUserController,
+            confirm::controller as ConfirmTotpUserController,
+            disable::controller as DisableTotpUserController,
+            regenerate_recovery_codes::controller as RegenerateRecoveryCodesUserController
+        },
+        webauthn::{
+            register::controller as WebauthnRegisterUserController,
+            register_finish::controller as WebauthnRegisterFinishUserController,
+            list::controller as ListWebauthnCredentialsUserController,
+            delete::controller as DeleteWebauthnCredentialUserController
+        },
+        api_keys::{
+            insert::controller as InsertApiKeyUserController,
+            list::controller as ListApiKeysUserController,
+            delete::controller as DeleteApiKeyUserController
+        },
         event::{
             register::{
                 create::controller as UserRegisterEventCreateController,
@@ -39,6 +124,11 @@ pub use self::{
                 create::controller as UserDeleteEventCreateController,
                 commit::controller as UserDeleteEventCommitController,
                 cancel::controller as UserDeleteEventCancelController
+            },
+            password_change::{
+                create::controller as UserPasswordChangeEventCreateController,
+                commit::controller as UserPasswordChangeEventCommitController,
+                cancel::controller as UserPasswordChangeEventCancelController
             }
         }
     }
@@ -0,0 +1,64 @@
+use actix_web::{
+    get,
+    HttpRequest,
+    Responder,
+    http::StatusCode,
+    web::{
+        Query,
+        Data
+    }
+};
+use crate::{
+    config::CauthConfig,
+    web::{
+        authorization_request::{
+            consent_csrf_token,
+            cookie_session,
+            AuthorizationRequest
+        },
+        consent_page::ConsentPage,
+        ServerResponse
+    }
+};
+
+/// Authorization endpoint of the authorization code grant as described in RFC 6749,
+/// showing the page where the user signs in and allows the client access
+#[get("/oauth/authorize")]
+pub async fn controller(
+    query: Query<AuthorizationRequest>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    let request = query.into_inner();
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let valid = match request.validate(&mut db_conn).await {
+        Ok(valid) => valid,
+        Err(err) => return request.error_response(err)
+    };
+
+    let session = cookie_session(&req, &mut db_conn, &data).await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    let page = ConsentPage {
+        request: &request,
+        client_name: &valid.client.name,
+        scopes: &valid.scopes,
+        user: session.as_ref().map(|(session, _)| session.user_login.as_str()),
+        csrf_token: session.as_ref().map(|(_, token)| consent_csrf_token(&data, token)),
+        error: None
+    };
+
+    return ServerResponse::html(StatusCode::OK, page.render());
+}
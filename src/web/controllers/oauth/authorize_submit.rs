@@ -0,0 +1,125 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::StatusCode,
+    web::{
+        Form,
+        Data
+    }
+};
+use serde::Deserialize;
+use crate::{
+    config::CauthConfig,
+    models::{
+        authorization_code::AuthorizationCode,
+        user::User
+    },
+    web::{
+        authorization_request::{
+            sign_in,
+            AuthorizationRequest,
+            SignInForm,
+            ValidAuthorizationRequest
+        },
+        consent_page::ConsentPage,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    #[serde(flatten)]
+    request: AuthorizationRequest,
+    /// Either approve or deny
+    decision: String,
+    #[serde(flatten)]
+    sign_in: SignInForm
+}
+
+/// Shows the consent page again with an error, asking for the credentials
+fn retry(form: &FormData, valid: &ValidAuthorizationRequest, error: &str) -> ServerResponse {
+    let page = ConsentPage {
+        request: &form.request,
+        client_name: &valid.client.name,
+        scopes: &valid.scopes,
+        user: None,
+        csrf_token: None,
+        error: Some(error)
+    };
+
+    return ServerResponse::html(StatusCode::UNAUTHORIZED, page.render());
+}
+
+/// Submission of the consent page, redirecting the user back to the client
+/// with an authorization code when the access is allowed.
+/// The user either signs in with the credentials in the form or the session cookie
+#[post("/oauth/authorize")]
+pub async fn controller(
+    form: Form<FormData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let valid = match form.request.validate(&mut db_conn).await {
+        Ok(valid) => valid,
+        Err(err) => return form.request.error_response(err)
+    };
+
+    if form.decision != "approve" {
+        return form.request.redirect(
+            &valid.redirect_uri,
+            vec![
+                ("error", "access_denied".to_string()),
+                ("error_description", "The user denied the access".to_string())
+            ]
+        );
+    }
+
+    let login = match sign_in(&req, &mut db_conn, &data, &form.sign_in).await {
+        Ok(login) => login,
+        Err(err) => {
+            // a consumed recovery code has to stay consumed
+            let _ = db_conn.commit().await;
+
+            return retry(&form, &valid, &err);
+        }
+    };
+
+    let user = match User::retrieve(&mut db_conn, &login).await {
+        Ok(user) => user,
+        Err(_) => return retry(&form, &valid, "Invalid login or password")
+    };
+
+    // permissions the user do not have are left out of the grant
+    let scopes = user.grant_scopes(&mut db_conn, &valid.scopes).await;
+
+    let code = AuthorizationCode::create(
+        &mut db_conn,
+        &data,
+        &valid.client.client_id,
+        &user.login,
+        &valid.redirect_uri,
+        &scopes,
+        &valid.code_challenge,
+        form.request.nonce.as_ref()
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return form.request.redirect(
+        &valid.redirect_uri,
+        vec![("code", code)]
+    );
+}
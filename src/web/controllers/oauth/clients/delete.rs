@@ -0,0 +1,91 @@
+use actix_web::{
+    delete,
+    Responder,
+    http::StatusCode, 
+    web::{
+        Path,
+        Data
+    }
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        oauth_client::{
+            OAuthClient,
+            OAuthClientDeleteError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+type PathData = String;
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn not_found_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NOT_FOUND",
+            "details": "A client with this id do not exist"
+        }))
+    );
+}
+
+#[delete("/oauth/clients/{client_id}")]
+pub async fn controller(
+    session: SessionToken,
+    path: Path<PathData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let permitted = LoginSession::has_permission(
+        &mut db_conn,
+        &data,
+        &session.token,
+        "authoric:oauth-clients:manage"
+    )
+    .await;
+
+    if !permitted {
+        return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        );
+    }
+
+    let result = OAuthClient::delete(
+        &mut db_conn,
+        &path.into_inner()
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(_) => return ok(),
+        Err(error) => match error {
+            OAuthClientDeleteError::NotFound => return not_found_error()
+        }
+    };
+}
@@ -0,0 +1,109 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        oauth_client::{
+            OAuthClient,
+            OAuthClientInsertError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+struct JsonData {
+    name: String,
+    redirect_uris: Option<Vec<String>>,
+    /// Public clients get no secret and have to use PKCE
+    public: Option<bool>,
+    service_account: Option<String>
+}
+
+fn ok(client: &OAuthClient, secret: Option<String>) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "client_id": client.client_id,
+            "client_secret": secret,
+            "name": client.name,
+            "redirect_uris": client.redirect_uris,
+            "service_account": client.service_account
+        }))
+    );
+}
+
+fn name_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NAME_ERROR",
+            "details": OAuthClientInsertError::NameError.to_string()
+        }))
+    );
+}
+
+#[post("/oauth/clients")]
+pub async fn controller(
+    session: SessionToken,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let permitted = LoginSession::has_permission(
+        &mut db_conn,
+        &data,
+        &session.token,
+        "authoric:oauth-clients:manage"
+    )
+    .await;
+
+    if !permitted {
+        return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        );
+    }
+
+    let result = OAuthClient::insert(
+        &mut db_conn,
+        &data,
+        &json.name,
+        &json.redirect_uris.clone().unwrap_or_default(),
+        json.public.unwrap_or(false),
+        json.service_account.as_ref()
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok((client, secret)) => return ok(&client, secret),
+        Err(error) => match error {
+            OAuthClientInsertError::NameError => return name_error()
+        }
+    };
+}
@@ -0,0 +1,77 @@
+use actix_web::{
+    Responder,
+    get,
+    http::StatusCode,
+    web::{
+        Data
+    }
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        oauth_client::OAuthClient
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+fn ok(clients: Vec<OAuthClient>) -> ServerResponse {
+    let clients = clients
+        .iter()
+        .map(|client| json!({
+            "client_id": client.client_id,
+            "name": client.name,
+            "public": client.is_public(),
+            "redirect_uris": client.redirect_uris,
+            "service_account": client.service_account,
+            "created_at": client.created_at
+        }))
+        .collect::<Vec<_>>();
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(clients))
+    );
+}
+
+#[get("/oauth/clients")]
+pub async fn controller(
+    session: SessionToken,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let permitted = LoginSession::has_permission(
+        &mut db_conn,
+        &data,
+        &session.token,
+        "authoric:oauth-clients:manage"
+    )
+    .await;
+
+    if !permitted {
+        return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        );
+    }
+
+    let clients = OAuthClient::list(&mut db_conn).await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok(clients);
+}
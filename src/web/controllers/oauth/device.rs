@@ -0,0 +1,103 @@
+use actix_web::{
+    get,
+    HttpRequest,
+    Responder,
+    http::StatusCode,
+    web::{
+        Query,
+        Data
+    }
+};
+use serde::Deserialize;
+use crate::{
+    config::CauthConfig,
+    models::{
+        device_code::DeviceCode,
+        oauth_client::OAuthClient
+    },
+    web::{
+        authorization_request::{
+            consent_csrf_token,
+            cookie_session
+        },
+        consent_page::DevicePage,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct QueryData {
+    user_code: Option<String>
+}
+
+/// Verification page of the device authorization grant as described in RFC 8628,
+/// where the user enters the code shown on the device, signs in and allows the client access
+#[get("/oauth/device")]
+pub async fn controller(
+    query: Query<QueryData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    let user_code = match &query.user_code {
+        Some(user_code) if !user_code.trim().is_empty() => user_code,
+        _ => {
+            let page = DevicePage {
+                user_code: None,
+                request: None,
+                user: None,
+                csrf_token: None,
+                error: None
+            };
+
+            return ServerResponse::html(StatusCode::OK, page.render());
+        }
+    };
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let client = match DeviceCode::retrieve_pending(&mut db_conn, &data, user_code).await {
+        Some(device_code) => OAuthClient::retrieve(&mut db_conn, &device_code.client_id)
+            .await
+            .ok()
+            .map(|client| (client, device_code.scopes)),
+        None => None
+    };
+
+    let (client, scopes) = match client {
+        Some(client) => client,
+        None => {
+            let page = DevicePage {
+                user_code: Some(user_code),
+                request: None,
+                user: None,
+                csrf_token: None,
+                error: Some("The code is invalid or has expired")
+            };
+
+            return ServerResponse::html(StatusCode::BAD_REQUEST, page.render());
+        }
+    };
+
+    let session = cookie_session(&req, &mut db_conn, &data).await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    let page = DevicePage {
+        user_code: Some(user_code),
+        request: Some((&client.name, &scopes)),
+        user: session.as_ref().map(|(session, _)| session.user_login.as_str()),
+        csrf_token: session.as_ref().map(|(_, token)| consent_csrf_token(&data, token)),
+        error: None
+    };
+
+    return ServerResponse::html(StatusCode::OK, page.render());
+}
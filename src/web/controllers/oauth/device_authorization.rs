@@ -0,0 +1,103 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::{
+        header::{
+            HeaderValue,
+            CACHE_CONTROL
+        },
+        StatusCode
+    },
+    web::{
+        Form,
+        Data
+    }
+};
+use percent_encoding::{
+    utf8_percent_encode,
+    NON_ALPHANUMERIC
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::device_code::DeviceCode,
+    web::{
+        client_credentials::{
+            authenticate_client,
+            invalid_client_error
+        },
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    /// Space separated permission names the client asks for
+    scope: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>
+}
+
+/// Device authorization endpoint as described in RFC 8628,
+/// starting a request the user approves on the verification page
+/// while the device polls POST /oauth/token
+#[post("/oauth/device_authorization")]
+pub async fn controller(
+    form: Form<FormData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let client = match authenticate_client(&req, &mut db_conn, &data, &form.client_id, &form.client_secret).await {
+        Some(client) => client,
+        None => return invalid_client_error()
+    };
+
+    let scopes = match &form.scope {
+        Some(scope) => scope
+            .split_whitespace()
+            .map(|scope| scope.to_string())
+            .collect(),
+        None => vec![]
+    };
+
+    let issued = match DeviceCode::create(&mut db_conn, &data, &client.client_id, &scopes).await {
+        Ok(issued) => issued,
+        Err(err) => return ServerResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Some(json!({
+                "error": "server_error",
+                "error_description": err.to_string()
+            }))
+        )
+    };
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    let verification_uri = format!("{}/oauth/device", data.jwt_issuer.trim_end_matches('/'));
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "device_code": issued.device_code,
+            "user_code": issued.user_code,
+            "verification_uri": verification_uri,
+            "verification_uri_complete": format!("{}?user_code={}", verification_uri, utf8_percent_encode(&issued.user_code, NON_ALPHANUMERIC)),
+            "expires_in": issued.expires_in,
+            "interval": issued.interval
+        }))
+    )
+    .with_header(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+}
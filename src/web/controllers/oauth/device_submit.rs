@@ -0,0 +1,124 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::StatusCode,
+    web::{
+        Form,
+        Data
+    }
+};
+use serde::Deserialize;
+use crate::{
+    config::CauthConfig,
+    models::{
+        device_code::DeviceCode,
+        oauth_client::OAuthClient,
+        user::User
+    },
+    web::{
+        authorization_request::{
+            sign_in,
+            SignInForm
+        },
+        consent_page::{
+            message_page,
+            DevicePage
+        },
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    user_code: String,
+    /// Either approve or deny
+    decision: String,
+    #[serde(flatten)]
+    sign_in: SignInForm
+}
+
+/// Submission of the device verification page, approving or denying the request of the device.
+/// The user either signs in with the credentials in the form or the session cookie
+#[post("/oauth/device")]
+pub async fn controller(
+    form: Form<FormData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let device_code = match DeviceCode::retrieve_pending(&mut db_conn, &data, &form.user_code).await {
+        Some(device_code) => device_code,
+        None => {
+            let page = DevicePage {
+                user_code: Some(&form.user_code),
+                request: None,
+                user: None,
+                csrf_token: None,
+                error: Some("The code is invalid or has expired")
+            };
+
+            return ServerResponse::html(StatusCode::BAD_REQUEST, page.render());
+        }
+    };
+
+    let login = match sign_in(&req, &mut db_conn, &data, &form.sign_in).await {
+        Ok(login) => login,
+        Err(err) => {
+            let client_name = match OAuthClient::retrieve(&mut db_conn, &device_code.client_id).await {
+                Ok(client) => client.name,
+                Err(_) => device_code.client_id.clone()
+            };
+
+            // a consumed recovery code has to stay consumed
+            let _ = db_conn.commit().await;
+
+            let page = DevicePage {
+                user_code: Some(&form.user_code),
+                request: Some((&client_name, &device_code.scopes)),
+                user: None,
+                csrf_token: None,
+                error: Some(&err)
+            };
+
+            return ServerResponse::html(StatusCode::UNAUTHORIZED, page.render());
+        }
+    };
+
+    let user = match User::retrieve(&mut db_conn, &login).await {
+        Ok(user) => user,
+        Err(_) => return ServerResponse::html(
+            StatusCode::UNAUTHORIZED,
+            message_page("Connect a device", "Invalid login or password")
+        )
+    };
+
+    let approved = form.decision == "approve";
+
+    // permissions the user do not have are left out of the grant
+    let scopes = match approved {
+        true => user.grant_scopes(&mut db_conn, &device_code.scopes).await,
+        false => vec![]
+    };
+
+    device_code.decide(&mut db_conn, &user.login, approved, &scopes).await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    let message = match approved {
+        true => "The device is connected, you can return to it",
+        false => "The device was denied access"
+    };
+
+    return ServerResponse::html(StatusCode::OK, message_page("Connect a device", message));
+}
@@ -0,0 +1,155 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::StatusCode,
+    web::{
+        Form,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        api_key::ApiKey,
+        login_session::LoginSession,
+        oauth_client::OAuthClient,
+        user::User
+    },
+    web::{
+        client_credentials::{
+            invalid_client_error,
+            ClientCredentials
+        },
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    token: String,
+    #[allow(dead_code)]
+    token_type_hint: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>
+}
+
+fn active(session: &LoginSession, user: &User, scope: Vec<String>, config: &CauthConfig) -> ServerResponse {
+    let mut body = json!({
+        "active": true,
+        "sub": user.login,
+        "username": user.login,
+        "token_type": "Bearer",
+        "scope": scope.join(" "),
+        "iat": session.created_at.timestamp(),
+        "exp": session.expires_at(config).timestamp()
+    });
+
+    if let Some(client_id) = &session.client_id {
+        body["client_id"] = json!(client_id);
+    }
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(body)
+    );
+}
+
+fn active_api_key(api_key: &ApiKey, scope: Vec<String>) -> ServerResponse {
+    let mut body = json!({
+        "active": true,
+        "sub": api_key.user_login,
+        "username": api_key.user_login,
+        "token_type": "Bearer",
+        "scope": scope.join(" "),
+        "iat": api_key.created_at.timestamp()
+    });
+
+    if let Some(expires_at) = api_key.expires_at {
+        body["exp"] = json!(expires_at.timestamp());
+    }
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(body)
+    );
+}
+
+fn inactive() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "active": false
+        }))
+    );
+}
+
+/// Token introspection as described in RFC 7662,
+/// login session tokens and API keys are reported as active
+#[post("/oauth/introspect")]
+pub async fn controller(
+    form: Form<FormData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    let credentials = match ClientCredentials::extract(&req, &form.client_id, &form.client_secret) {
+        Some(credentials) => credentials,
+        None => return invalid_client_error()
+    };
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let authenticated = OAuthClient::authenticate(
+        &mut db_conn,
+        &data,
+        &credentials.client_id,
+        &credentials.client_secret
+    )
+    .await;
+
+    if authenticated.is_err() {
+        return invalid_client_error();
+    }
+
+    let session = match LoginSession::retrieve(&mut db_conn, &data, &form.token).await {
+        Ok(session) => session,
+        Err(_) => {
+            let api_key = match ApiKey::authenticate(&mut db_conn, &data, &form.token).await {
+                Ok(api_key) => api_key,
+                Err(_) => return inactive()
+            };
+            let scope = LoginSession::permissions(&mut db_conn, &data, &form.token).await;
+
+            match db_conn.commit().await {
+                Ok(_) => (),
+                Err(err) => {
+                    eprintln!("Error committing changes: {}", err);
+                }
+            };
+
+            return active_api_key(&api_key, scope);
+        }
+    };
+
+    let user = match User::retrieve(&mut db_conn, &session.user_login).await {
+        Ok(user) => user,
+        Err(_) => return inactive()
+    };
+
+    let scope = LoginSession::permissions(&mut db_conn, &data, &form.token).await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return active(&session, &user, scope, &data);
+}
@@ -0,0 +1,10 @@
+pub mod introspect;
+pub mod revoke;
+pub mod authorize;
+pub mod authorize_submit;
+pub mod token;
+pub mod device_authorization;
+pub mod device;
+pub mod device_submit;
+pub mod clients;
+pub mod userinfo;
@@ -0,0 +1,94 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::StatusCode,
+    web::{
+        Form,
+        Data
+    }
+};
+use serde::Deserialize;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        oauth_client::OAuthClient,
+        refresh_token::RefreshToken
+    },
+    web::{
+        client_credentials::{
+            invalid_client_error,
+            ClientCredentials
+        },
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    token: String,
+    token_type_hint: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>
+}
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+/// Token revocation as described in RFC 7009.
+/// Accepts both login session and refresh tokens, revoking the refresh token family with either.
+/// Only tokens issued to the authenticated client are revoked,
+/// responds with 200 even when the token is unknown or belongs to another client
+#[post("/oauth/revoke")]
+pub async fn controller(
+    form: Form<FormData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    let credentials = match ClientCredentials::extract(&req, &form.client_id, &form.client_secret) {
+        Some(credentials) => credentials,
+        None => return invalid_client_error()
+    };
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let authenticated = OAuthClient::authenticate(
+        &mut db_conn,
+        &data,
+        &credentials.client_id,
+        &credentials.client_secret
+    )
+    .await;
+
+    if authenticated.is_err() {
+        return invalid_client_error();
+    }
+
+    // the hint only decides which kind of token is looked up first
+    let client_id = &credentials.client_id;
+    if form.token_type_hint.as_deref() == Some("refresh_token") {
+        if !RefreshToken::revoke_by_token(&mut db_conn, &data, &form.token, client_id).await {
+            let _ = LoginSession::delete_by_client_token(&mut db_conn, &data, &form.token, client_id).await;
+        }
+    } else if LoginSession::delete_by_client_token(&mut db_conn, &data, &form.token, client_id).await.is_err() {
+        let _ = RefreshToken::revoke_by_token(&mut db_conn, &data, &form.token, client_id).await;
+    }
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok();
+}
@@ -0,0 +1,368 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::{
+        header::{
+            HeaderValue,
+            CACHE_CONTROL
+        },
+        StatusCode
+    },
+    web::{
+        Form,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgConnection;
+use crate::{
+    config::CauthConfig,
+    models::{
+        authorization_code::AuthorizationCode,
+        device_code::{
+            DeviceCode,
+            DeviceCodePollError
+        },
+        login_session::{
+            LoginSession,
+            TokenScope
+        },
+        oauth_client::OAuthClient,
+        refresh_token::{
+            RefreshToken,
+            RefreshTokenRotateError,
+            TokenPair
+        },
+        signing_key::SigningKey,
+        user::User
+    },
+    util::token::verify_code_verifier,
+    web::{
+        client_credentials::{
+            authenticate_client,
+            invalid_client_error
+        },
+        session_metadata,
+        ServerResponse
+    }
+};
+
+/// Grant type of the device authorization grant as described in RFC 8628
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+#[derive(Deserialize)]
+pub struct FormData {
+    grant_type: String,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+    device_code: Option<String>,
+    scope: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>
+}
+
+fn token_response(pair: &TokenPair, scopes: Option<&Vec<String>>, id_token: Option<String>) -> ServerResponse {
+    let mut body = json!({
+        "access_token": pair.token,
+        "token_type": "Bearer",
+        "expires_in": pair.expires_in,
+        "refresh_token": pair.refresh_token
+    });
+
+    if let Some(scopes) = scopes {
+        body["scope"] = json!(scopes.join(" "));
+    }
+
+    if let Some(id_token) = id_token {
+        body["id_token"] = json!(id_token);
+    }
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(body)
+    )
+    .with_header(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+}
+
+fn access_token_response(token: &String, scopes: &Vec<String>, config: &CauthConfig) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "access_token": token,
+            "token_type": "Bearer",
+            "expires_in": config.access_token_lifetime,
+            "scope": scopes.join(" ")
+        }))
+    )
+    .with_header(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+}
+
+fn error(error: &str, description: &str) -> ServerResponse {
+    let status = match error {
+        "server_error" => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST
+    };
+
+    return ServerResponse::new(
+        status,
+        Some(json!({
+            "error": error,
+            "error_description": description
+        }))
+    )
+    .with_header(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+}
+
+/// Token endpoint as described in RFC 6749, supporting the authorization_code grant with PKCE,
+/// the refresh_token grant, the client_credentials grant and the device_code grant of RFC 8628
+#[post("/oauth/token")]
+pub async fn controller(
+    form: Form<FormData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let client = match authenticate_client(&req, &mut db_conn, &data, &form.client_id, &form.client_secret).await {
+        Some(client) => client,
+        None => return invalid_client_error()
+    };
+
+    let response = match form.grant_type.as_str() {
+        "authorization_code" => authorization_code_grant(&mut db_conn, &data, &form, &client, &req).await,
+        "refresh_token" => refresh_token_grant(&mut db_conn, &data, &form, &client, &req).await,
+        "client_credentials" => client_credentials_grant(&mut db_conn, &data, &form, &client, &req).await,
+        DEVICE_CODE_GRANT_TYPE => device_code_grant(&mut db_conn, &data, &form, &client, &req).await,
+        _ => error("unsupported_grant_type", "Only the authorization_code, refresh_token, client_credentials and device_code grants are supported")
+    };
+
+    // committed on client errors too, so used codes and revoked token families are persisted.
+    // server errors are rolled back, so the presented code or refresh token stays usable
+    if response.status().is_server_error() {
+        let _ = db_conn.rollback().await;
+        return response;
+    }
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return response;
+}
+
+async fn authorization_code_grant(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    form: &FormData,
+    client: &OAuthClient,
+    req: &HttpRequest
+) -> ServerResponse {
+    let (code, redirect_uri, code_verifier) = match (&form.code, &form.redirect_uri, &form.code_verifier) {
+        (Some(code), Some(redirect_uri), Some(code_verifier)) => (code, redirect_uri, code_verifier),
+        _ => return error("invalid_request", "The code, redirect_uri and code_verifier parameters are required")
+    };
+
+    let authorization = match AuthorizationCode::take(conn, config, code).await {
+        Some(authorization) => authorization,
+        None => return error("invalid_grant", "The authorization code is invalid or has expired")
+    };
+
+    if authorization.client_id != client.client_id || &authorization.redirect_uri != redirect_uri {
+        return error("invalid_grant", "The authorization code was issued to another client or redirect uri");
+    }
+
+    if !verify_code_verifier(code_verifier, &authorization.code_challenge) {
+        return error("invalid_grant", "The code_verifier do not match the code_challenge");
+    }
+
+    return issue_tokens(
+        conn,
+        config,
+        client,
+        &authorization.user_login,
+        &authorization.scopes,
+        authorization.nonce.as_ref(),
+        req
+    )
+    .await;
+}
+
+async fn device_code_grant(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    form: &FormData,
+    client: &OAuthClient,
+    req: &HttpRequest
+) -> ServerResponse {
+    let device_code = match &form.device_code {
+        Some(device_code) => device_code,
+        None => return error("invalid_request", "The device_code parameter is required")
+    };
+
+    let authorization = match DeviceCode::poll(conn, config, &client.client_id, device_code).await {
+        Ok(authorization) => authorization,
+        Err(err) => {
+            let code = match err {
+                DeviceCodePollError::NotFound => "invalid_grant",
+                DeviceCodePollError::Pending => "authorization_pending",
+                DeviceCodePollError::SlowDown => "slow_down",
+                DeviceCodePollError::Denied => "access_denied",
+                DeviceCodePollError::Expired => "expired_token"
+            };
+
+            return error(code, &err.to_string());
+        }
+    };
+
+    // approved requests always have the user who approved them
+    let user_login = match &authorization.user_login {
+        Some(user_login) => user_login,
+        None => return error("invalid_grant", "The device code was not approved by a user")
+    };
+
+    return issue_tokens(
+        conn,
+        config,
+        client,
+        user_login,
+        &authorization.scopes,
+        None,
+        req
+    )
+    .await;
+}
+
+/// Issues the token pair of a grant approved by the user,
+/// together with an ID token when the openid scope was granted
+async fn issue_tokens(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    client: &OAuthClient,
+    user_login: &String,
+    scopes: &Vec<String>,
+    nonce: Option<&String>,
+    req: &HttpRequest
+) -> ServerResponse {
+    let scope = TokenScope {
+        client_id: Some(client.client_id.clone()),
+        scopes: Some(scopes.clone())
+    };
+
+    let pair = match RefreshToken::issue_scoped(
+        conn,
+        config,
+        user_login,
+        &scope,
+        &session_metadata(req)
+    )
+    .await {
+        Ok(pair) => pair,
+        Err(err) => return error("server_error", &err.to_string())
+    };
+
+    let id_token = match scopes.iter().any(|scope| scope == "openid") {
+        true => match SigningKey::sign_id_token(
+            conn,
+            config,
+            user_login,
+            &client.client_id,
+            nonce,
+            scopes
+        )
+        .await {
+            Ok(id_token) => Some(id_token),
+            Err(err) => return error("server_error", &err.to_string())
+        },
+        false => None
+    };
+
+    return token_response(&pair, Some(scopes), id_token);
+}
+
+async fn refresh_token_grant(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    form: &FormData,
+    client: &OAuthClient,
+    req: &HttpRequest
+) -> ServerResponse {
+    let refresh_token = match &form.refresh_token {
+        Some(refresh_token) => refresh_token,
+        None => return error("invalid_request", "The refresh_token parameter is required")
+    };
+
+    return match RefreshToken::rotate(
+        conn,
+        config,
+        refresh_token,
+        Some(&client.client_id),
+        &session_metadata(req)
+    )
+    .await {
+        Ok(pair) => token_response(&pair, None, None),
+        Err(err) => match err {
+            RefreshTokenRotateError::TokenCollision | RefreshTokenRotateError::CannotSign(_) => {
+                error("server_error", &err.to_string())
+            },
+            _ => error("invalid_grant", &err.to_string())
+        }
+    };
+}
+
+async fn client_credentials_grant(
+    conn: &mut PgConnection,
+    config: &CauthConfig,
+    form: &FormData,
+    client: &OAuthClient,
+    req: &HttpRequest
+) -> ServerResponse {
+    let service_account = match (&client.service_account, client.is_public()) {
+        (Some(service_account), false) => service_account,
+        _ => return error("unauthorized_client", "The client has no service account to issue tokens for")
+    };
+
+    let user = match User::retrieve(conn, service_account).await {
+        Ok(user) => user,
+        Err(_) => return error("unauthorized_client", "The service account of the client do not exist")
+    };
+
+    // every permission of the service account is granted when no scope is requested
+    let permissions = user.permissions(conn).await;
+    let scopes = match &form.scope {
+        Some(scope) => scope
+            .split_whitespace()
+            .filter(|scope| permissions.iter().any(|permission| permission == scope))
+            .map(|scope| scope.to_string())
+            .collect(),
+        None => permissions
+    };
+
+    let scope = TokenScope {
+        client_id: Some(client.client_id.clone()),
+        scopes: Some(scopes.clone())
+    };
+
+    return match LoginSession::insert(
+        conn,
+        config,
+        user.login.clone(),
+        None,
+        &scope,
+        &session_metadata(req)
+    )
+    .await {
+        Ok(token) => access_token_response(&token, &scopes, config),
+        Err(err) => error("server_error", &err.to_string())
+    };
+}
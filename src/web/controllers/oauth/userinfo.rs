@@ -0,0 +1,92 @@
+use actix_web::{
+    get,
+    HttpRequest,
+    Responder,
+    http::{
+        header::{
+            HeaderValue,
+            CACHE_CONTROL,
+            WWW_AUTHENTICATE
+        },
+        StatusCode
+    },
+    web::Data
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        user::User
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+fn error(status: StatusCode, error: &'static str, description: &str) -> ServerResponse {
+    return ServerResponse::new(
+        status,
+        Some(json!({
+            "error": error,
+            "error_description": description
+        }))
+    )
+    .with_header(
+        WWW_AUTHENTICATE,
+        HeaderValue::from_static(match error {
+            "insufficient_scope" => "Bearer error=\"insufficient_scope\"",
+            _ => "Bearer error=\"invalid_token\""
+        })
+    );
+}
+
+/// UserInfo endpoint as described in OpenID Connect Core,
+/// returning the claims of the user an access token with the openid scope was issued for
+#[get("/userinfo")]
+pub async fn controller(
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    let token = match SessionToken::from_header(&req) {
+        Some(token) => token,
+        None => return error(StatusCode::UNAUTHORIZED, "invalid_token", "No access token was provided")
+    };
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let session = match LoginSession::retrieve(&mut db_conn, &data, &token).await {
+        Ok(session) => session,
+        Err(err) => return error(StatusCode::UNAUTHORIZED, "invalid_token", &err.to_string())
+    };
+
+    let scopes = match session.scopes {
+        Some(scopes) if scopes.iter().any(|scope| scope == "openid") => scopes,
+        _ => return error(StatusCode::FORBIDDEN, "insufficient_scope", "The access token was not issued with the openid scope")
+    };
+
+    let user = match User::retrieve(&mut db_conn, &session.user_login).await {
+        Ok(user) => user,
+        Err(err) => return error(StatusCode::UNAUTHORIZED, "invalid_token", &err.to_string())
+    };
+
+    let claims = user.openid_claims(&mut db_conn, &data, &scopes).await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(claims))
+    )
+    .with_header(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+}
@@ -4,11 +4,9 @@ use actix_web::{
     http::StatusCode, 
     web::{
         Path,
-        Data,
-        Query
+        Data
     }
 };
-use serde::Deserialize;
 use serde_json::json;
 use crate::{
     config::CauthConfig,
@@ -19,14 +17,12 @@ use crate::{
         },
         login_session::LoginSession
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-struct QueryData {
-    session_token: String,
-}
-
 type PathData = String;
 
 fn ok() -> ServerResponse {
@@ -48,7 +44,7 @@ fn not_found() -> ServerResponse {
 
 #[delete("/permissions/{name}")]
 pub async fn controller(
-  query: Query<QueryData>,
+  session: SessionToken,
   data: Data<CauthConfig>,
   name: Path<PathData>
 ) -> impl Responder {
@@ -60,7 +56,8 @@ pub async fn controller(
 
     let permitted = LoginSession::has_permission(
         &mut db_conn,
-        &query.session_token,
+        &data,
+        &session.token,
         "authoric:permissions:delete"
     )
     .await;
@@ -4,8 +4,7 @@ use actix_web::{
     http::StatusCode, 
     web::{
         Json,
-        Data,
-        Query
+        Data
     }
 };
 use serde::Deserialize;
@@ -19,14 +18,12 @@ use crate::{
         },
         login_session::LoginSession
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-struct QueryData {
-    session_token: String,
-}
-
 #[derive(Deserialize)]
 struct JsonData {
     name: String,
@@ -52,7 +49,7 @@ fn name_error() -> ServerResponse {
 
 #[post("/permissions")]
 pub async fn controller(
-    query: Query<QueryData>,
+    session: SessionToken,
     json: Json<JsonData>,
     data: Data<CauthConfig>
 ) -> impl Responder {
@@ -64,7 +61,8 @@ pub async fn controller(
 
     let permitted = LoginSession::has_permission(
         &mut db_conn,
-        &query.session_token,
+        &data,
+        &session.token,
         "authoric:permissions:post"
     )
     .await;
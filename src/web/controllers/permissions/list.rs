@@ -16,18 +16,21 @@ use crate::{
         permission::Permission,
         login_session::LoginSession
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
 #[derive(Deserialize)]
 struct QueryData {
-    session_token: String,
     order_in: Option<Order>,
     page: Option<usize>
 }
 
 #[get("/permissions")]
 pub async fn controller(
+    session: SessionToken,
     query: Query<QueryData>,
     data: Data<CauthConfig>
 ) -> impl Responder {
@@ -38,7 +41,8 @@ pub async fn controller(
 
     let permitted = LoginSession::has_permission(
         &mut db_conn,
-        &query.session_token,
+        &data,
+        &session.token,
         "authoric:permissions:get"
     )
     .await;
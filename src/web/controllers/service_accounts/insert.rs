@@ -0,0 +1,127 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode, 
+    web::{
+        Json,
+        Data,
+    }
+};
+use serde::Deserialize;
+use serde_json::{
+    json,
+    Value
+};
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        user::{
+            User,
+            UserInsertError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+struct JsonData {
+    login: String,
+    details: Option<Value>
+}
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn name_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NAME_ERROR",
+            "details": "A user with this login already exists"
+        }))
+    );
+}
+
+fn unsupported_hash_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "UNSUPPORTED_HASH",
+            "details": "The password hash is malformed or its format is not supported"
+        }))
+    );
+}
+
+fn cannot_insert_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_INSERT",
+            "details": "The user cannot be stored"
+        }))
+    );
+}
+
+#[post("/service-accounts")]
+pub async fn controller(
+    session: SessionToken,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let permitted = LoginSession::has_permission(
+        &mut db_conn,
+        &data,
+        &session.token,
+        "authoric:service-accounts:manage"
+    )
+    .await;
+
+    if !permitted {
+        return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        );
+    }
+
+    let details = json.details
+        .clone()
+        .unwrap_or(json!({}));
+
+    let result = User::insert_service_account(
+        &mut db_conn,
+        &json.login,
+        &details
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(_) => return ok(),
+        Err(error) => match error {
+            UserInsertError::NameError => return name_error(),
+            UserInsertError::CannotHash(_) => unreachable!("service accounts have no password to hash"),
+            UserInsertError::UnsupportedHash => return unsupported_hash_error(),
+            UserInsertError::CannotInsert(_) => return cannot_insert_error()
+        }
+    };
+}
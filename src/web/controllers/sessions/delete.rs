@@ -0,0 +1,88 @@
+use actix_web::{
+    delete,
+    Responder,
+    http::StatusCode, 
+    web::{
+        Path,
+        Data
+    }
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::login_session::{
+        LoginSession,
+        LoginSessionDeleteError
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+type PathData = i32;
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn not_found_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NOT_FOUND",
+            "details": "A session with this id do not exist"
+        }))
+    );
+}
+
+#[delete("/sessions/{id}")]
+pub async fn controller(
+    session: SessionToken,
+    path: Path<PathData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let permitted = LoginSession::has_permission(
+        &mut db_conn,
+        &data,
+        &session.token,
+        "authoric:sessions:manage"
+    )
+    .await;
+
+    if !permitted {
+        return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        );
+    }
+
+    let result = LoginSession::delete(
+        &mut db_conn,
+        path.into_inner()
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(_) => return ok(),
+        Err(error) => match error {
+            LoginSessionDeleteError::NotFound => return not_found_error()
+        }
+    };
+}
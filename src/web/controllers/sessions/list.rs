@@ -0,0 +1,94 @@
+use actix_web::{
+    Responder,
+    get,
+    http::StatusCode,
+    web::{
+        Path,
+        Data
+    }
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        user::User
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+type PathData = String;
+
+fn ok(sessions: Vec<LoginSession>) -> ServerResponse {
+    let sessions = sessions
+        .iter()
+        .map(|session| json!({
+            "id": session.id,
+            "created_at": session.created_at,
+            "last_seen_at": session.last_seen_at,
+            "user_agent": session.user_agent,
+            "ip": session.ip
+        }))
+        .collect::<Vec<_>>();
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(sessions))
+    );
+}
+
+fn not_found_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NOT_FOUND",
+            "details": "The user with specified login do not exist"
+        }))
+    );
+}
+
+#[get("/sessions/users/{login}")]
+pub async fn controller(
+    session: SessionToken,
+    path: Path<PathData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .acquire()
+        .await
+        .unwrap();
+
+    let permitted = LoginSession::has_permission(
+        &mut db_conn,
+        &data,
+        &session.token,
+        "authoric:sessions:manage"
+    )
+    .await;
+
+    if !permitted {
+        return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        );
+    }
+
+    let login = path.into_inner();
+
+    if let Err(_) = User::retrieve(&mut db_conn, &login).await {
+        return not_found_error();
+    }
+
+    let sessions = LoginSession::list_by_user(
+        &mut db_conn,
+        &data,
+        &login
+    )
+    .await;
+
+    return ok(sessions);
+}
@@ -0,0 +1,90 @@
+use actix_web::{
+    delete,
+    Responder,
+    http::StatusCode, 
+    web::{
+        Path,
+        Data
+    }
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        api_key::{
+            ApiKey,
+            ApiKeyRevokeError
+        },
+        login_session::LoginSession
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+type PathData = i32;
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn not_found_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NOT_FOUND",
+            "details": "You do not have an API key with this id"
+        }))
+    );
+}
+
+#[delete("/user/api-keys/{id}")]
+pub async fn controller(
+    session: SessionToken,
+    path: Path<PathData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let user = match LoginSession::get_first_party_user(
+        &mut db_conn,
+        &data,
+        &session.token
+    )
+    .await {
+        Ok(user) => user,
+        Err(_) => return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        )
+    };
+
+    let result = ApiKey::revoke(
+        &mut db_conn,
+        &user.login,
+        path.into_inner()
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(_) => return ok(),
+        Err(error) => match error {
+            ApiKeyRevokeError::NotFound => return not_found_error()
+        }
+    };
+}
@@ -0,0 +1,67 @@
+use actix_web::{
+    Responder,
+    get,
+    http::StatusCode,
+    web::{
+        Data
+    }
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        api_key::ApiKey,
+        login_session::LoginSession
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+fn ok(api_keys: Vec<ApiKey>) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(api_keys))
+    );
+}
+
+#[get("/user/api-keys")]
+pub async fn controller(
+    session: SessionToken,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let user = match LoginSession::get_first_party_user(
+        &mut db_conn,
+        &data,
+        &session.token
+    )
+    .await {
+        Ok(user) => user,
+        Err(_) => return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        )
+    };
+
+    let api_keys = ApiKey::list_by_user(
+        &mut db_conn,
+        &user.login
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok(api_keys);
+}
@@ -0,0 +1,3 @@
+pub mod insert;
+pub mod list;
+pub mod delete;
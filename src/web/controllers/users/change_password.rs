@@ -0,0 +1,161 @@
+use actix_web::{
+    put,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::{
+            LoginSession,
+            LoginSessionRetrieveError
+        },
+        user::{
+            User,
+            UserUpdatePasswordError,
+            UserVerifyPasswordError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    current_password: String,
+    new_password: String,
+    revoke_other_sessions: Option<bool>
+}
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn unauthorized_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "UNAUTHORIZED",
+            "details": "The session associated with this token was not found"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session associated with this token has expired"
+        }))
+    );
+}
+
+fn invalid_credentials_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_CREDENTIALS",
+            "details": "The current password is invalid"
+        }))
+    );
+}
+
+fn cannot_hash_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_HASH",
+            "details": format!("Cannot hash the user's password: {}", details)
+        }))
+    );
+}
+
+fn cannot_update_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_UPDATE",
+            "details": format!("Cannot store the user's password: {}", details)
+        }))
+    );
+}
+
+#[put("/user/password")]
+pub async fn controller(
+    session: SessionToken,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    // API keys cannot change the password, only a login session of the user
+    let current = match LoginSession::retrieve_first_party(&mut db_conn, &data, &session.token).await {
+        Ok(session) => session,
+        Err(error) => match error {
+            LoginSessionRetrieveError::NotFound => return unauthorized_error(),
+            LoginSessionRetrieveError::Expired => return expired_error()
+        }
+    };
+
+    let result = User::verify_password(
+        &mut db_conn,
+        &data,
+        &current.user_login,
+        &json.current_password
+    )
+    .await;
+
+    match result {
+        Ok(_) => (),
+        Err(error) => match error {
+            UserVerifyPasswordError::NotFound => return unauthorized_error(),
+            UserVerifyPasswordError::Unauthorized => return invalid_credentials_error()
+        }
+    };
+
+    let result = User::update_password(
+        &mut db_conn,
+        &data,
+        &current.user_login,
+        &json.new_password
+    )
+    .await;
+
+    if result.is_ok() && json.revoke_other_sessions.unwrap_or(false) {
+        current
+            .revoke_others(&mut db_conn)
+            .await;
+    }
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(_) => return ok(),
+        Err(error) => match error {
+            UserUpdatePasswordError::NotFound => return unauthorized_error(),
+            UserUpdatePasswordError::CannotHash(details) => return cannot_hash_error(details),
+            UserUpdatePasswordError::CannotUpdate(details) => return cannot_update_error(details)
+        }
+    };
+}
@@ -4,11 +4,9 @@ use actix_web::{
     http::StatusCode, 
     web::{
         Path,
-        Data,
-        Query
+        Data
     }
 };
-use serde::Deserialize;
 use serde_json::json;
 use crate::{
     config::CauthConfig,
@@ -20,14 +18,12 @@ use crate::{
         event::UserDeleteEvent,
         login_session::LoginSession
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-pub struct QueryData {
-    session_token: String
-}
-
 type PathData = String;
 
 fn ok() -> ServerResponse {
@@ -49,7 +45,7 @@ fn not_found_error() -> ServerResponse {
 
 #[delete("/users/{login}")]
 pub async fn controller(
-    query: Query<QueryData>,
+    session: SessionToken,
     path: Path<PathData>,
     data: Data<CauthConfig>
 ) -> impl Responder {
@@ -63,14 +59,16 @@ pub async fn controller(
 
     let has_permission = LoginSession::has_permission(
         &mut db_conn,
-        &query.session_token,
+        &data,
+        &session.token,
         &"authoric:users:delete".to_string()
     )
     .await;
 
-    let logged_user = LoginSession::retrieve(
+    let logged_user = LoginSession::retrieve_first_party(
         &mut db_conn,
-        &query.session_token
+        &data,
+        &session.token
     ).await;
 
     if !has_permission && logged_user.is_err() { 
@@ -79,7 +77,7 @@ pub async fn controller(
             None
         );
     }
-    let has_same_username = logged_user.unwrap().user_login == login;
+    let has_same_username = logged_user.is_ok_and(|logged_user| logged_user.user_login == login);
 
     let permitted = has_permission || has_same_username;
 
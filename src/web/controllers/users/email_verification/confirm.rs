@@ -0,0 +1,75 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::email_verification_token::{
+        EmailVerificationConfirmError,
+        EmailVerificationToken
+    },
+    web::ServerResponse
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    token: String
+}
+
+fn ok(user_login: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({ "login": user_login }))
+    );
+}
+
+fn invalid_token_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_TOKEN",
+            "details": "The email verification token is invalid, was already used or has expired"
+        }))
+    );
+}
+
+#[post("/user/email/verification/confirm")]
+pub async fn controller(
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let result = EmailVerificationToken::confirm(
+        &mut db_conn,
+        &data,
+        &json.token
+    )
+    .await;
+
+    // the consumed token is deleted even when it cannot be used
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(user_login) => return ok(user_login),
+        Err(error) => match error {
+            EmailVerificationConfirmError::InvalidToken => return invalid_token_error()
+        }
+    };
+}
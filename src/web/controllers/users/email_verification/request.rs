@@ -0,0 +1,113 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::Data
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        email_verification_token::{
+            EmailVerificationSendError,
+            EmailVerificationToken
+        },
+        login_session::{
+            LoginSession,
+            LoginSessionGetUserError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn unauthorized_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "UNAUTHORIZED",
+            "details": "The session associated with this token was not found"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session associated with this token has expired"
+        }))
+    );
+}
+
+fn missing_email_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "MISSING_EMAIL",
+            "details": "The user has no email to verify"
+        }))
+    );
+}
+
+fn already_verified_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "ALREADY_VERIFIED",
+            "details": "The email of the user is already verified"
+        }))
+    );
+}
+
+fn cannot_send_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_SEND",
+            "details": details
+        }))
+    );
+}
+
+#[post("/user/email/verification")]
+pub async fn controller(
+    session: SessionToken,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .acquire()
+        .await
+        .unwrap();
+
+    let user = match LoginSession::get_first_party_user(&mut db_conn, &data, &session.token).await {
+        Ok(user) => user,
+        Err(error) => match error {
+            LoginSessionGetUserError::NotFound => return unauthorized_error(),
+            LoginSessionGetUserError::Expired => return expired_error()
+        }
+    };
+
+    return match EmailVerificationToken::send(&data, &user).await {
+        Ok(_) => ok(),
+        Err(error) => match error {
+            EmailVerificationSendError::MissingEmail => missing_email_error(),
+            EmailVerificationSendError::AlreadyVerified => already_verified_error(),
+            error => {
+                log::error!("Cannot send the email verification of {}: {}", user.login, error.to_string());
+                cannot_send_error(error.to_string())
+            }
+        }
+    };
+}
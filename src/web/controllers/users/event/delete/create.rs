@@ -4,8 +4,7 @@ use actix_web::{
     http::StatusCode, 
     web::{
         Json,
-        Data,
-        Query
+        Data
     }
 };
 use serde::Deserialize;
@@ -15,14 +14,12 @@ use crate::{
     models::{
         event::{user_delete::UserDeleteEventInsertError, EventCredentials, UserDeleteEvent}, login_session::LoginSession, user::User
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-struct QueryData {
-    session_token: String
-}
-
 #[derive(Deserialize)]
 struct JsonData {
     login: String
@@ -45,9 +42,19 @@ fn user_not_found_error() -> ServerResponse {
     );
 }
 
+fn cannot_create_event_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_CREATE_EVENT",
+            "details": "The event cannot be stored, try again"
+        }))
+    );
+}
+
 #[post("/events/users/delete")]
 pub async fn controller(
-    query: Query<QueryData>,
+    session: SessionToken,
     json: Json<JsonData>,
     data: Data<CauthConfig>
 ) -> impl Responder {
@@ -59,14 +66,16 @@ pub async fn controller(
 
     let has_permission = LoginSession::has_permission(
         &mut db_conn,
-        &query.session_token,
+        &data,
+        &session.token,
         &"authoric:users:delete".to_string()
     )
     .await;
 
-    let logged_user = LoginSession::retrieve(
+    let logged_user = LoginSession::retrieve_first_party(
         &mut db_conn,
-        &query.session_token
+        &data,
+        &session.token
     ).await;
 
     if !has_permission && logged_user.is_err() { 
@@ -75,7 +84,7 @@ pub async fn controller(
             None
         );
     }
-    let has_same_username = logged_user.unwrap().user_login == json.login;
+    let has_same_username = logged_user.is_ok_and(|logged_user| logged_user.user_login == json.login);
 
     let permitted = has_permission || has_same_username;
 
@@ -102,7 +111,8 @@ pub async fn controller(
     match result {
         Ok(credentials) => return ok(credentials),
         Err(error) => match error {
-            UserDeleteEventInsertError::UserNotFound => return user_not_found_error()
+            UserDeleteEventInsertError::UserNotFound => return user_not_found_error(),
+            UserDeleteEventInsertError::CannotInsert => return cannot_create_event_error()
         }
     };
 }
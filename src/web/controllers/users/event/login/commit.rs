@@ -1,5 +1,6 @@
 use actix_web::{
     post,
+    HttpRequest,
     Responder,
     http::StatusCode, 
     web::{
@@ -12,9 +13,12 @@ use serde_json::json;
 use crate::{
     config::CauthConfig,
     models::{
-        event::{user_login::UserLoginEventCommitError, UserLoginEvent}, login_session::LoginSession, user::User
+        event::{user_login::UserLoginEventCommitError, UserLoginEvent}, login_session::LoginSession, refresh_token::TokenPair, user::User
     },
-    web::ServerResponse
+    web::{
+        session_metadata,
+        ServerResponse
+    }
 };
 
 #[derive(Deserialize)]
@@ -23,10 +27,10 @@ struct JsonData {
     key: String
 }
 
-fn ok() -> ServerResponse {
+fn ok(pair: TokenPair) -> ServerResponse {
     return ServerResponse::new(
         StatusCode::OK,
-        None
+        Some(json!(pair))
     );
 }
 
@@ -50,10 +54,31 @@ fn unauthorized_error() -> ServerResponse {
     );
 }
 
+fn token_collision_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "TOKEN_COLLISION",
+            "details": "Cannot generate a unique session token"
+        }))
+    );
+}
+
+fn cannot_sign_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_SIGN",
+            "details": format!("Cannot sign the JWT access token: {}", details)
+        }))
+    );
+}
+
 #[post("/events/users/login/commit")]
 pub async fn controller(
     json: Json<JsonData>,
-    data: Data<CauthConfig>
+    data: Data<CauthConfig>,
+    req: HttpRequest
 ) -> impl Responder {
     // these will never error
     let mut db_conn = data.db_conn
@@ -63,23 +88,32 @@ pub async fn controller(
 
     let result = UserLoginEvent::commit(
         &mut db_conn,
+        &data,
         &json.id,
-        &json.key
+        &json.key,
+        &session_metadata(&req)
     )
     .await;
-    
-    match db_conn.commit().await {
-        Ok(_) => (),
-        Err(err) => {
-            eprintln!("Error while commiting changes to the database: {}", err);
-        }
-    };
+
+    // the event stays usable when the tokens cannot be issued
+    if let Err(UserLoginEventCommitError::TokenCollision | UserLoginEventCommitError::CannotSign(_)) = &result {
+        let _ = db_conn.rollback().await;
+    } else {
+        match db_conn.commit().await {
+            Ok(_) => (),
+            Err(err) => {
+                eprintln!("Error while commiting changes to the database: {}", err);
+            }
+        };
+    }
 
     match result {
-        Ok(_) => return ok(),
+        Ok(pair) => return ok(pair),
         Err(error) => match error {
             UserLoginEventCommitError::NotFound => return not_found_error(),
-            UserLoginEventCommitError::Unauthorized => return unauthorized_error()
+            UserLoginEventCommitError::Unauthorized => return unauthorized_error(),
+            UserLoginEventCommitError::TokenCollision => return token_collision_error(),
+            UserLoginEventCommitError::CannotSign(details) => return cannot_sign_error(details)
         } 
     }
 }
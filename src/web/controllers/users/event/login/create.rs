@@ -27,6 +27,7 @@ use crate::{
 pub struct JsonData {
     login: String,
     password: String,
+    code: Option<String>
 }
 
 fn ok(credentials: EventCredentials) -> ServerResponse {
@@ -56,6 +57,36 @@ fn unauthorized_error() -> ServerResponse {
     );
 }
 
+fn mfa_required_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "MFA_REQUIRED",
+            "details": "Two-factor authentication is enabled, provide a code"
+        }))
+    );
+}
+
+fn invalid_mfa_code_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "INVALID_MFA_CODE",
+            "details": "The two-factor authentication code is invalid"
+        }))
+    );
+}
+
+fn cannot_create_event_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_CREATE_EVENT",
+            "details": "The event cannot be stored, try again"
+        }))
+    );
+}
+
 #[post("/events/users/login")]
 pub async fn controller(
     json: Json<JsonData>,
@@ -69,8 +100,10 @@ pub async fn controller(
     
     let result = UserLoginEvent::insert(
         &mut db_conn, 
+        &data,
         &json.login,
-        &json.password
+        &json.password,
+        json.code.as_ref()
     )
     .await;
 
@@ -85,7 +118,10 @@ pub async fn controller(
         Ok(credentials) => return ok(credentials),
         Err(error) => match error {
             UserLoginEventInsertError::Unauthorized => return unauthorized_error(),
-            UserLoginEventInsertError::UserNotFound => return user_not_found_error()
+            UserLoginEventInsertError::UserNotFound => return user_not_found_error(),
+            UserLoginEventInsertError::MfaRequired => return mfa_required_error(),
+            UserLoginEventInsertError::InvalidMfaCode => return invalid_mfa_code_error(),
+            UserLoginEventInsertError::CannotInsert => return cannot_create_event_error()
         }
     };
 }
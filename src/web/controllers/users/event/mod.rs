@@ -1,3 +1,4 @@
 pub mod register;
 pub mod login;
 pub mod delete;
+pub mod password_change;
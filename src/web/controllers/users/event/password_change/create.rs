@@ -0,0 +1,141 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode, 
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        event::{
+            user_password_change::UserPasswordChangeEventInsertError,
+            EventCredentials,
+            UserPasswordChangeEvent
+        },
+        login_session::{
+            LoginSession,
+            LoginSessionRetrieveError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    current_password: String,
+    new_password: String,
+    revoke_other_sessions: Option<bool>
+}
+
+fn ok(credentials: EventCredentials) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(credentials))
+    );
+}
+
+fn unauthorized_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "UNAUTHORIZED",
+            "details": "The session associated with this token was not found"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session associated with this token has expired"
+        }))
+    );
+}
+
+fn invalid_credentials_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_CREDENTIALS",
+            "details": "The current password is invalid"
+        }))
+    );
+}
+
+fn cannot_hash_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_HASH",
+            "details": format!("Cannot hash the user's password: {}", details)
+        }))
+    );
+}
+
+fn cannot_create_event_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_CREATE_EVENT",
+            "details": "The event cannot be stored, try again"
+        }))
+    );
+}
+
+#[post("/events/users/password")]
+pub async fn controller(
+    session: SessionToken,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let current = match LoginSession::retrieve_first_party(&mut db_conn, &data, &session.token).await {
+        Ok(session) => session,
+        Err(error) => match error {
+            LoginSessionRetrieveError::NotFound => return unauthorized_error(),
+            LoginSessionRetrieveError::Expired => return expired_error()
+        }
+    };
+
+    let result = UserPasswordChangeEvent::insert(
+        &mut db_conn,
+        &data,
+        &current,
+        &json.current_password,
+        &json.new_password,
+        json.revoke_other_sessions.unwrap_or(false)
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(credentials) => return ok(credentials),
+        Err(error) => match error {
+            UserPasswordChangeEventInsertError::UserNotFound => return unauthorized_error(),
+            UserPasswordChangeEventInsertError::Unauthorized => return invalid_credentials_error(),
+            UserPasswordChangeEventInsertError::CannotHash(details) => return cannot_hash_error(details),
+            UserPasswordChangeEventInsertError::CannotInsert => return cannot_create_event_error()
+        }
+    };
+}
@@ -12,6 +12,10 @@ use serde_json::json;
 use crate::{
     config::CauthConfig,
     models::{
+        email_verification_token::{
+            EmailVerificationSendError,
+            EmailVerificationToken
+        },
         event::{user_register::UserRegisterEventCommitError, EventCredentials, UserRegisterEvent}, login_session::LoginSession, user::User
     },
     web::ServerResponse
@@ -76,7 +80,22 @@ pub async fn controller(
     };
 
     match result {
-        Ok(_) => return ok(),
+        Ok(user_login) => {
+            // these will never error
+            let mut db_conn = data.db_conn
+                .acquire()
+                .await
+                .unwrap();
+
+            if let Ok(user) = User::retrieve(&mut db_conn, &user_login).await {
+                match EmailVerificationToken::send(&data, &user).await {
+                    Ok(_) | Err(EmailVerificationSendError::MissingEmail) => (),
+                    Err(err) => log::error!("Cannot send the email verification of {}: {}", user.login, err.to_string())
+                };
+            }
+
+            return ok();
+        },
         Err(error) => match error {
             UserRegisterEventCommitError::NotFound => return not_found_error(),
             UserRegisterEventCommitError::Unauthorized => return unauthorized_error()
@@ -25,7 +25,9 @@ use crate::{
 struct JsonData {
     login: String,
     password: String,
-    details: Option<Value>
+    details: Option<Value>,
+    /// Stored as the email member of the details and verified with a token sent to it
+    email: Option<String>
 }
 
 fn ok(credentials: EventCredentials) -> ServerResponse {
@@ -55,6 +57,16 @@ fn cannot_hash_error(details: String) -> ServerResponse {
     );
 }
 
+fn cannot_create_event_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_CREATE_EVENT",
+            "details": "The event cannot be stored, try again"
+        }))
+    );
+}
+
 #[post("/events/users/register")]
 pub async fn controller(
     json: Json<JsonData>,
@@ -66,12 +78,17 @@ pub async fn controller(
         .await
         .unwrap();
     
-    let details = json.details
+    let mut details = json.details
         .clone()
         .unwrap_or(json!({}));
+
+    if let (Some(email), Some(members)) = (&json.email, details.as_object_mut()) {
+        members.insert("email".to_string(), json!(email));
+    }
     
     let result = UserRegisterEvent::insert(
         &mut db_conn,
+        &data,
         &json.login,
         &json.password,
         &details
@@ -82,7 +99,8 @@ pub async fn controller(
         Ok(credentials) => return ok(credentials),
         Err(error) => match error {
             UserRegisterEventInsertError::AlreadyExists => return already_exist_error(),
-            UserRegisterEventInsertError::CannotHash(details) => return cannot_hash_error(details)
+            UserRegisterEventInsertError::CannotHash(details) => return cannot_hash_error(details),
+            UserRegisterEventInsertError::CannotInsert => return cannot_create_event_error()
         }
     };
 }
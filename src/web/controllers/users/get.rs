@@ -3,11 +3,9 @@ use actix_web::{
     get,
     http::StatusCode,
     web::{
-        Query,
         Data
     }
 };
-use serde::Deserialize;
 use serde_json::json;
 use crate::{
     config::CauthConfig,
@@ -18,14 +16,12 @@ use crate::{
             LoginSessionGetUserError
         }
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-struct QueryData {
-  session_token: String
-}
-
 fn ok(user: User) -> ServerResponse {
     return ServerResponse::new(
         StatusCode::OK,
@@ -43,9 +39,19 @@ fn not_found_error() -> ServerResponse {
     );
 }
 
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session associated with this token has expired"
+        }))
+    );
+}
+
 #[get("/user")]
 pub async fn controller(
-  query: Query<QueryData>,
+  session: SessionToken,
   data: Data<CauthConfig>
 ) -> impl Responder {
     // these will never error
@@ -56,14 +62,16 @@ pub async fn controller(
 
     let result = LoginSession::get_user(
         &mut db_conn,
-        &query.session_token
+        &data,
+        &session.token
     )
     .await;
 
     match result {
         Ok(user) => return ok(user),
         Err(error) => match error {
-            LoginSessionGetUserError::NotFound => return not_found_error() 
+            LoginSessionGetUserError::NotFound => return not_found_error(),
+            LoginSessionGetUserError::Expired => return expired_error()
         }
     };
 }
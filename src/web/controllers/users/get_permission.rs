@@ -4,11 +4,9 @@ use actix_web::{
     http::StatusCode,
     web::{
         Path,
-        Query,
         Data
     }
 };
-use serde::Deserialize;
 use serde_json::json;
 use crate::{
     config::CauthConfig,
@@ -16,20 +14,18 @@ use crate::{
         user::User,
         login_session::LoginSession
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-struct QueryData {
-    session_token: String
-}
-
 type PathData = String;
 
 #[get("/user/permissions/{permission_name}")]
 pub async fn controller(
+    session: SessionToken,
     path: Path<PathData>,
-    query: Query<QueryData>,
     data: Data<CauthConfig>
 ) -> impl Responder {
     // these will never rror
@@ -42,7 +38,8 @@ pub async fn controller(
 
     let result = LoginSession::has_permission(
         &mut db_conn,
-        &query.session_token,
+        &data,
+        &session.token,
         &permission_name
     )
     .await;
@@ -15,6 +15,10 @@ use serde_json::{
 use crate::{
     config::CauthConfig,
     models::{
+        email_verification_token::{
+            EmailVerificationSendError,
+            EmailVerificationToken
+        },
         user::{
             User,
             UserInsertError
@@ -27,7 +31,9 @@ use crate::{
 struct JsonData {
     login: String,
     password: String,
-    details: Option<Value>
+    details: Option<Value>,
+    /// Stored as the email member of the details and verified with a token sent to it
+    email: Option<String>
 }
 
 fn ok() -> ServerResponse {
@@ -57,6 +63,26 @@ fn cannot_hash_error(details: String) -> ServerResponse {
     );
 }
 
+fn unsupported_hash_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "UNSUPPORTED_HASH",
+            "details": "The password hash is malformed or its format is not supported"
+        }))
+    );
+}
+
+fn cannot_insert_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_INSERT",
+            "details": "The user cannot be stored"
+        }))
+    );
+}
+
 #[post("/users")]
 pub async fn controller(
     json: Json<JsonData>,
@@ -68,23 +94,39 @@ pub async fn controller(
         .await
         .unwrap();
     
-    let details = json.details
+    let mut details = json.details
         .clone()
         .unwrap_or(json!({}));
+
+    if let (Some(email), Some(members)) = (&json.email, details.as_object_mut()) {
+        members.insert("email".to_string(), json!(email));
+    }
     
     let result = User::insert(
         &mut db_conn, 
+        &data,
         &json.login, 
         &json.password, 
         &details
     )
     .await;
     
+    if result.is_ok() {
+        if let Ok(user) = User::retrieve(&mut db_conn, &json.login).await {
+            match EmailVerificationToken::send(&data, &user).await {
+                Ok(_) | Err(EmailVerificationSendError::MissingEmail) => (),
+                Err(err) => log::error!("Cannot send the email verification of {}: {}", user.login, err.to_string())
+            };
+        }
+    }
+
     match result {
         Ok(_) => return ok(),
         Err(error) => match error {
             UserInsertError::NameError => return name_error(),
-            UserInsertError::CannotHash(details) => return cannot_hash_error(details)
+            UserInsertError::CannotHash(details) => return cannot_hash_error(details),
+            UserInsertError::UnsupportedHash => return unsupported_hash_error(),
+            UserInsertError::CannotInsert(_) => return cannot_insert_error()
         }
     };
 }
@@ -1,5 +1,6 @@
 use actix_web::{
     post,
+    HttpRequest,
     Responder,
     http::StatusCode, 
     web::{
@@ -16,22 +17,25 @@ use crate::{
             User,
             UserLoginError
         },
+        refresh_token::TokenPair,
     },
-    web::ServerResponse
+    web::{
+        session_metadata,
+        ServerResponse
+    }
 };
 
 #[derive(Deserialize)]
 pub struct JsonData {
     login: String,
     password: String,
+    code: Option<String>,
 }
 
-fn ok(token: String) -> ServerResponse {
+fn ok(pair: TokenPair) -> ServerResponse {
     return ServerResponse::new(
         StatusCode::OK,
-        Some(json!({
-            "token": token
-        }))
+        Some(json!(pair))
     );
 }
 
@@ -55,20 +59,51 @@ fn invalid_credentials_error() -> ServerResponse {
     );
 }
 
-fn cannot_hash_error(details: String) -> ServerResponse {
+fn token_collision_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "TOKEN_COLLISION",
+            "details": "Cannot generate a unique session token"
+        }))
+    )
+}
+
+fn cannot_sign_error(details: String) -> ServerResponse {
     return ServerResponse::new(
         StatusCode::INTERNAL_SERVER_ERROR,
         Some(json!({
-            "code": "CANNOT_HASH",
-            "details": format!("Cannot create the token hash: {}", details)
+            "code": "CANNOT_SIGN",
+            "details": format!("Cannot sign the JWT access token: {}", details)
         }))
     )
 }
 
+fn mfa_required_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "MFA_REQUIRED",
+            "details": "Two-factor authentication is enabled, provide a code"
+        }))
+    );
+}
+
+fn invalid_mfa_code_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "INVALID_MFA_CODE",
+            "details": "The two-factor authentication code is invalid"
+        }))
+    );
+}
+
 #[post("/user")]
 pub async fn controller(
     json: Json<JsonData>,
-    data: Data<CauthConfig>
+    data: Data<CauthConfig>,
+    req: HttpRequest
 ) -> impl Responder {
     // these will never error
     let mut db_conn = data.db_conn
@@ -78,8 +113,11 @@ pub async fn controller(
 
     let result = User::login(
         &mut db_conn,
+        &data,
         &json.login,
-        &json.password
+        &json.password,
+        json.code.as_ref(),
+        &session_metadata(&req)
     )
     .await;
 
@@ -91,11 +129,14 @@ pub async fn controller(
     };
 
     match result {
-        Ok(token) => return ok(token),
+        Ok(pair) => return ok(pair),
         Err(error) => match error {
             UserLoginError::InvalidCredentials => return invalid_credentials_error(),
             UserLoginError::NotFound => return not_found_error(),
-            UserLoginError::CannotHash(details) => return cannot_hash_error(details)
+            UserLoginError::TokenCollision => return token_collision_error(),
+            UserLoginError::CannotSign(details) => return cannot_sign_error(details),
+            UserLoginError::MfaRequired => return mfa_required_error(),
+            UserLoginError::InvalidMfaCode => return invalid_mfa_code_error()
         }
     };
 }
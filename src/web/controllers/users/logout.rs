@@ -4,25 +4,21 @@ use actix_web::{
     http::StatusCode, 
     web::{
         Path,
-        Data,
-        Query
+        Data
     }
 };
-use serde::Deserialize;
 use serde_json::json;
 use crate::{
     config::CauthConfig,
     models::{
         login_session::{LoginSession, LoginSessionDeleteError}, user::User
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-pub struct QueryData {
-    session_token: String
-}
-
 fn ok() -> ServerResponse {
     return ServerResponse::new(
         StatusCode::OK,
@@ -42,7 +38,7 @@ fn not_found_error() -> ServerResponse {
 
 #[delete("/user")]
 pub async fn controller(
-    query: Query<QueryData>,
+    session: SessionToken,
     data: Data<CauthConfig>
 ) -> impl Responder {
     // these will never error
@@ -53,7 +49,8 @@ pub async fn controller(
 
     let result = LoginSession::delete_by_token(
         &mut db_conn,
-        &query.session_token
+        &data,
+        &session.token
     )
     .await;
 
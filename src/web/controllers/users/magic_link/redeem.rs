@@ -0,0 +1,195 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Query,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        event::{
+            EventCredentials,
+            UserLoginEvent
+        },
+        login_session::LoginSessionInsertError,
+        magic_link_token::{
+            MagicLinkRedeemError,
+            MagicLinkToken
+        },
+        refresh_token::{
+            RefreshToken,
+            TokenPair
+        }
+    },
+    web::{
+        session_metadata,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    token: String,
+    code: Option<String>
+}
+
+#[derive(Deserialize)]
+struct QueryData {
+    auto_commit: Option<bool>
+}
+
+fn ok(pair: TokenPair) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(pair))
+    );
+}
+
+fn event_created(credentials: EventCredentials) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(credentials))
+    );
+}
+
+fn disabled_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::NOT_FOUND,
+        Some(json!({
+            "code": "MAGIC_LINK_DISABLED",
+            "details": "Logging in with magic links is disabled"
+        }))
+    );
+}
+
+fn invalid_token_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_TOKEN",
+            "details": "The magic link is invalid, was already used or has expired"
+        }))
+    );
+}
+
+fn mfa_required_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "MFA_REQUIRED",
+            "details": "Two-factor authentication is enabled, provide a code"
+        }))
+    );
+}
+
+fn invalid_mfa_code_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "INVALID_MFA_CODE",
+            "details": "The two-factor authentication code is invalid"
+        }))
+    );
+}
+
+fn token_collision_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "TOKEN_COLLISION",
+            "details": "Cannot generate a unique session token"
+        }))
+    )
+}
+
+fn cannot_sign_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_SIGN",
+            "details": format!("Cannot sign the JWT access token: {}", details)
+        }))
+    )
+}
+
+fn cannot_create_event_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_CREATE_EVENT",
+            "details": "The event cannot be stored, try again"
+        }))
+    );
+}
+
+#[post("/user/magic-link/redeem")]
+pub async fn controller(
+    json: Json<JsonData>,
+    query: Query<QueryData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    if !data.magic_link_enabled {
+        return disabled_error();
+    }
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let result = MagicLinkToken::redeem(
+        &mut db_conn,
+        &data,
+        &json.token,
+        json.code.as_ref()
+    )
+    .await;
+
+    let user_login = match result {
+        Ok(user_login) => user_login,
+        Err(error) => {
+            // a recovery code consumed on the way stays consumed
+            let _ = db_conn.commit().await;
+
+            return match error {
+                MagicLinkRedeemError::InvalidToken => invalid_token_error(),
+                MagicLinkRedeemError::MfaRequired => mfa_required_error(),
+                MagicLinkRedeemError::InvalidMfaCode => invalid_mfa_code_error()
+            };
+        }
+    };
+
+    // the session can be created in two phases like with POST /events/users/login
+    let response = match query.auto_commit.unwrap_or(true) {
+        true => match RefreshToken::issue(&mut db_conn, &data, &user_login, &session_metadata(&req)).await {
+            Ok(pair) => ok(pair),
+            Err(error) => match error {
+                LoginSessionInsertError::CannotSign(details) => return cannot_sign_error(details),
+                LoginSessionInsertError::UserNotFound => return invalid_token_error(),
+                LoginSessionInsertError::TokenCollision => return token_collision_error()
+            }
+        },
+        false => match UserLoginEvent::insert_authenticated(&mut db_conn, &user_login).await {
+            Ok(credentials) => event_created(credentials),
+            Err(_) => return cannot_create_event_error()
+        }
+    };
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return response;
+}
@@ -0,0 +1,89 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        magic_link_token::MagicLinkToken,
+        user::User
+    },
+    util::notifier::notify,
+    web::ServerResponse
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    email: String
+}
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn disabled_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::NOT_FOUND,
+        Some(json!({
+            "code": "MAGIC_LINK_DISABLED",
+            "details": "Logging in with magic links is disabled"
+        }))
+    );
+}
+
+#[post("/user/magic-link")]
+pub async fn controller(
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    if !data.magic_link_enabled {
+        return disabled_error();
+    }
+
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    // the response is the same whether a user has this email or not and when the address is rate limited,
+    // so the route cannot be used to find out which addresses are registered
+    if MagicLinkToken::is_rate_limited(&mut db_conn, &json.email).await {
+        log::warn!("Too many magic links requested for {}, no link is sent", json.email);
+        return ok();
+    }
+
+    let mut notifications = vec![];
+
+    for user in User::list_by_email(&mut db_conn, &json.email).await {
+        match MagicLinkToken::create(&mut db_conn, &data, &user.login, &json.email).await {
+            Ok(token) => notifications.push(MagicLinkToken::notification(&data, &user, &json.email, &token)),
+            Err(err) => log::error!("{}", err.to_string())
+        };
+    }
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    for notification in notifications {
+        if let Err(err) = notify(&data.notifier, &notification).await {
+            log::error!("Cannot send the magic link of {}: {}", notification.login, err.to_string());
+        }
+    }
+
+    return ok();
+}
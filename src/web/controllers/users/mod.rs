@@ -4,6 +4,15 @@ pub mod get;
 pub mod get_permission;
 pub mod login;
 pub mod logout;
+pub mod refresh;
 pub mod grant_group;
 pub mod revoke_group;
+pub mod change_password;
+pub mod password_reset;
+pub mod email_verification;
+pub mod magic_link;
+pub mod sessions;
+pub mod totp;
+pub mod webauthn;
+pub mod api_keys;
 pub mod event;
@@ -0,0 +1,120 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        password_reset_token::PasswordResetToken,
+        user::{
+            User,
+            UserUpdatePasswordError
+        }
+    },
+    web::ServerResponse
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    token: String,
+    new_password: String
+}
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn invalid_token_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_TOKEN",
+            "details": "The password reset token is invalid, was already used or has expired"
+        }))
+    );
+}
+
+fn cannot_hash_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_HASH",
+            "details": format!("Cannot hash the user's password: {}", details)
+        }))
+    );
+}
+
+fn cannot_update_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_UPDATE",
+            "details": format!("Cannot store the user's password: {}", details)
+        }))
+    );
+}
+
+#[post("/user/password-reset/confirm")]
+pub async fn controller(
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let reset_token = match PasswordResetToken::consume(&mut db_conn, &data, &json.token).await {
+        Some(reset_token) => reset_token,
+        None => {
+            // the consumed token is deleted even when it has expired
+            let _ = db_conn.commit().await;
+            return invalid_token_error();
+        }
+    };
+
+    let result = User::update_password(
+        &mut db_conn,
+        &data,
+        &reset_token.user_login,
+        &json.new_password
+    )
+    .await;
+
+    match result {
+        Ok(_) => (),
+        Err(error) => match error {
+            UserUpdatePasswordError::NotFound => return invalid_token_error(),
+            UserUpdatePasswordError::CannotHash(details) => return cannot_hash_error(details),
+            UserUpdatePasswordError::CannotUpdate(details) => return cannot_update_error(details)
+        }
+    };
+
+    // whoever knew the old password is logged out
+    LoginSession::revoke_all(
+        &mut db_conn,
+        &reset_token.user_login
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok();
+}
@@ -0,0 +1,82 @@
+use actix_web::{
+    post,
+    rt,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use crate::{
+    config::CauthConfig,
+    models::{
+        password_reset_token::PasswordResetToken,
+        user::User
+    },
+    util::notifier::notify,
+    web::ServerResponse
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    login: String
+}
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+#[post("/user/password-reset")]
+pub async fn controller(
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    // the response is the same whether the user exists or not,
+    // so the route cannot be used to find out which logins are taken
+    let user = match User::retrieve(&mut db_conn, &json.login).await {
+        Ok(user) if !user.service_account => user,
+        _ => return ok()
+    };
+
+    if PasswordResetToken::is_rate_limited(&mut db_conn, &user.login).await {
+        log::warn!("Too many password resets requested for {}, no token is sent", user.login);
+        return ok();
+    }
+
+    let token = match PasswordResetToken::create(&mut db_conn, &data, &user.login).await {
+        Ok(token) => token,
+        Err(err) => {
+            log::error!("{}", err.to_string());
+            return ok();
+        }
+    };
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    let notification = PasswordResetToken::notification(&data, &user, &token);
+
+    // sent off the request, as the time the delivery takes would tell which logins exist
+    rt::spawn(async move {
+        if let Err(err) = notify(&data.notifier, &notification).await {
+            log::error!("Cannot send the password reset token of {}: {}", notification.login, err.to_string());
+        }
+    });
+
+    return ok();
+}
@@ -0,0 +1,137 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::StatusCode, 
+    web::{
+        Json,
+        Data,
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::refresh_token::{
+        RefreshToken,
+        RefreshTokenRotateError,
+        TokenPair
+    },
+    web::{
+        session_metadata,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    refresh_token: String
+}
+
+fn ok(pair: TokenPair) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(pair))
+    );
+}
+
+fn not_found_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "NOT_FOUND",
+            "details": "Provided refresh token do not exist"
+        }))
+    );
+}
+
+fn reused_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "TOKEN_REUSED",
+            "details": "Provided refresh token was already used, all tokens of this login have been revoked"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "Provided refresh token has expired"
+        }))
+    );
+}
+
+fn token_collision_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "TOKEN_COLLISION",
+            "details": "Cannot generate a unique session token"
+        }))
+    )
+}
+
+fn cannot_sign_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_SIGN",
+            "details": format!("Cannot sign the JWT access token: {}", details)
+        }))
+    )
+}
+
+#[post("/user/refresh")]
+pub async fn controller(
+    json: Json<JsonData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let result = RefreshToken::rotate(
+        &mut db_conn,
+        &data,
+        &json.refresh_token,
+        None,
+        &session_metadata(&req)
+    )
+    .await;
+
+    // committed on reuse and expiry too, so revoking the token family is persisted.
+    // other errors are rolled back, so the refresh token and its session stay usable
+    let persist = matches!(
+        result,
+        Ok(_) | Err(RefreshTokenRotateError::Reused) | Err(RefreshTokenRotateError::Expired)
+    );
+
+    if persist {
+        match db_conn.commit().await {
+            Ok(_) => (),
+            Err(err) => {
+                eprintln!("Error committing changes: {}", err);
+            }
+        };
+    } else {
+        let _ = db_conn.rollback().await;
+    }
+
+    match result {
+        Ok(pair) => return ok(pair),
+        Err(error) => match error {
+            RefreshTokenRotateError::NotFound => return not_found_error(),
+            RefreshTokenRotateError::Reused => return reused_error(),
+            RefreshTokenRotateError::Expired => return expired_error(),
+            RefreshTokenRotateError::TokenCollision => return token_collision_error(),
+            RefreshTokenRotateError::CannotSign(details) => return cannot_sign_error(details)
+        }
+    };
+}
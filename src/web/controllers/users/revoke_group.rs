@@ -3,26 +3,22 @@ use actix_web::{
     Responder,
     http::StatusCode, 
     web::{
-        Query,
         Data,
         Path
     }
 };
-use serde::Deserialize;
 use serde_json::json;
 use crate::{
     config::CauthConfig,
     models::{
         login_session::LoginSession, user::{User, UserRevokeError}
     },
-    web::ServerResponse
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
 };
 
-#[derive(Deserialize)]
-struct QueryData {
-    session_token: String
-}
-
 type PathData = (String, String);
 
 fn ok() -> ServerResponse {
@@ -64,8 +60,8 @@ fn not_granted_error() -> ServerResponse {
 
 #[delete("/users/{name}/{permission_name}")]
 pub async fn controller(
+    session: SessionToken,
     data: Data<CauthConfig>,
-    query: Query<QueryData>,
     path: Path<PathData>
 ) -> impl Responder {
     // these will never error
@@ -76,7 +72,8 @@ pub async fn controller(
 
     let permitted = LoginSession::has_permission(
         &mut db_conn,
-        &query.session_token,
+        &data,
+        &session.token,
         "authoric:users:update"
     )
     .await;
@@ -0,0 +1,61 @@
+use actix_web::{
+    delete,
+    Responder,
+    http::StatusCode, 
+    web::{
+        Data
+    }
+};
+use crate::{
+    config::CauthConfig,
+    models::login_session::LoginSession,
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+#[delete("/user/sessions")]
+pub async fn controller(
+    session: SessionToken,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let current = match LoginSession::retrieve_first_party(
+        &mut db_conn,
+        &data,
+        &session.token
+    )
+    .await {
+        Ok(session) => session,
+        Err(_) => return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        )
+    };
+
+    current
+        .revoke_others(&mut db_conn)
+        .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok();
+}
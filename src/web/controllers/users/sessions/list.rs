@@ -0,0 +1,71 @@
+use actix_web::{
+    Responder,
+    get,
+    http::StatusCode,
+    web::{
+        Data
+    }
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::login_session::LoginSession,
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+fn ok(sessions: Vec<LoginSession>, current: LoginSession) -> ServerResponse {
+    let sessions = sessions
+        .iter()
+        .map(|session| json!({
+            "id": session.id,
+            "created_at": session.created_at,
+            "last_seen_at": session.last_seen_at,
+            "user_agent": session.user_agent,
+            "ip": session.ip,
+            "client_id": session.client_id,
+            "current": session.id == current.id
+        }))
+        .collect::<Vec<_>>();
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(sessions))
+    );
+}
+
+#[get("/user/sessions")]
+pub async fn controller(
+    session: SessionToken,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .acquire()
+        .await
+        .unwrap();
+
+    let current = match LoginSession::retrieve_first_party(
+        &mut db_conn,
+        &data,
+        &session.token
+    )
+    .await {
+        Ok(session) => session,
+        Err(_) => return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        )
+    };
+
+    let sessions = LoginSession::list_by_user(
+        &mut db_conn,
+        &data,
+        &current.user_login
+    )
+    .await;
+
+    return ok(sessions, current);
+}
@@ -0,0 +1,3 @@
+pub mod list;
+pub mod delete;
+pub mod delete_others;
@@ -0,0 +1,136 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::{
+            LoginSession,
+            LoginSessionGetUserError
+        },
+        user_totp::{
+            UserTotp,
+            UserTotpConfirmError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    code: String
+}
+
+fn ok(recovery_codes: Vec<String>) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "recovery_codes": recovery_codes
+        }))
+    );
+}
+
+fn unauthorized_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "UNAUTHORIZED",
+            "details": "The session associated with this token was not found"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session associated with this token has expired"
+        }))
+    );
+}
+
+fn not_enrolled_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NOT_ENROLLED",
+            "details": "Two-factor authentication was not enrolled, use POST /user/totp first"
+        }))
+    );
+}
+
+fn invalid_mfa_code_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_MFA_CODE",
+            "details": "The two-factor authentication code is invalid"
+        }))
+    );
+}
+
+fn cannot_generate_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_GENERATE",
+            "details": details
+        }))
+    );
+}
+
+#[post("/user/totp/confirm")]
+pub async fn controller(
+    session: SessionToken,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let user = match LoginSession::get_first_party_user(&mut db_conn, &data, &session.token).await {
+        Ok(user) => user,
+        Err(error) => match error {
+            LoginSessionGetUserError::NotFound => return unauthorized_error(),
+            LoginSessionGetUserError::Expired => return expired_error()
+        }
+    };
+
+    let result = UserTotp::confirm(
+        &mut db_conn,
+        &user.login,
+        &json.code
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(recovery_codes) => return ok(recovery_codes),
+        Err(error) => match error {
+            UserTotpConfirmError::NotEnrolled => return not_enrolled_error(),
+            UserTotpConfirmError::InvalidCode => return invalid_mfa_code_error(),
+            UserTotpConfirmError::CannotGenerateRecoveryCodes(err) => return cannot_generate_error(err.to_string())
+        }
+    };
+}
@@ -0,0 +1,122 @@
+use actix_web::{
+    delete,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::{
+            LoginSession,
+            LoginSessionGetUserError
+        },
+        user_totp::{
+            UserTotp,
+            UserTotpVerifyError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    code: String
+}
+
+fn ok() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        None
+    );
+}
+
+fn unauthorized_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "UNAUTHORIZED",
+            "details": "The session associated with this token was not found"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session associated with this token has expired"
+        }))
+    );
+}
+
+fn not_enrolled_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NOT_ENROLLED",
+            "details": "Two-factor authentication is not enabled"
+        }))
+    );
+}
+
+fn invalid_mfa_code_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_MFA_CODE",
+            "details": "The two-factor authentication code is invalid"
+        }))
+    );
+}
+
+#[delete("/user/totp")]
+pub async fn controller(
+    session: SessionToken,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let user = match LoginSession::get_first_party_user(&mut db_conn, &data, &session.token).await {
+        Ok(user) => user,
+        Err(error) => match error {
+            LoginSessionGetUserError::NotFound => return unauthorized_error(),
+            LoginSessionGetUserError::Expired => return expired_error()
+        }
+    };
+
+    // the second factor can only be disabled with a current code
+    let result = match UserTotp::verify(&mut db_conn, &user.login, &json.code).await {
+        Ok(_) => UserTotp::disable(&mut db_conn, &user.login).await,
+        Err(err) => Err(err)
+    };
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(_) => return ok(),
+        Err(error) => match error {
+            UserTotpVerifyError::NotEnrolled => return not_enrolled_error(),
+            UserTotpVerifyError::InvalidCode => return invalid_mfa_code_error()
+        }
+    };
+}
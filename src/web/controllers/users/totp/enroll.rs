@@ -0,0 +1,104 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::Data
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::{
+            LoginSession,
+            LoginSessionGetUserError
+        },
+        user_totp::{
+            TotpEnrollment,
+            UserTotp,
+            UserTotpEnrollError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+fn ok(enrollment: TotpEnrollment) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(enrollment))
+    );
+}
+
+fn unauthorized_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "UNAUTHORIZED",
+            "details": "The session associated with this token was not found"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session associated with this token has expired"
+        }))
+    );
+}
+
+fn already_enabled_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "ALREADY_ENABLED",
+            "details": "Two-factor authentication is already enabled"
+        }))
+    );
+}
+
+#[post("/user/totp")]
+pub async fn controller(
+    session: SessionToken,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let user = match LoginSession::get_first_party_user(&mut db_conn, &data, &session.token).await {
+        Ok(user) => user,
+        Err(error) => match error {
+            LoginSessionGetUserError::NotFound => return unauthorized_error(),
+            LoginSessionGetUserError::Expired => return expired_error()
+        }
+    };
+
+    let result = UserTotp::enroll(
+        &mut db_conn,
+        &data,
+        &user.login
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(enrollment) => return ok(enrollment),
+        Err(error) => match error {
+            UserTotpEnrollError::AlreadyEnabled => return already_enabled_error(),
+            UserTotpEnrollError::UserNotFound => return unauthorized_error()
+        }
+    };
+}
@@ -0,0 +1,146 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::{
+            LoginSession,
+            LoginSessionGetUserError
+        },
+        recovery_code::{
+            RecoveryCode,
+            RecoveryCodeGenerateError
+        },
+        user_totp::{
+            UserTotp,
+            UserTotpVerifyError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    code: String
+}
+
+fn ok(recovery_codes: Vec<String>) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "recovery_codes": recovery_codes
+        }))
+    );
+}
+
+fn unauthorized_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "UNAUTHORIZED",
+            "details": "The session associated with this token was not found"
+        }))
+    );
+}
+
+fn expired_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": "SESSION_EXPIRED",
+            "details": "The session associated with this token has expired"
+        }))
+    );
+}
+
+fn not_enrolled_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "NOT_ENROLLED",
+            "details": "Two-factor authentication is not enabled"
+        }))
+    );
+}
+
+fn invalid_mfa_code_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_MFA_CODE",
+            "details": "The two-factor authentication code is invalid"
+        }))
+    );
+}
+
+fn cannot_generate_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": "CANNOT_GENERATE",
+            "details": details
+        }))
+    );
+}
+
+#[post("/user/totp/recovery-codes")]
+pub async fn controller(
+    session: SessionToken,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let user = match LoginSession::get_first_party_user(&mut db_conn, &data, &session.token).await {
+        Ok(user) => user,
+        Err(error) => match error {
+            LoginSessionGetUserError::NotFound => return unauthorized_error(),
+            LoginSessionGetUserError::Expired => return expired_error()
+        }
+    };
+
+    // the codes can only be regenerated with a current code of the authenticator app
+    if let Err(error) = UserTotp::verify(&mut db_conn, &user.login, &json.code).await {
+        return match error {
+            UserTotpVerifyError::NotEnrolled => not_enrolled_error(),
+            UserTotpVerifyError::InvalidCode => invalid_mfa_code_error()
+        };
+    }
+
+    let result = RecoveryCode::generate(
+        &mut db_conn,
+        &user.login
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(recovery_codes) => return ok(recovery_codes),
+        Err(error) => match error {
+            RecoveryCodeGenerateError::CannotHash(details) => return cannot_generate_error(details),
+            RecoveryCodeGenerateError::UserNotFound => return unauthorized_error()
+        }
+    };
+}
@@ -0,0 +1,4 @@
+pub mod register;
+pub mod register_finish;
+pub mod list;
+pub mod delete;
@@ -0,0 +1,66 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::Data
+};
+use serde_json::Value;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        webauthn_credential::WebauthnCredential
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+fn ok(options: Value) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(options)
+    );
+}
+
+#[post("/user/webauthn/register")]
+pub async fn controller(
+    session: SessionToken,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let current = match LoginSession::retrieve_first_party(
+        &mut db_conn,
+        &data,
+        &session.token
+    )
+    .await {
+        Ok(session) => session,
+        Err(_) => return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        )
+    };
+
+    let options = WebauthnCredential::start_registration(
+        &mut db_conn,
+        &data,
+        &current.user_login
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok(options);
+}
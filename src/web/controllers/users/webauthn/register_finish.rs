@@ -0,0 +1,120 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        login_session::LoginSession,
+        webauthn_credential::{
+            RegistrationCredential,
+            WebauthnCredential,
+            WebauthnRegisterError
+        }
+    },
+    web::{
+        session_token::SessionToken,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    credential: RegistrationCredential,
+    name: Option<String>
+}
+
+fn ok(credential: WebauthnCredential) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(credential))
+    );
+}
+
+fn challenge_not_found_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "CHALLENGE_NOT_FOUND",
+            "details": "The challenge do not exist or has expired, start the registration again"
+        }))
+    );
+}
+
+fn invalid_response_error(details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "INVALID_RESPONSE",
+            "details": details
+        }))
+    );
+}
+
+fn credential_exists_error() -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::BAD_REQUEST,
+        Some(json!({
+            "code": "CREDENTIAL_EXISTS",
+            "details": "This credential is already registered"
+        }))
+    );
+}
+
+#[post("/user/webauthn/register/finish")]
+pub async fn controller(
+    session: SessionToken,
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let current = match LoginSession::retrieve_first_party(
+        &mut db_conn,
+        &data,
+        &session.token
+    )
+    .await {
+        Ok(session) => session,
+        Err(_) => return ServerResponse::new(
+            StatusCode::UNAUTHORIZED,
+            None
+        )
+    };
+
+    let result = WebauthnCredential::finish_registration(
+        &mut db_conn,
+        &data,
+        &current.user_login,
+        &json.credential,
+        &json.name.clone().unwrap_or("Security key".to_string())
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(credential) => return ok(credential),
+        Err(error) => match error {
+            WebauthnRegisterError::ChallengeNotFound => return challenge_not_found_error(),
+            WebauthnRegisterError::InvalidResponse(details) => return invalid_response_error(details),
+            WebauthnRegisterError::CredentialExists => return credential_exists_error()
+        }
+    };
+}
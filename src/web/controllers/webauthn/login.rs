@@ -0,0 +1,56 @@
+use actix_web::{
+    post,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::Value;
+use crate::{
+    config::CauthConfig,
+    models::webauthn_credential::WebauthnCredential,
+    web::ServerResponse
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    login: Option<String>
+}
+
+fn ok(options: Value) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(options)
+    );
+}
+
+#[post("/webauthn/login")]
+pub async fn controller(
+    json: Json<JsonData>,
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let options = WebauthnCredential::start_authentication(
+        &mut db_conn,
+        &data,
+        json.login.as_ref()
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    return ok(options);
+}
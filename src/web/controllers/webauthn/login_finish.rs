@@ -0,0 +1,105 @@
+use actix_web::{
+    post,
+    HttpRequest,
+    Responder,
+    http::StatusCode,
+    web::{
+        Json,
+        Data
+    }
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::{
+        refresh_token::TokenPair,
+        webauthn_credential::{
+            AuthenticationCredential,
+            WebauthnAuthenticateError,
+            WebauthnCredential
+        }
+    },
+    web::{
+        session_metadata,
+        ServerResponse
+    }
+};
+
+#[derive(Deserialize)]
+pub struct JsonData {
+    credential: AuthenticationCredential
+}
+
+fn ok(pair: TokenPair) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!(pair))
+    );
+}
+
+fn unauthorized_error(code: &str, details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::UNAUTHORIZED,
+        Some(json!({
+            "code": code,
+            "details": details
+        }))
+    );
+}
+
+fn internal_error(code: &str, details: String) -> ServerResponse {
+    return ServerResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(json!({
+            "code": code,
+            "details": details
+        }))
+    );
+}
+
+#[post("/webauthn/login/finish")]
+pub async fn controller(
+    json: Json<JsonData>,
+    data: Data<CauthConfig>,
+    req: HttpRequest
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .begin()
+        .await
+        .unwrap();
+
+    let result = WebauthnCredential::finish_authentication(
+        &mut db_conn,
+        &data,
+        &json.credential,
+        &session_metadata(&req)
+    )
+    .await;
+
+    match db_conn.commit().await {
+        Ok(_) => (),
+        Err(err) => {
+            eprintln!("Error committing changes: {}", err);
+        }
+    };
+
+    match result {
+        Ok(pair) => return ok(pair),
+        Err(error) => {
+            let details = error.to_string();
+
+            match error {
+                WebauthnAuthenticateError::CredentialNotFound => return unauthorized_error("CREDENTIAL_NOT_FOUND", details),
+                WebauthnAuthenticateError::ChallengeNotFound => return unauthorized_error("CHALLENGE_NOT_FOUND", details),
+                WebauthnAuthenticateError::InvalidResponse(_) => return unauthorized_error("INVALID_RESPONSE", details),
+                WebauthnAuthenticateError::InvalidSignature => return unauthorized_error("INVALID_SIGNATURE", details),
+                WebauthnAuthenticateError::UserNotVerified => return unauthorized_error("USER_NOT_VERIFIED", details),
+                WebauthnAuthenticateError::CounterRegression => return unauthorized_error("COUNTER_REGRESSION", details),
+                WebauthnAuthenticateError::TokenCollision => return internal_error("TOKEN_COLLISION", details),
+                WebauthnAuthenticateError::CannotSign(_) => return internal_error("CANNOT_SIGN", details)
+            }
+        }
+    };
+}
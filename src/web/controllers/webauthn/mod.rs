@@ -0,0 +1,2 @@
+pub mod login;
+pub mod login_finish;
@@ -0,0 +1,39 @@
+use actix_web::{
+    Responder,
+    get,
+    http::StatusCode,
+    web::Data
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::signing_key::SigningKey,
+    web::ServerResponse
+};
+
+#[get("/.well-known/jwks.json")]
+pub async fn controller(
+    data: Data<CauthConfig>
+) -> impl Responder {
+    // these will never error
+    let mut db_conn = data.db_conn
+        .acquire()
+        .await
+        .unwrap();
+
+    let keys = SigningKey::published(
+        &mut db_conn,
+        &data
+    )
+    .await
+    .iter()
+    .map(|key| key.to_jwk())
+    .collect::<Vec<_>>();
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "keys": keys
+        }))
+    );
+}
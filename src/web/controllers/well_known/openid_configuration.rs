@@ -0,0 +1,48 @@
+use actix_web::{
+    Responder,
+    get,
+    http::StatusCode,
+    web::Data
+};
+use serde_json::json;
+use crate::{
+    config::CauthConfig,
+    models::user::OPENID_SCOPES,
+    web::ServerResponse
+};
+
+/// OpenID Connect discovery document, the endpoints are published under the issuer
+/// so jwt_issuer has to be set to the public url of authoric
+#[get("/.well-known/openid-configuration")]
+pub async fn controller(
+    data: Data<CauthConfig>
+) -> impl Responder {
+    let issuer = data.jwt_issuer.trim_end_matches('/');
+
+    return ServerResponse::new(
+        StatusCode::OK,
+        Some(json!({
+            "issuer": data.jwt_issuer,
+            "authorization_endpoint": format!("{}/oauth/authorize", issuer),
+            "token_endpoint": format!("{}/oauth/token", issuer),
+            "userinfo_endpoint": format!("{}/userinfo", issuer),
+            "jwks_uri": format!("{}/.well-known/jwks.json", issuer),
+            "introspection_endpoint": format!("{}/oauth/introspect", issuer),
+            "revocation_endpoint": format!("{}/oauth/revoke", issuer),
+            "device_authorization_endpoint": format!("{}/oauth/device_authorization", issuer),
+            "scopes_supported": OPENID_SCOPES,
+            "response_types_supported": ["code"],
+            "grant_types_supported": [
+                "authorization_code",
+                "refresh_token",
+                "client_credentials",
+                "urn:ietf:params:oauth:grant-type:device_code"
+            ],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["EdDSA"],
+            "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+            "code_challenge_methods_supported": ["S256"],
+            "claims_supported": ["iss", "sub", "aud", "exp", "iat", "nonce", "preferred_username", "email", "email_verified", data.oidc_groups_claim]
+        }))
+    );
+}
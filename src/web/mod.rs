@@ -1,14 +1,20 @@
 pub mod controllers;
+pub mod session_token;
+pub mod client_credentials;
+pub mod authorization_request;
+pub mod consent_page;
 
 use actix_web::{
   body::BoxBody, http::{
     header::{
-      HeaderName, HeaderValue
+      HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, X_FRAME_OPTIONS
     }, StatusCode
   }, web::Data, App, HttpRequest, HttpResponse, HttpServer, Responder
 };
+use std::net::IpAddr;
 use crate::{
     config::CauthConfig,
+    models::login_session::SessionMetadata,
     web::controllers::{
         ListPermissionsController,
         InsertPermissionController,
@@ -18,14 +24,63 @@ use crate::{
         DeleteGroupController,
         GrantPermissionGroupController,
         RevokePermissionGroupController,
+        JwksController,
+        OpenIdConfigurationController,
+        IntrospectOAuthController,
+        RevokeOAuthController,
+        AuthorizeOAuthController,
+        AuthorizeSubmitOAuthController,
+        TokenOAuthController,
+        DeviceAuthorizationOAuthController,
+        DeviceOAuthController,
+        DeviceSubmitOAuthController,
+        UserinfoOAuthController,
+        InsertOAuthClientController,
+        ListOAuthClientsController,
+        DeleteOAuthClientController,
+        ForwardAuthController,
+        WebauthnLoginController,
+        WebauthnLoginFinishController,
+        InsertApiKeyController,
+        ListApiKeysController,
+        DeleteApiKeyController,
+        InsertServiceAccountController,
+        ListFederationProvidersController,
+        LoginFederationController,
+        CallbackFederationController,
+        ListSessionsController,
+        DeleteSessionController,
+        RevokeUserSessionsController,
         InsertUserController,
         DeleteUserController,
         GetUserController,
         GetPermissionUserController,
         LoginUserController,
         LogoutUserController,
+        RefreshUserController,
         GrantGroupUserController,
         RevokeGroupUserController,
+        ChangePasswordUserController,
+        RequestPasswordResetUserController,
+        ConfirmPasswordResetUserController,
+        RequestEmailVerificationUserController,
+        ConfirmEmailVerificationUserController,
+        RequestMagicLinkUserController,
+        RedeemMagicLinkUserController,
+        ListSessionsUserController,
+        DeleteSessionUserController,
+        DeleteOtherSessionsUserController,
+        EnrollTotpUserController,
+        ConfirmTotpUserController,
+        DisableTotpUserController,
+        RegenerateRecoveryCodesUserController,
+        WebauthnRegisterUserController,
+        WebauthnRegisterFinishUserController,
+        ListWebauthnCredentialsUserController,
+        DeleteWebauthnCredentialUserController,
+        InsertApiKeyUserController,
+        ListApiKeysUserController,
+        DeleteApiKeyUserController,
         UserRegisterEventCreateController,
         UserRegisterEventCommitController,
         UserRegisterEventCancelController,
@@ -35,6 +90,9 @@ use crate::{
         UserDeleteEventCreateController,
         UserDeleteEventCommitController,
         UserDeleteEventCancelController,
+        UserPasswordChangeEventCreateController,
+        UserPasswordChangeEventCommitController,
+        UserPasswordChangeEventCancelController,
     }
 };
 
@@ -51,14 +109,63 @@ pub async fn run_server(config: CauthConfig) -> std::io::Result<()> {
             .service(DeleteGroupController)
             .service(GrantPermissionGroupController)
             .service(RevokePermissionGroupController)
+            .service(JwksController)
+            .service(OpenIdConfigurationController)
+            .service(IntrospectOAuthController)
+            .service(RevokeOAuthController)
+            .service(AuthorizeOAuthController)
+            .service(AuthorizeSubmitOAuthController)
+            .service(TokenOAuthController)
+            .service(DeviceAuthorizationOAuthController)
+            .service(DeviceOAuthController)
+            .service(DeviceSubmitOAuthController)
+            .service(UserinfoOAuthController)
+            .service(InsertOAuthClientController)
+            .service(ListOAuthClientsController)
+            .service(DeleteOAuthClientController)
+            .service(ForwardAuthController)
+            .service(WebauthnLoginController)
+            .service(WebauthnLoginFinishController)
+            .service(InsertApiKeyController)
+            .service(ListApiKeysController)
+            .service(DeleteApiKeyController)
+            .service(InsertServiceAccountController)
+            .service(ListFederationProvidersController)
+            .service(LoginFederationController)
+            .service(CallbackFederationController)
+            .service(ListSessionsController)
+            .service(DeleteSessionController)
+            .service(RevokeUserSessionsController)
             .service(InsertUserController)
             .service(DeleteUserController)
             .service(GetUserController)
             .service(GetPermissionUserController)
             .service(LoginUserController)
             .service(LogoutUserController)
+            .service(RefreshUserController)
             .service(GrantGroupUserController)
             .service(RevokeGroupUserController)
+            .service(ChangePasswordUserController)
+            .service(RequestPasswordResetUserController)
+            .service(ConfirmPasswordResetUserController)
+            .service(RequestEmailVerificationUserController)
+            .service(ConfirmEmailVerificationUserController)
+            .service(RequestMagicLinkUserController)
+            .service(RedeemMagicLinkUserController)
+            .service(ListSessionsUserController)
+            .service(DeleteSessionUserController)
+            .service(DeleteOtherSessionsUserController)
+            .service(EnrollTotpUserController)
+            .service(ConfirmTotpUserController)
+            .service(DisableTotpUserController)
+            .service(RegenerateRecoveryCodesUserController)
+            .service(WebauthnRegisterUserController)
+            .service(WebauthnRegisterFinishUserController)
+            .service(ListWebauthnCredentialsUserController)
+            .service(DeleteWebauthnCredentialUserController)
+            .service(InsertApiKeyUserController)
+            .service(ListApiKeysUserController)
+            .service(DeleteApiKeyUserController)
             .service(UserRegisterEventCreateController)
             .service(UserRegisterEventCommitController)
             .service(UserRegisterEventCancelController)
@@ -68,6 +175,9 @@ pub async fn run_server(config: CauthConfig) -> std::io::Result<()> {
             .service(UserDeleteEventCreateController)
             .service(UserDeleteEventCommitController)
             .service(UserDeleteEventCancelController)
+            .service(UserPasswordChangeEventCreateController)
+            .service(UserPasswordChangeEventCommitController)
+            .service(UserPasswordChangeEventCancelController)
     })
     .bind(("127.0.0.1", config.port))?
     .run()
@@ -78,16 +188,52 @@ pub async fn run_server(config: CauthConfig) -> std::io::Result<()> {
 
 pub struct ServerResponse {
   status: StatusCode,
-  body: Option<serde_json::Value>
+  body: Option<serde_json::Value>,
+  html: Option<String>,
+  headers: Vec<(HeaderName, HeaderValue)>
 }
 
 impl ServerResponse {
   pub fn new(status: StatusCode, body: Option<serde_json::Value>) -> Self {
     return Self {
       status,
-      body
+      body,
+      html: None,
+      headers: vec![]
     };
   }
+
+  /// ## ServerResponse::html
+  ///
+  /// Creates a response with an HTML page instead of a JSON body,
+  /// used by the few pages shown directly to the user's browser.
+  /// The pages cannot be framed, so other sites cannot trick the user into clicking their buttons
+  ///
+  pub fn html(status: StatusCode, html: String) -> Self {
+    return Self {
+      status,
+      body: None,
+      html: Some(html),
+      headers: vec![
+        (X_FRAME_OPTIONS, HeaderValue::from_static("DENY")),
+        (CONTENT_SECURITY_POLICY, HeaderValue::from_static("frame-ancestors 'none'"))
+      ]
+    };
+  }
+
+  /// ## ServerResponse::with_header
+  ///
+  /// Adds a header to the response, replacing the one with the same name
+  ///
+  pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+    self.headers.push((name, value));
+
+    return self;
+  }
+
+  pub fn status(&self) -> StatusCode {
+    return self.status;
+  }
 }
 
 impl Responder for ServerResponse {
@@ -95,12 +241,26 @@ impl Responder for ServerResponse {
 
   fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
     let mut response = HttpResponse::new(self.status);
+    let content_type = match self.html {
+      Some(_) => "text/html; charset=utf-8",
+      None => "application/json"
+    };
     response.headers_mut()
       .insert(
         HeaderName::from_static("content-type"), 
-        HeaderValue::from_static("application/json")
+        HeaderValue::from_static(content_type)
       );
 
+    // appended, so a response can set several cookies
+    for (name, value) in self.headers {
+      response.headers_mut().append(name, value);
+    }
+
+    if let Some(html) = self.html {
+      return response
+        .set_body(BoxBody::new(html));
+    }
+
     if let Some(body) = &self.body {
       let body = serde_json::to_string(body).unwrap();
 
@@ -111,3 +271,93 @@ impl Responder for ServerResponse {
     }
   }
 }
+
+/// ## client_ip
+///
+/// Picks the address of the client, which is the peer unless it's one of the trusted proxies.
+/// Behind them X-Forwarded-For is read from the right, skipping the trusted proxies,
+/// as the entries further left are supplied by the client itself
+///
+pub fn client_ip(peer: Option<IpAddr>, forwarded_for: &str, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+  let mut client = peer?;
+
+  for entry in forwarded_for.split(',').rev() {
+    if !trusted_proxies.contains(&client) {
+      break;
+    }
+
+    match entry.trim().parse::<IpAddr>() {
+      Ok(ip) => client = ip,
+      Err(_) => break
+    };
+  }
+
+  return Some(client);
+}
+
+/// ## session_metadata
+///
+/// Reads the client's user agent and ip address from the request,
+/// to be stored with a newly created login session
+///
+pub fn session_metadata(req: &HttpRequest) -> SessionMetadata {
+  let user_agent = req
+    .headers()
+    .get("user-agent")
+    .and_then(|value| value.to_str().ok())
+    .map(|value| value.to_string());
+
+  let trusted_proxies = req
+    .app_data::<Data<CauthConfig>>()
+    .map(|config| config.trusted_proxies.clone())
+    .unwrap_or_default();
+  let forwarded_for = req
+    .headers()
+    .get_all("x-forwarded-for")
+    .filter_map(|value| value.to_str().ok())
+    .collect::<Vec<_>>()
+    .join(",");
+  let ip = client_ip(req.peer_addr().map(|addr| addr.ip()), &forwarded_for, &trusted_proxies)
+    .map(|ip| ip.to_string());
+
+  return SessionMetadata {
+    user_agent,
+    ip
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ip(value: &str) -> IpAddr {
+    return value.parse().unwrap();
+  }
+
+  #[test]
+  fn ignores_forwarded_for_of_untrusted_peer() {
+    let client = client_ip(Some(ip("203.0.113.7")), "198.51.100.1", &[]);
+    assert_eq!(client, Some(ip("203.0.113.7")));
+
+    let client = client_ip(Some(ip("203.0.113.7")), "198.51.100.1", &[ip("10.0.0.1")]);
+    assert_eq!(client, Some(ip("203.0.113.7")));
+  }
+
+  #[test]
+  fn reads_forwarded_for_behind_trusted_proxies() {
+    let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+
+    let client = client_ip(Some(ip("10.0.0.1")), "198.51.100.1", &proxies);
+    assert_eq!(client, Some(ip("198.51.100.1")));
+
+    // the client made up the leftmost entry, the proxies appended the rest
+    let client = client_ip(Some(ip("10.0.0.1")), "192.0.2.66, 198.51.100.1, 10.0.0.2", &proxies);
+    assert_eq!(client, Some(ip("198.51.100.1")));
+
+    let client = client_ip(Some(ip("10.0.0.1")), "not an ip", &proxies);
+    assert_eq!(client, Some(ip("10.0.0.1")));
+
+    let client = client_ip(Some(ip("10.0.0.1")), "", &proxies);
+    assert_eq!(client, Some(ip("10.0.0.1")));
+  }
+}
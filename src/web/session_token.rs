@@ -0,0 +1,152 @@
+use std::future::{
+  ready,
+  Ready
+};
+use actix_web::{
+  dev::Payload,
+  error::InternalError,
+  http::{
+    header::{
+      AUTHORIZATION,
+      ORIGIN,
+      REFERER
+    },
+    Method,
+    StatusCode
+  },
+  web::{
+    Data,
+    Query
+  },
+  Error,
+  FromRequest,
+  HttpRequest,
+  Responder
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::{
+  config::CauthConfig,
+  web::ServerResponse
+};
+
+/// ## SessionToken
+///
+/// Extracts the login session token of the request, looking in order at:
+/// + the `Authorization: Bearer <token>` header
+/// + the cookie named after `session_cookie_name` from the config
+/// + the legacy `session_token` query parameter
+///
+/// Browsers attach the cookie to requests forged by other sites, so it's only read
+/// by GET and HEAD requests, or when the Origin or Referer header shows the request is same-origin
+///
+/// Responds with 401 when none of them is present
+///
+pub struct SessionToken {
+  pub token: String
+}
+
+#[derive(Deserialize)]
+struct QueryData {
+  session_token: Option<String>
+}
+
+fn missing_token_error(req: &HttpRequest) -> Error {
+  let response = ServerResponse::new(
+    StatusCode::UNAUTHORIZED,
+    Some(json!({
+      "code": "UNAUTHORIZED",
+      "details": "No session token was provided"
+    }))
+  )
+  .respond_to(req);
+
+  return InternalError::from_response("No session token was provided", response).into();
+}
+
+impl SessionToken {
+  /// ## SessionToken::from_header
+  ///
+  /// Reads the token from the `Authorization: Bearer <token>` header
+  ///
+  pub fn from_header(req: &HttpRequest) -> Option<String> {
+    let header = req
+      .headers()
+      .get(AUTHORIZATION)?
+      .to_str()
+      .ok()?;
+    let (scheme, token) = header.split_once(' ')?;
+
+    if !scheme.eq_ignore_ascii_case("bearer") || token.trim().is_empty() {
+      return None;
+    }
+
+    return Some(token.trim().to_string());
+  }
+
+  /// ## SessionToken::from_cookie
+  ///
+  /// Reads the token from the session cookie with the name set in the config
+  ///
+  pub fn from_cookie(req: &HttpRequest) -> Option<String> {
+    let config = req.app_data::<Data<CauthConfig>>()?;
+    let cookie = req.cookie(&config.session_cookie_name)?;
+
+    return Some(cookie.value().to_string());
+  }
+
+  /// ## SessionToken::is_same_origin
+  ///
+  /// Checks if the Origin header, or the Referer header when it's missing,
+  /// points at the host the request was sent to. Requests with neither are not same-origin
+  ///
+  pub fn is_same_origin(req: &HttpRequest) -> bool {
+    let source = match req.headers().get(ORIGIN).or_else(|| req.headers().get(REFERER)) {
+      Some(source) => source,
+      None => return false
+    };
+
+    let source_host = source
+      .to_str()
+      .ok()
+      .and_then(|source| source.split_once("://"))
+      .map(|(_, rest)| rest.split(['/', '?', '#']).next().unwrap_or(""));
+
+    return match source_host {
+      Some(source_host) if !source_host.is_empty() => {
+        source_host.eq_ignore_ascii_case(req.connection_info().host())
+      },
+      _ => false
+    };
+  }
+
+  /// ## SessionToken::from_query
+  ///
+  /// Reads the token from the legacy `session_token` query parameter
+  ///
+  pub fn from_query(req: &HttpRequest) -> Option<String> {
+    let query = Query::<QueryData>::from_query(req.query_string()).ok()?;
+
+    return query.into_inner().session_token;
+  }
+}
+
+impl FromRequest for SessionToken {
+  type Error = Error;
+  type Future = Ready<Result<Self, Self::Error>>;
+
+  fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+    let cookie_allowed = req.method() == Method::GET
+      || req.method() == Method::HEAD
+      || Self::is_same_origin(req);
+
+    let token = Self::from_header(req)
+      .or_else(|| Self::from_cookie(req).filter(|_| cookie_allowed))
+      .or_else(|| Self::from_query(req));
+
+    return match token {
+      Some(token) => ready(Ok(SessionToken { token })),
+      None => ready(Err(missing_token_error(req)))
+    };
+  }
+}